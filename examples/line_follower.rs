@@ -0,0 +1,134 @@
+/*
+Line following robot. Two reflectance sensors either side of the line are read with the SAADC, and
+two motors on an H-bridge steer towards whichever sensor sees more of the line. The control loop
+and a slower status job share one task through the scheduler, and the display shows which way the
+robot is turning.
+
+Wiring: the left and right sensors on P1 and P2, the left motor's direction pins on P13 and P14
+with its speed on P12, and the right motor's on P15 and P16 with its speed on P0.
+*/
+
+#![no_std]
+#![no_main]
+
+use core::cell::Cell;
+
+use cortex_m_rt::entry;
+use defmt::{info, warn};
+use defmt_rtt as _;
+use nrf52833_hal::{
+    gpio::Level,
+    pwm::{Channel, Pwm, PwmChannel},
+    time::Hertz,
+};
+use panic_probe as _;
+
+use async_fluid::{
+    adc::{Adc, MAX_READING},
+    board::{self, Board, Drive},
+    executor::Executor,
+    led::{FrameBuffer, LedMatrix},
+    motor::Motor,
+    pin_array,
+    scheduler::Scheduler,
+    time::TickDuration,
+};
+
+const CONTROL_PERIOD: TickDuration = TickDuration::millis(10);
+const STATUS_PERIOD: TickDuration = TickDuration::millis(500);
+// Speed on a straight line, in percent
+const CRUISE: i32 = 40;
+// Steering per unit of difference between the sensors, as a fraction of full scale
+const GAIN: i32 = 60;
+
+static FRAME: FrameBuffer = FrameBuffer::new();
+
+async fn scheduler_task(scheduler: &mut Scheduler<'_, 2>) {
+    scheduler.run().await
+}
+
+async fn display_task(leds: &mut LedMatrix) {
+    FRAME.refresh(leds).await
+}
+
+#[entry]
+fn main() -> ! {
+    #[allow(clippy::unwrap_used)] // Nothing else has taken the peripherals yet
+    let Board {
+        mut display,
+        edge,
+        spare,
+        ..
+    } = Board::take().unwrap();
+
+    let mut adc = Adc::new(spare.saadc);
+    let pwm = Pwm::new(spare.pwm0);
+    pwm.set_period(Hertz(1000));
+    pwm.set_output_pin(
+        Channel::C0,
+        board::push_pull(edge.p12, Level::Low, Drive::Standard),
+    );
+    pwm.set_output_pin(
+        Channel::C1,
+        board::push_pull(edge.p0, Level::Low, Drive::Standard),
+    );
+    let mut left = Motor::new(
+        board::push_pull(edge.p13, Level::Low, Drive::Standard),
+        board::push_pull(edge.p14, Level::Low, Drive::Standard),
+        PwmChannel::new(&pwm, Channel::C0),
+    );
+    // Mounted facing the other way
+    let mut right = Motor::new(
+        board::push_pull(edge.p15, Level::Low, Drive::Standard),
+        board::push_pull(edge.p16, Level::Low, Drive::Standard),
+        PwmChannel::new(&pwm, Channel::C1),
+    )
+    .reversed();
+    let (left_sensor, right_sensor) = (edge.p1, edge.p2);
+
+    // Positive steers right
+    let steering = Cell::new(0);
+    let mut control = |_| {
+        let (Ok(l), Ok(r)) = (adc.read(&left_sensor), adc.read(&right_sensor)) else {
+            warn!("Couldn't read the line sensors");
+            return;
+        };
+        // The line reflects less, so the sensor over it reads higher
+        let turn = (i32::from(r) - i32::from(l)) * GAIN / i32::from(MAX_READING);
+        steering.set(turn);
+        left.set_speed((CRUISE + turn) as i8);
+        right.set_speed((CRUISE - turn) as i8);
+    };
+    let screen = FRAME.handle();
+    let mut status = |_| {
+        let turn = steering.get();
+        info!("Steering {}", turn);
+        // Light the column the robot is heading for
+        let col = ((turn + GAIN) * (LedMatrix::COLS as i32 - 1) / (2 * GAIN)) as usize;
+        screen.clear();
+        screen.update(|frame| frame[LedMatrix::ROWS / 2] = 1 << col);
+    };
+
+    let mut scheduler = Scheduler::<2>::new();
+    #[allow(clippy::unwrap_used)] // There's room for both jobs
+    {
+        scheduler
+            .register(
+                "control",
+                CONTROL_PERIOD,
+                TickDuration::millis(0),
+                &mut control,
+            )
+            .unwrap();
+        scheduler
+            .register(
+                "status",
+                STATUS_PERIOD,
+                TickDuration::millis(5),
+                &mut status,
+            )
+            .unwrap();
+    }
+    let tasks = pin_array![scheduler_task(&mut scheduler), display_task(&mut display)];
+    Executor::run_tasks(tasks);
+}
@@ -0,0 +1,104 @@
+/*
+Remote LED control between two boards. Flash both with this, and the buttons on either board move
+the blinking LED on the other, by sending each press as a one byte datagram.
+*/
+
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use defmt::{info, warn};
+use defmt_rtt as _;
+use embedded_hal::digital::PinState;
+use futures::{FutureExt, select_biased};
+use panic_probe as _;
+
+use async_fluid::{
+    board::{Board, Button},
+    channel::{Channel, Receiver, Sender},
+    config::Config,
+    executor::Executor,
+    gpio::InputChannel,
+    led::{Direction, LedBlinker, LedMatrix},
+    pin_array,
+    radio::{Radio, RadioConfig},
+    time::{TickDuration, Timer},
+};
+
+const GROUP: u8 = 42;
+const BLINK_DURATION: TickDuration = TickDuration::millis(200);
+const BUTTON_DEBOUNCE: TickDuration = TickDuration::millis(50);
+
+async fn button_task(button: Button, direction: Direction, sender: Sender<'_, Direction>) {
+    #[allow(clippy::unwrap_used)] // Gpiotemanager is already initialized
+    let mut input = InputChannel::new(button).unwrap();
+    loop {
+        input.wait_for(PinState::Low).await;
+        // The channel overwrites by default, so this can't fail
+        let _ = sender.send(direction);
+        input.wait_for(PinState::High).await;
+        Timer::delay(BUTTON_DEBOUNCE).await;
+    }
+}
+
+async fn send_task(radio: &Radio, mut presses: Receiver<'_, Direction>) {
+    // Only ends if the button tasks do
+    while let Ok(direction) = presses.recv().await {
+        info!("Sending {}", direction);
+        let byte = match direction {
+            Direction::Left => b'L',
+            Direction::Right => b'R',
+        };
+        if let Err(err) = radio.send(&[byte]).await {
+            warn!("Couldn't send {}: {}", direction, err);
+        }
+    }
+}
+
+async fn display_task(leds: &mut LedMatrix, radio: &Radio) {
+    #[allow(clippy::unwrap_used)] // Row 0 is on the display
+    let mut blinky = LedBlinker::new(leds, 0).unwrap();
+    loop {
+        select_biased! {
+            datagram = radio.recv().fuse() => {
+                let direction = match datagram.data.first() {
+                    Some(b'L') => Direction::Left,
+                    Some(b'R') => Direction::Right,
+                    _ => continue,
+                };
+                info!("Received {} at {} dBm", direction, datagram.rssi);
+                blinky.shift(direction);
+            }
+            () = Timer::delay(BLINK_DURATION).fuse() => blinky.toggle(),
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    #[allow(clippy::unwrap_used)] // Nothing else has taken the peripherals yet
+    let Board {
+        mut display,
+        buttons,
+        spare,
+        mut nvic,
+        ..
+    } = Board::take().unwrap();
+    let radio = Radio::new(
+        spare.radio,
+        &mut nvic,
+        Config::new().radio,
+        RadioConfig {
+            group: GROUP,
+            ..RadioConfig::new()
+        },
+    );
+    let presses = Channel::<Direction>::new();
+    let tasks = pin_array![
+        button_task(buttons.left, Direction::Left, presses.get_sender()),
+        button_task(buttons.right, Direction::Right, presses.get_sender()),
+        send_task(&radio, presses.get_recv()),
+        display_task(&mut display, &radio),
+    ];
+    Executor::run_tasks(tasks);
+}
@@ -1,4 +1,13 @@
 MEMORY {
-  FLASH : ORIGIN = 0x00000000, LENGTH = 512K
+  FLASH : ORIGIN = 0x00000000, LENGTH = 444K
+  /* One page for the settings store, kept out of the firmware so settings survive reflashing */
+  SETTINGS : ORIGIN = 0x0006F000, LENGTH = 4K
+  /* The last 64K is kept out of the firmware for the data logger, so logs survive reflashing */
+  DATALOG : ORIGIN = 0x00070000, LENGTH = 64K
   RAM   : ORIGIN = 0x20000000, LENGTH = 128K
 }
+
+__settings_start = ORIGIN(SETTINGS);
+__settings_end = ORIGIN(SETTINGS) + LENGTH(SETTINGS);
+__datalog_start = ORIGIN(DATALOG);
+__datalog_end = ORIGIN(DATALOG) + LENGTH(DATALOG);
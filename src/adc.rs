@@ -0,0 +1,106 @@
+/*
+One-shot analog readings of the edge connector pins with the SAADC. Only the pins with an analog
+input can be read: on the micro:bit those are the large rings P0, P1 and P2, since the other analog
+pins drive the display. A reading takes about 15 us, so it's waited for, as for the temperature
+sensor. Readings are 12 bit, against the internal 0.6 V reference with a gain of 1/6, so the full
+scale is 3.6 V.
+*/
+
+use core::sync::atomic::{Ordering, compiler_fence};
+
+use nrf52833_hal::{gpio::Port, pac::SAADC};
+use snafu::prelude::*;
+
+use crate::board::EdgePin;
+
+pub const MAX_READING: i16 = (1 << 12) - 1;
+const FULL_SCALE_MV: i32 = 3600;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdcError {
+    #[snafu(display("The pin has no analog input"))]
+    NotAnalog,
+}
+
+pub struct Adc {
+    saadc: SAADC,
+}
+
+impl Adc {
+    // Calibrates the offset, which takes a few hundred us
+    pub fn new(saadc: SAADC) -> Self {
+        saadc.enable.write(|w| w.enable().enabled());
+        saadc.resolution.write(|w| w.val()._12bit());
+        saadc.samplerate.write(|w| w.mode().task());
+        saadc.ch[0].config.write(|w| {
+            w.refsel().internal();
+            w.gain().gain1_6();
+            w.tacq()._10us();
+            w.mode().se();
+            w.resp().bypass();
+            w.resn().bypass();
+            w.burst().disabled()
+        });
+        saadc.ch[0].pseln.write(|w| w.pseln().nc());
+        saadc.events_calibratedone.reset();
+        saadc.tasks_calibrateoffset.write(|w| unsafe { w.bits(1) });
+        while saadc.events_calibratedone.read().bits() == 0 {
+            core::hint::spin_loop();
+        }
+        saadc.events_calibratedone.reset();
+        Self { saadc }
+    }
+
+    pub fn free(self) -> SAADC {
+        self.saadc.enable.write(|w| w.enable().disabled());
+        self.saadc
+    }
+
+    // From 0 to `MAX_READING`. Single ended, so slightly negative readings from noise near 0 V
+    // are clamped.
+    pub fn read(&mut self, pin: &EdgePin) -> Result<i16, AdcError> {
+        let input = analog_input(pin).context(NotAnalogSnafu)?;
+        // SAFETY: PSELP takes the analog input plus one, and `analog_input` only gives 0 to 7
+        self.saadc.ch[0]
+            .pselp
+            .write(|w| unsafe { w.bits(u32::from(input) + 1) });
+        let mut sample: i16 = 0;
+        // SAFETY: The sample outlives the conversion, which is waited for below
+        self.saadc
+            .result
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(&raw mut sample as u32) });
+        self.saadc
+            .result
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(1) });
+        // The DMA mustn't start before the pointer is set
+        compiler_fence(Ordering::SeqCst);
+        self.saadc.events_end.reset();
+        self.saadc.tasks_start.write(|w| unsafe { w.bits(1) });
+        self.saadc.tasks_sample.write(|w| unsafe { w.bits(1) });
+        while self.saadc.events_end.read().bits() == 0 {
+            core::hint::spin_loop();
+        }
+        self.saadc.events_end.reset();
+        self.saadc.ch[0].pselp.write(|w| w.pselp().nc());
+        // Nor may the sample be read before the DMA has written it
+        compiler_fence(Ordering::SeqCst);
+        Ok(sample.clamp(0, MAX_READING))
+    }
+
+    pub fn read_millivolts(&mut self, pin: &EdgePin) -> Result<i32, AdcError> {
+        Ok(i32::from(self.read(pin)?) * FULL_SCALE_MV / i32::from(MAX_READING))
+    }
+}
+
+// The SAADC input of a pin, if it has one. AIN0 to AIN3 are P0.02 to P0.05, and AIN4 to AIN7 are
+// P0.28 to P0.31.
+fn analog_input(pin: &EdgePin) -> Option<u8> {
+    match (pin.port(), pin.pin()) {
+        (Port::Port0, pin @ 2..=5) => Some(pin - 2),
+        (Port::Port0, pin @ 28..=31) => Some(pin - 24),
+        _ => None,
+    }
+}
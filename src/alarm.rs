@@ -0,0 +1,134 @@
+/*
+Alarm sounds on the speaker, for signalling status on a device with nobody watching the display:
+beep codes that count out a fault number, SOS, and a rising alert. A pattern is a sequence of
+tones, so patterns can be chained or repeated with the usual iterator adaptors, and playback stops
+as soon as its cancellation token is cancelled, leaving the speaker off.
+
+    alarm.play(alarm::beep_code(3).cycle(), &FAULT_CLEARED).await
+*/
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::OutputPin;
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    morse,
+    time::{TickDuration, Timer},
+    utils::InfallibleExt,
+};
+
+// Near the speaker's resonance, so it's loudest
+pub const ALARM_HZ: u32 = 2000;
+const BEEP: TickDuration = TickDuration::millis(150);
+const BEEP_GAP: TickDuration = TickDuration::millis(150);
+// Between repeats of a code, long enough to count the beeps
+const CODE_GAP: TickDuration = TickDuration::millis(1000);
+const SOS_UNIT: TickDuration = TickDuration::millis(100);
+const RISING_FROM_HZ: u32 = 800;
+const RISING_TO_HZ: u32 = 3200;
+const RISING_STEPS: u32 = 12;
+const RISING_STEP: TickDuration = TickDuration::millis(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tone {
+    // Zero for silence
+    pub hz: u32,
+    pub duration: TickDuration,
+}
+
+impl Tone {
+    pub const fn new(hz: u32, duration: TickDuration) -> Self {
+        Self { hz, duration }
+    }
+
+    pub const fn rest(duration: TickDuration) -> Self {
+        Self::new(0, duration)
+    }
+}
+
+// `count` short beeps, then a long pause
+pub fn beep_code(count: u8) -> impl Iterator<Item = Tone> + Clone {
+    (0..count)
+        .flat_map(move |i| {
+            let beep = Tone::new(ALARM_HZ, BEEP);
+            // The pause after the last beep comes from the code gap
+            let gap = (i + 1 < count).then_some(Tone::rest(BEEP_GAP));
+            core::iter::once(beep).chain(gap)
+        })
+        .chain(core::iter::once(Tone::rest(CODE_GAP)))
+}
+
+// SOS in morse, then the gap between words
+pub fn sos() -> impl Iterator<Item = Tone> + Clone {
+    morse::elements("SOS")
+        .map(|element| {
+            let duration = SOS_UNIT * element.units;
+            if element.on {
+                Tone::new(ALARM_HZ, duration)
+            } else {
+                Tone::rest(duration)
+            }
+        })
+        .chain(core::iter::once(Tone::rest(SOS_UNIT * 7)))
+}
+
+// A sweep up in even steps, then a short pause
+pub fn rising() -> impl Iterator<Item = Tone> + Clone {
+    (0..RISING_STEPS)
+        .map(|step| {
+            let hz = RISING_FROM_HZ + (RISING_TO_HZ - RISING_FROM_HZ) * step / (RISING_STEPS - 1);
+            Tone::new(hz, RISING_STEP)
+        })
+        .chain(core::iter::once(Tone::rest(RISING_STEP * 4)))
+}
+
+pub struct Alarm<P> {
+    pin: P,
+}
+
+impl<P: OutputPin<Error = Infallible>> Alarm<P> {
+    pub fn new(mut pin: P) -> Self {
+        pin.set_low().unwrap_infallible();
+        Self { pin }
+    }
+
+    // Plays the tones in order, stopping early if the token is cancelled. Cycling a pattern
+    // plays it until cancelled.
+    pub async fn play(
+        &mut self,
+        tones: impl IntoIterator<Item = Tone>,
+        token: &CancellationToken,
+    ) -> Result<(), Cancelled> {
+        let result = token
+            .run(async {
+                for tone in tones {
+                    self.sound(tone).await;
+                }
+            })
+            .await;
+        // Cancelled part way through a cycle, when the speaker may be on
+        self.pin.set_low().unwrap_infallible();
+        result
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+
+    // A square wave, as for morse
+    async fn sound(&mut self, tone: Tone) {
+        if tone.hz == 0 {
+            Timer::delay(tone.duration).await;
+            return;
+        }
+        let half_period = TickDuration::from_ticks((32768 / (2 * u64::from(tone.hz))).max(1));
+        for _ in 0..tone.duration.ticks() / (2 * half_period.ticks()) {
+            self.pin.set_high().unwrap_infallible();
+            Timer::delay(half_period).await;
+            self.pin.set_low().unwrap_infallible();
+            Timer::delay(half_period).await;
+        }
+    }
+}
@@ -0,0 +1,128 @@
+/*
+Wake to poll latency benchmark. TIMER1 periodically fires an interrupt which toggles a GPIO (so
+it can be seen on a scope), records the cycle count and wakes the benchmark task. When the task
+is next polled the difference in cycle counts is the latency of the executor.
+*/
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    task::Poll,
+};
+
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::digital::StatefulOutputPin;
+use nrf52833_hal::{
+    gpio::{Output, Pin, PushPull},
+    pac::{Interrupt, NVIC, TIMER1, interrupt},
+    timer::{Periodic, Timer},
+};
+
+use crate::{
+    cycles,
+    utils::{AtomicWaker, InfallibleExt, LockMut},
+};
+
+struct BenchIsr {
+    timer: Timer<TIMER1, Periodic>,
+    pin: Pin<Output<PushPull>>,
+}
+
+static BENCH_ISR: LockMut<BenchIsr> = LockMut::new();
+static WOKEN_AT: AtomicU32 = AtomicU32::new(0);
+static FIRED: AtomicBool = AtomicBool::new(false);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+// Latencies are in CPU cycles
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LatencyStats {
+    pub min: u32,
+    pub max: u32,
+    pub total: u64,
+    pub samples: u32,
+}
+
+impl LatencyStats {
+    const fn new() -> Self {
+        Self {
+            min: u32::MAX,
+            max: 0,
+            total: 0,
+            samples: 0,
+        }
+    }
+
+    fn record(&mut self, cycles: u32) {
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+        self.total += u64::from(cycles);
+        self.samples += 1;
+    }
+
+    pub fn avg(&self) -> u32 {
+        if self.samples == 0 {
+            0
+        } else {
+            (self.total / u64::from(self.samples)) as u32
+        }
+    }
+}
+
+pub struct LatencyBench;
+
+impl LatencyBench {
+    pub fn init(timer: TIMER1, pin: Pin<Output<PushPull>>, dcb: &mut DCB, dwt: &mut DWT) {
+        cycles::enable(dcb, dwt);
+        let mut timer = Timer::periodic(timer);
+        timer.enable_interrupt();
+        BENCH_ISR.init(BenchIsr { timer, pin });
+    }
+
+    // Takes `samples` measurements, one every `period_us` microseconds
+    pub async fn run(samples: u32, period_us: u32) -> LatencyStats {
+        let mut stats = LatencyStats::new();
+        FIRED.store(false, Ordering::Relaxed);
+        BENCH_ISR.with_lock(|bench| bench.timer.start(period_us));
+        unsafe { NVIC::unmask(Interrupt::TIMER1) }
+        for _ in 0..samples {
+            wait_for_interrupt().await;
+            let latency = cycles::since(WOKEN_AT.load(Ordering::Relaxed));
+            stats.record(latency);
+        }
+        NVIC::mask(Interrupt::TIMER1);
+        BENCH_ISR.with_lock(|bench| bench.timer.task_stop().write(|w| unsafe { w.bits(1) }));
+        info!(
+            "Wake latency over {} samples: min {} avg {} max {} cycles",
+            stats.samples,
+            stats.min,
+            stats.avg(),
+            stats.max
+        );
+        stats
+    }
+}
+
+async fn wait_for_interrupt() {
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        if FIRED.swap(false, Ordering::Relaxed) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+#[interrupt]
+fn TIMER1() {
+    let now = cycles::now();
+    BENCH_ISR.with_lock(|bench| {
+        bench.timer.reset_event();
+        bench.pin.toggle().unwrap_infallible();
+    });
+    WOKEN_AT.store(now, Ordering::Relaxed);
+    FIRED.store(true, Ordering::Relaxed);
+    WAKER.wake();
+}
@@ -1,48 +1,622 @@
+use core::marker::PhantomData;
+
 use nrf52833_hal::{
-    self as hal,
-    gpio::{Floating, Input, Level, Pin, p0, p1},
+    clocks::{Clocks, LfOscConfiguration},
+    gpio::{
+        DriveConfig, Floating, Input, Level, OpenDrainConfig, OpenDrainIO, Output, Pin, Port,
+        PushPull, p0, p1,
+    },
+    pac::{
+        self, CLOCK, DCB, DWT, GPIOTE, NFCT, NVIC, NVMC, P0, P1, POWER, PWM0, QDEC, RADIO, RTC0,
+        SAADC, TEMP, TIMER1, TWIM0, UARTE0, WDT,
+    },
+    twim, uarte,
 };
+use snafu::prelude::*;
 
-use crate::{gpiote::GpioteManager, led::LedMatrix, time::Ticker};
+use crate::{
+    config::Config,
+    gpiote::GpioteManager,
+    led::{LedMatrix, LedPin},
+    time::Ticker,
+};
 
 pub type Button = Pin<Input<Floating>>;
 
-pub struct Board {
-    pub leds: LedMatrix,
+// The pieces of the board, each of which can be moved into the task that uses it
+pub type Display = LedMatrix;
+pub type InternalI2c = twim::Pins;
+// The UART to the interface chip, which shows up as a serial port over USB
+pub type InterfaceUart = uarte::Pins;
+
+pub struct Buttons {
+    pub left: Button,
+    pub right: Button,
+}
+
+pub struct Speaker {
+    pub pin: Pin<Output<PushPull>>,
+}
+
+pub type EdgePin = Pin<Input<Floating>>;
+
+// The edge connector pads, named as on the silkscreen, that aren't already used by the display or
+// buttons. They all start as floating inputs, so nothing is driven until a task asks for it.
+pub struct EdgeConnector {
+    // The large rings
+    pub p0: EdgePin,
+    pub p1: EdgePin,
+    pub p2: EdgePin,
+    // P8 and P9 are the NFC antenna pins, which have to be set as GPIOs in the UICR to be used
+    pub p8: EdgePin,
+    pub p9: EdgePin,
+    pub p12: EdgePin,
+    // SPI SCK, MISO and MOSI by convention
+    pub p13: EdgePin,
+    pub p14: EdgePin,
+    pub p15: EdgePin,
+    pub p16: EdgePin,
+    // The external I2C bus SCL and SDA, which are pulled up on the board
+    pub p19: EdgePin,
+    pub p20: EdgePin,
+}
+
+// How hard an output drives. Standard drive is good for a couple of mA, high drive for about 10 mA
+// at 3V, e.g. an LED straight off a pin, or a long bus with a stiff pull-up. The chip can only
+// supply so much over all its pins, so high drive is for a few pins at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Drive {
+    Standard,
+    High,
+}
+
+// A pin that drives low, and lets go for high so the line floats up, and can read the line
+pub type OpenDrainPin = Pin<Output<OpenDrainIO>>;
+
+// For buses like 1-Wire and I2C, where any device can pull the line low. The line starts released.
+// `pull_up` adds the internal pull-up of about 13 kΩ, which is enough for a short bus, but most
+// need a stronger one on the board.
+pub fn open_drain<MODE>(pin: Pin<MODE>, drive: Drive, pull_up: bool) -> OpenDrainPin {
+    let config = match drive {
+        Drive::Standard => OpenDrainConfig::Standard0Disconnect1,
+        Drive::High => OpenDrainConfig::HighDrive0Disconnect1,
+    };
+    let pin = pin.into_open_drain_input_output(config, Level::High);
+    if pull_up {
+        // The HAL has no open drain mode with a pull
+        set_pull_up(&pin);
+    }
+    pin
+}
+
+pub fn push_pull<MODE>(pin: Pin<MODE>, level: Level, drive: Drive) -> Pin<Output<PushPull>> {
+    let config = match drive {
+        Drive::Standard => DriveConfig::Standard0Standard1,
+        Drive::High => DriveConfig::HighDrive0HighDrive1,
+    };
+    pin.into_push_pull_output_drive(level, config)
+}
+
+// A physical pin, by port and number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PinId {
+    pub port: u8,
+    pub pin: u8,
+}
+
+// A physical pin as a type, so the pins claimed can be part of a type too
+pub struct PinNum<const PORT: u8, const PIN: u8>;
+
+impl<const PORT: u8, const PIN: u8> PinNum<PORT, PIN> {
+    pub const ID: PinId = PinId {
+        port: PORT,
+        pin: PIN,
+    };
+}
+
+// The HAL's own pin types, which know which physical pin they are until they're degraded
+pub trait PhysicalPin {
+    type Id;
+    type Mode;
+
+    fn degrade(self) -> Pin<Self::Mode>;
+}
+
+macro_rules! physical_pins {
+    ($port:literal, $module:ident; $($ty:ident = $pin:literal),+ $(,)?) => {
+        $(
+            impl<MODE> PhysicalPin for $module::$ty<MODE> {
+                type Id = PinNum<$port, $pin>;
+                type Mode = MODE;
+
+                fn degrade(self) -> Pin<MODE> {
+                    $module::$ty::degrade(self)
+                }
+            }
+        )+
+    };
+}
+
+physical_pins!(0, p0;
+    P0_00 = 0, P0_01 = 1, P0_02 = 2, P0_03 = 3, P0_04 = 4, P0_05 = 5, P0_06 = 6, P0_07 = 7,
+    P0_08 = 8, P0_09 = 9, P0_10 = 10, P0_11 = 11, P0_12 = 12, P0_13 = 13, P0_14 = 14, P0_15 = 15,
+    P0_16 = 16, P0_17 = 17, P0_18 = 18, P0_19 = 19, P0_20 = 20, P0_21 = 21, P0_22 = 22,
+    P0_23 = 23, P0_24 = 24, P0_25 = 25, P0_26 = 26, P0_27 = 27, P0_28 = 28, P0_29 = 29,
+    P0_30 = 30, P0_31 = 31,
+);
+physical_pins!(1, p1;
+    P1_00 = 0, P1_01 = 1, P1_02 = 2, P1_03 = 3, P1_04 = 4, P1_05 = 5, P1_06 = 6, P1_07 = 7,
+    P1_08 = 8, P1_09 = 9, P1_10 = 10, P1_11 = 11, P1_12 = 12, P1_13 = 13, P1_14 = 14, P1_15 = 15,
+);
+
+// A list of the pins claimed, the last claimed first, as in `(PinNum<0, 3>, (PinNum<0, 2>, ()))`
+pub trait PinSet {
+    // A bit per pin of each port. Evaluating it fails the build if a pin is in the list twice.
+    const CLAIMED: [u32; 2];
+}
+
+impl PinSet for () {
+    const CLAIMED: [u32; 2] = [0; 2];
+}
+
+impl<const PORT: u8, const PIN: u8, S: PinSet> PinSet for (PinNum<PORT, PIN>, S) {
+    const CLAIMED: [u32; 2] = claim(S::CLAIMED, PinNum::<PORT, PIN>::ID);
+}
+
+const fn claim(mut claimed: [u32; 2], PinId { port, pin }: PinId) -> [u32; 2] {
+    assert!(port < 2 && pin < 32, "There's no such pin");
+    let bit = 1 << pin;
+    assert!(
+        claimed[port as usize] & bit == 0,
+        "A pin is claimed by two drivers"
+    );
+    claimed[port as usize] |= bit;
+    claimed
+}
+
+// The pins taken so far, in the type, so taking one twice fails to compile rather than leaving
+// two drivers fighting over it. A board takes the pins it hands out through one, and
+// `BoardSupport::claims` carries on from there for pins the application's own drivers take
+// outside the `Board`, like ones stolen from the HAL:
+//
+//     let (pin, claims) = MicrobitV2::claims().take(stolen.p0_05);
+//     // Fails to compile, since the board gave P0.00 to the speaker
+//     let (tone, claims) = claims.take(stolen.p0_00);
+pub struct Claims<S> {
+    _pins: PhantomData<S>,
+}
+
+impl Claims<()> {
+    pub const fn new() -> Self {
+        Self { _pins: PhantomData }
+    }
+}
+
+impl Default for Claims<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The claims after taking the pin P
+pub type WithPin<P, S> = Claims<(<P as PhysicalPin>::Id, S)>;
+
+impl<S: PinSet> Claims<S> {
+    // Degrades the pin for the driver, adding it to the claims
+    pub fn take<P: PhysicalPin>(self, pin: P) -> (Pin<P::Mode>, WithPin<P, S>)
+    where
+        (P::Id, S): PinSet,
+    {
+        const { <(P::Id, S) as PinSet>::CLAIMED };
+        (pin.degrade(), Claims { _pins: PhantomData })
+    }
+}
+
+// Names the type of the claims after taking these pins, by port and number, in the order taken:
+//
+//     type Pins = pin_set![(0, 21), (0, 22), (1, 5)];
+#[macro_export]
+macro_rules! pin_set {
+    (@reversed [$($reversed:tt)*]) => {
+        $crate::pin_set!(@build $($reversed)*)
+    };
+    (@reversed [$($reversed:tt)*] $pin:tt $(, $rest:tt)*) => {
+        $crate::pin_set!(@reversed [$pin $($reversed)*] $($rest),*)
+    };
+    (@build) => {
+        ()
+    };
+    (@build ($port:literal, $pin:literal) $($rest:tt)*) => {
+        ($crate::board::PinNum<$port, $pin>, $crate::pin_set!(@build $($rest)*))
+    };
+    ($($pin:tt),* $(,)?) => {
+        $crate::pin_set!(@reversed [] $($pin),*)
+    };
+}
+
+// All of the pins a board has to provide to the rest of the crate
+pub struct BoardPins {
+    pub display_rows: [LedPin; LedMatrix::ROWS],
+    pub display_cols: [LedPin; LedMatrix::COLS],
     pub btn_l: Button,
     pub btn_r: Button,
+    pub i2c_internal: InternalI2c,
+    pub uart: InterfaceUart,
+    pub speaker: Pin<Output<PushPull>>,
+    pub edge: EdgeConnector,
+}
+
+// Implement this for a custom nRF52833 board to provide its own pin map and clock setup
+pub trait BoardSupport {
+    // Every pin `pins` hands out, as `pins` took them
+    type Pins: PinSet;
+
+    // Takes each pin through `Claims`, so the compiler checks none is handed out twice, and
+    // that `Pins` names them all
+    fn pins(p0: p0::Parts, p1: p1::Parts) -> (BoardPins, Claims<Self::Pins>);
+
+    // For the application's own drivers to carry on claiming pins from, so they can't take one
+    // the board hands out
+    fn claims() -> Claims<Self::Pins> {
+        Claims { _pins: PhantomData }
+    }
+
+    // The RTC used by the time driver needs the low frequency clock to be running
+    fn init_clocks(clock: CLOCK, source: LfClockSource) {
+        let clocks = Clocks::new(clock);
+        match source {
+            LfClockSource::Rc => {
+                clocks.set_lfclk_src_rc().start_lfclk();
+            }
+            LfClockSource::Synth => {
+                clocks
+                    .enable_ext_hfosc()
+                    .set_lfclk_src_synth()
+                    .start_lfclk();
+            }
+            LfClockSource::Xtal => {
+                clocks
+                    .set_lfclk_src_external(LfOscConfiguration::NoExternalNoBypass)
+                    .start_lfclk();
+            }
+        }
+    }
+}
+
+pub struct MicrobitV2;
+
+impl BoardSupport for MicrobitV2 {
+    type Pins = pin_set![
+        // Display rows, then columns
+        (0, 21),
+        (0, 22),
+        (0, 15),
+        (0, 24),
+        (0, 19),
+        (0, 28),
+        (0, 11),
+        (0, 31),
+        (1, 5),
+        (0, 30),
+        // Buttons
+        (0, 14),
+        (0, 23),
+        // Internal I2C
+        (0, 8),
+        (0, 16),
+        // Interface UART
+        (0, 6),
+        (1, 8),
+        // Speaker
+        (0, 0),
+        // Edge connector, in the order of `EdgeConnector`'s fields
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (0, 10),
+        (0, 9),
+        (0, 12),
+        (0, 17),
+        (0, 1),
+        (0, 13),
+        (1, 2),
+        (0, 26),
+        (1, 0),
+    ];
+
+    fn pins(p0: p0::Parts, p1: p1::Parts) -> (BoardPins, Claims<Self::Pins>) {
+        let claims = Claims::new();
+        let (row0, claims) = claims.take(p0.p0_21.into_push_pull_output(Level::Low));
+        let (row1, claims) = claims.take(p0.p0_22.into_push_pull_output(Level::Low));
+        let (row2, claims) = claims.take(p0.p0_15.into_push_pull_output(Level::Low));
+        let (row3, claims) = claims.take(p0.p0_24.into_push_pull_output(Level::Low));
+        let (row4, claims) = claims.take(p0.p0_19.into_push_pull_output(Level::Low));
+        let (col0, claims) = claims.take(p0.p0_28.into_push_pull_output(Level::High));
+        let (col1, claims) = claims.take(p0.p0_11.into_push_pull_output(Level::High));
+        let (col2, claims) = claims.take(p0.p0_31.into_push_pull_output(Level::High));
+        let (col3, claims) = claims.take(p1.p1_05.into_push_pull_output(Level::High));
+        let (col4, claims) = claims.take(p0.p0_30.into_push_pull_output(Level::High));
+        let (btn_l, claims) = claims.take(p0.p0_14.into_floating_input());
+        let (btn_r, claims) = claims.take(p0.p0_23.into_floating_input());
+        let (scl, claims) = claims.take(p0.p0_08.into_floating_input());
+        let (sda, claims) = claims.take(p0.p0_16.into_floating_input());
+        let (txd, claims) = claims.take(p0.p0_06.into_push_pull_output(Level::High));
+        let (rxd, claims) = claims.take(p1.p1_08.into_floating_input());
+        let (speaker, claims) = claims.take(p0.p0_00.into_push_pull_output(Level::Low));
+        let (edge_p0, claims) = claims.take(p0.p0_02.into_floating_input());
+        let (edge_p1, claims) = claims.take(p0.p0_03.into_floating_input());
+        let (edge_p2, claims) = claims.take(p0.p0_04.into_floating_input());
+        let (edge_p8, claims) = claims.take(p0.p0_10.into_floating_input());
+        let (edge_p9, claims) = claims.take(p0.p0_09.into_floating_input());
+        let (edge_p12, claims) = claims.take(p0.p0_12.into_floating_input());
+        let (edge_p13, claims) = claims.take(p0.p0_17.into_floating_input());
+        let (edge_p14, claims) = claims.take(p0.p0_01.into_floating_input());
+        let (edge_p15, claims) = claims.take(p0.p0_13.into_floating_input());
+        let (edge_p16, claims) = claims.take(p1.p1_02.into_floating_input());
+        let (edge_p19, claims) = claims.take(p0.p0_26.into_floating_input());
+        let (edge_p20, claims) = claims.take(p1.p1_00.into_floating_input());
+        let pins = BoardPins {
+            display_rows: [row0, row1, row2, row3, row4],
+            display_cols: [col0, col1, col2, col3, col4],
+            btn_l,
+            btn_r,
+            i2c_internal: twim::Pins { scl, sda },
+            uart: uarte::Pins {
+                txd,
+                rxd,
+                cts: None,
+                rts: None,
+            },
+            speaker,
+            edge: EdgeConnector {
+                p0: edge_p0,
+                p1: edge_p1,
+                p2: edge_p2,
+                p8: edge_p8,
+                p9: edge_p9,
+                p12: edge_p12,
+                p13: edge_p13,
+                p14: edge_p14,
+                p15: edge_p15,
+                p16: edge_p16,
+                p19: edge_p19,
+                p20: edge_p20,
+            },
+        };
+        (pins, claims)
+    }
+}
+
+// Destructured to hand each part to the task that uses it, as in
+// `let Board { display, buttons, .. } = Board::take()?;`
+pub struct Board {
+    pub display: Display,
+    pub buttons: Buttons,
+    pub i2c_internal: InternalI2c,
+    pub uart: InterfaceUart,
+    pub speaker: Speaker,
+    pub edge: EdgeConnector,
+    // Handed back when the config doesn't claim them
+    pub rtc0: Option<RTC0>,
+    pub gpiote: Option<GPIOTE>,
+    pub spare: SparePeripherals,
+    // For the application's own drivers to set up their interrupts
+    pub nvic: NVIC,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LfClockSource {
+    // The internal RC oscillator, the only option on the micro:bit, which has no 32 kHz crystal
+    Rc,
+    // Derived from the external high frequency crystal, which costs more power
+    Synth,
+    // An external 32.768 kHz crystal
+    Xtal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonPull {
+    // Rely on the board's own pull ups
+    None,
+    // Add the internal pull ups too, for boards without external ones
+    Up,
+}
+
+// What the board claims and how it sets it up
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BoardConfig {
+    priorities: Config,
+    lf_clock: LfClockSource,
+    button_pull: ButtonPull,
+    time: bool,
+    gpiote: bool,
+}
+
+impl BoardConfig {
+    pub const fn new() -> Self {
+        Self {
+            priorities: Config::new(),
+            lf_clock: LfClockSource::Rc,
+            button_pull: ButtonPull::None,
+            time: true,
+            gpiote: true,
+        }
+    }
+
+    pub const fn priorities(mut self, priorities: Config) -> Self {
+        self.priorities = priorities;
+        self
+    }
+
+    pub const fn lf_clock(mut self, source: LfClockSource) -> Self {
+        self.lf_clock = source;
+        self
+    }
+
+    pub const fn button_pull(mut self, pull: ButtonPull) -> Self {
+        self.button_pull = pull;
+        self
+    }
+
+    // Leaves RTC0 unclaimed, so the time driver and `Timer` can't be used
+    pub const fn without_time(mut self) -> Self {
+        self.time = false;
+        self
+    }
+
+    // Leaves GPIOTE unclaimed, so `InputChannel`s can't be used
+    pub const fn without_gpiote(mut self) -> Self {
+        self.gpiote = false;
+        self
+    }
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BoardError {
+    #[snafu(display("The device peripherals have already been taken"))]
+    PeripheralsTaken,
+    #[snafu(display("The core peripherals have already been taken"))]
+    CorePeripheralsTaken,
+}
+
+// Just the peripherals the board needs, so the rest can be handed to other drivers
+pub struct BoardPeripherals {
+    pub clock: CLOCK,
+    pub rtc0: RTC0,
+    pub gpiote: GPIOTE,
+    pub p0: P0,
+    pub p1: P1,
+    pub spare: SparePeripherals,
+}
+
+// Peripherals the board doesn't use itself, passed through for the application's own drivers
+pub struct SparePeripherals {
+    pub nfct: NFCT,
+    pub nvmc: NVMC,
+    pub power: POWER,
+    pub pwm0: PWM0,
+    pub qdec: QDEC,
+    pub radio: RADIO,
+    pub saadc: SAADC,
+    pub temp: TEMP,
+    pub timer1: TIMER1,
+    pub twim0: TWIM0,
+    pub uarte0: UARTE0,
+    pub wdt: WDT,
+    pub dcb: DCB,
+    pub dwt: DWT,
 }
 
 impl Board {
-    pub fn new() -> Self {
-        let p = hal::pac::Peripherals::take().unwrap();
-        let mut core_p = hal::pac::CorePeripherals::take().unwrap();
-        Ticker::init(p.RTC0, &mut core_p.NVIC);
-        GpioteManager::init(p.GPIOTE);
-        let p0parts = p0::Parts::new(p.P0);
-        let p1parts = p1::Parts::new(p.P1);
-
-        let rows = [
-            p0parts.p0_21.into_push_pull_output(Level::Low).degrade(),
-            p0parts.p0_22.into_push_pull_output(Level::Low).degrade(),
-            p0parts.p0_15.into_push_pull_output(Level::Low).degrade(),
-            p0parts.p0_24.into_push_pull_output(Level::Low).degrade(),
-            p0parts.p0_19.into_push_pull_output(Level::Low).degrade(),
-        ];
-        let cols = [
-            p0parts.p0_28.into_push_pull_output(Level::High).degrade(),
-            p0parts.p0_11.into_push_pull_output(Level::High).degrade(),
-            p0parts.p0_31.into_push_pull_output(Level::High).degrade(),
-            p1parts.p1_05.into_push_pull_output(Level::High).degrade(),
-            p0parts.p0_30.into_push_pull_output(Level::High).degrade(),
-        ];
+    // Takes the peripheral singletons, so this fails if they've already been taken
+    pub fn take() -> Result<Self, BoardError> {
+        Self::take_with_config(BoardConfig::default())
+    }
+
+    pub fn take_with_config(config: BoardConfig) -> Result<Self, BoardError> {
+        // The core peripherals are the ones an application is likely to have taken already, e.g.
+        // for SysTick, so they're tried first and the device peripherals are left for it to take
+        // rather than lost
+        let core_p = pac::CorePeripherals::take().context(CorePeripheralsTakenSnafu)?;
+        let p = pac::Peripherals::take().context(PeripheralsTakenSnafu)?;
+        let parts = BoardPeripherals {
+            clock: p.CLOCK,
+            rtc0: p.RTC0,
+            gpiote: p.GPIOTE,
+            p0: p.P0,
+            p1: p.P1,
+            spare: SparePeripherals {
+                nfct: p.NFCT,
+                nvmc: p.NVMC,
+                power: p.POWER,
+                pwm0: p.PWM0,
+                qdec: p.QDEC,
+                radio: p.RADIO,
+                saadc: p.SAADC,
+                temp: p.TEMP,
+                timer1: p.TIMER1,
+                twim0: p.TWIM0,
+                uarte0: p.UARTE0,
+                wdt: p.WDT,
+                dcb: core_p.DCB,
+                dwt: core_p.DWT,
+            },
+        };
+        Ok(Self::init::<MicrobitV2>(parts, core_p.NVIC, config))
+    }
+
+    // Sets up the board from peripherals that have already been taken
+    pub fn init<B: BoardSupport>(
+        parts: BoardPeripherals,
+        mut nvic: NVIC,
+        config: BoardConfig,
+    ) -> Self {
+        B::init_clocks(parts.clock, config.lf_clock);
+        let rtc0 = if config.time {
+            Ticker::init(parts.rtc0, &mut nvic, config.priorities.rtc0);
+            None
+        } else {
+            Some(parts.rtc0)
+        };
+        let gpiote = if config.gpiote {
+            GpioteManager::init(parts.gpiote, &mut nvic, config.priorities.gpiote);
+            None
+        } else {
+            Some(parts.gpiote)
+        };
+        let (pins, _) = B::pins(p0::Parts::new(parts.p0), p1::Parts::new(parts.p1));
+        if config.button_pull == ButtonPull::Up {
+            set_pull_up(&pins.btn_l);
+            set_pull_up(&pins.btn_r);
+        }
         Self {
-            leds: LedMatrix {
-                pin_rows: rows,
-                pin_cols: cols,
+            display: LedMatrix {
+                pin_rows: pins.display_rows,
+                pin_cols: pins.display_cols,
+            },
+            buttons: Buttons {
+                left: pins.btn_l,
+                right: pins.btn_r,
             },
-            btn_l: p0parts.p0_14.into_floating_input().degrade(),
-            btn_r: p0parts.p0_23.into_floating_input().degrade(),
+            i2c_internal: pins.i2c_internal,
+            uart: pins.uart,
+            speaker: Speaker { pin: pins.speaker },
+            edge: pins.edge,
+            rtc0,
+            gpiote,
+            spare: parts.spare,
+            nvic,
         }
     }
 }
+
+// Lets the pin going low wake the chip from System OFF, e.g. a button press
+pub fn sense_low<MODE>(pin: &Pin<MODE>) {
+    // SAFETY: Only changes the sense of a pin the caller owns
+    unsafe { (*port(pin)).pin_cnf[usize::from(pin.pin())].modify(|_, w| w.sense().low()) };
+}
+
+// The pin's type stays the same, since the pull doesn't change how it's used
+fn set_pull_up<MODE>(pin: &Pin<MODE>) {
+    // SAFETY: Only changes the pull of a pin the caller owns
+    unsafe { (*port(pin)).pin_cnf[usize::from(pin.pin())].modify(|_, w| w.pull().pullup()) };
+}
+
+fn port<MODE>(pin: &Pin<MODE>) -> *const pac::p0::RegisterBlock {
+    match pin.port() {
+        Port::Port0 => P0::ptr(),
+        Port::Port1 => P1::ptr(),
+    }
+}
@@ -0,0 +1,65 @@
+/*
+Double-reset detection, to enter a setup mode without a button for it: pressing reset twice in
+quick succession boots into `BootMode::Config`, and a single press boots normally.
+
+A magic number is left in `.uninit` RAM at boot, which a reset doesn't clear, and cleared again
+once the window for a second press has passed. A reset that finds it still there was the second
+of a pair. Only resets from the reset pin count, so a crash or watchdog reset soon after boot
+doesn't land the board in config mode, and a power-on reset starts from garbage RAM anyway.
+
+    let mode = BootMode::detect(&p.POWER);
+    // In a task, so boot carries on while the window is open
+    boot_mode::end_window(TickDuration::millis(500)).await;
+*/
+
+use core::mem::MaybeUninit;
+
+use nrf52833_hal::pac::POWER;
+
+use crate::time::{TickDuration, Timer};
+
+const MAGIC: u32 = 0x4452_5354;
+
+#[unsafe(link_section = ".uninit.boot_mode")]
+static mut FLAG: MaybeUninit<u32> = MaybeUninit::uninit();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootMode {
+    Normal,
+    // Reset twice within the window
+    Config,
+}
+
+impl BootMode {
+    // Call first thing at boot, before anything else resets the board, then `end_window` once
+    // it's running. Clears the reset pin flag, so only the first call after a reset sees it.
+    pub fn detect(power: &POWER) -> Self {
+        let by_pin = power.resetreas.read().resetpin().is_detected();
+        if by_pin {
+            // The flags are cleared by writing ones
+            power.resetreas.write(|w| w.resetpin().set_bit());
+        }
+        // SAFETY: Only accessed at boot and by `end_window`, never from an interrupt, and every
+        // value is a valid u32
+        let armed = unsafe { (&raw const FLAG).cast::<u32>().read_volatile() } == MAGIC;
+        let mode = if by_pin && armed {
+            info!("Double reset, booting into config mode");
+            BootMode::Config
+        } else {
+            BootMode::Normal
+        };
+        // A reset during config mode counts as a first press again
+        let flag = if mode == BootMode::Normal { MAGIC } else { 0 };
+        // SAFETY: As above
+        unsafe { (&raw mut FLAG).cast::<u32>().write_volatile(flag) };
+        mode
+    }
+}
+
+// Waits out the window for a second reset, after which a reset counts as a first press
+pub async fn end_window(window: TickDuration) {
+    Timer::delay(window).await;
+    // SAFETY: As in `detect`
+    unsafe { (&raw mut FLAG).cast::<u32>().write_volatile(0) };
+}
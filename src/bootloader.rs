@@ -0,0 +1,75 @@
+/*
+Rebooting into a bootloader to update the firmware. The bootloader checks GPREGRET, a register
+that survives a soft reset, for a magic value when it starts, and stays in update mode instead of
+starting the application if it finds one. The values are the ones the Adafruit nRF52 bootloader
+and Nordic's secure bootloader look for.
+*/
+
+use cortex_m::peripheral::SCB;
+use embedded_hal::digital::PinState;
+use futures::{FutureExt, select_biased};
+use nrf52833_hal::pac::POWER;
+
+use crate::{
+    gpio::{EdgeWait, InputChannel},
+    time::{TickDuration, Timer},
+};
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootMode {
+    // Mounts as a USB drive that takes UF2 files
+    Uf2,
+    // DFU over the serial port only
+    SerialDfu,
+    // Nordic's buttonless DFU, over BLE
+    BleDfu,
+}
+
+impl BootMode {
+    const fn magic(self) -> u8 {
+        match self {
+            Self::Uf2 => 0x57,
+            Self::SerialDfu => 0x4E,
+            Self::BleDfu => 0xB1,
+        }
+    }
+}
+
+pub struct Bootloader {
+    power: POWER,
+}
+
+impl Bootloader {
+    pub fn new(power: POWER) -> Self {
+        Self { power }
+    }
+
+    pub fn reboot_into(&mut self, mode: BootMode) -> ! {
+        info!(
+            "Rebooting into the bootloader with GPREGRET {}",
+            mode.magic()
+        );
+        // SAFETY: Every value is valid, only the bootloader reads the register
+        self.power
+            .gpregret
+            .write(|w| unsafe { w.gpregret().bits(mode.magic()) });
+        SCB::sys_reset();
+    }
+
+    // Reboots once the button has been held down for `hold`. Buttons are active low.
+    pub async fn reboot_on_long_press<E: EdgeWait>(
+        &mut self,
+        button: &mut InputChannel<E>,
+        hold: TickDuration,
+        mode: BootMode,
+    ) -> ! {
+        loop {
+            button.wait_for(PinState::Low).await;
+            select_biased! {
+                () = button.wait_for(PinState::High).fuse() => {}
+                () = Timer::delay(hold).fuse() => self.reboot_into(mode),
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*
+Cancellation shared between tasks. One task (or an interrupt) cancels the token, and every task
+waiting on it stops what it was doing at its next await. Unlike dropping a future from outside,
+the cancelled task gets to tidy up, e.g. turning off a speaker it was driving. A token can be
+reset and reused, and it can live in a static.
+*/
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use futures::{FutureExt, select_biased};
+use snafu::prelude::*;
+
+use crate::utils::WaiterList;
+
+const MAX_WAITING: usize = 8;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[snafu(display("The operation was cancelled"))]
+pub struct Cancelled;
+
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+    waiting: WaiterList<MAX_WAITING>,
+}
+
+impl CancellationToken {
+    pub const fn new() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+            waiting: WaiterList::new(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.waiting.wake_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    // Lets the token be used again, once whatever it cancelled has stopped
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Release);
+    }
+
+    pub async fn cancelled(&self) {
+        poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            self.waiting.register(cx);
+            // Cancelled while registering, and the waker was taken before it was pushed
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            Poll::Pending
+        })
+        .await;
+    }
+
+    // Runs the future until it finishes, or until the token is cancelled. Fails straight away if
+    // it already is.
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> Result<T, Cancelled> {
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        select_biased! {
+            () = self.cancelled().fuse() => Err(Cancelled),
+            output = future.fuse() => Ok(output),
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,86 +1,179 @@
 use core::{
-    cell::{Cell, RefCell},
+    cell::Cell,
     future::poll_fn,
     task::{Poll, Waker},
 };
 
+use snafu::prelude::*;
+
+use crate::utils::AtomicWaker;
+
+pub mod broadcast;
+pub mod mpsc;
+pub mod spsc;
+pub mod watch;
+
+// What `send` does when the last value hasn't been received yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OverflowPolicy {
+    // Replace the unread value with the new one
+    Overwrite,
+    // Keep the unread value, and fail the send
+    Reject,
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelError {
+    #[snafu(display("The channel already holds an unread value"))]
+    Full,
+    #[snafu(display("Every sender has been dropped"))]
+    Disconnected,
+}
+
 pub struct Sender<'a, T> {
     channel: &'a Channel<T>,
 }
 
 impl<'a, T> Sender<'a, T> {
-    const fn new(channel: &'a Channel<T>) -> Self {
+    fn new(channel: &'a Channel<T>) -> Self {
+        channel.senders.set(channel.senders.get() + 1);
+        channel.had_sender.set(true);
         Self { channel }
     }
 
-    pub fn send(&self, item: T) {
-        self.channel.send(item);
+    pub fn send(&self, item: T) -> Result<(), ChannelError> {
+        self.channel.send(item)
     }
 }
 
-pub struct Receiver<'a, T> {
-    channel: &'a Channel<T>,
-    state: RecvState,
+impl<T> Clone for Sender<'_, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.channel)
+    }
 }
 
-enum RecvState {
-    Init,
-    Wait,
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        let senders = self.channel.senders.get() - 1;
+        self.channel.senders.set(senders);
+        // Let a waiting receiver see the disconnect
+        if senders == 0 {
+            self.channel.waker.wake();
+        }
+    }
+}
+
+pub struct Receiver<'a, T> {
+    channel: &'a Channel<T>,
 }
 
 impl<'a, T> Receiver<'a, T> {
     const fn new(channel: &'a Channel<T>) -> Self {
-        Self {
-            channel,
-            state: RecvState::Init,
-        }
+        Self { channel }
     }
 
-    pub async fn recv(&mut self) -> T {
-        poll_fn(move |cx| match self.state {
-            RecvState::Init => {
-                self.channel.register(cx.waker().clone());
-                self.state = RecvState::Wait;
-                Poll::Pending
+    // Fails once every sender has been dropped and the last value has been received, rather
+    // than waiting forever
+    pub async fn recv(&mut self) -> Result<T, ChannelError> {
+        poll_fn(move |cx| {
+            // Register before checking, so that a send in between isn't missed
+            self.channel.register(cx.waker());
+            match self.channel.recv() {
+                Some(item) => Poll::Ready(Ok(item)),
+                None if self.channel.is_disconnected() => Poll::Ready(DisconnectedSnafu.fail()),
+                None => Poll::Pending,
             }
-            RecvState::Wait => self
-                .channel
-                .recv()
-                .map_or_else(|| Poll::Pending, |val| Poll::Ready(val)),
         })
         .await
     }
 }
 
+// Holds a single value. Values lost to overflow are counted, whatever the policy.
 pub struct Channel<T> {
     item: Cell<Option<T>>,
-    waker: RefCell<Option<Waker>>,
+    waker: AtomicWaker,
+    policy: OverflowPolicy,
+    dropped: Cell<usize>,
+    senders: Cell<usize>,
+    // So a receiver created before its senders doesn't see a disconnect
+    had_sender: Cell<bool>,
 }
 
 impl<T> Channel<T> {
+    #[cfg(not(loom))]
     pub const fn new() -> Self {
+        Self::with_policy(OverflowPolicy::Overwrite)
+    }
+
+    #[cfg(not(loom))]
+    pub const fn with_policy(policy: OverflowPolicy) -> Self {
+        Self {
+            item: Cell::new(Option::None),
+            waker: AtomicWaker::new(),
+            policy,
+            dropped: Cell::new(0),
+            senders: Cell::new(0),
+            had_sender: Cell::new(false),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self::with_policy(OverflowPolicy::Overwrite)
+    }
+
+    #[cfg(loom)]
+    pub fn with_policy(policy: OverflowPolicy) -> Self {
         Self {
             item: Cell::new(Option::None),
-            waker: RefCell::new(None),
+            waker: AtomicWaker::new(),
+            policy,
+            dropped: Cell::new(0),
+            senders: Cell::new(0),
+            had_sender: Cell::new(false),
         }
     }
 
-    pub fn send(&self, item: T) {
-        self.item.replace(Option::Some(item));
-        if let Some(waker) = self.waker.borrow().as_ref() {
-            waker.wake_by_ref();
+    // Only fails with the `Reject` policy, when there's already an unread value
+    pub fn send(&self, item: T) -> Result<(), ChannelError> {
+        let unread = self.item.take();
+        if unread.is_some() {
+            self.dropped.set(self.dropped.get() + 1);
+            warn!(
+                "Channel overflowed, {} values dropped so far",
+                self.dropped.get()
+            );
+            if self.policy == OverflowPolicy::Reject {
+                self.item.set(unread);
+                return FullSnafu.fail();
+            }
         }
+        self.item.set(Option::Some(item));
+        self.waker.wake();
+        Ok(())
+    }
+
+    // The number of values that were overwritten or rejected before being received
+    pub fn dropped(&self) -> usize {
+        self.dropped.get()
     }
 
     pub fn recv(&self) -> Option<T> {
         self.item.take()
     }
 
-    pub fn register(&self, waker: Waker) {
-        self.waker.replace(Some(waker));
+    // Whether senders were handed out and have all been dropped since
+    pub fn is_disconnected(&self) -> bool {
+        self.had_sender.get() && self.senders.get() == 0
     }
 
-    pub const fn get_sender(&self) -> Sender<'_, T> {
+    pub fn register(&self, waker: &Waker) {
+        self.waker.register(waker);
+    }
+
+    pub fn get_sender(&self) -> Sender<'_, T> {
         Sender::new(self)
     }
 
@@ -88,3 +181,9 @@ impl<T> Channel<T> {
         Receiver::new(self)
     }
 }
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,99 @@
+/*
+Broadcast channel, where every subscriber sees every value. The last N values are kept, and each
+subscriber keeps its own position in them, so a subscriber that falls more than N values behind
+skips ahead to the oldest one still kept, and the skipped values are counted as missed.
+*/
+
+use core::{
+    cell::{Cell, RefCell},
+    future::poll_fn,
+    task::Poll,
+};
+
+use crate::utils::WaiterList;
+
+const MAX_WAITING: usize = 8;
+
+pub struct Broadcast<T, const N: usize> {
+    values: RefCell<heapless::Deque<T, N>>,
+    // Total values ever sent, which is the position of the next one
+    sent: Cell<u64>,
+    waiting: WaiterList<MAX_WAITING>,
+}
+
+impl<T: Clone, const N: usize> Broadcast<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            values: RefCell::new(heapless::Deque::new()),
+            sent: Cell::new(0),
+            waiting: WaiterList::new(),
+        }
+    }
+
+    pub fn send(&self, value: T) {
+        {
+            let mut values = self.values.borrow_mut();
+            if values.is_full() {
+                values.pop_front();
+            }
+            // Can't fail, since there's room now
+            let _ = values.push_back(value);
+        }
+        self.sent.set(self.sent.get() + 1);
+        self.waiting.wake_all();
+    }
+
+    // Only sees values sent after subscribing
+    pub fn subscribe(&self) -> Subscriber<'_, T, N> {
+        Subscriber {
+            broadcast: self,
+            next: self.sent.get(),
+            missed: 0,
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> Default for Broadcast<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Subscriber<'a, T, const N: usize> {
+    broadcast: &'a Broadcast<T, N>,
+    next: u64,
+    missed: u64,
+}
+
+impl<T: Clone, const N: usize> Subscriber<'_, T, N> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        let values = self.broadcast.values.borrow();
+        let sent = self.broadcast.sent.get();
+        let oldest = sent - values.len() as u64;
+        if self.next < oldest {
+            warn!(
+                "Subscriber fell behind, missed {} values",
+                oldest - self.next
+            );
+            self.missed += oldest - self.next;
+            self.next = oldest;
+        }
+        let value = values.get((self.next - oldest) as usize)?.clone();
+        self.next += 1;
+        Some(value)
+    }
+
+    pub async fn recv(&mut self) -> T {
+        poll_fn(|cx| {
+            // Register before checking, so that a send in between isn't missed
+            self.broadcast.waiting.register(cx);
+            self.try_recv().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    // The number of values that were dropped before this subscriber got to them
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
@@ -0,0 +1,85 @@
+/*
+Bounded queue with any number of senders and one receiver, for requests from many tasks to one
+service task. Unlike `Channel` it keeps every value up to its capacity, and unlike `Spsc` any task
+can send, at the cost of a short critical section per value. It can live in a static.
+*/
+
+use core::{
+    cell::RefCell,
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use critical_section::Mutex;
+
+use super::{ChannelError, FullSnafu};
+
+pub struct Mpsc<T, const N: usize> {
+    inner: Mutex<RefCell<Inner<T, N>>>,
+}
+
+struct Inner<T, const N: usize> {
+    queue: heapless::Deque<T, N>,
+    waker: Option<Waker>,
+}
+
+impl<T, const N: usize> Mpsc<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                queue: heapless::Deque::new(),
+                waker: None,
+            })),
+        }
+    }
+
+    // Fails when the queue is full, rather than dropping a queued value
+    pub fn send(&self, item: T) -> Result<(), ChannelError> {
+        let waker = critical_section::with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if inner.queue.push_back(item).is_err() {
+                return FullSnafu.fail();
+            }
+            Ok(inner.waker.take())
+        })?;
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        critical_section::with(|cs| self.inner.borrow_ref_mut(cs).queue.pop_front())
+    }
+
+    // Only one task should receive, as only the last one to wait is woken
+    pub async fn recv(&self) -> T {
+        poll_fn(|cx| {
+            critical_section::with(|cs| {
+                let mut inner = self.inner.borrow_ref_mut(cs);
+                match inner.queue.pop_front() {
+                    Some(item) => Poll::Ready(item),
+                    None => {
+                        inner.waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Default for Mpsc<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,147 @@
+/*
+Lock free single producer single consumer ring, for handing values from an interrupt to a task
+without masking interrupts. Each side only writes its own position, so plain loads and stores are
+enough. The producer and consumer can each be taken once, which keeps it single producer and
+single consumer even when the ring is a static.
+*/
+
+use core::{future::poll_fn, mem::MaybeUninit, task::Poll};
+
+use crate::utils::{
+    AtomicWaker,
+    sync::{AtomicBool, AtomicUsize, Ordering, UnsafeCell},
+};
+
+// N must be a power of two
+pub struct Spsc<T, const N: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+    // Positions count up forever and wrap, so the ring is full when they're N apart
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    waker: AtomicWaker,
+    producer_taken: AtomicBool,
+    consumer_taken: AtomicBool,
+}
+
+// SAFETY: Only the producer writes the slots between tail and head, and only the consumer reads
+// the ones between head and tail
+unsafe impl<T: Send, const N: usize> Sync for Spsc<T, N> {}
+
+impl<T, const N: usize> Spsc<T, N> {
+    const MASK: usize = {
+        assert!(
+            N.is_power_of_two(),
+            "The ring capacity must be a power of two"
+        );
+        N - 1
+    };
+
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        Self {
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            producer_taken: AtomicBool::new(false),
+            consumer_taken: AtomicBool::new(false),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            buffer: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            producer_taken: AtomicBool::new(false),
+            consumer_taken: AtomicBool::new(false),
+        }
+    }
+
+    // None if it has already been taken
+    pub fn producer(&self) -> Option<Producer<'_, T, N>> {
+        (!self.producer_taken.swap(true, Ordering::AcqRel)).then_some(Producer { ring: self })
+    }
+
+    pub fn consumer(&self) -> Option<Consumer<'_, T, N>> {
+        (!self.consumer_taken.swap(true, Ordering::AcqRel)).then_some(Consumer { ring: self })
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Default for Spsc<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Spsc<T, N> {
+    fn drop(&mut self) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        for pos in 0..tail.wrapping_sub(head) {
+            let slot = &self.buffer[head.wrapping_add(pos) & Self::MASK];
+            // SAFETY: The slots between head and tail hold values that were never read
+            slot.with_mut(|data| unsafe { (*data).assume_init_drop() });
+        }
+    }
+}
+
+pub struct Producer<'a, T, const N: usize> {
+    ring: &'a Spsc<T, N>,
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    // Gives the item back if the ring is full
+    pub fn enqueue(&mut self, item: T) -> Result<(), T> {
+        let ring = self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(ring.head.load(Ordering::Acquire)) == N {
+            return Err(item);
+        }
+        // SAFETY: The slot is free, and only this producer writes free slots
+        ring.buffer[tail & Spsc::<T, N>::MASK].with_mut(|data| unsafe { (*data).write(item) });
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        ring.waker.wake();
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    ring: &'a Spsc<T, N>,
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    pub fn dequeue(&mut self) -> Option<T> {
+        let ring = self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        if head == ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: The producer filled the slot before moving the tail past it, and won't touch it
+        // again until the head has moved past it too
+        let item = ring.buffer[head & Spsc::<T, N>::MASK]
+            .with(|data| unsafe { (*data).assume_init_read() });
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(item)
+    }
+
+    pub async fn recv(&mut self) -> T {
+        poll_fn(|cx| {
+            // Register before checking, so that an enqueue in between isn't missed
+            self.ring.waker.register(cx.waker());
+            self.dequeue().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+}
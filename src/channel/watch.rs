@@ -0,0 +1,135 @@
+/*
+Watch channel, which holds only the latest value. Receivers wait for it to change rather than for
+each value, so a slow receiver just sees the newest one, and one that starts late still gets the
+current value straight away. It can live in a static, for state like a setting that any task can
+change and any task can follow.
+*/
+
+use core::{cell::RefCell, future::poll_fn, task::Poll};
+
+use critical_section::Mutex;
+
+use crate::utils::WaiterList;
+
+const MAX_WAITING: usize = 8;
+
+pub struct Watch<T> {
+    inner: Mutex<RefCell<Inner<T>>>,
+    waiting: WaiterList<MAX_WAITING>,
+}
+
+struct Inner<T> {
+    value: Option<T>,
+    // Bumped on every send, so receivers can tell whether they've seen the value
+    version: u64,
+}
+
+impl<T: Clone> Inner<T> {
+    // The value, if its version isn't the one seen, marking it seen
+    fn changed_since(&self, seen: &mut Option<u64>) -> Option<T> {
+        let value = self.value.clone()?;
+        if *seen == Some(self.version) {
+            return None;
+        }
+        *seen = Some(self.version);
+        Some(value)
+    }
+}
+
+impl<T: Clone> Watch<T> {
+    // Receivers wait for the first send
+    pub const fn new() -> Self {
+        Self::with_value(None)
+    }
+
+    pub const fn new_with(value: T) -> Self {
+        Self::with_value(Some(value))
+    }
+
+    const fn with_value(value: Option<T>) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner { value, version: 0 })),
+            waiting: WaiterList::new(),
+        }
+    }
+
+    pub fn send(&self, value: T) {
+        critical_section::with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            inner.value = Some(value);
+            inner.version += 1;
+        });
+        self.waiting.wake_all();
+    }
+
+    pub fn get(&self) -> Option<T> {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).value.clone())
+    }
+
+    // Changes the value in place, and wakes the receivers if `f` returns true
+    pub fn send_modify(&self, f: impl FnOnce(&mut Option<T>) -> bool) {
+        let changed = critical_section::with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if !f(&mut inner.value) {
+                return false;
+            }
+            inner.version += 1;
+            true
+        });
+        if changed {
+            self.waiting.wake_all();
+        }
+    }
+
+    // Sees the current value, if there is one, on its first `changed`
+    pub fn receiver(&self) -> WatchReceiver<'_, T> {
+        WatchReceiver {
+            watch: self,
+            seen: None,
+        }
+    }
+}
+
+impl<T: Clone> Default for Watch<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct WatchReceiver<'a, T> {
+    watch: &'a Watch<T>,
+    // The version last returned
+    seen: Option<u64>,
+}
+
+impl<T: Clone> WatchReceiver<'_, T> {
+    pub fn get(&self) -> Option<T> {
+        self.watch.get()
+    }
+
+    // The value, if it changed since this receiver last saw it
+    pub fn try_changed(&mut self) -> Option<T> {
+        critical_section::with(|cs| {
+            self.watch
+                .inner
+                .borrow_ref(cs)
+                .changed_since(&mut self.seen)
+        })
+    }
+
+    pub async fn changed(&mut self) -> T {
+        poll_fn(|cx| {
+            // Checked and registered in one critical section, so a send can't slip in between
+            // and wake the waiters before this one is one of them
+            critical_section::with(|cs| {
+                let inner = self.watch.inner.borrow_ref(cs);
+                if let Some(value) = inner.changed_since(&mut self.seen) {
+                    return Poll::Ready(value);
+                }
+                self.watch.waiting.register(cx);
+                Poll::Pending
+            })
+        })
+        .await
+    }
+}
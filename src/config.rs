@@ -0,0 +1,59 @@
+/*
+Interrupt priorities for the crate's drivers, so time critical interrupts can preempt bulk ones.
+All interrupts otherwise run at the default, and most urgent, priority.
+*/
+
+// NVIC priority levels, most urgent first. The nRF52833 implements the top 3 bits of the priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Priority {
+    P0 = 0x00,
+    P1 = 0x20,
+    P2 = 0x40,
+    P3 = 0x60,
+    P4 = 0x80,
+    P5 = 0xA0,
+    P6 = 0xC0,
+    P7 = 0xE0,
+}
+
+impl Priority {
+    pub const fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    // The time driver's interrupt
+    pub rtc0: Priority,
+    // Input channel edges
+    pub gpiote: Priority,
+    // The radio, which has to restart the receiver after each packet
+    pub radio: Priority,
+    // The serial port, which is bulk data that can wait
+    pub uarte0: Priority,
+    // NFC field detection, which only wakes a task
+    pub nfct: Priority,
+}
+
+impl Config {
+    // Leaves the two most urgent levels free for the application's own time critical interrupts
+    pub const fn new() -> Self {
+        Self {
+            rtc0: Priority::P2,
+            gpiote: Priority::P3,
+            radio: Priority::P4,
+            uarte0: Priority::P5,
+            nfct: Priority::P6,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
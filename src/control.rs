@@ -0,0 +1,128 @@
+/*
+Feedback control, e.g. holding a motor's speed or a robot on a line. `Pid` is a fixed-point PID
+controller with Q14 gains, as for the `dsp` filters, and `ControlLoop` runs one against a fixed
+schedule, reading the input and applying the output every period.
+
+The gains are per sample, so the integral and derivative gains have to be scaled with the period:
+halving the period means halving `ki` and doubling `kd` to get the same response.
+*/
+
+use core::pin::pin;
+
+use crate::{
+    dsp::ONE,
+    time::{PeriodicTimer, TickDuration},
+};
+
+const FRAC_BITS: u32 = ONE.trailing_zeros();
+
+// Q14 gains, so `ONE` is a gain of 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PidGains {
+    pub kp: i32,
+    pub ki: i32,
+    pub kd: i32,
+}
+
+// The derivative is taken of the input rather than the error, so a change of setpoint doesn't kick
+// the output. The integral is clamped to the output range, so it can't wind up while the output is
+// saturated and then overshoot once it comes back.
+pub struct Pid {
+    gains: PidGains,
+    setpoint: i32,
+    min: i32,
+    max: i32,
+    // In Q14
+    integral: i64,
+    last_input: Option<i32>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains, min: i32, max: i32) -> Self {
+        assert!(min <= max, "The output range is empty");
+        Self {
+            gains,
+            setpoint: 0,
+            min,
+            max,
+            integral: 0,
+            last_input: None,
+        }
+    }
+
+    pub fn setpoint(&self) -> i32 {
+        self.setpoint
+    }
+
+    pub fn set_setpoint(&mut self, setpoint: i32) {
+        self.setpoint = setpoint;
+    }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.gains = gains;
+    }
+
+    // Takes the next measurement, and returns the output to apply
+    pub fn update(&mut self, input: i32) -> i32 {
+        let PidGains { kp, ki, kd } = self.gains;
+        let error = i64::from(self.setpoint) - i64::from(input);
+        let (min, max) = (
+            i64::from(self.min) << FRAC_BITS,
+            i64::from(self.max) << FRAC_BITS,
+        );
+        self.integral = (self.integral + i64::from(ki) * error).clamp(min, max);
+        // Nothing to take the derivative against on the first sample
+        let change = i64::from(input) - i64::from(self.last_input.unwrap_or(input));
+        self.last_input = Some(input);
+        let output = i64::from(kp) * error + self.integral - i64::from(kd) * change;
+        ((output + (1 << (FRAC_BITS - 1))) >> FRAC_BITS)
+            .clamp(i64::from(self.min), i64::from(self.max)) as i32
+    }
+
+    // Forgets the integral and the last input, e.g. after the loop has been paused
+    pub fn reset(&mut self) {
+        self.integral = 0;
+        self.last_input = None;
+    }
+}
+
+pub struct ControlLoop<R, W> {
+    pid: Pid,
+    period: TickDuration,
+    read: R,
+    write: W,
+}
+
+impl<R: FnMut() -> i32, W: FnMut(i32)> ControlLoop<R, W> {
+    // `read` measures the input, and `write` applies the output, e.g. a motor's speed
+    pub fn new(pid: Pid, period: TickDuration, read: R, write: W) -> Self {
+        Self {
+            pid,
+            period,
+            read,
+            write,
+        }
+    }
+
+    pub fn pid(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+
+    // Reads and applies once
+    pub fn step(&mut self) -> i32 {
+        let output = self.pid.update((self.read)());
+        (self.write)(output);
+        output
+    }
+
+    // Steps every period, starting now. A step that runs late skips the periods it missed rather
+    // than catching up, since the gains assume a steady period.
+    pub async fn run(&mut self) -> ! {
+        let mut timer = pin!(PeriodicTimer::new(self.period));
+        loop {
+            self.step();
+            timer.as_mut().tick().await;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+/*
+Checksums for data that goes through flash or over a wire, so each subsystem doesn't roll its own.
+The CRCs are table driven, with the tables worked out at compile time, which costs 1.5 kB of flash
+and takes a lookup per byte rather than eight shifts.
+
+- CRC-32 is the one zlib, Ethernet and PNG use, for longer blocks.
+- CRC-16 is CCITT-FALSE, the one the radio checks its packets with in hardware.
+- CRC-8 is Dallas/Maxim's, for 1-Wire devices, and small enough to do bit by bit.
+
+For data that arrives in pieces, `Crc32` and `Crc16` carry the CRC between them:
+
+    let mut crc = Crc32::new();
+    crc.update(header);
+    crc.update(payload);
+    let check = crc.finish();
+*/
+
+const CRC32_POLY: u32 = 0xEDB8_8320;
+const CRC16_POLY: u16 = 0x1021;
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+static CRC16_TABLE: [u16; 256] = crc16_table();
+
+// Reflected, so the lowest bit goes first
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ CRC32_POLY
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = (byte as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ CRC16_POLY
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { crc: u32::MAX }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc = data.iter().fold(self.crc, |crc, &byte| {
+            CRC32_TABLE[usize::from(crc as u8 ^ byte)] ^ (crc >> 8)
+        });
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16 {
+    crc: u16,
+}
+
+impl Crc16 {
+    pub const fn new() -> Self {
+        Self { crc: u16::MAX }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc = data.iter().fold(self.crc, |crc, &byte| {
+            CRC16_TABLE[usize::from((crc >> 8) as u8 ^ byte)] ^ (crc << 8)
+        });
+    }
+
+    pub fn finish(&self) -> u16 {
+        self.crc
+    }
+}
+
+impl Default for Crc16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(data);
+    crc.finish()
+}
+
+// Comes out as zero over data followed by its CRC
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0x8C
+            }
+        })
+    })
+}
@@ -0,0 +1,56 @@
+/*
+CPU cycle counting with the DWT's CYCCNT, for profiling sections of code. The counter is 32 bits,
+so it wraps every 67 seconds at 64 MHz, and differences are only right for spans shorter than that.
+*/
+
+use cortex_m::peripheral::{DCB, DWT};
+
+// The CPU runs from the 64 MHz HFCLK
+pub const PER_MICRO: u32 = 64;
+
+// Has to be called before the counter counts anything
+pub fn enable(dcb: &mut DCB, dwt: &mut DWT) {
+    dcb.enable_trace();
+    dwt.enable_cycle_counter();
+}
+
+pub fn now() -> u32 {
+    DWT::cycle_count()
+}
+
+pub fn since(start: u32) -> u32 {
+    now().wrapping_sub(start)
+}
+
+// Busy waits, for timing a bit-banged protocol to the microsecond. Interrupts can still stretch
+// it, so mask them around anything with a maximum as well as a minimum. `time::delay_us` yields
+// to other tasks instead for longer waits.
+pub fn delay_us(micros: u32) {
+    let start = now();
+    while since(start) < micros * PER_MICRO {
+        core::hint::spin_loop();
+    }
+}
+
+// Logs how many cycles passed between `measure` and the guard being dropped, e.g.
+// `let _guard = cycles::measure("filter");` at the top of a block
+pub fn measure(name: &'static str) -> Measure {
+    Measure { name, start: now() }
+}
+
+pub struct Measure {
+    name: &'static str,
+    start: u32,
+}
+
+impl Measure {
+    pub fn elapsed(&self) -> u32 {
+        since(self.start)
+    }
+}
+
+impl Drop for Measure {
+    fn drop(&mut self) {
+        info!("{} took {} cycles", self.name, self.elapsed());
+    }
+}
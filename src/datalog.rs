@@ -0,0 +1,269 @@
+/*
+Data logger, which appends timestamped records to the flash region reserved in memory.x, so they
+survive a reset or reflash. Tasks queue records with `log`, and the logger task writes them out,
+since each flash write stalls the CPU. The log can then be dumped as CSV over any `fmt::Write`,
+like a UARTE.
+*/
+
+use core::{
+    fmt,
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::Poll,
+};
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use nrf52833_hal::{nvmc::Nvmc, pac::NVMC};
+use snafu::prelude::*;
+
+use crate::{
+    time::{TickInstant, Ticker},
+    utils::{AtomicWaker, LockMut, WaiterList, mpmc::Queue},
+};
+
+const RECORD_SIZE: usize = 16;
+const QUEUE_SIZE: usize = 16;
+const MAX_WAITING: usize = 8;
+const PAGE_SIZE: usize = <Nvmc<NVMC> as NorFlash>::ERASE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record {
+    pub timestamp: TickInstant,
+    // What the value is, e.g. which sensor it came from
+    pub kind: u32,
+    pub value: i32,
+}
+
+impl Record {
+    fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[..8].copy_from_slice(&self.timestamp.ticks().to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.kind.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.value.to_le_bytes());
+        bytes
+    }
+
+    // Erased flash reads as all ones, which marks the end of the log
+    fn from_bytes(bytes: [u8; RECORD_SIZE]) -> Option<Self> {
+        if bytes.iter().all(|&byte| byte == 0xFF) {
+            return None;
+        }
+        let [
+            t0,
+            t1,
+            t2,
+            t3,
+            t4,
+            t5,
+            t6,
+            t7,
+            k0,
+            k1,
+            k2,
+            k3,
+            v0,
+            v1,
+            v2,
+            v3,
+        ] = bytes;
+        Some(Self {
+            timestamp: TickInstant::from_ticks(u64::from_le_bytes([
+                t0, t1, t2, t3, t4, t5, t6, t7,
+            ])),
+            kind: u32::from_le_bytes([k0, k1, k2, k3]),
+            value: i32::from_le_bytes([v0, v1, v2, v3]),
+        })
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DatalogError {
+    #[snafu(display("The data log's flash region is full"))]
+    LogFull,
+    #[snafu(display("Too many records are waiting to be written"))]
+    QueueFull,
+    #[snafu(display("The flash controller rejected the operation"))]
+    Flash,
+}
+
+static FLASH: LockMut<Nvmc<NVMC>> = LockMut::new();
+// Bytes of the region used so far
+static USED: AtomicUsize = AtomicUsize::new(0);
+static PENDING: Queue<Record, QUEUE_SIZE> = Queue::new();
+static WRITER: AtomicWaker = AtomicWaker::new();
+// Holds the logger task off appending while the region is being erased
+static ERASING: AtomicBool = AtomicBool::new(false);
+// Every producer waiting for space in the queue, not just the latest
+static WAITING: WaiterList<MAX_WAITING> = WaiterList::new();
+
+pub struct DataLogger {}
+
+impl DataLogger {
+    // Takes the data log's region, from `FlashRegions`, and finds the end of the existing log
+    pub fn init(mut flash: Nvmc<NVMC>) -> Self {
+        let mut used = 0;
+        while used < flash.capacity() && read_record(&mut flash, used).is_some() {
+            used += RECORD_SIZE;
+        }
+        info!("Data log holds {} records", used / RECORD_SIZE);
+        USED.store(used, Ordering::Relaxed);
+        FLASH.init(flash);
+        Self {}
+    }
+
+    pub fn len(&self) -> usize {
+        USED.load(Ordering::Relaxed) / RECORD_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Erases a page at a time, since each stalls the CPU for up to 85 ms, and lets other tasks
+    // run in between. Records logged meanwhile queue up until it's done. The log is emptied
+    // before the first page goes, so a cancelled erase doesn't leave it pointing past the pages
+    // that were erased.
+    pub async fn erase(&self) -> Result<(), DatalogError> {
+        let _erasing = Erasing::start();
+        USED.store(0, Ordering::Relaxed);
+        let capacity = FLASH.with_lock(|flash| flash.capacity());
+        for page in (0..capacity).step_by(PAGE_SIZE) {
+            let (from, to) = (page as u32, (page + PAGE_SIZE) as u32);
+            FLASH.with_lock(|flash| flash.erase(from, to).ok().context(FlashSnafu))?;
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    // The logger task, which writes queued records out to flash
+    pub async fn run(&self) -> ! {
+        loop {
+            let record = poll_fn(|cx| {
+                WRITER.register(cx.waker());
+                if ERASING.load(Ordering::Acquire) {
+                    return Poll::Pending;
+                }
+                PENDING.dequeue().map_or(Poll::Pending, Poll::Ready)
+            })
+            .await;
+            WAITING.wake_all();
+            if let Err(err) = self.append(record) {
+                warn!("Dropping data log record: {}", err);
+            }
+        }
+    }
+
+    fn append(&self, record: Record) -> Result<(), DatalogError> {
+        FLASH.with_lock(|flash| {
+            let used = USED.load(Ordering::Relaxed);
+            ensure!(used + RECORD_SIZE <= flash.capacity(), LogFullSnafu);
+            // A cancelled erase leaves the later pages as they were, and writing over them would
+            // garble the records, so the log is full there until it's erased again
+            let mut bytes = [0; RECORD_SIZE];
+            flash
+                .read(used as u32, &mut bytes)
+                .ok()
+                .context(FlashSnafu)?;
+            ensure!(bytes.iter().all(|&byte| byte == 0xFF), LogFullSnafu);
+            flash
+                .write(used as u32, &record.to_bytes())
+                .ok()
+                .context(FlashSnafu)?;
+            USED.store(used + RECORD_SIZE, Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<Record> {
+        let offset = index.checked_mul(RECORD_SIZE)?;
+        if offset >= USED.load(Ordering::Relaxed) {
+            return None;
+        }
+        FLASH.with_lock(|flash| read_record(flash, offset))
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        (0..self.len()).map_while(|index| self.get(index))
+    }
+
+    // Writes the log as CSV, one record per line
+    pub fn dump(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out, "ticks,kind,value")?;
+        for record in self.records() {
+            writeln!(
+                out,
+                "{},{},{}",
+                record.timestamp.ticks(),
+                record.kind,
+                record.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn read_record(flash: &mut Nvmc<NVMC>, offset: usize) -> Option<Record> {
+    let mut bytes = [0; RECORD_SIZE];
+    flash.read(offset as u32, &mut bytes).ok()?;
+    Record::from_bytes(bytes)
+}
+
+// Holds the logger task off for as long as an erase runs, even if it's cancelled
+struct Erasing;
+
+impl Erasing {
+    fn start() -> Self {
+        ERASING.store(true, Ordering::Release);
+        Self
+    }
+}
+
+impl Drop for Erasing {
+    fn drop(&mut self) {
+        ERASING.store(false, Ordering::Release);
+        WRITER.wake();
+    }
+}
+
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await;
+}
+
+// Queues a record for the logger task, stamped with the current time. Fails if the queue is full.
+pub fn try_log(kind: u32, value: i32) -> Result<(), DatalogError> {
+    let record = Record {
+        timestamp: Ticker::now(),
+        kind,
+        value,
+    };
+    PENDING.enqueue(record).ok().context(QueueFullSnafu)?;
+    WRITER.wake();
+    Ok(())
+}
+
+// Like try_log, but waits for space in the queue. Only registers to be woken when the queue is
+// full, and in the same critical section as the check, so no waker is left behind once it's
+// logged and no space is missed in between.
+pub async fn log(kind: u32, value: i32) {
+    poll_fn(|cx| {
+        critical_section::with(|_| {
+            if try_log(kind, value).is_ok() {
+                return Poll::Ready(());
+            }
+            WAITING.register(cx);
+            Poll::Pending
+        })
+    })
+    .await;
+}
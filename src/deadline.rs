@@ -0,0 +1,121 @@
+/*
+Soft real time monitor. A task declares how often it has to run, and the executor reports every
+poll here, so a task that goes longer than that between polls is logged and counted as a miss.
+That catches starvation by tasks that hog the CPU, and wakes that got lost. The poll check only
+sees a miss once the task finally runs, so `watch` also looks for tasks that are overdue now.
+*/
+
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use critical_section::Mutex;
+
+use crate::{
+    executor::MAX_TASKS,
+    time::{TickDuration, TickInstant, Ticker, Timer},
+};
+
+#[derive(Clone, Copy)]
+struct Deadline {
+    period: TickDuration,
+    last_poll: TickInstant,
+    misses: u32,
+    // Already counted by `watch`, so the poll that ends it doesn't count it again
+    overdue: bool,
+}
+
+impl Deadline {
+    fn late_by(&self, now: TickInstant) -> Option<TickDuration> {
+        (now - self.last_poll)
+            .checked_sub(self.period)
+            .filter(|late| late.ticks() > 0)
+    }
+}
+
+static DEADLINES: Mutex<RefCell<[Option<Deadline>; MAX_TASKS]>> =
+    Mutex::new(RefCell::new([None; MAX_TASKS]));
+static CURRENT_TASK: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn with_deadlines<R>(f: impl FnOnce(&mut [Option<Deadline>; MAX_TASKS]) -> R) -> R {
+    critical_section::with(|cs| f(&mut DEADLINES.borrow_ref_mut(cs)))
+}
+
+pub struct DeadlineMonitor;
+
+impl DeadlineMonitor {
+    // Declares that the calling task has to be polled at least once every `period`. Must be called
+    // from inside the task.
+    pub fn declare(period: TickDuration) {
+        let task = CURRENT_TASK.load(Ordering::Relaxed);
+        with_deadlines(|deadlines| match deadlines.get_mut(task) {
+            Some(deadline) => {
+                *deadline = Some(Deadline {
+                    period,
+                    last_poll: Ticker::now(),
+                    misses: 0,
+                    overdue: false,
+                });
+            }
+            None => warn!("Task {} can't be monitored", task),
+        });
+    }
+
+    pub fn misses(task: usize) -> u32 {
+        with_deadlines(|deadlines| {
+            deadlines
+                .get(task)
+                .copied()
+                .flatten()
+                .map_or(0, |deadline| deadline.misses)
+        })
+    }
+
+    pub fn total_misses() -> u32 {
+        with_deadlines(|deadlines| deadlines.iter().flatten().map(|d| d.misses).sum())
+    }
+
+    // Checks every `check_every` for tasks that are overdue, for running as its own task
+    pub async fn watch(check_every: TickDuration) -> ! {
+        loop {
+            Timer::delay(check_every).await;
+            let now = Ticker::now();
+            with_deadlines(|deadlines| {
+                for (task, deadline) in deadlines.iter_mut().enumerate() {
+                    let Some(deadline) = deadline.as_mut().filter(|d| !d.overdue) else {
+                        continue;
+                    };
+                    if let Some(late) = deadline.late_by(now) {
+                        warn!("Task {} is overdue by {} ticks", task, late.ticks());
+                        deadline.misses += 1;
+                        deadline.overdue = true;
+                    }
+                }
+            });
+        }
+    }
+}
+
+// Called by the executor just before it polls a task
+pub(crate) fn polling(task: usize) {
+    CURRENT_TASK.store(task, Ordering::Relaxed);
+    with_deadlines(|deadlines| {
+        let Some(Some(deadline)) = deadlines.get_mut(task) else {
+            return;
+        };
+        let now = Ticker::now();
+        if let Some(late) = deadline.late_by(now)
+            && !deadline.overdue
+        {
+            warn!(
+                "Task {} missed its deadline by {} ticks",
+                task,
+                late.ticks()
+            );
+            deadline.misses += 1;
+        }
+        deadline.overdue = false;
+        deadline.last_poll = now;
+    });
+}
@@ -0,0 +1,46 @@
+/*
+Identity of the chip, read from the factory information registers (FICR), so boards running the same
+firmware can tell each other apart. The device ID is random and unique per chip, and the device
+address is the random static Bluetooth address Nordic programs alongside it. With defmt, every log
+line is prefixed with the short ID, to tell apart the logs of several boards, and the ticker's time
+in microseconds. That's the clock timers run on, so the times line up with their deadlines, rather
+than with when the host got round to reading the line. It reads 0 until the ticker is initialised.
+*/
+
+use nrf52833_hal::pac::{FICR, ficr};
+
+fn ficr() -> &'static ficr::RegisterBlock {
+    // SAFETY: The FICR is read only, and programmed at the factory
+    unsafe { &*FICR::ptr() }
+}
+
+pub fn device_id() -> u64 {
+    let ficr = ficr();
+    u64::from(ficr.deviceid[1].read().bits()) << 32 | u64::from(ficr.deviceid[0].read().bits())
+}
+
+// The device ID folded into 16 bits. Not unique, but a collision in a room full of boards is
+// unlikely.
+pub fn short_id() -> u16 {
+    let id = device_id();
+    (id ^ id >> 16 ^ id >> 32 ^ id >> 48) as u16
+}
+
+// The 48 bit device address, least significant byte first like on air. The top two bits are set,
+// as a random static address needs.
+pub fn radio_address() -> [u8; 6] {
+    let ficr = ficr();
+    let low = ficr.deviceaddr[0].read().bits().to_le_bytes();
+    let high = (ficr.deviceaddr[1].read().bits() as u16 | 0xC000).to_le_bytes();
+    [low[0], low[1], low[2], low[3], high[0], high[1]]
+}
+
+#[cfg(feature = "defmt")]
+defmt::timestamp!(
+    "{=u16:04x} {=u64:us}",
+    short_id(),
+    // Doesn't take the ticker lock, so lines logged while it's held get a time too
+    crate::time::Ticker::now()
+        .duration_since_epoch()
+        .to_micros()
+);
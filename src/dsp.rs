@@ -0,0 +1,272 @@
+/*
+Fixed-point filters for post-processing sensor streams (ADC, accelerometer, microphone). The filters
+work on `i32` samples with integer maths, since the M0 targets have no FPU and the M4F one only
+does single precision. Coefficients are fractions scaled by 2^14 (Q14), so 1.0 is `ONE`. The
+Goertzel tone detector is the exception, since its accumulators need the range of a float.
+*/
+
+// 1.0 in the Q14 format of the biquad coefficients
+pub const ONE: i32 = 1 << FRAC_BITS;
+const FRAC_BITS: u32 = 14;
+
+pub trait Filter {
+    // Takes the next sample, and returns the filtered one
+    fn update(&mut self, sample: i32) -> i32;
+    // Forgets the history, as if no sample had been seen
+    fn reset(&mut self);
+}
+
+// Averages the last `N` samples. Until the window fills up, it averages what it has so far.
+pub struct MovingAverage<const N: usize> {
+    window: [i32; N],
+    next: usize,
+    len: usize,
+    sum: i64,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "The window must hold at least one sample") };
+        Self {
+            window: [0; N],
+            next: 0,
+            len: 0,
+            sum: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, sample: i32) -> i32 {
+        self.sum += i64::from(sample) - i64::from(self.window[self.next]);
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        (self.sum / self.len as i64) as i32
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+// Exponential smoothing with a smoothing factor of 1 / 2^shift, so each sample moves the output by
+// that fraction of the way. Bigger shifts smooth more but respond slower. The state keeps extra
+// fractional bits, so small steps aren't lost to rounding.
+pub struct Ema {
+    shift: u32,
+    state: Option<i64>,
+}
+
+impl Ema {
+    pub const fn new(shift: u32) -> Self {
+        assert!(shift < 32, "The shift must be less than 32");
+        Self { shift, state: None }
+    }
+}
+
+impl Filter for Ema {
+    fn update(&mut self, sample: i32) -> i32 {
+        let scaled = i64::from(sample) << FRAC_BITS;
+        // Start from the first sample instead of ramping up from zero
+        let state = self.state.get_or_insert(scaled);
+        *state += (scaled - *state) >> self.shift;
+        ((*state + (1 << (FRAC_BITS - 1))) >> FRAC_BITS) as i32
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+// Removes the DC offset, e.g. a microphone's bias or gravity on an accelerometer axis, by
+// subtracting a slow moving average. The shift sets how slow, as for `Ema`.
+pub struct DcBlocker {
+    average: Ema,
+}
+
+impl DcBlocker {
+    pub const fn new(shift: u32) -> Self {
+        Self {
+            average: Ema::new(shift),
+        }
+    }
+}
+
+impl Filter for DcBlocker {
+    fn update(&mut self, sample: i32) -> i32 {
+        sample - self.average.update(sample)
+    }
+
+    fn reset(&mut self) {
+        self.average.reset();
+    }
+}
+
+// Q14 coefficients of a biquad, with a0 normalised to 1. These come from the usual design formulas
+// (e.g. the Audio EQ Cookbook), computed ahead of time and multiplied by `ONE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BiquadCoefficients {
+    pub b0: i32,
+    pub b1: i32,
+    pub b2: i32,
+    pub a1: i32,
+    pub a2: i32,
+}
+
+// Second order IIR filter in direct form I, which can't overflow its state the way the transposed
+// forms can when the coefficients are rounded
+pub struct Biquad {
+    coefficients: BiquadCoefficients,
+    inputs: [i32; 2],
+    outputs: [i32; 2],
+}
+
+impl Biquad {
+    pub const fn new(coefficients: BiquadCoefficients) -> Self {
+        Self {
+            coefficients,
+            inputs: [0; 2],
+            outputs: [0; 2],
+        }
+    }
+}
+
+impl Filter for Biquad {
+    fn update(&mut self, sample: i32) -> i32 {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+        let [x1, x2] = self.inputs.map(i64::from);
+        let [y1, y2] = self.outputs.map(i64::from);
+        let acc = i64::from(b0) * i64::from(sample) + i64::from(b1) * x1 + i64::from(b2) * x2
+            - i64::from(a1) * y1
+            - i64::from(a2) * y2;
+        let output = ((acc + (1 << (FRAC_BITS - 1))) >> FRAC_BITS)
+            .clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32;
+        self.inputs = [sample, self.inputs[0]];
+        self.outputs = [output, self.outputs[0]];
+        output
+    }
+
+    fn reset(&mut self) {
+        self.inputs = [0; 2];
+        self.outputs = [0; 2];
+    }
+}
+
+// Measures the power at one frequency over blocks of `block_len` samples, which is much cheaper than
+// an FFT when only a few tones matter. The frequency is rounded to the nearest bin, which are
+// `sample_rate / block_len` apart, so longer blocks resolve the tone more finely but respond slower.
+pub struct Goertzel {
+    coefficient: f32,
+    block_len: usize,
+    count: usize,
+    s1: f32,
+    s2: f32,
+}
+
+impl Goertzel {
+    pub fn new(target_hz: f32, sample_rate_hz: f32, block_len: usize) -> Self {
+        assert!(block_len > 0, "The block must hold at least one sample");
+        let bin =
+            (block_len as f64 * f64::from(target_hz) / f64::from(sample_rate_hz) + 0.5) as u64;
+        let omega = 2.0 * core::f64::consts::PI * bin as f64 / block_len as f64;
+        Self {
+            coefficient: (2.0 * cos(omega)) as f32,
+            block_len,
+            count: 0,
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    // Feeds samples in, returning the highest power of the blocks completed by them, so a tone in
+    // any of them shows. The power is normalised to the squared amplitude of the tone, in sample
+    // units, so a full scale tone is about `i16::MAX^2`.
+    pub fn process(&mut self, samples: &[i16]) -> Option<f32> {
+        let mut power = None;
+        for &sample in samples {
+            let s0 = f32::from(sample) + self.coefficient * self.s1 - self.s2;
+            self.s2 = self.s1;
+            self.s1 = s0;
+            self.count += 1;
+            if self.count == self.block_len {
+                let raw =
+                    self.s1 * self.s1 + self.s2 * self.s2 - self.coefficient * self.s1 * self.s2;
+                let half = self.block_len as f32 / 2.0;
+                let block = raw / (half * half);
+                power = Some(power.map_or(block, |power: f32| power.max(block)));
+                self.reset();
+            }
+        }
+        power
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+// Waits for a tone, e.g. a whistle, on the microphone. `threshold` is the tone's amplitude in
+// sample units.
+pub struct ToneDetector {
+    goertzel: Goertzel,
+    threshold_power: f32,
+}
+
+impl ToneDetector {
+    pub fn new(goertzel: Goertzel, threshold: u16) -> Self {
+        Self {
+            goertzel,
+            threshold_power: f32::from(threshold) * f32::from(threshold),
+        }
+    }
+
+    // Keeps filling the buffer from `read` (e.g. a PDM or SAADC capture) until a block has the tone
+    // above the threshold, and returns the highest block power in that buffer
+    pub async fn wait<const N: usize>(
+        &mut self,
+        buffer: &mut [i16; N],
+        mut read: impl AsyncFnMut(&mut [i16; N]),
+    ) -> f32 {
+        self.goertzel.reset();
+        loop {
+            read(buffer).await;
+            if let Some(power) = self.goertzel.process(buffer)
+                && power >= self.threshold_power
+            {
+                debug!("Detected tone with power {}", power);
+                return power;
+            }
+        }
+    }
+}
+
+// Taylor series around zero after folding the angle into [-pi, pi], which is plenty accurate for
+// filter design without pulling in libm
+fn cos(x: f64) -> f64 {
+    use core::f64::consts::PI;
+
+    let mut x = x % (2.0 * PI);
+    if x > PI {
+        x -= 2.0 * PI;
+    } else if x < -PI {
+        x += 2.0 * PI;
+    }
+    let x2 = x * x;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..12 {
+        term *= -x2 / f64::from((2 * n - 1) * (2 * n));
+        sum += term;
+    }
+    sum
+}
@@ -0,0 +1,98 @@
+use snafu::prelude::*;
+
+#[cfg(feature = "datalog")]
+use crate::datalog::DatalogError;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+use crate::inactivity::InactivityError;
+#[cfg(feature = "plotter")]
+use crate::plotter::PlotterError;
+#[cfg(feature = "radio")]
+use crate::radio::RadioError;
+#[cfg(feature = "remote")]
+use crate::remote::RemoteError;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+use crate::scheduler::SchedulerError;
+#[cfg(feature = "settings")]
+use crate::settings::SettingsError;
+#[cfg(feature = "shell")]
+use crate::shell::ShellError;
+#[cfg(all(any(feature = "nrf52833", feature = "std"), not(loom)))]
+use crate::shutdown::ShutdownError;
+#[cfg(feature = "telemetry")]
+use crate::telemetry::TelemetryError;
+#[cfg(feature = "nrf52833")]
+use crate::{
+    adc::AdcError, board::BoardError, gpiote::GpioteError, hx711::Hx711Error, nfc::NfcError,
+    onewire::OneWireError, supervisor::SupervisorError,
+};
+use crate::{
+    channel::ChannelError, framing::FramingError, interrupts::InterruptError,
+    lsm303agr::Lsm303Error,
+};
+
+// Crate wide error type, every module's error can be converted into it with `?`
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("ADC error: {source}"))]
+    Adc { source: AdcError },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("Board error: {source}"))]
+    Board { source: BoardError },
+    #[snafu(context(false), display("Channel error: {source}"))]
+    Channel { source: ChannelError },
+    #[cfg(feature = "datalog")]
+    #[snafu(context(false), display("Data log error: {source}"))]
+    Datalog { source: DatalogError },
+    #[snafu(context(false), display("Framing error: {source}"))]
+    Framing { source: FramingError },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("GPIOTE error: {source}"))]
+    Gpiote { source: GpioteError },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("HX711 error: {source}"))]
+    Hx711 { source: Hx711Error },
+    #[cfg(any(feature = "nrf52833", feature = "std"))]
+    #[snafu(context(false), display("Inactivity error: {source}"))]
+    Inactivity { source: InactivityError },
+    #[snafu(context(false), display("Interrupt error: {source}"))]
+    Interrupt { source: InterruptError },
+    #[snafu(context(false), display("LSM303AGR error: {source}"))]
+    Lsm303 { source: Lsm303Error },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("NFC error: {source}"))]
+    Nfc { source: NfcError },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("1-Wire error: {source}"))]
+    OneWire { source: OneWireError },
+    #[cfg(feature = "plotter")]
+    #[snafu(context(false), display("Plotter error: {source}"))]
+    Plotter { source: PlotterError },
+    #[cfg(feature = "radio")]
+    #[snafu(context(false), display("Radio error: {source}"))]
+    Radio { source: RadioError },
+    #[cfg(feature = "remote")]
+    #[snafu(context(false), display("Remote control error: {source}"))]
+    Remote { source: RemoteError },
+    #[cfg(any(feature = "nrf52833", feature = "std"))]
+    #[snafu(context(false), display("Scheduler error: {source}"))]
+    Scheduler { source: SchedulerError },
+    #[cfg(feature = "settings")]
+    #[snafu(context(false), display("Settings error: {source}"))]
+    Settings { source: SettingsError },
+    #[cfg(feature = "shell")]
+    #[snafu(context(false), display("Shell error: {source}"))]
+    Shell { source: ShellError },
+    #[cfg(all(any(feature = "nrf52833", feature = "std"), not(loom)))]
+    #[snafu(context(false), display("Shutdown error: {source}"))]
+    Shutdown { source: ShutdownError },
+    #[cfg(feature = "nrf52833")]
+    #[snafu(context(false), display("Supervisor error: {source}"))]
+    Supervisor { source: SupervisorError },
+    #[cfg(feature = "telemetry")]
+    #[snafu(context(false), display("Telemetry error: {source}"))]
+    Telemetry { source: TelemetryError },
+}
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
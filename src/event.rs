@@ -0,0 +1,103 @@
+/*
+Typed event bus. The application declares the events it uses with `events!`, which wraps them in
+one enum, and tasks subscribe to a single event type rather than to the whole enum. Built on the
+broadcast channel, so every subscriber to a type sees every event of that type.
+*/
+
+use core::marker::PhantomData;
+
+use crate::channel::broadcast::{Broadcast, Subscriber};
+
+// Declares an enum with one variant per event type, along with the conversions the bus uses to
+// wrap and unwrap them, e.g. `events! { pub enum AppEvent { Button(ButtonEvent), Tick(TimerTick) } }`
+#[macro_export]
+macro_rules! events {
+    ($vis:vis enum $name:ident { $($variant:ident($event:ty)),+ $(,)? }) => {
+        #[derive(Clone)]
+        $vis enum $name {
+            $($variant($event)),+
+        }
+
+        $(
+            impl From<$event> for $name {
+                fn from(event: $event) -> Self {
+                    Self::$variant(event)
+                }
+            }
+
+            impl TryFrom<$name> for $event {
+                type Error = $name;
+
+                #[allow(unreachable_patterns)]
+                fn try_from(event: $name) -> Result<Self, $name> {
+                    match event {
+                        $name::$variant(event) => Ok(event),
+                        other => Err(other),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+// Keeps the last N events, which is how far a subscriber can fall behind
+pub struct EventBus<E, const N: usize> {
+    events: Broadcast<E, N>,
+}
+
+impl<E: Clone, const N: usize> EventBus<E, N> {
+    pub const fn new() -> Self {
+        Self {
+            events: Broadcast::new(),
+        }
+    }
+
+    pub fn publish(&self, event: impl Into<E>) {
+        self.events.send(event.into());
+    }
+
+    pub fn subscribe<T>(&self) -> EventSubscriber<'_, E, T, N>
+    where
+        E: TryInto<T>,
+    {
+        EventSubscriber {
+            events: self.events.subscribe(),
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<E: Clone, const N: usize> Default for EventBus<E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct EventSubscriber<'a, E, T, const N: usize> {
+    events: Subscriber<'a, E, N>,
+    _event: PhantomData<T>,
+}
+
+impl<E: Clone + TryInto<T>, T, const N: usize> EventSubscriber<'_, E, T, N> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        while let Some(event) = self.events.try_recv() {
+            if let Ok(event) = event.try_into() {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    pub async fn recv(&mut self) -> T {
+        loop {
+            if let Ok(event) = self.events.recv().await.try_into() {
+                return event;
+            }
+        }
+    }
+
+    // Events of any type that were dropped before this subscriber got to them
+    pub fn missed(&self) -> u64 {
+        self.events.missed()
+    }
+}
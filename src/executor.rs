@@ -3,40 +3,602 @@ use core::{
     task::{Context, RawWaker, RawWakerVTable, Waker},
 };
 
-use cortex_m::asm;
-use defmt::info;
-use heapless::mpmc::Queue;
+use portable_atomic::{AtomicBool, Ordering};
+
+use crate::utils::mpmc::Queue;
+
+// Scheduler events happen on every wake and poll, so they're only logged with the `trace-executor`
+// feature, and can be rate limited with `Executor::set_trace_rate`
+macro_rules! executor_trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace-executor")]
+        if $crate::executor::trace_rate::sample() {
+            trace!($($arg)*);
+        }
+    };
+}
+
+// Pins each task and builds the array `Executor::run_tasks` takes, so the tasks don't each need
+// their own `pin!` binding
+#[macro_export]
+macro_rules! pin_array {
+    ($($task:expr),+ $(,)?) => {
+        [$(
+            ::core::pin::pin!($task)
+                as ::core::pin::Pin<&mut dyn ::core::future::Future<Output = ()>>
+        ),+]
+    };
+}
 
 pub struct Executor {}
 
-const MAX_TASKS: usize = 8;
-type TaskQueue = Queue<usize, MAX_TASKS>;
+pub(crate) const MAX_TASKS: usize = 8;
+
+// The tasks of one executor that have been woken, in the order they were. A task is only queued
+// once however often it's woken before it's polled, and there are fewer tasks than the queue has
+// room for, so it can never fill up and drop a wake.
+struct ReadyQueue {
+    queue: Queue<TaskRef, MAX_TASKS>,
+    queued: [AtomicBool; MAX_TASKS],
+}
+
+impl ReadyQueue {
+    const fn new() -> Self {
+        Self {
+            queue: Queue::new(),
+            queued: [const { AtomicBool::new(false) }; MAX_TASKS],
+        }
+    }
+
+    fn enqueue(&self, task_ref: TaskRef) {
+        let Some(queued) = self.queued.get(task_ref.id()) else {
+            warn!("Ignoring wake for bad task ID {}", task_ref.id());
+            return;
+        };
+        if queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // Can't fail, each task takes at most one place
+        let _ = self.queue.enqueue(task_ref);
+    }
+
+    // Clears the task's flag before it's polled, so a wake during the poll queues it again
+    fn dequeue(&self) -> Option<TaskRef> {
+        let task_ref = self.queue.dequeue()?;
+        self.queued[task_ref.id()].store(false, Ordering::Release);
+        Some(task_ref)
+    }
+}
+
+// `run_tasks` is the only executor for now. Wakers still carry its ID, so that executors running
+// at interrupt priorities can later share the waker format without waking each other's tasks.
+const MAIN_EXECUTOR: u8 = 0;
+
+static TASK_ID_READY: ReadyQueue = ReadyQueue::new();
+
+// Identifies a task across executors. The generation is bumped whenever a spawned task's slot is
+// reused, so a waker left over from a finished task can't wake the one that replaced it.
+// Statically allocated tasks never finish, so they stay at generation 0.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TaskRef {
+    executor: u8,
+    generation: u8,
+    task: u16,
+}
+
+impl TaskRef {
+    const fn new(executor: u8, task: usize, generation: u8) -> Self {
+        Self {
+            executor,
+            generation,
+            task: task as u16,
+        }
+    }
+
+    const fn id(self) -> usize {
+        self.task as usize
+    }
+
+    // Packed into the waker's data pointer, which is at least 32 bits on every target
+    const fn pack(self) -> usize {
+        ((self.executor as usize) << 24) | ((self.generation as usize) << 16) | self.task as usize
+    }
+
+    const fn unpack(data: usize) -> Self {
+        Self {
+            executor: (data >> 24) as u8,
+            generation: (data >> 16) as u8,
+            task: data as u16,
+        }
+    }
+}
+
+// Takes the next ready task off the queue and has `poll` poll it, with the task's waker. Returns
+// false if no task was ready.
+fn poll_next(poll: impl FnOnce(TaskRef, &mut Context<'_>)) -> bool {
+    let Some(task_ref) = TASK_ID_READY.dequeue() else {
+        return false;
+    };
+    poll_task(task_ref, poll);
+    true
+}
+
+fn poll_task(task_ref: TaskRef, poll: impl FnOnce(TaskRef, &mut Context<'_>)) {
+    executor_trace!("Running task {}", task_ref.id());
+    let waker = WakerManager::get_waker(task_ref);
+    let mut cx = Context::from_waker(&waker);
+    #[cfg(feature = "deadline-monitor")]
+    crate::deadline::polling(task_ref.id());
+    #[cfg(feature = "starvation-watchdog")]
+    let _timer = crate::starvation::PollTimer::start(task_ref.id());
+    poll(task_ref, &mut cx);
+}
 
-static TASK_ID_READY: TaskQueue = TaskQueue::new();
+// Finds the ready queue of the executor a waker was made for
+fn ready_queue(executor: u8) -> Option<&'static ReadyQueue> {
+    match executor {
+        MAIN_EXECUTOR => Some(&TASK_ID_READY),
+        _ => None,
+    }
+}
 
 impl Executor {
     pub fn run_tasks<const N: usize>(mut tasks: [Pin<&mut dyn Future<Output = ()>>; N]) -> ! {
         const { assert!(N < MAX_TASKS, "Too many tasks have been selected to run") };
         for task_id in 0..tasks.len() {
-            TASK_ID_READY.enqueue(task_id).expect("Task queue is full");
+            TASK_ID_READY.enqueue(TaskRef::new(MAIN_EXECUTOR, task_id, 0));
         }
+        #[cfg(all(feature = "std", feature = "alloc"))]
+        let _ = spawner::EXECUTOR_THREAD.set(std::thread::current().id());
+        #[cfg(feature = "alloc")]
+        let mut spawned = spawner::SpawnedTasks::new();
         loop {
-            while let Some(task) = TASK_ID_READY.dequeue() {
-                assert!(task <= tasks.len(), "Bad task ID {task}");
-                info!("Running task {}", task);
-                let _ = tasks[task]
-                    .as_mut()
-                    .poll(&mut Context::from_waker(&WakerManager::get_waker(task)));
+            #[cfg(feature = "alloc")]
+            spawned.adopt_pending();
+            loop {
+                let polled = poll_next(|task_ref, cx| {
+                    let task = task_ref.id();
+                    // Only a misbehaving waker could produce an unknown ID
+                    match tasks.get_mut(task) {
+                        Some(task) => {
+                            let _ = task.as_mut().poll(cx);
+                        }
+                        None => warn!("Ignoring wake for bad task ID {}", task),
+                    }
+                });
+                // Spawned tasks have their own queue, and take turns with the others so neither
+                // can starve the other
+                #[cfg(feature = "alloc")]
+                let polled = spawned.poll_next() | polled;
+                if !polled {
+                    break;
+                }
+            }
+            #[cfg(feature = "alloc")]
+            if spawner::has_pending() {
+                continue;
             }
-            asm::wfi();
+            wait_for_wake();
         }
     }
 
     // When an interrupt is fired, this method can be called to make sure the appropriate task ID
-    // is ran on next poll of the executor
+    // is ran on next poll of the executor. Only the statically allocated tasks of `run_tasks` can
+    // be woken by ID, spawned tasks need their waker.
     pub fn wake_task(task_id: usize) {
-        info!("Waking task {}", task_id);
-        assert!(TASK_ID_READY.enqueue(task_id).is_ok(), "Task Queue is full");
+        if task_id >= MAX_TASKS {
+            warn!("Ignoring wake for bad task ID {}", task_id);
+            return;
+        }
+        Self::wake(TaskRef::new(MAIN_EXECUTOR, task_id, 0));
+    }
+
+    fn wake(task_ref: TaskRef) {
+        let task = task_ref.id();
+        executor_trace!("Waking task {}", task);
+        #[cfg(feature = "alloc")]
+        if task >= spawner::FIRST_ID {
+            if spawner::wake(task_ref) {
+                notify_wake();
+            }
+            return;
+        }
+        let Some(queue) = ready_queue(task_ref.executor) else {
+            warn!(
+                "Ignoring wake for task {} of unknown executor {}",
+                task, task_ref.executor
+            );
+            return;
+        };
+        queue.enqueue(task_ref);
+        notify_wake();
+    }
+}
+
+#[cfg(feature = "trace-executor")]
+mod trace_rate {
+    use portable_atomic::{AtomicU32, Ordering};
+
+    use super::Executor;
+
+    static EVERY: AtomicU32 = AtomicU32::new(1);
+    static EVENTS: AtomicU32 = AtomicU32::new(0);
+
+    pub(super) fn sample() -> bool {
+        EVENTS
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(EVERY.load(Ordering::Relaxed))
+    }
+
+    impl Executor {
+        // Only logs one in every `every` scheduler events. By default they're all logged.
+        pub fn set_trace_rate(every: u32) {
+            EVERY.store(every.max(1), Ordering::Relaxed);
+        }
+    }
+}
+
+// Sleeps until a task may have been woken. On the MCU, tasks are only ever woken by interrupts.
+#[cfg(not(feature = "std"))]
+fn wait_for_wake() {
+    cortex_m::asm::wfi();
+}
+
+#[cfg(not(feature = "std"))]
+fn notify_wake() {}
+
+#[cfg(feature = "std")]
+static WOKEN: std::sync::Mutex<bool> = std::sync::Mutex::new(false);
+#[cfg(feature = "std")]
+static WOKEN_CHANGED: std::sync::Condvar = std::sync::Condvar::new();
+
+// On the host, tasks can be woken by other threads
+#[cfg(feature = "std")]
+fn wait_for_wake() {
+    let mut woken = WOKEN.lock().unwrap();
+    while !*woken {
+        woken = WOKEN_CHANGED.wait(woken).unwrap();
+    }
+    *woken = false;
+}
+
+#[cfg(feature = "std")]
+fn notify_wake() {
+    *WOKEN.lock().unwrap() = true;
+    WOKEN_CHANGED.notify_one();
+}
+
+// Runs the tasks of `run_tasks` a poll at a time in tests, so the order the scheduler picks can be
+// checked without hardware. Wakes and interrupts are injected between polls:
+//
+//     let tasks = pin_array!(sensor, logger);
+//     let mut harness = ExecutorHarness::new(tasks);
+//     harness.run_until_idle();
+//     harness.interrupt(|| Executor::wake_task(1));
+//     assert_eq!(harness.run_until_idle(), [1]);
+#[cfg(feature = "std")]
+pub use harness::ExecutorHarness;
+
+#[cfg(feature = "std")]
+mod harness {
+    use core::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::{Executor, MAIN_EXECUTOR, MAX_TASKS, TASK_ID_READY, TaskRef};
+
+    // The ready queue is a global, so only one harness can run at a time
+    static HARNESS_LOCK: Mutex<()> = Mutex::new(());
+
+    pub struct ExecutorHarness<'a, const N: usize> {
+        tasks: [Pin<&'a mut dyn Future<Output = ()>>; N],
+        finished: [bool; N],
+        polls: Vec<usize>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl<'a, const N: usize> ExecutorHarness<'a, N> {
+        // Every task starts out ready, in order, as with `run_tasks`
+        pub fn new(tasks: [Pin<&'a mut dyn Future<Output = ()>>; N]) -> Self {
+            const { assert!(N < MAX_TASKS, "Too many tasks have been selected to run") };
+            let lock = HARNESS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            // Drop wakes left over from an earlier harness
+            while TASK_ID_READY.dequeue().is_some() {}
+            for task_id in 0..N {
+                TASK_ID_READY.enqueue(TaskRef::new(MAIN_EXECUTOR, task_id, 0));
+            }
+            Self {
+                tasks,
+                finished: [false; N],
+                polls: Vec::new(),
+                _lock: lock,
+            }
+        }
+
+        pub fn wake(&self, task_id: usize) {
+            Executor::wake_task(task_id);
+        }
+
+        // Runs `isr` between polls, as an interrupt would fire
+        pub fn interrupt(&self, isr: impl FnOnce()) {
+            isr();
+        }
+
+        // Polls the next ready task, returning its ID, or None if none was ready. A wake for a task
+        // that's finished is taken off the queue without polling it again.
+        pub fn step(&mut self) -> Option<usize> {
+            let mut polled = None;
+            let ready = super::poll_next(|task_ref, cx: &mut Context<'_>| {
+                let task = task_ref.id();
+                let Some(future) = self.tasks.get_mut(task) else {
+                    warn!("Ignoring wake for bad task ID {}", task);
+                    return;
+                };
+                if self.finished[task] {
+                    return;
+                }
+                if let Poll::Ready(()) = future.as_mut().poll(cx) {
+                    self.finished[task] = true;
+                }
+                polled = Some(task);
+            });
+            if !ready {
+                return None;
+            }
+            if let Some(task) = polled {
+                self.polls.push(task);
+            }
+            polled.or_else(|| self.step())
+        }
+
+        // Polls until no task is ready, returning the order they were polled in
+        pub fn run_until_idle(&mut self) -> Vec<usize> {
+            let start = self.polls.len();
+            while self.step().is_some() {}
+            self.polls[start..].to_vec()
+        }
+
+        // Every poll since the harness was made
+        pub fn polls(&self) -> &[usize] {
+            &self.polls
+        }
+
+        pub fn is_finished(&self, task_id: usize) -> bool {
+            self.finished.get(task_id).copied().unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub use spawner::{InterruptSpawner, Spawner};
+
+#[cfg(feature = "alloc")]
+mod spawner {
+    extern crate alloc;
+
+    use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+    use core::{cell::RefCell, marker::PhantomData, pin::Pin, task::Context};
+
+    use critical_section::Mutex;
+
+    use super::{Executor, MAIN_EXECUTOR, MAX_TASKS, TaskRef};
+
+    // Spawned tasks get the IDs after every statically allocated one
+    pub(super) const FIRST_ID: usize = MAX_TASKS;
+
+    type BoxedTask = Pin<Box<dyn Future<Output = ()>>>;
+
+    struct PendingTasks(Vec<BoxedTask>);
+
+    // SAFETY: Tasks that aren't Send are only ever pushed through a Spawner, which can only be
+    // created in thread mode, and are only taken out by the executor, also in thread mode. So such a
+    // task never actually crosses into an interrupt context. Tasks from an InterruptSpawner may, but
+    // they're Send. On the host, thread mode is the executor's thread.
+    unsafe impl Send for PendingTasks {}
+
+    #[cfg(feature = "std")]
+    pub(super) static EXECUTOR_THREAD: std::sync::OnceLock<std::thread::ThreadId> =
+        std::sync::OnceLock::new();
+
+    static PENDING: Mutex<RefCell<PendingTasks>> =
+        Mutex::new(RefCell::new(PendingTasks(Vec::new())));
+
+    // Spawned tasks that have been woken, by slot. Any number can be spawned, so they can't share
+    // the fixed size queue of the static tasks. Instead each slot is queued at most once, and the
+    // queue always has room for every slot, so a wake never allocates or gets dropped, even in an
+    // interrupt.
+    struct ReadyTasks {
+        queue: VecDeque<usize>,
+        slots: Vec<ReadySlot>,
+    }
+
+    struct ReadySlot {
+        generation: u8,
+        queued: bool,
+    }
+
+    static READY: Mutex<RefCell<ReadyTasks>> = Mutex::new(RefCell::new(ReadyTasks {
+        queue: VecDeque::new(),
+        slots: Vec::new(),
+    }));
+
+    // Returns whether the task was queued, rather than already being in the queue or stale
+    pub(super) fn wake(task_ref: TaskRef) -> bool {
+        critical_section::with(|cs| {
+            let ready = &mut *READY.borrow_ref_mut(cs);
+            let slot = task_ref.id() - FIRST_ID;
+            let Some(state) = ready.slots.get_mut(slot) else {
+                return false;
+            };
+            // A waker left over from a finished task mustn't wake the one now in its slot
+            if state.generation != task_ref.generation || state.queued {
+                return false;
+            }
+            state.queued = true;
+            // Never allocates, there's room for every slot
+            ready.queue.push_back(slot);
+            true
+        })
+    }
+
+    fn next_ready() -> Option<TaskRef> {
+        critical_section::with(|cs| {
+            let ready = &mut *READY.borrow_ref_mut(cs);
+            let slot = ready.queue.pop_front()?;
+            let state = &mut ready.slots[slot];
+            state.queued = false;
+            Some(TaskRef::new(
+                MAIN_EXECUTOR,
+                FIRST_ID + slot,
+                state.generation,
+            ))
+        })
+    }
+
+    pub(super) fn has_pending() -> bool {
+        critical_section::with(|cs| !PENDING.borrow_ref(cs).0.is_empty())
+    }
+
+    // Spawns boxed tasks onto the running executor at runtime. Requires the `heap` to be
+    // initialized.
+    #[derive(Clone, Copy)]
+    pub struct Spawner {
+        // Tasks aren't Send, so the spawner must stay in thread mode
+        _not_send: PhantomData<*const ()>,
+    }
+
+    impl Spawner {
+        pub fn spawn(&self, task: impl Future<Output = ()> + 'static) {
+            let task: BoxedTask = Box::pin(task);
+            critical_section::with(|cs| PENDING.borrow_ref_mut(cs).0.push(task));
+        }
+    }
+
+    // Spawns tasks from interrupt handlers, or anywhere else. The task is only boxed and queued
+    // here, and the executor gives it a slot and polls it once back in thread mode, so a handler
+    // can start a task for each radio packet or pin event without doing the work itself.
+    //
+    //     static SPAWNER: InterruptSpawner = Executor::interrupt_spawner();
+    //     // In the interrupt handler
+    //     SPAWNER.spawn(handle_packet(packet));
+    //
+    // Boxing allocates, which takes a critical section, so it's best kept out of the highest
+    // priority interrupts.
+    #[derive(Clone, Copy)]
+    pub struct InterruptSpawner {
+        _private: (),
+    }
+
+    impl InterruptSpawner {
+        pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+            let task: BoxedTask = Box::pin(task);
+            critical_section::with(|cs| PENDING.borrow_ref_mut(cs).0.push(task));
+            // On the MCU, the executor wakes up anyway once the interrupt returns
+            super::notify_wake();
+        }
+    }
+
+    impl Executor {
+        pub const fn interrupt_spawner() -> InterruptSpawner {
+            InterruptSpawner { _private: () }
+        }
+
+        pub fn spawner() -> Spawner {
+            #[cfg(not(feature = "std"))]
+            assert!(
+                matches!(
+                    cortex_m::peripheral::SCB::vect_active(),
+                    cortex_m::peripheral::scb::VectActive::ThreadMode
+                ),
+                "The spawner can only be used from thread mode"
+            );
+            #[cfg(feature = "std")]
+            assert!(
+                EXECUTOR_THREAD.get() == Some(&std::thread::current().id()),
+                "The spawner can only be used from the executor's thread"
+            );
+            Spawner {
+                _not_send: PhantomData,
+            }
+        }
+    }
+
+    struct Slot {
+        task: Option<BoxedTask>,
+        generation: u8,
+    }
+
+    pub(super) struct SpawnedTasks {
+        slots: Vec<Slot>,
+    }
+
+    impl SpawnedTasks {
+        pub(super) const fn new() -> Self {
+            Self { slots: Vec::new() }
+        }
+
+        pub(super) fn adopt_pending(&mut self) {
+            let pending =
+                critical_section::with(|cs| core::mem::take(&mut PENDING.borrow_ref_mut(cs).0));
+            for task in pending {
+                // Reuse the slot of a finished task if there is one
+                let slot = match self.slots.iter().position(|slot| slot.task.is_none()) {
+                    Some(slot) => slot,
+                    None => {
+                        self.slots.push(Slot {
+                            task: None,
+                            generation: 0,
+                        });
+                        self.slots.len() - 1
+                    }
+                };
+                let generation = self.slots[slot].generation.wrapping_add(1);
+                self.slots[slot] = Slot {
+                    task: Some(task),
+                    generation,
+                };
+                critical_section::with(|cs| {
+                    let ready = &mut *READY.borrow_ref_mut(cs);
+                    if slot == ready.slots.len() {
+                        ready.slots.push(ReadySlot {
+                            generation,
+                            queued: false,
+                        });
+                        // Allocating here, in thread mode, is what lets `wake` never allocate
+                        ready.queue.reserve(ready.slots.len() - ready.queue.len());
+                    } else {
+                        ready.slots[slot].generation = generation;
+                    }
+                });
+                executor_trace!("Spawned task {}", FIRST_ID + slot);
+                Executor::wake(TaskRef::new(MAIN_EXECUTOR, FIRST_ID + slot, generation));
+            }
+        }
+
+        // Polls the next woken spawned task, returning false if none was
+        pub(super) fn poll_next(&mut self) -> bool {
+            let Some(task_ref) = next_ready() else {
+                return false;
+            };
+            super::poll_task(task_ref, |task_ref, cx| self.poll(task_ref, cx));
+            true
+        }
+
+        fn poll(&mut self, task_ref: TaskRef, cx: &mut Context<'_>) {
+            let slot = task_ref.id() - FIRST_ID;
+            // A finished task, or the one now in its slot, may still get woken by a stale waker
+            if let Some(Slot {
+                task: Some(task),
+                generation,
+            }) = self.slots.get_mut(slot)
+                && *generation == task_ref.generation
+                && task.as_mut().poll(cx).is_ready()
+            {
+                self.slots[slot].task = None;
+            }
+        }
     }
 }
 
@@ -50,18 +612,19 @@ static VTABLE: RawWakerVTable = RawWakerVTable::new(
 );
 
 impl WakerManager {
-    fn get_waker(task_id: usize) -> Waker {
-        unsafe { Waker::new(task_id as *const (), &VTABLE) }
+    // The task reference is stored in the data pointer itself, so wakers need no allocation
+    fn get_waker(task_ref: TaskRef) -> Waker {
+        unsafe { Waker::new(task_ref.pack() as *const (), &VTABLE) }
     }
 
     unsafe fn clone(p: *const ()) -> RawWaker {
         RawWaker::new(p, &VTABLE)
     }
     unsafe fn wake(p: *const ()) {
-        Executor::wake_task(p as usize);
+        Executor::wake(TaskRef::unpack(p as usize));
     }
     unsafe fn wake_by_ref(p: *const ()) {
-        Executor::wake_task(p as usize);
+        Executor::wake(TaskRef::unpack(p as usize));
     }
     const unsafe fn drop(_p: *const ()) {}
 }
@@ -0,0 +1,281 @@
+/*
+Keeps flash writes and erases from glitching everything else. While the NVMC writes or erases, the
+CPU stalls, so interrupts go unhandled and the display's row stays lit, which shows as a flicker.
+A word only takes about 40 µs to write, but a page takes 85 ms to erase.
+
+So flash work waits for a quiet window, which something that would glitch declares when a stall
+won't hurt it, like the display between frames with every row off. Writes go in chunks short
+enough to fit a window, and erases are split into partial erases of a couple of milliseconds, which
+add up to a whole one. Nothing waits forever though: without a window for a while, the work goes
+ahead anyway, so it still gets done when nothing declares windows.
+
+    static QUIET: QuietWindows = QuietWindows::new();
+    // In the display task
+    buffer.refresh_with_quiet_windows(&mut leds, &QUIET).await;
+    // Anywhere
+    let mut flash = ScheduledFlash::new(FlashRegions::new(nvmc).partial(region), &QUIET);
+    flash.erase(0, 4096).await?;
+*/
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+use crate::{
+    time::{TickDuration, TickInstant, Ticker, Timer, timeout},
+    utils::{LockCell, WaiterList},
+};
+
+// Flash users waiting for a window at once
+const MAX_WAITING: usize = 4;
+// Bytes written per window
+const WRITE_CHUNK: usize = 32;
+// Covers the 8 words of a chunk, with some to spare
+const WRITE_TIME: TickDuration = TickDuration::micros(500);
+
+// Flash that can erase a page a bit at a time
+pub trait PartialErase: NorFlash {
+    // How long each step stalls the CPU
+    const STEP_TIME: TickDuration;
+    // Steps to erase a whole page
+    const STEPS: u32;
+
+    fn erase_step(&mut self, page_offset: u32) -> Result<(), Self::Error>;
+}
+
+pub struct QuietWindows {
+    // The end of the window that's open, if one is
+    until: LockCell<Option<TickInstant>>,
+    // Flash operations waiting for a window
+    wanted: AtomicUsize,
+    waiting: WaiterList<MAX_WAITING>,
+}
+
+impl QuietWindows {
+    pub const fn new() -> Self {
+        Self {
+            until: LockCell::new(None),
+            wanted: AtomicUsize::new(0),
+            waiting: WaiterList::new(),
+        }
+    }
+
+    // Whether flash work is waiting, so there's any point opening a window
+    pub fn is_wanted(&self) -> bool {
+        self.wanted.load(Ordering::Acquire) > 0
+    }
+
+    // Opens a window for `length` and waits it out, while flash work runs. Returns straight away
+    // when nothing is waiting for one.
+    pub async fn quiet_for(&self, length: TickDuration) {
+        if !self.is_wanted() {
+            return;
+        }
+        let until = Ticker::now() + length;
+        self.until.with_lock(|cell| cell.set(Some(until)));
+        self.waiting.wake_all();
+        Timer::delay(length).await;
+        self.until.with_lock(|cell| cell.set(None));
+    }
+
+    // Waits until a window is open with at least `needed` of it left
+    pub async fn window(&self, needed: TickDuration) {
+        let _wanted = Wanted::new(&self.wanted);
+        poll_fn(|cx| {
+            let until = self.until.with_lock(|cell| cell.get());
+            if until.is_some_and(|until| Ticker::now() + needed <= until) {
+                return Poll::Ready(());
+            }
+            self.waiting.register(cx);
+            Poll::Pending
+        })
+        .await;
+    }
+}
+
+impl Default for QuietWindows {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Counts a waiting operation for as long as it waits, even if it's cancelled
+struct Wanted<'a>(&'a AtomicUsize);
+
+impl<'a> Wanted<'a> {
+    fn new(wanted: &'a AtomicUsize) -> Self {
+        wanted.fetch_add(1, Ordering::AcqRel);
+        Self(wanted)
+    }
+}
+
+impl Drop for Wanted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub struct ScheduledFlash<'a, F> {
+    flash: F,
+    quiet: &'a QuietWindows,
+    // How long to wait for a window before going ahead anyway
+    max_wait: TickDuration,
+}
+
+impl<'a, F: PartialErase + ReadNorFlash> ScheduledFlash<'a, F> {
+    pub fn new(flash: F, quiet: &'a QuietWindows) -> Self {
+        Self {
+            flash,
+            quiet,
+            max_wait: TickDuration::millis(1000),
+        }
+    }
+
+    pub fn with_max_wait(self, max_wait: TickDuration) -> Self {
+        Self { max_wait, ..self }
+    }
+
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+
+    // Reading doesn't stall, so it doesn't wait for a window
+    pub fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), F::Error> {
+        self.flash.read(offset, bytes)
+    }
+
+    // Writes a chunk per window. The offset and length have to be multiples of the write size.
+    pub async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), F::Error> {
+        let chunk = WRITE_CHUNK.next_multiple_of(F::WRITE_SIZE);
+        for (i, part) in bytes.chunks(chunk).enumerate() {
+            self.wait_for_window(WRITE_TIME).await;
+            self.flash.write(offset + (i * chunk) as u32, part)?;
+        }
+        Ok(())
+    }
+
+    // Erases each page a step per window. The range has to be whole pages.
+    pub async fn erase(&mut self, from: u32, to: u32) -> Result<(), F::Error> {
+        for page in (from..to).step_by(F::ERASE_SIZE) {
+            for _ in 0..F::STEPS {
+                self.wait_for_window(F::STEP_TIME).await;
+                self.flash.erase_step(page)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn wait_for_window(&self, needed: TickDuration) {
+        if timeout(self.max_wait, self.quiet.window(needed))
+            .await
+            .is_err()
+        {
+            debug!("No quiet window for flash, going ahead");
+        }
+    }
+}
+
+#[cfg(feature = "nrf52833")]
+pub use nrf::PartialNvmc;
+
+#[cfg(feature = "nrf52833")]
+mod nrf {
+    use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+    use nrf52833_hal::{
+        nvmc::{Nvmc, NvmcError as Error},
+        pac::NVMC,
+    };
+
+    use super::PartialErase;
+    use crate::time::TickDuration;
+
+    // Milliseconds each partial erase runs for
+    const STEP_MS: u32 = 2;
+    // The most a page erase can take, in milliseconds
+    const PAGE_ERASE_MS: u32 = 85;
+
+    // The HAL's NVMC driver, with partial erases added
+    pub struct PartialNvmc {
+        nvmc: Nvmc<NVMC>,
+        // Where the region starts in the address space
+        base: usize,
+    }
+
+    impl PartialNvmc {
+        // The region has to be page aligned, and kept out of the firmware by the linker
+        pub fn new(nvmc: NVMC, region: &'static mut [u8]) -> Self {
+            let base = region.as_ptr() as usize;
+            Self {
+                nvmc: Nvmc::new(nvmc, region),
+                base,
+            }
+        }
+
+        pub fn free(self) -> (NVMC, &'static mut [u8]) {
+            self.nvmc.free()
+        }
+    }
+
+    impl ErrorType for PartialNvmc {
+        type Error = Error;
+    }
+
+    impl ReadNorFlash for PartialNvmc {
+        const READ_SIZE: usize = <Nvmc<NVMC> as ReadNorFlash>::READ_SIZE;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+            self.nvmc.read(offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            self.nvmc.capacity()
+        }
+    }
+
+    impl NorFlash for PartialNvmc {
+        const WRITE_SIZE: usize = <Nvmc<NVMC> as NorFlash>::WRITE_SIZE;
+        const ERASE_SIZE: usize = <Nvmc<NVMC> as NorFlash>::ERASE_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+            self.nvmc.erase(from, to)
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+            self.nvmc.write(offset, bytes)
+        }
+    }
+
+    impl PartialErase for PartialNvmc {
+        const STEP_TIME: TickDuration = TickDuration::millis(STEP_MS as u64);
+        const STEPS: u32 = PAGE_ERASE_MS.div_ceil(STEP_MS);
+
+        fn erase_step(&mut self, page_offset: u32) -> Result<(), Error> {
+            if !(page_offset as usize).is_multiple_of(Self::ERASE_SIZE) {
+                return Err(Error::Unaligned);
+            }
+            if page_offset as usize >= self.capacity() {
+                return Err(Error::OutOfBounds);
+            }
+            // SAFETY: The driver owns the NVMC, and the page is inside its region
+            let nvmc = unsafe { &*NVMC::ptr() };
+            nvmc.config.write(|w| w.wen().een());
+            nvmc.erasepagepartialcfg
+                .write(|w| unsafe { w.duration().bits(STEP_MS as u8) });
+            nvmc.erasepagepartial
+                .write(|w| unsafe { w.bits((self.base + page_offset as usize) as u32) });
+            while nvmc.ready.read().ready().is_busy() {
+                core::hint::spin_loop();
+            }
+            nvmc.config.write(|w| w.wen().ren());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,43 @@
+/*
+Logging macros which forward to defmt or log, depending on which feature is enabled, and otherwise
+compile to nothing. Only plain `{}` placeholders should be used so the format strings work with
+both backends.
+*/
+#![macro_use]
+#![allow(unused_macros)]
+
+#[cfg(all(feature = "defmt", feature = "log"))]
+compile_error!("The `defmt` and `log` features are mutually exclusive");
+
+macro_rules! log_impl {
+    ($level:ident, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(feature = "defmt")]
+            ::defmt::$level!($s $(, $x)*);
+            #[cfg(feature = "log")]
+            ::log::$level!($s $(, $x)*);
+            #[cfg(not(any(feature = "defmt", feature = "log")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { log_impl!(trace, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_impl!(debug, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_impl!(info, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_impl!(warn, $($arg)*) };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { log_impl!(error, $($arg)*) };
+}
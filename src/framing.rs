@@ -0,0 +1,307 @@
+/*
+Framing for binary packets over a byte stream like the serial port, where nothing else says where
+one packet ends and the next begins.
+
+- COBS rewrites the packet so it has no zero bytes, and ends it with a zero, at a cost of one
+  byte in 254. A receiver that starts part way through a packet, or loses a byte, picks up again
+  at the next zero.
+- SLIP ends the packet with 0xC0, and escapes any 0xC0 or 0xDB inside it, which is cheaper for
+  most data but doubles the worst case. It's here for tools that already speak it.
+
+`FramedLink` sends COBS frames with a CRC-16 on the end over anything that implements `ByteLink`,
+and drops frames that arrive damaged, so each packet either gets through whole or not at all:
+
+    let mut link = FramedLink::new(serial);
+    link.send(&[1, 2, 3]).await?;
+    let packet = link.recv().await;
+*/
+
+use snafu::prelude::*;
+
+use crate::crc::crc16;
+
+// The most a framed link carries in a packet, so a frame with its CRC fits in one COBS block
+pub const MAX_PAYLOAD: usize = 252;
+const CRC_SIZE: usize = 2;
+// The packet and CRC, encoded, with the zero on the end
+const MAX_ENCODED: usize = cobs_max_encoded_len(MAX_PAYLOAD + CRC_SIZE) + 1;
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FramingError {
+    #[snafu(display("The packet doesn't fit in the buffer"))]
+    TooLong,
+    #[snafu(display("The frame isn't validly encoded"))]
+    Malformed,
+    #[snafu(display("The frame's CRC didn't match"))]
+    BadCrc,
+}
+
+// The most `data.len()` bytes can take COBS encoded, without the zero on the end
+pub const fn cobs_max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+// Encodes into `out` without the zero on the end, returning the encoded length
+pub fn cobs_encode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    ensure!(out.len() >= cobs_max_encoded_len(data.len()), TooLongSnafu);
+    // Where the code byte of the current block goes
+    let mut code_at = 0;
+    let mut len = 1;
+    let mut code = 1u8;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte != 0 {
+            out[len] = byte;
+            len += 1;
+            code += 1;
+        }
+        // A full block at the very end needs no empty one after it
+        if byte == 0 || (code == 0xFF && i + 1 < data.len()) {
+            out[code_at] = code;
+            code_at = len;
+            len += 1;
+            code = 1;
+        }
+    }
+    out[code_at] = code;
+    Ok(len)
+}
+
+// Decodes a frame without the zero on the end into `out`, returning the decoded length
+pub fn cobs_decode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    let mut len = 0;
+    let mut at = 0;
+    while at < data.len() {
+        let code = usize::from(data[at]);
+        ensure!(code != 0 && at + code <= data.len(), MalformedSnafu);
+        let block = &data[at + 1..at + code];
+        ensure!(!block.contains(&0), MalformedSnafu);
+        out.get_mut(len..len + block.len())
+            .context(TooLongSnafu)?
+            .copy_from_slice(block);
+        len += block.len();
+        at += code;
+        // A full block doesn't end in a zero, and neither does the last one
+        if code != 0xFF && at < data.len() {
+            *out.get_mut(len).context(TooLongSnafu)? = 0;
+            len += 1;
+        }
+    }
+    Ok(len)
+}
+
+// Encodes into `out` with an END at both ends, which flushes any noise from before the frame
+pub fn slip_encode(data: &[u8], out: &mut [u8]) -> Result<usize, FramingError> {
+    let mut len = 0;
+    let mut push = |byte| {
+        *out.get_mut(len).context(TooLongSnafu)? = byte;
+        len += 1;
+        Ok(())
+    };
+    push(SLIP_END)?;
+    for &byte in data {
+        match byte {
+            SLIP_END => {
+                push(SLIP_ESC)?;
+                push(SLIP_ESC_END)?;
+            }
+            SLIP_ESC => {
+                push(SLIP_ESC)?;
+                push(SLIP_ESC_ESC)?;
+            }
+            byte => push(byte)?,
+        }
+    }
+    push(SLIP_END)?;
+    Ok(len)
+}
+
+// Collects a COBS frame a byte at a time, as it arrives
+pub struct CobsDecoder<const N: usize> {
+    encoded: heapless::Vec<u8, N>,
+    // Set when the frame outgrew the buffer, so the rest of it is skipped
+    overflowed: bool,
+}
+
+impl<const N: usize> CobsDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            encoded: heapless::Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    // Returns the decoded frame once its zero arrives. Empty frames, like a zero sent to flush the
+    // line, are skipped.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<heapless::Vec<u8, N>, FramingError>> {
+        if byte != 0 {
+            if self.encoded.push(byte).is_err() {
+                self.overflowed = true;
+            }
+            return None;
+        }
+        let overflowed = core::mem::take(&mut self.overflowed);
+        let encoded = core::mem::take(&mut self.encoded);
+        if overflowed {
+            return Some(TooLongSnafu.fail());
+        }
+        if encoded.is_empty() {
+            return None;
+        }
+        let mut decoded = [0; N];
+        Some(cobs_decode(&encoded, &mut decoded).map(|len| {
+            // Can't fail, decoding never grows the frame
+            heapless::Vec::from_slice(&decoded[..len]).unwrap_or_default()
+        }))
+    }
+}
+
+impl<const N: usize> Default for CobsDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Collects a SLIP frame a byte at a time, as it arrives
+pub struct SlipDecoder<const N: usize> {
+    decoded: heapless::Vec<u8, N>,
+    escaped: bool,
+    // Set when the frame is broken, so the rest of it is skipped
+    error: Option<FramingError>,
+}
+
+impl<const N: usize> SlipDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            decoded: heapless::Vec::new(),
+            escaped: false,
+            error: None,
+        }
+    }
+
+    // Returns the decoded frame once its END arrives. Empty frames are skipped.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<heapless::Vec<u8, N>, FramingError>> {
+        if byte == SLIP_END {
+            self.escaped = false;
+            let decoded = core::mem::take(&mut self.decoded);
+            return match self.error.take() {
+                Some(err) => Some(Err(err)),
+                None if decoded.is_empty() => None,
+                None => Some(Ok(decoded)),
+            };
+        }
+        if self.error.is_some() {
+            return None;
+        }
+        let byte = match (core::mem::take(&mut self.escaped), byte) {
+            (false, SLIP_ESC) => {
+                self.escaped = true;
+                return None;
+            }
+            (false, byte) => byte,
+            (true, SLIP_ESC_END) => SLIP_END,
+            (true, SLIP_ESC_ESC) => SLIP_ESC,
+            (true, _) => {
+                self.error = Some(FramingError::Malformed);
+                return None;
+            }
+        };
+        if self.decoded.push(byte).is_err() {
+            self.error = Some(FramingError::TooLong);
+        }
+        None
+    }
+}
+
+impl<const N: usize> Default for SlipDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A byte stream to frame packets over, like the serial port
+pub trait ByteLink {
+    fn read_byte(&mut self) -> impl Future<Output = u8>;
+    fn write(&mut self, bytes: &[u8]) -> impl Future<Output = ()>;
+}
+
+#[cfg(feature = "serial")]
+impl ByteLink for crate::serial::Serial {
+    fn read_byte(&mut self) -> impl Future<Output = u8> {
+        crate::serial::Serial::read_byte(self)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> impl Future<Output = ()> {
+        crate::serial::Serial::write(self, bytes)
+    }
+}
+
+pub struct FramedLink<L> {
+    link: L,
+    decoder: CobsDecoder<MAX_ENCODED>,
+    // Frames dropped for being damaged
+    dropped: u32,
+}
+
+impl<L: ByteLink> FramedLink<L> {
+    pub fn new(link: L) -> Self {
+        Self {
+            link,
+            decoder: CobsDecoder::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn free(self) -> L {
+        self.link
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub async fn send(&mut self, payload: &[u8]) -> Result<(), FramingError> {
+        ensure!(payload.len() <= MAX_PAYLOAD, TooLongSnafu);
+        let mut frame = [0; MAX_PAYLOAD + CRC_SIZE];
+        frame[..payload.len()].copy_from_slice(payload);
+        frame[payload.len()..][..CRC_SIZE].copy_from_slice(&crc16(payload).to_le_bytes());
+        let mut encoded = [0; MAX_ENCODED];
+        let len = cobs_encode(&frame[..payload.len() + CRC_SIZE], &mut encoded)?;
+        encoded[len] = 0;
+        self.link.write(&encoded[..=len]).await;
+        Ok(())
+    }
+
+    // Waits for the next packet that arrives whole, dropping damaged ones
+    pub async fn recv(&mut self) -> heapless::Vec<u8, MAX_PAYLOAD> {
+        loop {
+            let byte = self.link.read_byte().await;
+            let Some(frame) = self.decoder.feed(byte) else {
+                continue;
+            };
+            match frame.and_then(check_crc) {
+                Ok(payload) => return payload,
+                Err(err) => {
+                    debug!("Dropping a frame: {}", err);
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+            }
+        }
+    }
+}
+
+fn check_crc(
+    frame: heapless::Vec<u8, MAX_ENCODED>,
+) -> Result<heapless::Vec<u8, MAX_PAYLOAD>, FramingError> {
+    let len = frame.len().checked_sub(CRC_SIZE).context(MalformedSnafu)?;
+    let (payload, crc) = frame.split_at(len);
+    ensure!(crc == crc16(payload).to_le_bytes(), BadCrcSnafu);
+    heapless::Vec::from_slice(payload)
+        .ok()
+        .context(TooLongSnafu)
+}
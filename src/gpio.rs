@@ -0,0 +1,116 @@
+use core::{future::poll_fn, task::Poll, task::Waker};
+
+use embedded_hal::digital::PinState;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+use {
+    crate::time::{Elapsed, TickDuration, TickInstant, timeout},
+    core::{pin::Pin, task::Context},
+    futures::Stream,
+};
+
+#[cfg(all(feature = "std", not(loom)))]
+mod mock;
+#[cfg(all(feature = "std", not(loom)))]
+pub use mock::MockPin;
+
+// A pin that can wake a task when its level changes. Implemented by each MCU backend (eg. GPIOTE on
+// the nRF52833), so the async GPIO layer is independent of the hardware.
+pub trait EdgeWait {
+    fn state(&mut self) -> PinState;
+
+    // Wake the waker on the next edge of the pin
+    fn register(&self, waker: &Waker);
+}
+
+pub struct InputChannel<E: EdgeWait> {
+    edge: E,
+}
+
+impl<E: EdgeWait> InputChannel<E> {
+    pub const fn from_edge(edge: E) -> Self {
+        Self { edge }
+    }
+
+    pub fn state(&mut self) -> PinState {
+        self.edge.state()
+    }
+
+    pub async fn wait_for(&mut self, ready_state: PinState) {
+        poll_fn(move |cx| {
+            if ready_state == self.edge.state() {
+                Poll::Ready(())
+            } else {
+                self.edge.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    #[cfg(any(feature = "nrf52833", feature = "std"))]
+    pub async fn wait_for_timeout(
+        &mut self,
+        ready_state: PinState,
+        duration: TickDuration,
+    ) -> Result<(), Elapsed> {
+        timeout(duration, self.wait_for(ready_state)).await
+    }
+}
+
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+impl<E: EdgeEvents> InputChannel<E> {
+    // Every change of level from here on, with the time it happened
+    pub fn into_stream(mut self) -> EdgeStream<E> {
+        self.edge.record_edges();
+        EdgeStream { edge: self.edge }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+// A pin whose backend queues each edge with its time as it happens, in the interrupt, so edges
+// aren't lost or stamped late while the task waits to run
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub trait EdgeEvents: EdgeWait {
+    // Starts queueing edges, dropping any queued before
+    fn record_edges(&mut self);
+
+    // The oldest queued edge
+    fn next_edge(&mut self) -> Option<(Edge, TickInstant)>;
+
+    // Edges dropped since recording started, because the queue was full
+    fn overflows(&self) -> u32;
+}
+
+// Edges come out in the order they happened, stamped by the interrupt, however late the task
+// polls. When the task falls so far behind that the queue fills, the edges that don't fit are
+// counted in `overflows`.
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub struct EdgeStream<E: EdgeEvents> {
+    edge: E,
+}
+
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+impl<E: EdgeEvents> EdgeStream<E> {
+    pub fn overflows(&self) -> u32 {
+        self.edge.overflows()
+    }
+}
+
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+impl<E: EdgeEvents + Unpin> Stream for EdgeStream<E> {
+    type Item = (Edge, TickInstant);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Register before checking, so that an edge in between isn't missed
+        self.edge.register(cx.waker());
+        self.edge
+            .next_edge()
+            .map_or(Poll::Pending, |edge| Poll::Ready(Some(edge)))
+    }
+}
@@ -0,0 +1,77 @@
+use core::{
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    task::Waker,
+};
+
+use embedded_hal::digital::PinState;
+
+use super::{Edge, EdgeEvents, EdgeWait};
+use crate::{
+    time::{TickInstant, Ticker},
+    utils::{AtomicWaker, mpmc::Queue},
+};
+
+// Edges queued for a stream, like the GPIOTE driver's
+const EDGE_QUEUE: usize = 16;
+
+// A pin whose level is driven by the simulation instead of hardware. Share it as a static and set
+// its state from another thread.
+pub struct MockPin {
+    high: AtomicBool,
+    waker: AtomicWaker,
+    recording: AtomicBool,
+    edges: Queue<(Edge, TickInstant), EDGE_QUEUE>,
+    overflows: AtomicU32,
+}
+
+impl MockPin {
+    pub const fn new(state: PinState) -> Self {
+        Self {
+            high: AtomicBool::new(matches!(state, PinState::High)),
+            waker: AtomicWaker::new(),
+            recording: AtomicBool::new(false),
+            edges: Queue::new(),
+            overflows: AtomicU32::new(0),
+        }
+    }
+
+    // Stands in for the interrupt, so a change of level is stamped with the ticker's time here
+    // rather than when a task gets round to it
+    pub fn set(&self, state: PinState) {
+        let high = matches!(state, PinState::High);
+        let was_high = self.high.swap(high, Ordering::Relaxed);
+        if high != was_high && self.recording.load(Ordering::Acquire) {
+            let edge = if high { Edge::Rising } else { Edge::Falling };
+            if self.edges.enqueue((edge, Ticker::now())).is_err() {
+                self.overflows.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.waker.wake();
+    }
+}
+
+impl EdgeWait for &MockPin {
+    fn state(&mut self) -> PinState {
+        PinState::from(self.high.load(Ordering::Relaxed))
+    }
+
+    fn register(&self, waker: &Waker) {
+        self.waker.register(waker);
+    }
+}
+
+impl EdgeEvents for &MockPin {
+    fn record_edges(&mut self) {
+        while self.edges.dequeue().is_some() {}
+        self.overflows.store(0, Ordering::Relaxed);
+        self.recording.store(true, Ordering::Release);
+    }
+
+    fn next_edge(&mut self) -> Option<(Edge, TickInstant)> {
+        self.edges.dequeue()
+    }
+
+    fn overflows(&self) -> u32 {
+        self.overflows.load(Ordering::Relaxed)
+    }
+}
@@ -1,106 +1,198 @@
+/*
+Input channel edges through GPIOTE. Each channel is claimed by one `GpioteEdge` with an atomic
+counter, and only that edge touches the channel's config, while the interrupt only touches the
+channels' events. So no lock is needed, and nothing masks interrupts to get at the registers.
+
+Once a channel's edges are streamed, the interrupt also stamps each one with the time and queues it
+on the channel's ring, so the stream sees every edge with the time it happened, however late the
+task gets to it.
+*/
+
 use core::{
-    future::poll_fn,
-    sync::atomic::{AtomicUsize, Ordering},
-    task::Poll,
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
 };
 
+use critical_section::Mutex;
+
 use embedded_hal::digital::{InputPin, PinState};
 use nrf52833_hal::{
     gpio::{Floating, Input, Pin},
-    gpiote::{Gpiote, GpioteChannel},
-    pac::{GPIOTE, Interrupt, NVIC, interrupt},
+    pac::{GPIOTE, Interrupt, NVIC, P0, P1, gpiote, interrupt},
+};
+use portable_atomic::{AtomicU32, AtomicUsize};
+
+use crate::{
+    channel::spsc::{Consumer, Producer, Spsc},
+    config::Priority,
+    gpio::{Edge, EdgeEvents, EdgeWait, InputChannel},
+    time::{TickInstant, Ticker},
+    utils::{InfallibleExt, WakerSlotArray},
 };
-
-use crate::utils::{AtomicWaker, InfallibleExt, LockMut};
 
 use snafu::prelude::*;
 
 type ChannelId = usize;
 
 #[derive(Snafu, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum GpioteError {
     #[snafu(display(
         "Too many InputChannels have been initialized, only {MAX_CHANNELS} are permitted."
     ))]
     OutOfChannels,
-}
-
-fn get_channel(gpiote: &Gpiote, channel: ChannelId) -> Result<GpioteChannel<'_>, GpioteError> {
-    Ok(match channel {
-        0 => gpiote.channel0(),
-        1 => gpiote.channel1(),
-        2 => gpiote.channel2(),
-        3 => gpiote.channel3(),
-        4 => gpiote.channel4(),
-        5 => gpiote.channel5(),
-        6 => gpiote.channel6(),
-        7 => gpiote.channel7(),
-        _ => return Err(GpioteError::OutOfChannels),
-    })
+    #[snafu(display("GpioteManager::init hasn't been called"))]
+    NotInitialized,
 }
 
 pub struct GpioteManager {}
 
 impl GpioteManager {
-    pub fn init(gpiote: GPIOTE) {
-        GPIOTE_MANAGER.init(Gpiote::new(gpiote));
+    // Takes GPIOTE so nothing else can use it. From then on its registers are shared out per
+    // channel.
+    pub fn init(_gpiote: GPIOTE, nvic: &mut NVIC, priority: Priority) {
+        // SAFETY: The interrupt isn't unmasked until the first channel is created
+        unsafe { nvic.set_priority(Interrupt::GPIOTE, priority.bits()) };
+        INITIALIZED.store(true, Ordering::Release);
     }
 }
 
-static GPIOTE_MANAGER: LockMut<Gpiote> = LockMut::new();
+fn gpiote() -> &'static gpiote::RegisterBlock {
+    // SAFETY: GpioteManager::init took GPIOTE, and each register is only used by its channel's
+    // edge or the interrupt, as described at the top
+    unsafe { &*GPIOTE::ptr() }
+}
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 const MAX_CHANNELS: usize = 8;
-static WAKE_TASKS: [AtomicWaker; MAX_CHANNELS] = [const { AtomicWaker::new() }; MAX_CHANNELS];
+static WAKE_TASKS: WakerSlotArray<MAX_CHANNELS> = WakerSlotArray::new();
 static NEXT_CHANNEL: AtomicUsize = AtomicUsize::new(0);
 
+// Edges queued per channel for a stream
+const EDGE_QUEUE: usize = 16;
+type EdgeEvent = (Edge, TickInstant);
+static EDGES: [Spsc<EdgeEvent, EDGE_QUEUE>; MAX_CHANNELS] = [const { Spsc::new() }; MAX_CHANNELS];
+// The interrupt's side of each channel's ring, once its edges are being recorded
+static PRODUCERS: [Mutex<RefCell<Option<Producer<'static, EdgeEvent, EDGE_QUEUE>>>>; MAX_CHANNELS] =
+    [const { Mutex::new(RefCell::new(None)) }; MAX_CHANNELS];
+static OVERFLOWS: [AtomicU32; MAX_CHANNELS] = [const { AtomicU32::new(0) }; MAX_CHANNELS];
+
 type InputChannelPin = Pin<Input<Floating>>;
 
 // Essentially registers an interrupt with a GPIO pin and a channel
 // When the pin transitions to the ready state, then an interrupt is fired
-pub struct InputChannel {
+pub struct GpioteEdge {
     pin: InputChannelPin,
     channel_id: ChannelId,
+    // The task's side of the channel's ring, once its edges are being recorded
+    edges: Option<Consumer<'static, EdgeEvent, EDGE_QUEUE>>,
 }
 
-impl InputChannel {
+impl GpioteEdge {
     pub fn new(pin: InputChannelPin) -> Result<Self, GpioteError> {
+        ensure!(INITIALIZED.load(Ordering::Acquire), NotInitializedSnafu);
         let channel_id = NEXT_CHANNEL.fetch_add(1, Ordering::Relaxed);
-        GPIOTE_MANAGER.with_lock(|gpiote| {
-            let channel = get_channel(gpiote, channel_id)?;
-            channel.input_pin(&pin).toggle().enable_interrupt();
-            unsafe { NVIC::unmask(Interrupt::GPIOTE) }
-            Ok(())
-        })?;
-        Ok(Self { pin, channel_id })
+        ensure!(channel_id < MAX_CHANNELS, OutOfChannelsSnafu);
+        let gpiote = gpiote();
+        // SAFETY: Only sets the pin number, which comes from a pin this edge owns
+        gpiote.config[channel_id].write(|w| {
+            unsafe { w.bits(pin.psel_bits() << 8) };
+            w.mode().event().polarity().toggle()
+        });
+        // SAFETY: Only sets this channel's bit
+        gpiote
+            .intenset
+            .write(|w| unsafe { w.bits(1 << channel_id) });
+        // SAFETY: The handler only uses the wakers and the events
+        unsafe { NVIC::unmask(Interrupt::GPIOTE) }
+        Ok(Self {
+            pin,
+            channel_id,
+            edges: None,
+        })
+    }
+}
+
+impl EdgeWait for GpioteEdge {
+    fn state(&mut self) -> PinState {
+        PinState::from(self.pin.is_high().unwrap_infallible())
     }
 
-    pub async fn wait_for(&mut self, ready_state: PinState) -> () {
-        poll_fn(move |cx| {
-            if ready_state == PinState::from(self.pin.is_high().unwrap_infallible()) {
-                Poll::Ready(())
-            } else {
-                critical_section::with(|cs| WAKE_TASKS[self.channel_id].register(cs, cx.waker()));
-                Poll::Pending
+    fn register(&self, waker: &Waker) {
+        WAKE_TASKS.register(self.channel_id, waker);
+    }
+}
+
+impl EdgeEvents for GpioteEdge {
+    fn record_edges(&mut self) {
+        let channel = self.channel_id;
+        match &mut self.edges {
+            Some(edges) => while edges.dequeue().is_some() {},
+            None => {
+                let ring = &EDGES[channel];
+                // Can't fail, the channel and so its ring belong to this edge alone
+                self.edges = ring.consumer();
+                let producer = ring.producer();
+                critical_section::with(|cs| *PRODUCERS[channel].borrow_ref_mut(cs) = producer);
             }
-        })
-        .await;
+        }
+        OVERFLOWS[channel].store(0, Ordering::Relaxed);
+    }
+
+    fn next_edge(&mut self) -> Option<EdgeEvent> {
+        self.edges.as_mut()?.dequeue()
+    }
+
+    fn overflows(&self) -> u32 {
+        OVERFLOWS[self.channel_id].load(Ordering::Relaxed)
+    }
+}
+
+impl InputChannel<GpioteEdge> {
+    pub fn new(pin: InputChannelPin) -> Result<Self, GpioteError> {
+        Ok(Self::from_edge(GpioteEdge::new(pin)?))
     }
 }
 
 #[interrupt]
 fn GPIOTE() {
-    GPIOTE_MANAGER.with_lock(handle_gpiote_interrupt);
+    let gpiote = gpiote();
+    for (channel, event) in gpiote.events_in.iter().enumerate() {
+        if event.read().bits() != 0 {
+            event.reset();
+            record(gpiote, channel);
+            WAKE_TASKS.wake(channel);
+        }
+    }
+    // Dummy read so the events are cleared before returning, or the interrupt fires again
+    let _ = gpiote.events_in[0].read();
 }
 
-fn handle_gpiote_interrupt(gpiote: &mut Gpiote) {
-    WAKE_TASKS
-        .iter()
-        .enumerate()
-        .filter(|(channel, _)| {
-            get_channel(gpiote, *channel).is_ok_and(|channel| channel.is_event_triggered())
-        })
-        .for_each(|(_, task)| task.wake());
-    gpiote.reset_events();
-    // Dummy read for clock cycles
-    let _ = gpiote.channel0().is_event_triggered();
+// Queues the edge with the time, if the channel's edges are being recorded. The channel toggles on
+// both edges, so which one it was comes from the pin's level now.
+fn record(gpiote: &gpiote::RegisterBlock, channel: ChannelId) {
+    critical_section::with(|cs| {
+        let mut producer = PRODUCERS[channel].borrow_ref_mut(cs);
+        let Some(producer) = producer.as_mut() else {
+            return;
+        };
+        let config = gpiote.config[channel].read();
+        let port = if config.port().bit_is_set() {
+            P1::ptr()
+        } else {
+            P0::ptr()
+        };
+        // SAFETY: Only reads the input levels
+        let levels = unsafe { (*port).in_.read().bits() };
+        let edge = if levels >> config.psel().bits() & 1 != 0 {
+            Edge::Rising
+        } else {
+            Edge::Falling
+        };
+        if producer.enqueue((edge, Ticker::now())).is_err() {
+            OVERFLOWS[channel].fetch_add(1, Ordering::Relaxed);
+        }
+    });
 }
@@ -0,0 +1,13 @@
+use core::mem::MaybeUninit;
+
+use embedded_alloc::LlffHeap as Heap;
+
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+// Hands the allocator its memory. Must be called once, before any task is spawned.
+pub fn init(memory: &'static mut [MaybeUninit<u8>]) {
+    // SAFETY: The memory is exclusively borrowed for the rest of the program, so it is valid and
+    // nothing else can touch it
+    unsafe { HEAP.init(memory.as_mut_ptr() as usize, memory.len()) }
+}
@@ -0,0 +1,169 @@
+/*
+HX711 24 bit ADC for load cells. The chip pulls DOUT low when a conversion is ready, which is
+waited for through GPIOTE, then the reading is clocked out MSB first with 24 pulses on PD_SCK, and
+one to three more pulses pick the channel and gain of the next conversion. The clock has to come
+back low within 50 us of each rising edge, or the chip powers down, so each pulse is sent with
+interrupts masked and timed with the cycle counter.
+
+    let mut scale = Hx711::new(InputChannel::new(dout)?, sck, &mut dcb, &mut dwt);
+    scale.tare(10).await?;
+    scale.set_scale(COUNTS_PER_GRAM);
+    let grams = scale.read_units().await?;
+*/
+
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::digital::{OutputPin, PinState};
+use nrf52833_hal::gpio::{Output, Pin, PushPull};
+use snafu::prelude::*;
+
+use crate::{
+    cycles::{self, delay_us},
+    gpio::{EdgeWait, InputChannel},
+    time::TickDuration,
+    utils::InfallibleExt,
+};
+
+// The chip converts at 10 or 80 Hz, depending on its RATE pin
+const READY_TIMEOUT: TickDuration = TickDuration::millis(200);
+const DATA_BITS: u32 = 24;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Hx711Error {
+    #[snafu(display("The HX711 didn't have a reading ready, it may not be connected"))]
+    NotReady,
+}
+
+// The input and gain for the next conversion. Channel A suits load cells, channel B is half as
+// sensitive and is fixed at a gain of 32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gain {
+    A128,
+    B32,
+    A64,
+}
+
+impl Gain {
+    fn extra_pulses(self) -> u32 {
+        match self {
+            Gain::A128 => 1,
+            Gain::B32 => 2,
+            Gain::A64 => 3,
+        }
+    }
+}
+
+pub struct Hx711<E: EdgeWait> {
+    data: InputChannel<E>,
+    clock: Pin<Output<PushPull>>,
+    gain: Gain,
+    // Raw reading with nothing on the scale
+    offset: i32,
+    // Raw counts per unit of weight
+    scale: i32,
+}
+
+impl<E: EdgeWait> Hx711<E> {
+    // Starts the cycle counter, which the clock pulses are timed with. The chip starts on channel
+    // A with a gain of 128.
+    pub fn new(
+        data: InputChannel<E>,
+        mut clock: Pin<Output<PushPull>>,
+        dcb: &mut DCB,
+        dwt: &mut DWT,
+    ) -> Self {
+        cycles::enable(dcb, dwt);
+        clock.set_low().unwrap_infallible();
+        Self {
+            data,
+            clock,
+            gain: Gain::A128,
+            offset: 0,
+            scale: 1,
+        }
+    }
+
+    // Takes effect from the conversion after the next read
+    pub fn set_gain(&mut self, gain: Gain) {
+        self.gain = gain;
+    }
+
+    // The signed raw reading
+    pub async fn read_raw(&mut self) -> Result<i32, Hx711Error> {
+        self.data
+            .wait_for_timeout(PinState::Low, READY_TIMEOUT)
+            .await
+            .ok()
+            .context(NotReadySnafu)?;
+        let mut raw = 0u32;
+        for _ in 0..DATA_BITS {
+            raw = raw << 1 | u32::from(self.pulse());
+        }
+        for _ in 0..self.gain.extra_pulses() {
+            self.pulse();
+        }
+        // Sign extend the 24 bits
+        Ok(((raw << 8) as i32) >> 8)
+    }
+
+    // Averages `samples` readings as the zero point, so take them with nothing on the scale
+    pub async fn tare(&mut self, samples: u8) -> Result<(), Hx711Error> {
+        self.offset = self.read_average(samples).await?;
+        debug!("HX711 tared at {}", self.offset);
+        Ok(())
+    }
+
+    // How many raw counts one unit (e.g. a gram) adds, found by weighing something known
+    pub fn set_scale(&mut self, counts_per_unit: i32) {
+        assert!(counts_per_unit != 0, "The scale can't be zero");
+        self.scale = counts_per_unit;
+    }
+
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    // The weight on the scale, rounded to whole units
+    pub async fn read_units(&mut self) -> Result<i32, Hx711Error> {
+        let counts = i64::from(self.read_raw().await?) - i64::from(self.offset);
+        // In half units, to round half away from zero
+        let halves = 2 * counts / i64::from(self.scale);
+        Ok(((halves + halves.signum()) / 2) as i32)
+    }
+
+    pub async fn read_average(&mut self, samples: u8) -> Result<i32, Hx711Error> {
+        let samples = samples.max(1);
+        let mut sum = 0i64;
+        for _ in 0..samples {
+            sum += i64::from(self.read_raw().await?);
+        }
+        Ok((sum / i64::from(samples)) as i32)
+    }
+
+    // Holding the clock high for over 60 us powers the chip down to under 1 uA
+    pub fn power_down(&mut self) {
+        self.clock.set_high().unwrap_infallible();
+        delay_us(60);
+    }
+
+    // The chip goes back to channel A with a gain of 128, and its first reading takes a while
+    // to settle
+    pub fn power_up(&mut self) {
+        self.clock.set_low().unwrap_infallible();
+        self.gain = Gain::A128;
+    }
+
+    // One clock pulse, returning the data bit, which is valid shortly after the rising edge
+    fn pulse(&mut self) -> bool {
+        let bit = critical_section::with(|_| {
+            self.clock.set_high().unwrap_infallible();
+            delay_us(1);
+            let bit = self.data.state() == PinState::High;
+            self.clock.set_low().unwrap_infallible();
+            bit
+        });
+        delay_us(1);
+        bit
+    }
+}
@@ -0,0 +1,115 @@
+/*
+Pictures for the 5x5 display, drawn as string art so icons and animation frames read as what they
+show. They're built at compile time, so they live in flash as consts, and a drawing with the wrong
+number of rows or columns fails the build rather than showing garbage:
+
+    const HEART: Image = Image::parse(
+        "01010
+         11111
+         11111
+         01110
+         00100",
+    );
+    display.show_image(HEART);
+
+Each row is a line of `0` (off) and `1` (on), from the top row down and the left column across.
+Spaces and tabs are ignored, so the lines can be indented to line up.
+*/
+
+pub const ROWS: usize = 5;
+pub const COLS: usize = 5;
+
+// One bit per column for each row, column 0 in the lowest bit, the same as the display's frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Image {
+    rows: [u8; ROWS],
+}
+
+impl Image {
+    pub const BLANK: Image = Image::from_rows([0; ROWS]);
+    pub const FULL: Image = Image::from_rows([0b11111; ROWS]);
+
+    // Columns past the last are dropped
+    pub const fn from_rows(rows: [u8; ROWS]) -> Self {
+        let mut masked = [0; ROWS];
+        let mut row = 0;
+        while row < ROWS {
+            masked[row] = rows[row] & 0b11111;
+            row += 1;
+        }
+        Self { rows: masked }
+    }
+
+    // Panics on anything but 5 rows of 5 `0`s and `1`s, which in a const is a build error
+    pub const fn parse(art: &str) -> Self {
+        let bytes = art.as_bytes();
+        let mut rows = [0; ROWS];
+        let (mut row, mut col) = (0, 0);
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b' ' | b'\t' | b'\r' => {}
+                b'\n' => {
+                    // Blank lines, like one after the opening quote, don't count as rows
+                    if col > 0 {
+                        assert!(col == COLS, "Each row of an image needs 5 columns");
+                        row += 1;
+                        col = 0;
+                    }
+                }
+                pixel @ (b'0' | b'1') => {
+                    assert!(row < ROWS, "An image needs 5 rows");
+                    assert!(col < COLS, "Each row of an image needs 5 columns");
+                    if pixel == b'1' {
+                        rows[row] |= 1 << col;
+                    }
+                    col += 1;
+                }
+                _ => panic!("Images are drawn with 0 and 1"),
+            }
+            i += 1;
+        }
+        if col > 0 {
+            assert!(col == COLS, "Each row of an image needs 5 columns");
+            row += 1;
+        }
+        assert!(row == ROWS, "An image needs 5 rows");
+        Self { rows }
+    }
+
+    pub const fn rows(&self) -> [u8; ROWS] {
+        self.rows
+    }
+
+    pub const fn is_on(&self, row: usize, col: usize) -> bool {
+        self.rows[row] & (1 << col) != 0
+    }
+
+    pub const fn inverted(&self) -> Self {
+        let mut rows = self.rows;
+        let mut row = 0;
+        while row < ROWS {
+            rows[row] = !rows[row] & 0b11111;
+            row += 1;
+        }
+        Self { rows }
+    }
+
+    // Left to right
+    pub const fn mirrored(&self) -> Self {
+        let mut rows = [0; ROWS];
+        let mut row = 0;
+        while row < ROWS {
+            rows[row] = self.rows[row].reverse_bits() >> (8 - COLS);
+            row += 1;
+        }
+        Self { rows }
+    }
+}
+
+impl From<Image> for [u8; ROWS] {
+    fn from(image: Image) -> Self {
+        image.rows
+    }
+}
@@ -0,0 +1,213 @@
+/*
+Powering down when nobody's using the device. Activity sources (the buttons, the radio, touch) are
+registered with the manager and touch their handle whenever something happens. After the idle
+period without any activity the device goes idle, when the display should dim and non-essential
+tasks pause, and after the sleep period it goes to sleep, which on the micro:bit is System OFF
+until a button is pressed. Any activity while idle makes it active again straight away.
+
+The manager only decides the state: `run` hands each change to a callback, which does the dimming
+or sleeping, and tasks that should pause while idle wait on `until_active`.
+
+    manager.run(|activity| match activity {
+        Activity::Active => display.set_brightness(MAX_BRIGHTNESS),
+        Activity::Idle => display.set_brightness(DIM),
+        Activity::Asleep => inactivity::sleep_until_button(&power, &buttons),
+    })
+*/
+
+use core::cell::{Cell, RefCell};
+
+use futures::{FutureExt, select_biased};
+use snafu::prelude::*;
+
+use crate::{
+    channel::watch::Watch,
+    time::{TickDuration, TickInstant, Ticker, Timer},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Activity {
+    Active,
+    Idle,
+    Asleep,
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InactivityError {
+    #[snafu(display("The inactivity manager can't track any more sources"))]
+    TooManySources,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InactivityConfig {
+    // Since the last activity
+    pub idle_after: TickDuration,
+    // Since the last activity too, so it should be longer than `idle_after`. Never sleeps if
+    // `None`.
+    pub sleep_after: Option<TickDuration>,
+}
+
+struct Source {
+    name: &'static str,
+    last_activity: TickInstant,
+}
+
+pub struct InactivityManager<const N: usize> {
+    config: InactivityConfig,
+    sources: RefCell<heapless::Vec<Source, N>>,
+    last_activity: Cell<TickInstant>,
+    state: Watch<Activity>,
+}
+
+impl<const N: usize> InactivityManager<N> {
+    pub fn new(config: InactivityConfig) -> Self {
+        Self {
+            config,
+            sources: RefCell::new(heapless::Vec::new()),
+            last_activity: Cell::new(Ticker::now()),
+            state: Watch::new_with(Activity::Active),
+        }
+    }
+
+    pub fn register(&self, name: &'static str) -> Result<ActivitySource<'_, N>, InactivityError> {
+        let mut sources = self.sources.borrow_mut();
+        let index = sources.len();
+        sources
+            .push(Source {
+                name,
+                last_activity: Ticker::now(),
+            })
+            .ok()
+            .context(TooManySourcesSnafu)?;
+        Ok(ActivitySource {
+            manager: self,
+            index,
+        })
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.state.get().unwrap_or(Activity::Active)
+    }
+
+    // The source with the most recent activity, if any have been registered
+    pub fn last_source(&self) -> Option<&'static str> {
+        self.sources
+            .borrow()
+            .iter()
+            .max_by_key(|source| source.last_activity)
+            .map(|source| source.name)
+    }
+
+    // Returns straight away while active, otherwise waits for some activity
+    pub async fn until_active(&self) {
+        let mut receiver = self.state.receiver();
+        while receiver.changed().await != Activity::Active {}
+    }
+
+    // Calls `on_change` with each new state, starting from active
+    pub async fn run(&self, mut on_change: impl FnMut(Activity)) -> ! {
+        let mut receiver = self.state.receiver();
+        let mut current = Activity::Active;
+        loop {
+            let activity = self.activity_at(Ticker::now());
+            if activity != current {
+                current = activity;
+                self.state.send(activity);
+                on_change(activity);
+            }
+            let deadline = match activity {
+                Activity::Active => Some(self.config.idle_after),
+                Activity::Idle => self.config.sleep_after,
+                Activity::Asleep => None,
+            };
+            let wait = async {
+                match deadline {
+                    Some(after) => {
+                        let elapsed = Ticker::now() - self.last_activity.get();
+                        Timer::delay(
+                            after
+                                .checked_sub(elapsed)
+                                .unwrap_or(TickDuration::from_ticks(0)),
+                        )
+                        .await;
+                    }
+                    None => core::future::pending().await,
+                }
+            };
+            // Activity while idle shows up as a change back to active
+            select_biased! {
+                _ = receiver.changed().fuse() => {},
+                () = wait.fuse() => {},
+            }
+        }
+    }
+
+    fn activity_at(&self, now: TickInstant) -> Activity {
+        let elapsed = now - self.last_activity.get();
+        match self.config.sleep_after {
+            Some(after) if elapsed >= after => Activity::Asleep,
+            _ if elapsed >= self.config.idle_after => Activity::Idle,
+            _ => Activity::Active,
+        }
+    }
+
+    fn touch(&self, index: usize) {
+        let now = Ticker::now();
+        self.last_activity.set(now);
+        let mut sources = self.sources.borrow_mut();
+        let Some(source) = sources.get_mut(index) else {
+            return;
+        };
+        source.last_activity = now;
+        let name = source.name;
+        self.state.send_modify(|state| {
+            let woke = *state != Some(Activity::Active);
+            if woke {
+                debug!("Activity from {}", name);
+                *state = Some(Activity::Active);
+            }
+            woke
+        });
+    }
+}
+
+// Held by whatever sees the activity, e.g. a button task
+pub struct ActivitySource<'a, const N: usize> {
+    manager: &'a InactivityManager<N>,
+    index: usize,
+}
+
+impl<const N: usize> ActivitySource<'_, N> {
+    pub fn touch(&self) {
+        self.manager.touch(self.index);
+    }
+}
+
+// Goes into System OFF until either button is pressed, which restarts the firmware from reset
+#[cfg(feature = "nrf52833")]
+pub fn sleep_until_button(power: &nrf52833_hal::pac::POWER, buttons: &crate::board::Buttons) -> ! {
+    // The buttons pull low when pressed
+    crate::board::sense_low(&buttons.left);
+    crate::board::sense_low(&buttons.right);
+    info!("Sleeping until a button is pressed");
+    power.systemoff.write(|w| w.systemoff().enter());
+    // Entering System OFF can take a moment, and under a debugger it's only emulated
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+// Whether the last reset was a wake from System OFF by a pin, like a button. Clears the flag, so
+// only the first call after the reset sees it.
+#[cfg(feature = "nrf52833")]
+pub fn woke_by_button(power: &nrf52833_hal::pac::POWER) -> bool {
+    let woke = power.resetreas.read().off().is_detected();
+    if woke {
+        // The flags are cleared by writing ones
+        power.resetreas.write(|w| w.off().set_bit());
+    }
+    woke
+}
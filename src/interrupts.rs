@@ -0,0 +1,140 @@
+/*
+Interrupt handlers that drivers set at runtime, so a driver can live in the library without taking
+the interrupt vector for itself. The binary keeps the vectors, and binds the ones its drivers use
+to the registry, whose table each driver fills in when it's initialised:
+
+    // In the binary
+    async_fluid::bind_interrupts!(SAADC, TIMER2);
+    // In the driver's init
+    interrupts::register(Interrupt::SAADC, on_saadc)?;
+
+An interrupt that fires with no handler registered is masked, so it can't keep firing.
+*/
+
+use portable_atomic::{AtomicPtr, Ordering};
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterruptError {
+    #[snafu(display("Interrupt {number} already has a handler"))]
+    AlreadyRegistered { number: u16 },
+    #[snafu(display("There's no interrupt {number}"))]
+    NoSuchInterrupt { number: u16 },
+}
+
+// A handler slot for each of N interrupts, by number
+pub struct HandlerTable<const N: usize> {
+    handlers: [AtomicPtr<()>; N],
+}
+
+impl<const N: usize> HandlerTable<N> {
+    pub const fn new() -> Self {
+        Self {
+            handlers: [const { AtomicPtr::new(core::ptr::null_mut()) }; N],
+        }
+    }
+
+    pub fn register(&self, number: u16, handler: fn()) -> Result<(), InterruptError> {
+        self.slot(number)?
+            .compare_exchange(
+                core::ptr::null_mut(),
+                handler as *mut (),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .ok()
+            .context(AlreadyRegisteredSnafu { number })?;
+        Ok(())
+    }
+
+    // Frees the slot for another driver, once the interrupt is masked
+    pub fn unregister(&self, number: u16) {
+        if let Ok(slot) = self.slot(number) {
+            slot.store(core::ptr::null_mut(), Ordering::Release);
+        }
+    }
+
+    pub fn is_registered(&self, number: u16) -> bool {
+        self.slot(number)
+            .is_ok_and(|slot| !slot.load(Ordering::Acquire).is_null())
+    }
+
+    // Calls the interrupt's handler, returning whether it had one
+    pub fn dispatch(&self, number: u16) -> bool {
+        let Ok(slot) = self.slot(number) else {
+            return false;
+        };
+        let handler = slot.load(Ordering::Acquire);
+        if handler.is_null() {
+            return false;
+        }
+        // SAFETY: Only ever set from a `fn()` in `register`
+        let handler = unsafe { core::mem::transmute::<*mut (), fn()>(handler) };
+        handler();
+        true
+    }
+
+    fn slot(&self, number: u16) -> Result<&AtomicPtr<()>, InterruptError> {
+        self.handlers
+            .get(usize::from(number))
+            .context(NoSuchInterruptSnafu { number })
+    }
+}
+
+impl<const N: usize> Default for HandlerTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "nrf52833")]
+pub use nrf::{dispatch, register, unregister};
+
+#[cfg(feature = "nrf52833")]
+mod nrf {
+    use cortex_m::interrupt::InterruptNumber;
+    use nrf52833_hal::pac::{Interrupt, NVIC};
+
+    use super::{HandlerTable, InterruptError};
+
+    // SPIM3 is the last of the nRF52833's interrupts
+    const INTERRUPTS: usize = 48;
+
+    static HANDLERS: HandlerTable<INTERRUPTS> = HandlerTable::new();
+
+    // The handler is called at whatever priority the interrupt has, which the driver sets
+    pub fn register(interrupt: Interrupt, handler: fn()) -> Result<(), InterruptError> {
+        HANDLERS.register(interrupt.number(), handler)
+    }
+
+    pub fn unregister(interrupt: Interrupt) {
+        NVIC::mask(interrupt);
+        HANDLERS.unregister(interrupt.number());
+    }
+
+    // Called from the vectors `bind_interrupts!` defines
+    pub fn dispatch(interrupt: Interrupt) {
+        if !HANDLERS.dispatch(interrupt.number()) {
+            warn!("Masking interrupt {} with no handler", interrupt.number());
+            NVIC::mask(interrupt);
+        }
+    }
+}
+
+// Defines the binary's vectors for the interrupts, each dispatching to the registry
+#[cfg(feature = "nrf52833")]
+#[macro_export]
+macro_rules! bind_interrupts {
+    ($($name:ident),+ $(,)?) => {
+        const _: () = {
+            use $crate::__pac::interrupt;
+            $(
+                #[interrupt]
+                fn $name() {
+                    $crate::interrupts::dispatch($crate::__pac::Interrupt::$name);
+                }
+            )+
+        };
+    };
+}
@@ -0,0 +1,219 @@
+/*
+Infrared remote control with the NEC protocol, decoded from the edges of a demodulating IR receiver
+(e.g. a TSOP38238 on an edge connector pin). The receiver's output is low while it sees the 38 kHz
+carrier, so a burst is a low pulse. A frame is a 9 ms burst, a 4.5 ms space, then 32 bits, each a
+562.5 us burst followed by a 562.5 us space for a 0 or a 1687.5 us space for a 1, and a final
+burst. The bits are the address, its inverse, the command and its inverse, least significant bit
+first. Holding a key sends repeat frames instead, a 9 ms burst and a 2.25 ms space, every 108 ms.
+
+The edges are timestamped in the interrupt, so the task can fall behind the receiver without the
+timings drifting. They're still checked with wide margins, since the ticks are about 30 us long.
+
+    let mut keys = NecReceiver::new(InputChannel::new(pin)?.into_stream());
+    while let Some(key) = keys.next().await { ... }
+*/
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{
+    gpio::Edge,
+    time::{TickDuration, TickInstant},
+};
+
+const LEADER_BURST: (TickDuration, TickDuration) =
+    (TickDuration::micros(7000), TickDuration::micros(11000));
+const LEADER_SPACE: (TickDuration, TickDuration) =
+    (TickDuration::micros(3500), TickDuration::micros(5500));
+const REPEAT_SPACE: (TickDuration, TickDuration) =
+    (TickDuration::micros(1700), TickDuration::micros(2800));
+const BIT_BURST: (TickDuration, TickDuration) =
+    (TickDuration::micros(250), TickDuration::micros(900));
+// Spaces shorter than the split are zeros, longer ones are ones
+const ZERO_SPACE: (TickDuration, TickDuration) =
+    (TickDuration::micros(250), TickDuration::micros(1125));
+const ONE_SPACE: (TickDuration, TickDuration) =
+    (TickDuration::micros(1125), TickDuration::micros(2300));
+// Repeats come every 108 ms, so a longer gap means the key was let go and pressed again
+const REPEAT_WINDOW: TickDuration = TickDuration::millis(150);
+const FRAME_BITS: u8 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NecKey {
+    // Eight bits, or sixteen for remotes using the extended protocol, where the second byte isn't
+    // the inverse of the first
+    pub address: u16,
+    pub command: u8,
+    // Whether this is the key being held, rather than a new press
+    pub repeat: bool,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    LeaderBurst,
+    LeaderSpace,
+    BitBurst { bits: u32, count: u8 },
+    BitSpace { bits: u32, count: u8 },
+}
+
+// Turns edges into keys. Anything that doesn't fit the timing drops the frame, and decoding starts
+// again at the next burst.
+pub struct NecDecoder {
+    state: State,
+    last_edge: Option<TickInstant>,
+    // The last key and when it (or a repeat of it) arrived, for repeat frames
+    last_key: Option<(NecKey, TickInstant)>,
+}
+
+impl NecDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            last_edge: None,
+            last_key: None,
+        }
+    }
+
+    // Takes the next edge of the receiver's output, and returns a key once a frame is complete
+    pub fn edge(&mut self, edge: Edge, at: TickInstant) -> Option<NecKey> {
+        let length = self
+            .last_edge
+            .and_then(|last| at.checked_duration_since(last))
+            .unwrap_or(TickDuration::from_ticks(0));
+        self.last_edge = Some(at);
+        let (state, key) = match (self.state, edge) {
+            (State::Idle, Edge::Falling) => (State::LeaderBurst, None),
+            (State::LeaderBurst, Edge::Rising) if within(length, LEADER_BURST) => {
+                (State::LeaderSpace, None)
+            }
+            (State::LeaderSpace, Edge::Falling) if within(length, LEADER_SPACE) => {
+                (State::BitBurst { bits: 0, count: 0 }, None)
+            }
+            (State::LeaderSpace, Edge::Falling) if within(length, REPEAT_SPACE) => {
+                (State::Idle, self.repeat(at))
+            }
+            (State::BitBurst { bits, count }, Edge::Rising) if within(length, BIT_BURST) => {
+                (State::BitSpace { bits, count }, None)
+            }
+            (State::BitSpace { bits, count }, Edge::Falling) => {
+                let bit = if within(length, ZERO_SPACE) {
+                    0
+                } else if within(length, ONE_SPACE) {
+                    1
+                } else {
+                    return self.restart(edge);
+                };
+                let bits = bits | bit << count;
+                let count = count + 1;
+                if count == FRAME_BITS {
+                    // This edge is the final burst, which needs no checking
+                    (State::Idle, self.frame(bits, at))
+                } else {
+                    (State::BitBurst { bits, count }, None)
+                }
+            }
+            _ => return self.restart(edge),
+        };
+        self.state = state;
+        key
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // Drops the frame. A falling edge may be the burst starting the next one.
+    fn restart(&mut self, edge: Edge) -> Option<NecKey> {
+        if !matches!(self.state, State::Idle) {
+            debug!("Dropping an IR frame with bad timing");
+        }
+        self.state = match edge {
+            Edge::Falling => State::LeaderBurst,
+            Edge::Rising => State::Idle,
+        };
+        None
+    }
+
+    fn frame(&mut self, bits: u32, at: TickInstant) -> Option<NecKey> {
+        let [address, address_inverse, command, command_inverse] = bits.to_le_bytes();
+        if command != !command_inverse {
+            debug!("Dropping an IR frame with a bad command checksum");
+            return None;
+        }
+        let address = if address == !address_inverse {
+            u16::from(address)
+        } else {
+            u16::from_le_bytes([address, address_inverse])
+        };
+        let key = NecKey {
+            address,
+            command,
+            repeat: false,
+        };
+        self.last_key = Some((key, at));
+        Some(key)
+    }
+
+    fn repeat(&mut self, at: TickInstant) -> Option<NecKey> {
+        let (key, last) = self.last_key?;
+        if at.checked_duration_since(last)? > REPEAT_WINDOW {
+            self.last_key = None;
+            return None;
+        }
+        self.last_key = Some((key, at));
+        Some(NecKey {
+            repeat: true,
+            ..key
+        })
+    }
+}
+
+impl Default for NecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn within(length: TickDuration, (min, max): (TickDuration, TickDuration)) -> bool {
+    min <= length && length < max
+}
+
+// The keys from a stream of timestamped edges, such as `InputChannel::into_stream`
+pub struct NecReceiver<S> {
+    edges: S,
+    decoder: NecDecoder,
+}
+
+impl<S: Stream<Item = (Edge, TickInstant)> + Unpin> NecReceiver<S> {
+    pub fn new(edges: S) -> Self {
+        Self {
+            edges,
+            decoder: NecDecoder::new(),
+        }
+    }
+}
+
+impl<S: Stream<Item = (Edge, TickInstant)> + Unpin> Stream for NecReceiver<S> {
+    type Item = NecKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<NecKey>> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.edges).poll_next(cx) {
+                Poll::Ready(Some((edge, at))) => {
+                    if let Some(key) = this.decoder.edge(edge, at) {
+                        return Poll::Ready(Some(key));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
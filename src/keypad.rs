@@ -0,0 +1,141 @@
+/*
+Matrix keypads, like the common 4x4 membrane ones, on edge connector pins. The rows are driven low
+one at a time and the columns, pulled up, read low where a key joins them to that row. The matrix
+is scanned every few milliseconds, and a key has to read the same for several scans in a row
+before its press or release counts, which rides out the contact bounce.
+
+Without diodes on the keys, holding three keys on the corners of a rectangle makes the fourth
+look pressed too, so only two keys at a time can be trusted.
+
+    let mut keys = pin!(keypad.into_stream());
+    while let Some(KeyEvent::Pressed(key)) = keys.next().await {
+        info!("{}", keypad::KEYS_4X4[key.row][key.col]);
+    }
+*/
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::{InputPin, OutputPin};
+use futures::Stream;
+
+use crate::{
+    time::{TickDuration, Timer},
+    utils::InfallibleExt,
+};
+
+const SCAN_PERIOD: TickDuration = TickDuration::millis(5);
+// How many scans a key has to read the same for, so 20 ms
+const DEBOUNCE_SCANS: u8 = 4;
+
+// The usual labels of a 4x4 keypad, by row and column
+pub const KEYS_4X4: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Key {
+    pub row: usize,
+    pub col: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyEvent {
+    Pressed(Key),
+    Released(Key),
+}
+
+pub struct Keypad<R, C, const ROWS: usize, const COLS: usize> {
+    rows: [R; ROWS],
+    cols: [C; COLS],
+    // The debounced state of each key
+    pressed: [[bool; COLS]; ROWS],
+    // How many scans in a row each key has read differently from its debounced state
+    changing: [[u8; COLS]; ROWS],
+}
+
+impl<R, C, const ROWS: usize, const COLS: usize> Keypad<R, C, ROWS, COLS>
+where
+    R: OutputPin<Error = Infallible>,
+    C: InputPin<Error = Infallible>,
+{
+    // The columns need pull-ups, either the pins' own or on the keypad
+    pub fn new(mut rows: [R; ROWS], cols: [C; COLS]) -> Self {
+        for row in &mut rows {
+            row.set_high().unwrap_infallible();
+        }
+        Self {
+            rows,
+            cols,
+            pressed: [[false; COLS]; ROWS],
+            changing: [[0; COLS]; ROWS],
+        }
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed[key.row][key.col]
+    }
+
+    // Scans until a key is pressed or released. When several change in the same scan, they come
+    // out one per call, in row then column order.
+    pub async fn next_event(&mut self) -> KeyEvent {
+        loop {
+            if let Some(event) = self.take_event() {
+                return event;
+            }
+            Timer::delay(SCAN_PERIOD).await;
+            self.scan();
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = KeyEvent> {
+        futures::stream::unfold(self, |mut keypad| async {
+            let event = keypad.next_event().await;
+            Some((event, keypad))
+        })
+    }
+
+    pub fn free(self) -> ([R; ROWS], [C; COLS]) {
+        (self.rows, self.cols)
+    }
+
+    fn scan(&mut self) {
+        for (r, row) in self.rows.iter_mut().enumerate() {
+            row.set_low().unwrap_infallible();
+            for (c, col) in self.cols.iter_mut().enumerate() {
+                let raw = col.is_low().unwrap_infallible();
+                let changing = &mut self.changing[r][c];
+                if raw == self.pressed[r][c] {
+                    *changing = 0;
+                } else {
+                    *changing = changing.saturating_add(1);
+                }
+            }
+            row.set_high().unwrap_infallible();
+        }
+    }
+
+    fn take_event(&mut self) -> Option<KeyEvent> {
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                if self.changing[row][col] < DEBOUNCE_SCANS {
+                    continue;
+                }
+                self.changing[row][col] = 0;
+                let pressed = !self.pressed[row][col];
+                self.pressed[row][col] = pressed;
+                let key = Key { row, col };
+                return Some(if pressed {
+                    KeyEvent::Pressed(key)
+                } else {
+                    KeyEvent::Released(key)
+                });
+            }
+        }
+        None
+    }
+}
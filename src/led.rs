@@ -1,15 +1,25 @@
+use core::{cell::Cell, pin::pin};
+
 use embedded_hal::digital::{OutputPin, PinState, StatefulOutputPin};
 use nrf52833_hal::gpio::{Output, Pin, PushPull};
 
-use crate::utils::InfallibleExt;
+use crate::{
+    channel::watch::Watch,
+    dsp::{Ema, Filter},
+    image::Image,
+    time::{PeriodicTimer, TickDuration, Ticker, Timer},
+    utils::{InfallibleExt, LockCell},
+};
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LedState {
     On,
     Off,
 }
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LedAxis {
     Col,
     Row,
@@ -56,6 +66,7 @@ impl LedMatrix {
 }
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Direction {
     Left,
     Right,
@@ -97,3 +108,235 @@ impl<'a> LedBlinker<'a> {
         self.leds.set(LedAxis::Row, self.row, LedState::On);
     }
 }
+
+// One bit per column for each row, column 0 in the lowest bit
+pub type Frame = [u8; LedMatrix::ROWS];
+
+// How long each row is lit while scanning, so the whole display refreshes at 100 Hz
+const ROW_TIME: TickDuration = TickDuration::micros(2000);
+// Long enough for a partial erase of a flash page, which stalls for 2 ms
+#[cfg(feature = "flash")]
+const QUIET_WINDOW: TickDuration = TickDuration::micros(2500);
+
+// Full brightness. Rows are dimmed by lighting them for part of their time, in whole ticks, so
+// only about 65 of the levels look different.
+pub const MAX_BRIGHTNESS: u8 = u8::MAX;
+
+// The brightness of the display, from 0 (dark) to `MAX_BRIGHTNESS`. Any task can send to it, like
+// the shell, a light sensor or a menu, without a handle to the display, and the scan picks the
+// change up from the next row.
+pub static BRIGHTNESS: Watch<u8> = Watch::new_with(MAX_BRIGHTNESS);
+
+// What the display shows, shared by every task that wants to draw on it. The matrix can only light
+// one row at a time, so `refresh` has to run as its own task, lighting each row in turn fast
+// enough that they all look lit.
+pub struct FrameBuffer {
+    frame: LockCell<Frame>,
+}
+
+impl FrameBuffer {
+    pub const fn new() -> Self {
+        Self {
+            frame: LockCell::new([0; LedMatrix::ROWS]),
+        }
+    }
+
+    pub fn handle(&self) -> DisplayHandle<'_> {
+        DisplayHandle { buffer: self }
+    }
+
+    pub async fn refresh(&self, leds: &mut LedMatrix) -> ! {
+        self.scan(leds, async || {}).await
+    }
+
+    // Like `refresh`, but opens a quiet window for flash work between frames, with every row off,
+    // whenever some is waiting
+    #[cfg(feature = "flash")]
+    pub async fn refresh_with_quiet_windows(
+        &self,
+        leds: &mut LedMatrix,
+        quiet: &crate::flash::QuietWindows,
+    ) -> ! {
+        self.scan(leds, async || quiet.quiet_for(QUIET_WINDOW).await)
+            .await
+    }
+
+    // Calls `between_frames` with every row off, after the last row of each frame
+    async fn scan(&self, leds: &mut LedMatrix, mut between_frames: impl AsyncFnMut()) -> ! {
+        for row in 0..LedMatrix::ROWS {
+            leds.set(LedAxis::Row, row, LedState::Off);
+        }
+        let mut timer = pin!(PeriodicTimer::new(ROW_TIME));
+        let mut brightness_changes = BRIGHTNESS.receiver();
+        let mut brightness = MAX_BRIGHTNESS;
+        // So the first row lit is row 0
+        let mut row = LedMatrix::ROWS - 1;
+        loop {
+            leds.set(LedAxis::Row, row, LedState::Off);
+            row = (row + 1) % LedMatrix::ROWS;
+            let bits = self.frame.with_lock(Cell::get)[row];
+            for col in 0..LedMatrix::COLS {
+                let state = if bits & (1 << col) == 0 {
+                    LedState::Off
+                } else {
+                    LedState::On
+                };
+                leds.set(LedAxis::Col, col, state);
+            }
+            if let Some(changed) = brightness_changes.try_changed() {
+                brightness = changed;
+            }
+            if brightness > 0 {
+                leds.set(LedAxis::Row, row, LedState::On);
+            }
+            if brightness < MAX_BRIGHTNESS {
+                let on_ticks = ROW_TIME.ticks() * u64::from(brightness) / u64::from(MAX_BRIGHTNESS);
+                if on_ticks > 0 {
+                    Timer::delay(TickDuration::from_ticks(on_ticks)).await;
+                }
+                leds.set(LedAxis::Row, row, LedState::Off);
+            }
+            timer.as_mut().tick().await;
+            if row == LedMatrix::ROWS - 1 {
+                leds.set(LedAxis::Row, row, LedState::Off);
+                let start = Ticker::now();
+                between_frames().await;
+                // Starts the schedule again after a pause, rather than skipping rows to catch up
+                if Ticker::now() - start >= ROW_TIME {
+                    timer.set(PeriodicTimer::new(ROW_TIME));
+                }
+            }
+        }
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cheap to copy into every task that draws. Changes show on the next refresh of the row.
+#[derive(Clone, Copy)]
+pub struct DisplayHandle<'a> {
+    buffer: &'a FrameBuffer,
+}
+
+impl DisplayHandle<'_> {
+    pub fn set(&self, row: usize, col: usize, state: LedState) {
+        check_bounds(row, col);
+        self.update(|frame| match state {
+            LedState::On => frame[row] |= 1 << col,
+            LedState::Off => frame[row] &= !(1 << col),
+        });
+    }
+
+    pub fn toggle(&self, row: usize, col: usize) {
+        check_bounds(row, col);
+        self.update(|frame| frame[row] ^= 1 << col);
+    }
+
+    pub fn is_on(&self, row: usize, col: usize) -> bool {
+        check_bounds(row, col);
+        self.frame()[row] & (1 << col) != 0
+    }
+
+    pub fn show(&self, frame: Frame) {
+        self.update(|current| *current = frame);
+    }
+
+    pub fn show_image(&self, image: Image) {
+        self.show(image.rows());
+    }
+
+    pub fn clear(&self) {
+        self.show([0; LedMatrix::ROWS]);
+    }
+
+    pub fn frame(&self) -> Frame {
+        self.buffer.frame.with_lock(Cell::get)
+    }
+
+    // Sends to `BRIGHTNESS`, so it's the same for every display handle
+    pub fn set_brightness(&self, brightness: u8) {
+        BRIGHTNESS.send(brightness);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        BRIGHTNESS.get().unwrap_or(MAX_BRIGHTNESS)
+    }
+
+    // Read, change and write back in one go, so tasks drawing different LEDs don't undo each other
+    pub fn update(&self, f: impl FnOnce(&mut Frame)) {
+        self.buffer.frame.with_lock(|cell| {
+            let mut frame = cell.get();
+            f(&mut frame);
+            cell.set(frame);
+        });
+    }
+}
+
+// Settings for `auto_brightness`
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoBrightness {
+    // Brightness in the dark, which should still be readable
+    pub min: u8,
+    // Brightness in full light
+    pub max: u8,
+    // How often the light is read
+    pub period: TickDuration,
+    // Smoothing of the readings as for `dsp::Ema`, so a passing shadow doesn't flicker the display
+    pub smoothing: u32,
+}
+
+impl AutoBrightness {
+    pub const fn new() -> Self {
+        Self {
+            min: 16,
+            max: MAX_BRIGHTNESS,
+            period: TickDuration::millis(250),
+            smoothing: 2,
+        }
+    }
+
+    // Scales the ambient light, from 0 (dark) to 255 (daylight), between `min` and `max`
+    pub fn brightness_for(&self, light: u8) -> u8 {
+        let (min, max) = (u32::from(self.min), u32::from(self.max.max(self.min)));
+        (min + (max - min) * u32::from(light) / u32::from(u8::MAX)) as u8
+    }
+}
+
+impl Default for AutoBrightness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Keeps the display bright enough to read outdoors without glaring indoors. `read_light` gives
+// the ambient light from 0 (dark) to 255 (daylight), e.g. from a light sensor on the edge
+// connector.
+pub async fn auto_brightness(
+    display: DisplayHandle<'_>,
+    config: AutoBrightness,
+    mut read_light: impl AsyncFnMut() -> u8,
+) -> ! {
+    let mut smoothed = Ema::new(config.smoothing);
+    let mut timer = pin!(PeriodicTimer::new(config.period));
+    loop {
+        let light = smoothed.update(i32::from(read_light().await)) as u8;
+        let brightness = config.brightness_for(light);
+        if brightness != display.brightness() {
+            trace!("Ambient light {}, brightness {}", light, brightness);
+            display.set_brightness(brightness);
+        }
+        timer.as_mut().tick().await;
+    }
+}
+
+fn check_bounds(row: usize, col: usize) {
+    assert!(
+        row < LedMatrix::ROWS && col < LedMatrix::COLS,
+        "LED ({row}, {col}) out of bounds"
+    );
+}
@@ -0,0 +1,130 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(feature = "nrf52833", feature = "std"))]
+compile_error!("The `nrf52833` and `std` features are mutually exclusive");
+
+// Must come first so the logging macros are visible to the other modules
+mod fmt;
+
+#[cfg(feature = "nrf52833")]
+pub mod adc;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod alarm;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "nrf52833")]
+pub mod board;
+#[cfg(feature = "nrf52833")]
+pub mod boot_mode;
+#[cfg(feature = "nrf52833")]
+pub mod bootloader;
+pub mod cancel;
+pub mod channel;
+#[cfg(feature = "nrf52833")]
+pub mod config;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod control;
+pub mod crc;
+#[cfg(feature = "nrf52833")]
+pub mod cycles;
+#[cfg(feature = "datalog")]
+pub mod datalog;
+#[cfg(feature = "deadline-monitor")]
+pub mod deadline;
+#[cfg(feature = "nrf52833")]
+pub mod device;
+pub mod dsp;
+mod error;
+pub use error::{Error, Result};
+// For the vectors `bind_interrupts!` defines in the binary
+#[cfg(feature = "nrf52833")]
+#[doc(hidden)]
+pub use nrf52833_hal::pac as __pac;
+pub mod event;
+#[cfg(all(feature = "flash", any(feature = "nrf52833", feature = "std")))]
+pub mod flash;
+pub mod framing;
+// Under loom only the concurrency primitives are built, since loom types can't be used in statics
+#[cfg(not(loom))]
+pub mod executor;
+pub mod gpio;
+#[cfg(feature = "nrf52833")]
+pub mod gpiote;
+#[cfg(feature = "nrf52833")]
+pub mod hx711;
+pub mod image;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod inactivity;
+pub mod interrupts;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod ir;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod keypad;
+// The host simulation uses the system allocator
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+pub mod heap;
+#[cfg(feature = "nrf52833")]
+pub mod led;
+pub mod lsm303agr;
+pub mod menu;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod morse;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod motor;
+#[cfg(feature = "nrf52833")]
+pub mod nfc;
+#[cfg(feature = "nrf52833")]
+pub mod notify;
+#[cfg(all(
+    feature = "nrf52833",
+    any(feature = "settings", feature = "datalog", feature = "flash")
+))]
+pub mod nvmc;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod odometry;
+#[cfg(feature = "nrf52833")]
+pub mod onewire;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod pipeline;
+#[cfg(feature = "plotter")]
+pub mod plotter;
+#[cfg(feature = "radio")]
+pub mod radio;
+#[cfg(feature = "ramlog")]
+pub mod ramlog;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod rate_limiter;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "nrf52833")]
+pub mod retention;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod scheduler;
+#[cfg(feature = "serial")]
+pub mod serial;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod servo;
+#[cfg(feature = "settings")]
+pub mod settings;
+#[cfg(feature = "shell")]
+pub mod shell;
+#[cfg(all(any(feature = "nrf52833", feature = "std"), not(loom)))]
+pub mod shutdown;
+#[cfg(feature = "stack")]
+pub mod stack;
+#[cfg(feature = "starvation-watchdog")]
+pub mod starvation;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod state_machine;
+#[cfg(feature = "nrf52833")]
+pub mod supervisor;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+#[cfg(feature = "nrf52833")]
+pub mod temperature;
+#[cfg(any(feature = "nrf52833", feature = "std"))]
+pub mod time;
+pub mod utils;
+// Under loom the queue can't be built in a const
+#[cfg(not(loom))]
+pub mod work_queue;
@@ -0,0 +1,573 @@
+/*
+LSM303AGR accelerometer and magnetometer, on the micro:bit's internal I2C bus. The accelerometer
+runs at 100 Hz in high resolution mode with a range of ±2 g, and the magnetometer continuously at
+10 Hz with temperature compensation. Readings are in mg and mGauss, with the calibration applied.
+
+The magnetometer picks up the board's own magnetic fields, which shift each axis (hard iron) and
+squash the sphere of readings into an ellipsoid (soft iron), so it's no use as a compass until
+calibrated by turning the board through every direction, the figure-eight users know from phones.
+The accelerometer only needs its offset measured while lying flat. The calibration can be kept in
+the settings store, so it only has to be done once:
+
+    let mut imu = Lsm303agr::new(i2c)?;
+    imu.load_or_calibrate(&mut settings, display).await?;
+
+For logging motion, the accelerometer's FIFO holds up to 32 readings, and raises its INT1 line once
+a watermark's worth have arrived, so they can be read in one I2C transfer per batch rather than
+one per reading, and the task only wakes a few times a second:
+
+    imu.enable_fifo(25)?;
+    loop {
+        let batch = imu.accel_batch(async || int1.wait_for(PinState::High).await).await?;
+        log(&batch.samples);
+    }
+*/
+
+use embedded_hal::i2c::I2c;
+use snafu::prelude::*;
+
+use crate::dsp::ONE;
+
+const ACCEL_ADDRESS: u8 = 0x19;
+const MAG_ADDRESS: u8 = 0x1E;
+
+const WHO_AM_I_A: u8 = 0x0F;
+const ACCEL_ID: u8 = 0x33;
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG3_A: u8 = 0x22;
+const CTRL_REG4_A: u8 = 0x23;
+const CTRL_REG5_A: u8 = 0x24;
+const OUT_X_L_A: u8 = 0x28;
+const FIFO_CTRL_REG_A: u8 = 0x2E;
+const FIFO_SRC_REG_A: u8 = 0x2F;
+// Set on the register address to read several accelerometer registers in one go
+const AUTO_INCREMENT: u8 = 0x80;
+
+const WHO_AM_I_M: u8 = 0x4F;
+const MAG_ID: u8 = 0x40;
+const CFG_REG_A_M: u8 = 0x60;
+const CFG_REG_C_M: u8 = 0x62;
+const OUTX_L_REG_M: u8 = 0x68;
+
+// 100 Hz with all three axes on
+const ACCEL_ODR_100HZ: u8 = 0x57;
+// Block data update, so the halves of a reading always match, and high resolution at ±2 g
+const ACCEL_BDU_HR: u8 = 0x88;
+// Temperature compensation, 10 Hz, continuous
+const MAG_CONTINUOUS: u8 = 0x80;
+const MAG_BDU: u8 = 0x10;
+// The FIFO watermark on INT1
+const I1_WTM: u8 = 0x04;
+const FIFO_EN: u8 = 0x40;
+// Stream mode, where the oldest reading is dropped when the FIFO is full
+const FIFO_STREAM: u8 = 0x80;
+const FIFO_BYPASS: u8 = 0x00;
+const FIFO_WTM: u8 = 0x80;
+const FIFO_OVERRUN: u8 = 0x40;
+const FIFO_UNREAD: u8 = 0x1F;
+
+// Readings the accelerometer's FIFO holds
+pub const FIFO_DEPTH: usize = 32;
+
+// An axis has to swing at least this far while calibrating, in mGauss. The earth's field is about
+// 500 mGauss, so turning through every direction swings each axis by twice that.
+const MIN_SWING: i32 = 400;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Lsm303Error {
+    #[snafu(display("The I2C transfer failed"))]
+    Bus,
+    #[snafu(display("The device on the bus isn't an LSM303AGR"))]
+    WrongDevice,
+    #[snafu(display("The board wasn't turned far enough to calibrate the magnetometer"))]
+    NotEnoughMovement,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Vector {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Vector {
+    fn axes(self) -> [i32; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    fn from_axes([x, y, z]: [i32; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+// Accelerometer readings from the FIFO, oldest first, with the calibration applied
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FifoBatch {
+    pub samples: heapless::Vec<Vector, FIFO_DEPTH>,
+    // The FIFO filled up before it was read, so the readings before these were lost
+    pub overrun: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    // Subtracted from the readings, in mg
+    pub accel_offset: [i16; 3],
+    // Subtracted from the readings, in mGauss
+    pub mag_offset: [i16; 3],
+    // Multiplies the readings once offset, in Q14
+    pub mag_scale: [i16; 3],
+}
+
+impl Calibration {
+    pub const SIZE: usize = 18;
+
+    // No correction at all
+    pub const fn new() -> Self {
+        Self {
+            accel_offset: [0; 3],
+            mag_offset: [0; 3],
+            mag_scale: [ONE as i16; 3],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let values = self
+            .accel_offset
+            .iter()
+            .chain(&self.mag_offset)
+            .chain(&self.mag_scale);
+        for (chunk, value) in bytes.chunks_exact_mut(2).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]));
+        let mut next = || core::array::from_fn(|_| values.next().unwrap_or_default());
+        Self {
+            accel_offset: next(),
+            mag_offset: next(),
+            mag_scale: next(),
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Finds the centre and the extent of each axis of the magnetometer from readings taken while the
+// board is turned through every direction
+#[derive(Clone, Debug)]
+pub struct MagCalibrator {
+    min: [i32; 3],
+    max: [i32; 3],
+}
+
+impl MagCalibrator {
+    pub const fn new() -> Self {
+        Self {
+            min: [i32::MAX; 3],
+            max: [i32::MIN; 3],
+        }
+    }
+
+    pub fn add(&mut self, raw: Vector) {
+        for (axis, value) in raw.axes().into_iter().enumerate() {
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+    }
+
+    // How far the axis that has moved least has swung, in mGauss
+    pub fn swing(&self) -> i32 {
+        (0..3)
+            .map(|axis| self.max[axis].saturating_sub(self.min[axis]))
+            .min()
+            .unwrap_or(0)
+            .max(0)
+    }
+
+    // The offsets and scales, once every axis has swung far enough
+    pub fn finish(&self) -> Result<([i16; 3], [i16; 3]), Lsm303Error> {
+        ensure!(self.swing() >= MIN_SWING, NotEnoughMovementSnafu);
+        let offset = core::array::from_fn(|axis| ((self.min[axis] + self.max[axis]) / 2) as i16);
+        let radius: [i32; 3] = core::array::from_fn(|axis| (self.max[axis] - self.min[axis]) / 2);
+        let mean = radius.iter().sum::<i32>() / 3;
+        // Stretches the short axes and squashes the long ones, so the ellipsoid becomes a sphere
+        let scale = core::array::from_fn(|axis| (mean * ONE / radius[axis]) as i16);
+        Ok((offset, scale))
+    }
+}
+
+impl Default for MagCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Lsm303agr<I> {
+    i2c: I,
+    calibration: Calibration,
+}
+
+impl<I: I2c> Lsm303agr<I> {
+    // Checks both halves of the chip are there, and starts them measuring
+    pub fn new(i2c: I) -> Result<Self, Lsm303Error> {
+        let mut imu = Self {
+            i2c,
+            calibration: Calibration::new(),
+        };
+        ensure!(
+            imu.read_register(ACCEL_ADDRESS, WHO_AM_I_A)? == ACCEL_ID
+                && imu.read_register(MAG_ADDRESS, WHO_AM_I_M)? == MAG_ID,
+            WrongDeviceSnafu
+        );
+        imu.write_register(ACCEL_ADDRESS, CTRL_REG1_A, ACCEL_ODR_100HZ)?;
+        imu.write_register(ACCEL_ADDRESS, CTRL_REG4_A, ACCEL_BDU_HR)?;
+        imu.write_register(MAG_ADDRESS, CFG_REG_A_M, MAG_CONTINUOUS)?;
+        imu.write_register(MAG_ADDRESS, CFG_REG_C_M, MAG_BDU)?;
+        Ok(imu)
+    }
+
+    pub fn free(self) -> I {
+        self.i2c
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn accel(&mut self) -> Result<Vector, Lsm303Error> {
+        let raw = self.accel_raw()?;
+        Ok(self.calibrate_accel(raw))
+    }
+
+    pub fn mag(&mut self) -> Result<Vector, Lsm303Error> {
+        let raw = self.mag_raw()?.axes();
+        let Calibration {
+            mag_offset,
+            mag_scale,
+            ..
+        } = self.calibration;
+        Ok(Vector::from_axes(core::array::from_fn(|axis| {
+            (raw[axis] - i32::from(mag_offset[axis])) * i32::from(mag_scale[axis]) / ONE
+        })))
+    }
+
+    // Without the calibration, in mg
+    pub fn accel_raw(&mut self) -> Result<Vector, Lsm303Error> {
+        let axes = self.read_axes(ACCEL_ADDRESS, OUT_X_L_A | AUTO_INCREMENT)?;
+        Ok(accel_from_raw(axes))
+    }
+
+    // Without the calibration, in mGauss
+    pub fn mag_raw(&mut self) -> Result<Vector, Lsm303Error> {
+        let axes = self.read_axes(MAG_ADDRESS, OUTX_L_REG_M)?;
+        // 1.5 mGauss each
+        Ok(Vector::from_axes(
+            axes.map(|value| i32::from(value) * 3 / 2),
+        ))
+    }
+
+    // Collects readings in the FIFO, raising INT1 once `watermark` of them are waiting. Readings
+    // already in it are dropped. Panics unless the watermark is from 1 to 31.
+    pub fn enable_fifo(&mut self, watermark: u8) -> Result<(), Lsm303Error> {
+        assert!(
+            (1..FIFO_DEPTH as u8).contains(&watermark),
+            "The FIFO watermark has to be from 1 to {}",
+            FIFO_DEPTH - 1
+        );
+        // Going through bypass mode empties it
+        self.write_register(ACCEL_ADDRESS, FIFO_CTRL_REG_A, FIFO_BYPASS)?;
+        self.write_register(ACCEL_ADDRESS, CTRL_REG5_A, FIFO_EN)?;
+        self.write_register(ACCEL_ADDRESS, FIFO_CTRL_REG_A, FIFO_STREAM | watermark)?;
+        self.write_register(ACCEL_ADDRESS, CTRL_REG3_A, I1_WTM)
+    }
+
+    pub fn disable_fifo(&mut self) -> Result<(), Lsm303Error> {
+        self.write_register(ACCEL_ADDRESS, CTRL_REG3_A, 0)?;
+        self.write_register(ACCEL_ADDRESS, FIFO_CTRL_REG_A, FIFO_BYPASS)?;
+        self.write_register(ACCEL_ADDRESS, CTRL_REG5_A, 0)
+    }
+
+    // Waits for the watermark, with `wait_for_int1` waiting for the INT1 line to go high, then
+    // reads every reading in the FIFO. When the watermark was already reached, it doesn't wait.
+    pub async fn accel_batch(
+        &mut self,
+        mut wait_for_int1: impl AsyncFnMut(),
+    ) -> Result<FifoBatch, Lsm303Error> {
+        let mut status = self.read_register(ACCEL_ADDRESS, FIFO_SRC_REG_A)?;
+        if status & FIFO_WTM == 0 {
+            wait_for_int1().await;
+            status = self.read_register(ACCEL_ADDRESS, FIFO_SRC_REG_A)?;
+        }
+        self.read_fifo_samples(status)
+    }
+
+    // Reads whatever's in the FIFO, which may be nothing, in one transfer
+    pub fn read_fifo(&mut self) -> Result<FifoBatch, Lsm303Error> {
+        let status = self.read_register(ACCEL_ADDRESS, FIFO_SRC_REG_A)?;
+        self.read_fifo_samples(status)
+    }
+
+    // The readings `status`, from FIFO_SRC_REG_A, says are waiting
+    fn read_fifo_samples(&mut self, status: u8) -> Result<FifoBatch, Lsm303Error> {
+        let overrun = status & FIFO_OVERRUN != 0;
+        // The count only goes to 31, and a full FIFO is flagged as overrun instead
+        let unread = if overrun {
+            FIFO_DEPTH
+        } else {
+            usize::from(status & FIFO_UNREAD)
+        };
+        if overrun {
+            warn!("Accelerometer FIFO overran, readings were lost");
+        }
+        if unread == 0 {
+            return Ok(FifoBatch::default());
+        }
+        let mut bytes = [0; FIFO_DEPTH * 6];
+        let bytes = &mut bytes[..unread * 6];
+        // With the FIFO on, the address wraps back to X after Z, so each reading follows the last
+        self.i2c
+            .write_read(ACCEL_ADDRESS, &[OUT_X_L_A | AUTO_INCREMENT], bytes)
+            .ok()
+            .context(BusSnafu)?;
+        let samples = bytes
+            .chunks_exact(6)
+            .map(|sample| {
+                let raw = accel_from_raw(core::array::from_fn(|axis| {
+                    i16::from_le_bytes([sample[2 * axis], sample[2 * axis + 1]])
+                }));
+                self.calibrate_accel(raw)
+            })
+            .collect();
+        Ok(FifoBatch { samples, overrun })
+    }
+
+    fn calibrate_accel(&self, raw: Vector) -> Vector {
+        let raw = raw.axes();
+        let offset = self.calibration.accel_offset;
+        Vector::from_axes(core::array::from_fn(|axis| {
+            raw[axis] - i32::from(offset[axis])
+        }))
+    }
+
+    fn read_axes(&mut self, address: u8, register: u8) -> Result<[i16; 3], Lsm303Error> {
+        let mut bytes = [0; 6];
+        self.i2c
+            .write_read(address, &[register], &mut bytes)
+            .ok()
+            .context(BusSnafu)?;
+        Ok(core::array::from_fn(|axis| {
+            i16::from_le_bytes([bytes[2 * axis], bytes[2 * axis + 1]])
+        }))
+    }
+
+    fn read_register(&mut self, address: u8, register: u8) -> Result<u8, Lsm303Error> {
+        let mut value = [0];
+        self.i2c
+            .write_read(address, &[register], &mut value)
+            .ok()
+            .context(BusSnafu)?;
+        Ok(value[0])
+    }
+
+    fn write_register(&mut self, address: u8, register: u8, value: u8) -> Result<(), Lsm303Error> {
+        self.i2c
+            .write(address, &[register, value])
+            .ok()
+            .context(BusSnafu)
+    }
+}
+
+// 12 bits, left justified, at about 1 mg each
+fn accel_from_raw(axes: [i16; 3]) -> Vector {
+    Vector::from_axes(axes.map(|value| i32::from(value >> 4)))
+}
+
+#[cfg(feature = "settings")]
+pub const CALIBRATION_KEY: u16 = 0x0100;
+
+#[cfg(feature = "settings")]
+impl Calibration {
+    // `None` if it was never saved
+    pub fn load<F>(
+        settings: &mut crate::settings::Settings<F>,
+    ) -> Result<Option<Self>, crate::settings::SettingsError>
+    where
+        F: embedded_storage::nor_flash::NorFlash,
+    {
+        let mut bytes = [0; Self::SIZE];
+        Ok(match settings.read(CALIBRATION_KEY, &mut bytes)? {
+            Some(Self::SIZE) => Some(Self::from_bytes(&bytes)),
+            Some(_) => {
+                warn!("Stored calibration has the wrong size, ignoring it");
+                None
+            }
+            None => None,
+        })
+    }
+
+    pub fn save<F>(
+        &self,
+        settings: &mut crate::settings::Settings<F>,
+    ) -> Result<(), crate::settings::SettingsError>
+    where
+        F: embedded_storage::nor_flash::NorFlash,
+    {
+        settings.write(CALIBRATION_KEY, &self.to_bytes())
+    }
+}
+
+#[cfg(feature = "nrf52833")]
+mod guided {
+    use core::pin::pin;
+
+    use embedded_hal::i2c::I2c;
+
+    use super::{Calibration, Lsm303Error, Lsm303agr, MagCalibrator, Vector};
+    use crate::{
+        image::Image,
+        led::DisplayHandle,
+        time::{PeriodicTimer, TickDuration, Timer},
+    };
+
+    const SETTLE: TickDuration = TickDuration::millis(2000);
+    const ACCEL_SAMPLES: i32 = 32;
+    const ACCEL_PERIOD: TickDuration = TickDuration::millis(10);
+    // The magnetometer's rate
+    const MAG_PERIOD: TickDuration = TickDuration::millis(100);
+    const MAG_DURATION: TickDuration = TickDuration::millis(20_000);
+    const ONE_G: i32 = 1000;
+
+    const FLAT: Image = Image::parse(
+        "00000
+         00000
+         00000
+         00000
+         11111",
+    );
+    const FIGURE_EIGHT: Image = Image::parse(
+        "01110
+         01010
+         00100
+         01010
+         01110",
+    );
+    const DONE: Image = Image::parse(
+        "00000
+         00001
+         00010
+         10100
+         01000",
+    );
+    // Around the figure eight as (row, column), crossing in the middle
+    const PATH: [(usize, usize); 12] = [
+        (2, 2),
+        (1, 3),
+        (0, 3),
+        (0, 2),
+        (0, 1),
+        (1, 1),
+        (2, 2),
+        (3, 3),
+        (4, 3),
+        (4, 2),
+        (4, 1),
+        (3, 1),
+    ];
+
+    impl<I: I2c> Lsm303agr<I> {
+        // Prompts on the display to lay the board flat, then to draw figure eights with it, and
+        // applies the result. The display's previous contents are lost.
+        pub async fn calibrate(
+            &mut self,
+            display: DisplayHandle<'_>,
+        ) -> Result<Calibration, Lsm303Error> {
+            info!("Calibrating, lay the board flat");
+            display.show_image(FLAT);
+            Timer::delay(SETTLE).await;
+            let accel_offset = self.accel_offset().await?;
+
+            info!("Draw figure eights with the board");
+            let mut calibrator = MagCalibrator::new();
+            let mut timer = pin!(PeriodicTimer::new(MAG_PERIOD));
+            let samples = MAG_DURATION.ticks() / MAG_PERIOD.ticks();
+            for (sample, &(row, col)) in (0..samples).zip(PATH.iter().cycle()) {
+                // A gap runs around the eight, to show which way to go
+                let mut frame = FIGURE_EIGHT.rows();
+                frame[row] &= !(1 << col);
+                display.show(frame);
+                timer.as_mut().tick().await;
+                calibrator.add(self.mag_raw()?);
+                if sample % 10 == 0 {
+                    debug!("Magnetometer swing {} mGauss", calibrator.swing());
+                }
+            }
+            display.clear();
+            let (mag_offset, mag_scale) = calibrator.finish()?;
+            let calibration = Calibration {
+                accel_offset,
+                mag_offset,
+                mag_scale,
+            };
+            self.set_calibration(calibration);
+            display.show_image(DONE);
+            info!("Calibrated");
+            Ok(calibration)
+        }
+
+        // The average reading lying flat, less gravity on whichever way up the board is
+        async fn accel_offset(&mut self) -> Result<[i16; 3], Lsm303Error> {
+            let mut sum = Vector::default();
+            let mut timer = pin!(PeriodicTimer::new(ACCEL_PERIOD));
+            for _ in 0..ACCEL_SAMPLES {
+                timer.as_mut().tick().await;
+                let raw = self.accel_raw()?;
+                sum.x += raw.x;
+                sum.y += raw.y;
+                sum.z += raw.z;
+            }
+            let z = sum.z / ACCEL_SAMPLES;
+            let gravity = if z < 0 { -ONE_G } else { ONE_G };
+            Ok([
+                (sum.x / ACCEL_SAMPLES) as i16,
+                (sum.y / ACCEL_SAMPLES) as i16,
+                (z - gravity) as i16,
+            ])
+        }
+
+        // Uses the stored calibration if there is one, and otherwise calibrates and stores it
+        #[cfg(feature = "settings")]
+        pub async fn load_or_calibrate<F>(
+            &mut self,
+            settings: &mut crate::settings::Settings<F>,
+            display: DisplayHandle<'_>,
+        ) -> crate::Result<Calibration>
+        where
+            F: embedded_storage::nor_flash::NorFlash,
+        {
+            if let Some(calibration) = Calibration::load(settings)? {
+                self.set_calibration(calibration);
+                return Ok(calibration);
+            }
+            let calibration = self.calibrate(display).await?;
+            calibration.save(settings)?;
+            Ok(calibration)
+        }
+    }
+}
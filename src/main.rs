@@ -3,54 +3,72 @@
 
 use core::{
     panic::PanicInfo,
-    pin::pin,
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use cortex_m::{self as _, asm, interrupt};
-use cortex_m_rt::entry;
-use defmt::{self as _, info};
+use cortex_m::{self as _, asm, interrupt, peripheral::SCB};
+use cortex_m_rt::{ExceptionFrame, entry, exception};
+use defmt::{self as _, info, warn};
 use defmt_rtt as _;
 use embedded_hal::digital::PinState;
-use futures::{FutureExt, select_biased};
 
-use crate::{
+use async_fluid::{
     board::{Board, Button},
     channel::{Channel, Receiver, Sender},
     executor::Executor,
-    gpiote::InputChannel,
+    gpio::InputChannel,
     led::{Direction, LedBlinker, LedMatrix},
+    pin_array,
+    state_machine::{Event, State, StateMachine, Transition},
     time::{TickDuration, Timer},
 };
 
-mod board;
-mod channel;
-mod executor;
-mod gpiote;
-mod led;
-mod time;
-mod utils;
+const BLINK_DURATION: TickDuration = TickDuration::millis(200);
 
-async fn led_task(
-    leds: &mut LedMatrix,
-    blink_duration: TickDuration,
-    mut btn_recv: Receiver<'_, ButtonDirection>,
-) {
-    let mut blinky = LedBlinker::new(leds, 0).unwrap();
-    loop {
-        select_biased! {
-            direction = btn_recv.recv().fuse() => {
-                blinky.shift(match direction {
-                    ButtonDirection::Left => Direction::Left,
-                    ButtonDirection::Right => Direction::Right,
-                });
-            }
-            () = Timer::delay(blink_duration).fuse() => { blinky.toggle(); }
-        }
+#[derive(Clone, Copy, PartialEq)]
+enum BlinkState {
+    On,
+    Off,
+}
+
+impl State for BlinkState {
+    fn timeout(&self) -> Option<TickDuration> {
+        Some(BLINK_DURATION)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+async fn led_task(leds: &mut LedMatrix, mut btn_recv: Receiver<'_, ButtonDirection>) {
+    let mut blinky = LedBlinker::new(leds, 0).unwrap();
+    StateMachine::new(BlinkState::Off)
+        .run(
+            async || btn_recv.recv().await,
+            async |state, event| match event {
+                Event::Input(Err(err)) => {
+                    // The button tasks never end, so this can't happen
+                    warn!("Button channel closed: {}", err);
+                    Transition::Stay
+                }
+                Event::Input(Ok(direction)) => {
+                    blinky.shift(match direction {
+                        ButtonDirection::Left => Direction::Left,
+                        ButtonDirection::Right => Direction::Right,
+                    });
+                    // Shifting turns the LED on
+                    Transition::To(BlinkState::On)
+                }
+                Event::Timeout => {
+                    blinky.toggle();
+                    Transition::To(match state {
+                        BlinkState::On => BlinkState::Off,
+                        BlinkState::Off => BlinkState::On,
+                    })
+                }
+            },
+        )
+        .await
+}
+
+#[derive(Debug, Copy, Clone, defmt::Format)]
 pub enum ButtonDirection {
     Left,
     Right,
@@ -67,22 +85,11 @@ pub async fn button_task(
     let mut input = InputChannel::new(button).unwrap();
     loop {
         input.wait_for(PinState::Low).await;
-        info!(
-            "{} Button Pressed",
-            match direction {
-                ButtonDirection::Left => "Left",
-                ButtonDirection::Right => "Right",
-            }
-        );
-        sender.send(direction);
+        info!("{} Button Pressed", direction);
+        // The channel overwrites by default, so this can't fail
+        let _ = sender.send(direction);
         input.wait_for(PinState::High).await;
-        info!(
-            "{} Button Released",
-            match direction {
-                ButtonDirection::Left => "Left",
-                ButtonDirection::Right => "Right",
-            }
-        );
+        info!("{} Button Released", direction);
         // Debounce
         Timer::delay(BUTTON_DEBOUNCE).await;
     }
@@ -90,25 +97,38 @@ pub async fn button_task(
 
 #[entry]
 fn main() -> ! {
-    info!("Starting");
-    let mut b = Board::new();
+    info!(
+        "Starting on device {=u64:016x}",
+        async_fluid::device::device_id()
+    );
+    #[cfg(feature = "ramlog")]
+    if async_fluid::ramlog::RamLog::init() {
+        info!(
+            "RAM log kept {} bytes from before the reset",
+            async_fluid::ramlog::RamLog::len()
+        );
+    }
+    #[allow(clippy::unwrap_used)] // Nothing else has taken the peripherals yet
+    let Board {
+        mut display,
+        buttons,
+        ..
+    } = Board::take().unwrap();
     let btn_channel = Channel::<ButtonDirection>::new();
-    let led_task = pin!(led_task(
-        &mut b.leds,
-        TickDuration::millis(200),
-        btn_channel.get_recv()
-    ));
-    let button_task_r = pin!(button_task(
-        b.btn_r,
-        ButtonDirection::Right,
-        btn_channel.get_sender()
-    ));
-    let button_task_l = pin!(button_task(
-        b.btn_l,
-        ButtonDirection::Left,
-        btn_channel.get_sender()
-    ));
-    Executor::run_tasks([button_task_l, button_task_r, led_task]);
+    let tasks = pin_array![
+        button_task(
+            buttons.left,
+            ButtonDirection::Left,
+            btn_channel.get_sender()
+        ),
+        button_task(
+            buttons.right,
+            ButtonDirection::Right,
+            btn_channel.get_sender()
+        ),
+        led_task(&mut display, btn_channel.get_recv()),
+    ];
+    Executor::run_tasks(tasks);
 }
 
 #[panic_handler]
@@ -118,7 +138,41 @@ fn panic_handler(info: &PanicInfo) -> ! {
     if !PANICKED.load(Ordering::Relaxed) {
         PANICKED.store(true, Ordering::Relaxed);
         defmt::error!("{}", defmt::Display2Format(info));
+        #[cfg(feature = "ramlog")]
+        async_fluid::ram_log!("{}", info);
     }
     asm::bkpt();
     asm::udf();
 }
+
+// UsageFault, BusFault and MemManage aren't enabled, so they all escalate to here. CFSR holds the
+// status bits of whichever one it was.
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    interrupt::disable();
+    // SAFETY: Only reads the fault status registers, and nothing else runs after a hard fault
+    let scb = unsafe { &*SCB::PTR };
+    defmt::error!(
+        "HardFault: r0={=u32:#010x} r1={=u32:#010x} r2={=u32:#010x} r3={=u32:#010x} r12={=u32:#010x}",
+        frame.r0(),
+        frame.r1(),
+        frame.r2(),
+        frame.r3(),
+        frame.r12()
+    );
+    defmt::error!(
+        "lr={=u32:#010x} pc={=u32:#010x} xpsr={=u32:#010x}",
+        frame.lr(),
+        frame.pc(),
+        frame.xpsr()
+    );
+    defmt::error!(
+        "cfsr={=u32:#010x} hfsr={=u32:#010x} mmfar={=u32:#010x} bfar={=u32:#010x}",
+        scb.cfsr.read(),
+        scb.hfsr.read(),
+        scb.mmfar.read(),
+        scb.bfar.read()
+    );
+    asm::bkpt();
+    asm::udf();
+}
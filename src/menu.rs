@@ -0,0 +1,215 @@
+/*
+Settings menus driven by a rotary encoder or buttons. A menu is a list of items, each an action, a
+number, or an on/off toggle, with a callback that's told about changes. Turning steps through the
+items, selecting runs an action, flips a toggle, or starts editing a number, which turning then
+changes until it's selected again to keep it, or backed out of to put it back.
+
+The menu only keeps the state and the text to show, so it renders on anything that can show a
+string: `run` hands the text to a callback, and cancels it when an input comes in, so a callback
+that scrolls the text forever is fine.
+
+    let mut menu = Menu::new([
+        MenuItem::value("Brightness", 5, 0..=9, 1, &mut set_brightness),
+        MenuItem::toggle("Sound", true, &mut set_sound),
+        MenuItem::action("Reset", &mut reset),
+    ]);
+    menu.run(inputs, async |text| scroll(text).await).await;
+*/
+
+use core::{fmt::Write, ops::ControlFlow, ops::RangeInclusive, pin::pin};
+
+use futures::{FutureExt, Stream, StreamExt, select_biased};
+
+pub const MAX_TEXT: usize = 32;
+
+// What the encoder or buttons did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MenuInput {
+    // Steps of the encoder, negative for backwards, or a button for each way
+    Turn(i32),
+    Select,
+    Back,
+}
+
+enum ItemKind<'a> {
+    Action(&'a mut dyn FnMut()),
+    Value {
+        value: i32,
+        range: RangeInclusive<i32>,
+        step: i32,
+        on_change: &'a mut dyn FnMut(i32),
+    },
+    Toggle {
+        on: bool,
+        on_change: &'a mut dyn FnMut(bool),
+    },
+}
+
+pub struct MenuItem<'a> {
+    label: &'static str,
+    kind: ItemKind<'a>,
+}
+
+impl<'a> MenuItem<'a> {
+    pub fn action(label: &'static str, on_select: &'a mut dyn FnMut()) -> Self {
+        Self {
+            label,
+            kind: ItemKind::Action(on_select),
+        }
+    }
+
+    // `on_change` is only called once an edit is kept
+    pub fn value(
+        label: &'static str,
+        value: i32,
+        range: RangeInclusive<i32>,
+        step: i32,
+        on_change: &'a mut dyn FnMut(i32),
+    ) -> Self {
+        assert!(!range.is_empty(), "The range is empty");
+        Self {
+            label,
+            kind: ItemKind::Value {
+                value: value.clamp(*range.start(), *range.end()),
+                range,
+                step,
+                on_change,
+            },
+        }
+    }
+
+    pub fn toggle(label: &'static str, on: bool, on_change: &'a mut dyn FnMut(bool)) -> Self {
+        Self {
+            label,
+            kind: ItemKind::Toggle { on, on_change },
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+}
+
+pub struct Menu<'a, const N: usize> {
+    items: [MenuItem<'a>; N],
+    selected: usize,
+    // The value from before the edit, while one is being edited
+    editing: Option<i32>,
+}
+
+impl<'a, const N: usize> Menu<'a, N> {
+    pub fn new(items: [MenuItem<'a>; N]) -> Self {
+        const { assert!(N > 0, "A menu needs at least one item") };
+        Self {
+            items,
+            selected: 0,
+            editing: None,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    // Breaks when backing out of the menu itself
+    pub fn handle(&mut self, input: MenuInput) -> ControlFlow<()> {
+        let item = &mut self.items[self.selected];
+        match (input, &mut item.kind, self.editing) {
+            (
+                MenuInput::Turn(steps),
+                ItemKind::Value {
+                    value, range, step, ..
+                },
+                Some(_),
+            ) => {
+                *value = value
+                    .saturating_add(steps.saturating_mul(*step))
+                    .clamp(*range.start(), *range.end());
+            }
+            (MenuInput::Turn(steps), ..) => {
+                // Wraps around both ways
+                self.selected =
+                    (self.selected as i64 + i64::from(steps)).rem_euclid(N as i64) as usize;
+            }
+            (MenuInput::Select, ItemKind::Action(on_select), _) => on_select(),
+            (MenuInput::Select, ItemKind::Toggle { on, on_change }, _) => {
+                *on = !*on;
+                on_change(*on);
+            }
+            (MenuInput::Select, ItemKind::Value { value, .. }, None) => self.editing = Some(*value),
+            (
+                MenuInput::Select,
+                ItemKind::Value {
+                    value, on_change, ..
+                },
+                Some(before),
+            ) => {
+                self.editing = None;
+                if *value != before {
+                    on_change(*value);
+                }
+            }
+            (MenuInput::Back, ItemKind::Value { value, .. }, Some(before)) => {
+                *value = before;
+                self.editing = None;
+            }
+            (MenuInput::Back, ..) => return ControlFlow::Break(()),
+        }
+        ControlFlow::Continue(())
+    }
+
+    // The selected item with its value, or just the value while it's being edited. Cut short if
+    // it doesn't fit.
+    pub fn text(&self) -> heapless::String<MAX_TEXT> {
+        let item = &self.items[self.selected];
+        let mut text = heapless::String::new();
+        // Overflowing only loses the end of the text
+        let _ = match (&item.kind, self.editing) {
+            (ItemKind::Value { value, .. }, Some(_)) => write!(text, "{value}"),
+            (ItemKind::Value { value, .. }, None) => write!(text, "{} {value}", item.label),
+            (ItemKind::Toggle { on, .. }, _) => {
+                write!(text, "{} {}", item.label, if *on { "on" } else { "off" })
+            }
+            (ItemKind::Action(_), _) => write!(text, "{}", item.label),
+        };
+        text
+    }
+
+    // Shows the text, and handles inputs until the menu is backed out of or they run out
+    pub async fn run(
+        &mut self,
+        inputs: impl Stream<Item = MenuInput>,
+        mut render: impl AsyncFnMut(&str),
+    ) {
+        let mut inputs = pin!(inputs);
+        loop {
+            let text = self.text();
+            let input = {
+                let mut shown = pin!(
+                    async {
+                        render(&text).await;
+                        // Keeps showing whatever it left on the display
+                        core::future::pending::<()>().await;
+                    }
+                    .fuse()
+                );
+                // Rendering first, so the text goes up even when inputs are queued
+                select_biased! {
+                    () = shown => None,
+                    input = inputs.next().fuse() => input,
+                }
+            };
+            let Some(input) = input else {
+                return;
+            };
+            if self.handle(input).is_break() {
+                return;
+            }
+        }
+    }
+}
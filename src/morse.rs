@@ -0,0 +1,156 @@
+/*
+Morse code on an output pin. Everything is timed in units of one dit: a dah is three units, the
+gap inside a letter is one, the gap between letters three and the gap between words seven. The
+pin is either keyed (high for the whole element, for an LED) or driven as a square wave (for the
+speaker).
+*/
+
+use embedded_hal::digital::OutputPin;
+use fugit::HertzU32;
+
+use crate::time::{TickDuration, Timer};
+
+const LETTERS: [&str; 26] = [
+    ".-", "-...", "-.-.", "-..", ".", "..-.", "--.", "....", "..", ".---", "-.-", ".-..", "--",
+    "-.", "---", ".--.", "--.-", ".-.", "...", "-", "..-", "...-", ".--", "-..-", "-.--", "--..",
+];
+const DIGITS: [&str; 10] = [
+    "-----", ".----", "..---", "...--", "....-", ".....", "-....", "--...", "---..", "----.",
+];
+
+const DIT: u32 = 1;
+const DAH: u32 = 3;
+const SYMBOL_GAP: u32 = 1;
+const LETTER_GAP: u32 = 3;
+const WORD_GAP: u32 = 7;
+
+pub fn encode(c: char) -> Option<&'static str> {
+    match c.to_ascii_uppercase() {
+        c @ 'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+        c @ '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Element {
+    pub on: bool,
+    pub units: u32,
+}
+
+// The on and off periods that make up the text. Characters without a code are skipped, and there
+// is no gap before the first element or after the last.
+pub fn elements(text: &str) -> Elements<'_> {
+    Elements {
+        text: text.chars(),
+        code: &[],
+        gap: 0,
+    }
+}
+
+#[derive(Clone)]
+pub struct Elements<'a> {
+    text: core::str::Chars<'a>,
+    // What's left of the current character's code
+    code: &'static [u8],
+    // The gap owed before the next symbol, zero until something has been sent
+    gap: u32,
+}
+
+impl Iterator for Elements<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        loop {
+            if let Some((&symbol, rest)) = self.code.split_first() {
+                if self.gap > 0 {
+                    let units = core::mem::take(&mut self.gap);
+                    return Some(Element { on: false, units });
+                }
+                self.code = rest;
+                self.gap = SYMBOL_GAP;
+                let units = if symbol == b'.' { DIT } else { DAH };
+                return Some(Element { on: true, units });
+            }
+            let c = self.text.next()?;
+            if c.is_whitespace() {
+                if self.gap > 0 {
+                    self.gap = WORD_GAP;
+                }
+            } else if let Some(code) = encode(c) {
+                self.code = code.as_bytes();
+                if self.gap > 0 {
+                    self.gap = self.gap.max(LETTER_GAP);
+                }
+            } else {
+                warn!("No morse code for {}, skipping it", c);
+            }
+        }
+    }
+}
+
+pub struct Morse<P> {
+    pin: P,
+    unit: TickDuration,
+    // Half the period of the tone, if the pin drives a speaker
+    half_period: Option<TickDuration>,
+}
+
+impl<P: OutputPin> Morse<P> {
+    // The standard word is PARIS, 50 units long, so a unit is 1.2 s divided by the speed
+    pub const fn unit_for_wpm(wpm: u64) -> TickDuration {
+        TickDuration::millis(1200 / wpm)
+    }
+
+    pub fn new(pin: P, unit: TickDuration) -> Self {
+        Self {
+            pin,
+            unit,
+            half_period: None,
+        }
+    }
+
+    pub fn with_tone(pin: P, unit: TickDuration, tone: HertzU32) -> Self {
+        let half_period = TickDuration::from_ticks(32768 / (2 * u64::from(tone.to_Hz())));
+        Self {
+            pin,
+            unit,
+            half_period: Some(half_period),
+        }
+    }
+
+    pub async fn send(&mut self, text: &str) -> Result<(), P::Error> {
+        for element in elements(text) {
+            let duration = self.unit * element.units;
+            if element.on {
+                self.sound(duration).await?;
+            } else {
+                Timer::delay(duration).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn sound(&mut self, duration: TickDuration) -> Result<(), P::Error> {
+        match self.half_period {
+            None => {
+                self.pin.set_high()?;
+                Timer::delay(duration).await;
+            }
+            Some(half_period) => {
+                for _ in 0..duration.ticks() / (2 * half_period.ticks()) {
+                    self.pin.set_high()?;
+                    Timer::delay(half_period).await;
+                    self.pin.set_low()?;
+                    Timer::delay(half_period).await;
+                }
+            }
+        }
+        self.pin.set_low()
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
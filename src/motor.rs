@@ -0,0 +1,105 @@
+/*
+DC motors on an H-bridge driver (e.g. a TB6612 or L298N on a robot board), where two direction pins
+set which way the current flows and a PWM channel sets how much of the time it does. Speeds are
+percentages, negative for reverse. Setting 0 lets the motor coast, while `brake` shorts it to stop
+it quickly.
+
+Suddenly reversing a motor draws a lot of current and can reset the micro:bit on a weak battery, so
+`ramp_to` eases into the new speed instead.
+*/
+
+use core::{convert::Infallible, pin::pin};
+
+use embedded_hal::{digital::OutputPin, pwm::SetDutyCycle};
+
+use crate::{
+    time::{PeriodicTimer, TickDuration},
+    utils::InfallibleExt,
+};
+
+pub const MAX_SPEED: i8 = 100;
+
+pub struct Motor<A, B, P> {
+    forward: A,
+    backward: B,
+    speed: P,
+    current: i8,
+    // Swaps the direction pins, for a motor mounted the other way round
+    reversed: bool,
+}
+
+impl<A, B, P> Motor<A, B, P>
+where
+    A: OutputPin<Error = Infallible>,
+    B: OutputPin<Error = Infallible>,
+    P: SetDutyCycle<Error = Infallible>,
+{
+    // Starts stopped, coasting
+    pub fn new(forward: A, backward: B, speed: P) -> Self {
+        let mut motor = Self {
+            forward,
+            backward,
+            speed,
+            current: 0,
+            reversed: false,
+        };
+        motor.set_speed(0);
+        motor
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.reversed = !self.reversed;
+        self.set_speed(self.current);
+        self
+    }
+
+    pub fn speed(&self) -> i8 {
+        self.current
+    }
+
+    // From -100 (full reverse) to 100 (full forward), clamped to that range
+    pub fn set_speed(&mut self, speed: i8) {
+        let speed = speed.clamp(-MAX_SPEED, MAX_SPEED);
+        self.current = speed;
+        let forward = (speed > 0) != self.reversed;
+        let (forward, backward) = match speed {
+            0 => (false, false),
+            _ => (forward, !forward),
+        };
+        self.forward.set_state(forward.into()).unwrap_infallible();
+        self.backward.set_state(backward.into()).unwrap_infallible();
+        self.speed
+            .set_duty_cycle_percent(speed.unsigned_abs())
+            .unwrap_infallible();
+    }
+
+    // Drives both sides of the bridge the same way, which stops the motor much faster than
+    // coasting
+    pub fn brake(&mut self) {
+        self.current = 0;
+        self.forward.set_high().unwrap_infallible();
+        self.backward.set_high().unwrap_infallible();
+        self.speed.set_duty_cycle_fully_on().unwrap_infallible();
+    }
+
+    // Steps one percent at a time to the target, taking `step` per percent, so a full reversal
+    // takes 200 steps
+    pub async fn ramp_to(&mut self, target: i8, step: TickDuration) {
+        let target = target.clamp(-MAX_SPEED, MAX_SPEED);
+        if self.current == target {
+            return;
+        }
+        let mut timer = pin!(PeriodicTimer::new(step));
+        while self.current != target {
+            let next = if target > self.current {
+                self.current + 1
+            } else {
+                self.current - 1
+            };
+            self.set_speed(next);
+            if next != target {
+                timer.as_mut().tick().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+/*
+Detects an NFC reader's field, e.g. a phone tapped against the antenna, with the NFCT peripheral's
+field sensing. Only the field is sensed, the tag itself isn't emulated. Sensing keeps working in
+System OFF, so a tap can also wake the chip from deep sleep, which it does through a reset with
+RESETREAS.NFC set.
+
+The NFC pins are GPIO unless UICR.NFCPINS gives them to the NFCT, which is only read at reset and
+has to be written when flashing. On the micro:bit they're edge connector pins 8 and 9, and an
+antenna has to be connected across them.
+*/
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use nrf52833_hal::pac::{Interrupt, NFCT, NVIC, POWER, UICR, interrupt, nfct};
+use snafu::prelude::*;
+
+use crate::{config::Priority, utils::AtomicWaker};
+
+static FIELD_WAKER: AtomicWaker = AtomicWaker::new();
+static DETECTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NfcError {
+    #[snafu(display("The NFC pins are configured as GPIO in UICR.NFCPINS"))]
+    PinsAreGpio,
+}
+
+pub struct Nfc {
+    _nfct: NFCT,
+}
+
+impl Nfc {
+    pub fn new(nfct: NFCT, nvic: &mut NVIC, priority: Priority) -> Result<Self, NfcError> {
+        // SAFETY: The UICR is only read
+        let uicr = unsafe { &*UICR::ptr() };
+        ensure!(uicr.nfcpins.read().protect().is_nfc(), PinsAreGpioSnafu);
+        // SAFETY: The priority is set before the interrupt is unmasked, and the handler only uses
+        // the driver's own statics
+        unsafe {
+            nvic.set_priority(Interrupt::NFCT, priority.bits());
+            NVIC::unmask(Interrupt::NFCT);
+        }
+        Ok(Self { _nfct: nfct })
+    }
+
+    pub fn field_present(&self) -> bool {
+        nfct().fieldpresent.read().fieldpresent().is_field_present()
+    }
+
+    // Waits for a field to appear. Returns straight away if there already is one.
+    pub async fn wait_for_field(&mut self) {
+        let nfct = nfct();
+        DETECTED.store(false, Ordering::Relaxed);
+        nfct.events_fielddetected.reset();
+        nfct.intenset.write(|w| w.fielddetected().set());
+        nfct.tasks_sense.write(|w| unsafe { w.bits(1) });
+        poll_fn(|cx| {
+            FIELD_WAKER.register(cx.waker());
+            if DETECTED.load(Ordering::Acquire) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        debug!("NFC field detected");
+    }
+
+    // Goes into System OFF until a field is detected, which restarts the firmware from reset. Any
+    // other wake sources, like GPIO sense, still work too.
+    pub fn sleep_until_field(self, power: &POWER) -> ! {
+        let nfct = nfct();
+        nfct.intenclr.write(|w| w.fielddetected().clear());
+        nfct.events_fielddetected.reset();
+        nfct.tasks_sense.write(|w| unsafe { w.bits(1) });
+        info!("Sleeping until an NFC field is detected");
+        power.systemoff.write(|w| w.systemoff().enter());
+        // Entering System OFF can take a moment, and under a debugger it's only emulated
+        loop {
+            cortex_m::asm::wfe();
+        }
+    }
+}
+
+// Whether the last reset was a wake from System OFF by an NFC field. Clears the flag, so only the
+// first call after the reset sees it.
+pub fn woke_by_field(power: &POWER) -> bool {
+    let woke = power.resetreas.read().nfc().is_detected();
+    if woke {
+        // The flags are cleared by writing ones
+        power.resetreas.write(|w| w.nfc().set_bit());
+    }
+    woke
+}
+
+fn nfct() -> &'static nfct::RegisterBlock {
+    // SAFETY: The registers are only touched by the driver, which owns NFCT
+    unsafe { &*NFCT::ptr() }
+}
+
+#[interrupt]
+fn NFCT() {
+    let nfct = nfct();
+    if nfct.events_fielddetected.read().bits() != 0 {
+        nfct.events_fielddetected.reset();
+        nfct.intenclr.write(|w| w.fielddetected().clear());
+        DETECTED.store(true, Ordering::Release);
+        FIELD_WAKER.wake();
+    }
+}
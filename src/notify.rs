@@ -0,0 +1,112 @@
+/*
+Notifications on the display and speaker. Any task can queue one, and the notifier task presents
+them one at a time: the icon replaces the display for the duration while the sound plays, then the
+display goes back to what it was showing. Tasks then never fight over the output hardware, and a
+notification raised while another is showing waits its turn instead of garbling both.
+
+    static NOTIFIER: Notifier<4> = Notifier::new();
+    NOTIFIER.notify(Notification::new(TICK, TickDuration::millis(800)).with_sound(CHIME))?;
+*/
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::OutputPin;
+use futures::future::join;
+
+use crate::{
+    alarm::{Alarm, Tone},
+    cancel::CancellationToken,
+    channel::{ChannelError, mpsc::Mpsc},
+    led::{DisplayHandle, Frame},
+    time::{TickDuration, Timer},
+};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Notification {
+    pub icon: Frame,
+    // Played once from the start, and cut short if it's longer than the duration. Empty for a
+    // silent notification.
+    pub sound: &'static [Tone],
+    pub duration: TickDuration,
+}
+
+impl Notification {
+    pub const fn new(icon: Frame, duration: TickDuration) -> Self {
+        Self {
+            icon,
+            sound: &[],
+            duration,
+        }
+    }
+
+    pub const fn with_sound(mut self, sound: &'static [Tone]) -> Self {
+        self.sound = sound;
+        self
+    }
+
+    // The sound, cut off at the end of the duration
+    fn sound_for_duration(&self) -> impl Iterator<Item = Tone> {
+        let mut left = self.duration;
+        self.sound.iter().map_while(move |tone| {
+            let duration = tone.duration.min(left);
+            left -= duration;
+            (duration.ticks() > 0).then_some(Tone::new(tone.hz, duration))
+        })
+    }
+}
+
+// Queues up to N notifications
+pub struct Notifier<const N: usize> {
+    queue: Mpsc<Notification, N>,
+    skip: CancellationToken,
+}
+
+impl<const N: usize> Notifier<N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: Mpsc::new(),
+            skip: CancellationToken::new(),
+        }
+    }
+
+    // Fails when the queue is full
+    pub fn notify(&self, notification: Notification) -> Result<(), ChannelError> {
+        self.queue.send(notification)
+    }
+
+    // Ends the notification being shown early, e.g. when a button acknowledges it. Does nothing
+    // when none is.
+    pub fn skip(&self) {
+        self.skip.cancel();
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub async fn run<P: OutputPin<Error = Infallible>>(
+        &self,
+        display: DisplayHandle<'_>,
+        alarm: &mut Alarm<P>,
+    ) -> ! {
+        loop {
+            let notification = self.queue.recv().await;
+            // A skip while nothing was showing isn't meant for this one
+            self.skip.reset();
+            let previous = display.frame();
+            display.show(notification.icon);
+            let shown = self.skip.run(Timer::delay(notification.duration));
+            let sound = alarm.play(notification.sound_for_duration(), &self.skip);
+            // Either way it's over, and the display goes back to what it was
+            let _ = join(shown, sound).await;
+            display.show(previous);
+        }
+    }
+}
+
+impl<const N: usize> Default for Notifier<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
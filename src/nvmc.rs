@@ -0,0 +1,76 @@
+/*
+The one owner of the NVMC, which hands out a flash driver for each region that the settings store,
+the data log and scheduled flash use, so none of them has to steal the peripheral:
+
+    let mut regions = FlashRegions::new(board.spare.nvmc);
+    let flash = ScheduledFlash::new(regions.partial(region), &QUIET);
+    let mut settings = Settings::new(regions.settings);
+    let log = DataLogger::init(regions.datalog);
+
+The drivers each hold an NVMC of their own, but it's only ever the one taken here, so nothing else
+can be using it. Each has its own region, and every flash operation blocks until it's done and is
+only run from tasks, so two drivers never have an operation under way at once either.
+*/
+
+#[cfg(any(feature = "settings", feature = "datalog"))]
+use nrf52833_hal::nvmc::Nvmc;
+use nrf52833_hal::pac::{NVMC, Peripherals};
+
+#[cfg(feature = "settings")]
+unsafe extern "C" {
+    // The region reserved in memory.x
+    static mut __settings_start: u8;
+    static __settings_end: u8;
+}
+
+#[cfg(feature = "datalog")]
+unsafe extern "C" {
+    // The region reserved in memory.x
+    static mut __datalog_start: u8;
+    static __datalog_end: u8;
+}
+
+pub struct FlashRegions {
+    #[cfg(feature = "settings")]
+    pub settings: Nvmc<NVMC>,
+    #[cfg(feature = "datalog")]
+    pub datalog: Nvmc<NVMC>,
+    // Only made from the NVMC
+    _nvmc: NVMC,
+}
+
+impl FlashRegions {
+    pub fn new(nvmc: NVMC) -> Self {
+        Self {
+            // SAFETY: The NVMC was taken, so only the drivers made here use it, and the linker
+            // keeps the firmware out of the region. The region is page aligned.
+            #[cfg(feature = "settings")]
+            settings: unsafe { region(&raw mut __settings_start, &raw const __settings_end) },
+            // SAFETY: As above
+            #[cfg(feature = "datalog")]
+            datalog: unsafe { region(&raw mut __datalog_start, &raw const __datalog_end) },
+            _nvmc: nvmc,
+        }
+    }
+
+    // Scheduled flash over a region of the application's own, which has to be page aligned and
+    // kept out of the firmware by the linker
+    #[cfg(feature = "flash")]
+    pub fn partial(&mut self, region: &'static mut [u8]) -> crate::flash::PartialNvmc {
+        // SAFETY: The NVMC was taken, so only the drivers made here use it
+        crate::flash::PartialNvmc::new(unsafe { steal() }, region)
+    }
+}
+
+// SAFETY: The NVMC has to have been taken by a `FlashRegions`
+unsafe fn steal() -> NVMC {
+    unsafe { Peripherals::steal() }.NVMC
+}
+
+// SAFETY: The region between the linker symbols can only be handed out once, and the NVMC has to
+// have been taken by a `FlashRegions`
+#[cfg(any(feature = "settings", feature = "datalog"))]
+unsafe fn region(start: *mut u8, end: *const u8) -> Nvmc<NVMC> {
+    let len = end as usize - start as usize;
+    unsafe { Nvmc::new(steal(), core::slice::from_raw_parts_mut(start, len)) }
+}
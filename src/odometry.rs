@@ -0,0 +1,73 @@
+/*
+Wheel odometry from a quadrature encoder. Every period the steps counted since the last one are
+turned into distance and speed, and published on a watch channel, so any number of tasks (a
+control loop, the display, a logger) can follow the wheel without owning the encoder. Speed is
+averaged over the period, against the time that actually passed, so a late wake doesn't make it
+jump.
+*/
+
+use core::pin::pin;
+
+use crate::{
+    channel::watch::Watch,
+    time::{PeriodicTimer, TickDuration, Ticker},
+};
+
+// Counts the steps of a wheel, e.g. the QDEC peripheral or an edge counter on one channel
+pub trait WheelEncoder {
+    // Steps since the last call, negative when turning backwards
+    fn take_steps(&mut self) -> i32;
+}
+
+// The QDEC accumulates steps in hardware, and reading clears them
+#[cfg(feature = "nrf52833")]
+impl WheelEncoder for nrf52833_hal::qdec::Qdec {
+    fn take_steps(&mut self) -> i32 {
+        i32::from(self.read())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Odometry {
+    // Total steps since the task started
+    pub steps: i64,
+    // Distance travelled in millimetres, less any travelled backwards
+    pub distance_mm: i64,
+    // Over the last period, in millimetres per second
+    pub speed_mm_s: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OdometryConfig {
+    // The wheel's circumference divided by the encoder's steps per turn, in micrometres
+    pub um_per_step: i32,
+    pub period: TickDuration,
+}
+
+// Publishes each update on `watch`
+pub async fn run(
+    mut encoder: impl WheelEncoder,
+    config: OdometryConfig,
+    watch: &Watch<Odometry>,
+) -> ! {
+    let mut timer = pin!(PeriodicTimer::new(config.period));
+    let mut odometry = Odometry::default();
+    // Steps counted before the task started don't count
+    encoder.take_steps();
+    let mut last = Ticker::now();
+    loop {
+        timer.as_mut().tick().await;
+        let now = Ticker::now();
+        let steps = encoder.take_steps();
+        odometry.steps += i64::from(steps);
+        odometry.distance_mm = odometry.steps * i64::from(config.um_per_step) / 1000;
+        let elapsed_us = (now - last).to_micros().max(1) as i64;
+        let moved_um = i64::from(steps) * i64::from(config.um_per_step);
+        // Micrometres per millisecond are millimetres per second
+        odometry.speed_mm_s = (moved_um * 1000 / elapsed_us) as i32;
+        last = now;
+        watch.send(odometry);
+    }
+}
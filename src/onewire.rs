@@ -0,0 +1,227 @@
+/*
+Bit-banged 1-Wire bus master. Every bit is a slot of about 70 us that starts with the master pulling
+the line low, so the slots are timed with the CPU cycle counter and interrupts are masked while the
+line is low, since an interrupt in the middle would stretch a 6 us pulse into a 0. The line needs a
+4.7 kΩ pull-up to 3V, and the pin is used open drain (see `board::open_drain`), so the master only
+ever pulls it low.
+
+Each device on the bus has a unique 64 bit ROM code, with the family in the first byte and a CRC
+in the last. With a single device it can be addressed without knowing its code.
+*/
+
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::digital::{InputPin, OutputPin};
+use snafu::prelude::*;
+
+use crate::{
+    board::OpenDrainPin,
+    crc::crc8,
+    cycles::{self, delay_us},
+    utils::InfallibleExt,
+};
+
+pub mod ds18b20;
+
+const SEARCH_ROM: u8 = 0xF0;
+const READ_ROM: u8 = 0x33;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xCC;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OneWireError {
+    #[snafu(display("No device answered the reset pulse"))]
+    NoPresence,
+    #[snafu(display("The CRC didn't match, the bus may be noisy or the pull-up too weak"))]
+    Crc,
+    #[snafu(display("The line is held low, it may be shorted or missing its pull-up"))]
+    BusLow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+
+    fn check(bytes: [u8; 8]) -> Result<Self, OneWireError> {
+        ensure!(crc8(&bytes) == 0, CrcSnafu);
+        Ok(Self(bytes))
+    }
+}
+
+pub struct OneWire {
+    pin: OpenDrainPin,
+}
+
+impl OneWire {
+    // Starts the cycle counter, which the slots are timed with
+    pub fn new(mut pin: OpenDrainPin, dcb: &mut DCB, dwt: &mut DWT) -> Self {
+        cycles::enable(dcb, dwt);
+        pin.set_high().unwrap_infallible();
+        Self { pin }
+    }
+
+    pub fn free(self) -> OpenDrainPin {
+        self.pin
+    }
+
+    // Starts every transaction. Fails if no device pulls the line low in reply.
+    pub fn reset(&mut self) -> Result<(), OneWireError> {
+        ensure!(self.is_high(), BusLowSnafu);
+        self.pin.set_low().unwrap_infallible();
+        delay_us(480);
+        let present = critical_section::with(|_| {
+            self.pin.set_high().unwrap_infallible();
+            delay_us(70);
+            !self.is_high()
+        });
+        delay_us(410);
+        ensure!(present, NoPresenceSnafu);
+        Ok(())
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        for bit in 0..8 {
+            self.write_bit(byte & (1 << bit) != 0);
+        }
+    }
+
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, bit| byte | (u8::from(self.read_bit()) << bit))
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) {
+        for byte in bytes {
+            *byte = self.read_byte();
+        }
+    }
+
+    // Resets the bus and addresses one device, or every device if `rom` is `None`. Only reads
+    // make sense with `None` when there's a single device.
+    pub fn select(&mut self, rom: Option<&Rom>) -> Result<(), OneWireError> {
+        self.reset()?;
+        match rom {
+            Some(rom) => {
+                self.write_byte(MATCH_ROM);
+                self.write_bytes(&rom.0);
+            }
+            None => self.write_byte(SKIP_ROM),
+        }
+        Ok(())
+    }
+
+    // The code of the only device on the bus. With several, their replies collide and the CRC fails.
+    pub fn read_rom(&mut self) -> Result<Rom, OneWireError> {
+        self.reset()?;
+        self.write_byte(READ_ROM);
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes);
+        Rom::check(bytes)
+    }
+
+    // Finds the codes of every device on the bus, one per call of `next`
+    pub fn search(&mut self) -> Search<'_> {
+        Search {
+            bus: self,
+            rom: [0; 8],
+            last_discrepancy: None,
+            done: false,
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        let low = if bit { 6 } else { 60 };
+        critical_section::with(|_| {
+            self.pin.set_low().unwrap_infallible();
+            delay_us(low);
+            self.pin.set_high().unwrap_infallible();
+        });
+        delay_us(70 - low);
+    }
+
+    fn read_bit(&mut self) -> bool {
+        let bit = critical_section::with(|_| {
+            self.pin.set_low().unwrap_infallible();
+            delay_us(6);
+            self.pin.set_high().unwrap_infallible();
+            delay_us(9);
+            self.is_high()
+        });
+        delay_us(55);
+        bit
+    }
+
+    fn is_high(&mut self) -> bool {
+        self.pin.is_high().unwrap_infallible()
+    }
+}
+
+// The ROM search, which walks the tree of codes one bit at a time. At each bit every device still
+// in the search sends the bit and its complement, so reading two zeros means the codes differ
+// there. The first pass takes the 0 branch at each difference, and each later pass goes back to
+// the last 0 taken and takes the 1 instead.
+pub struct Search<'a> {
+    bus: &'a mut OneWire,
+    rom: [u8; 8],
+    last_discrepancy: Option<usize>,
+    done: bool,
+}
+
+impl Iterator for Search<'_> {
+    type Item = Result<Rom, OneWireError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Err(error) = self.bus.reset() {
+            self.done = true;
+            return Some(Err(error));
+        }
+        self.bus.write_byte(SEARCH_ROM);
+        let mut discrepancy = None;
+        for index in 0..64 {
+            let (byte, mask) = (index / 8, 1 << (index % 8));
+            let bit = self.bus.read_bit();
+            let complement = self.bus.read_bit();
+            let choice = match (bit, complement) {
+                // Every device left dropped out, e.g. one was unplugged mid search
+                (true, true) => {
+                    self.done = true;
+                    return Some(NoPresenceSnafu.fail());
+                }
+                (false, false) => {
+                    let choice = match self.last_discrepancy {
+                        Some(last) if index < last => self.rom[byte] & mask != 0,
+                        Some(last) => index == last,
+                        None => false,
+                    };
+                    if !choice {
+                        discrepancy = Some(index);
+                    }
+                    choice
+                }
+                (bit, _) => bit,
+            };
+            if choice {
+                self.rom[byte] |= mask;
+            } else {
+                self.rom[byte] &= !mask;
+            }
+            self.bus.write_bit(choice);
+        }
+        self.last_discrepancy = discrepancy;
+        self.done = discrepancy.is_none();
+        Some(Rom::check(self.rom))
+    }
+}
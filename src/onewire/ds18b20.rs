@@ -0,0 +1,86 @@
+/*
+DS18B20 temperature probe. A measurement is started on the bus, then waited for asynchronously,
+since it takes up to 750 ms at the full 12 bit resolution, and then read back from the scratchpad.
+Temperatures are in sixteenths of a degree Celsius. With several probes on one bus, find their ROM
+codes with `OneWire::search` and give each its own driver.
+*/
+
+use snafu::prelude::*;
+
+use super::{CrcSnafu, OneWire, OneWireError, Rom};
+use crate::{
+    crc::crc8,
+    time::{TickDuration, Timer},
+};
+
+pub const FAMILY: u8 = 0x28;
+
+const CONVERT: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xBE;
+const WRITE_SCRATCHPAD: u8 = 0x4E;
+// Alarm thresholds, which aren't used but have to be written along with the config
+const ALARM_HIGH: u8 = 0x7F;
+const ALARM_LOW: u8 = 0x80;
+
+pub struct Ds18b20 {
+    // `None` for the only device on the bus
+    rom: Option<Rom>,
+    resolution: u8,
+}
+
+impl Ds18b20 {
+    pub fn new(rom: Rom) -> Self {
+        if rom.family() != FAMILY {
+            warn!("1-Wire device of family {} isn't a DS18B20", rom.family());
+        }
+        Self {
+            rom: Some(rom),
+            resolution: 12,
+        }
+    }
+
+    // For a bus with a single probe, which then doesn't need its ROM code
+    pub fn only() -> Self {
+        Self {
+            rom: None,
+            resolution: 12,
+        }
+    }
+
+    // From 9 to 12 bits. Each bit less halves the conversion time and the precision, from 1/16 of
+    // a degree in 750 ms down to 1/2 in 94 ms. Not kept over a power cycle.
+    pub fn set_resolution(&mut self, bus: &mut OneWire, bits: u8) -> Result<(), OneWireError> {
+        assert!((9..=12).contains(&bits), "Resolution must be 9 to 12 bits");
+        bus.select(self.rom.as_ref())?;
+        bus.write_bytes(&[
+            WRITE_SCRATCHPAD,
+            ALARM_HIGH,
+            ALARM_LOW,
+            (bits - 9) << 5 | 0x1F,
+        ]);
+        self.resolution = bits;
+        Ok(())
+    }
+
+    pub fn conversion_time(&self) -> TickDuration {
+        TickDuration::micros(93_750 << (self.resolution - 9))
+    }
+
+    pub async fn read_sixteenths(&mut self, bus: &mut OneWire) -> Result<i16, OneWireError> {
+        bus.select(self.rom.as_ref())?;
+        bus.write_byte(CONVERT);
+        Timer::delay(self.conversion_time()).await;
+        bus.select(self.rom.as_ref())?;
+        bus.write_byte(READ_SCRATCHPAD);
+        let mut scratchpad = [0; 9];
+        bus.read_bytes(&mut scratchpad);
+        ensure!(crc8(&scratchpad) == 0, CrcSnafu);
+        // The bits below the resolution are undefined
+        let mask = !((1 << (12 - self.resolution)) - 1);
+        Ok(i16::from_le_bytes([scratchpad[0], scratchpad[1]]) & mask)
+    }
+
+    pub async fn read_celsius(&mut self, bus: &mut OneWire) -> Result<i32, OneWireError> {
+        Ok(i32::from(self.read_sixteenths(bus).await?).div_euclid(16))
+    }
+}
@@ -0,0 +1,249 @@
+/*
+Declarative sensor pipelines. A pipeline starts at a source, which is either a function sampled on a
+fixed period or an async function that paces itself, runs each value through a chain of stages, and
+hands whatever comes out to a sink. Stages can drop values (filtering, decimation), so a value only
+reaches the sink once every stage has let it through. The `dsp` filters work as stages too.
+
+    pipeline::sample(SAMPLE_PERIOD, || accel.read_x())
+        .decimate(4)
+        .then(dsp::MovingAverage::<8>::new())
+        .map(|x| x * SCALE)
+        .run_into(sender)
+        .await
+*/
+
+use crate::{
+    channel::Sender,
+    dsp,
+    time::{TickDuration, TickInstant, Ticker, Timer},
+};
+
+// Produces the values flowing into a pipeline
+pub trait Source {
+    type Item;
+
+    fn next(&mut self) -> impl Future<Output = Self::Item>;
+}
+
+// Transforms one value, or drops it by returning `None`
+pub trait Stage<In> {
+    type Out;
+
+    fn process(&mut self, input: In) -> Option<Self::Out>;
+}
+
+// Consumes the values coming out of a pipeline
+pub trait Sink<T> {
+    fn send(&mut self, item: T) -> impl Future<Output = ()>;
+}
+
+pub struct Pipeline<S, St> {
+    source: S,
+    stage: St,
+}
+
+// Calls `read` every period, against a fixed schedule so slow stages or sinks don't add drift
+pub fn sample<T, F: FnMut() -> T>(period: TickDuration, read: F) -> Pipeline<Sampled<F>, Identity> {
+    Pipeline::new(Sampled {
+        period,
+        next: None,
+        read,
+    })
+}
+
+// Takes values from an async function that waits for its own data, e.g. a channel or a sensor's
+// data-ready interrupt
+pub fn from_fn<T, F: AsyncFnMut() -> T>(read: F) -> Pipeline<FromFn<F>, Identity> {
+    Pipeline::new(FromFn(read))
+}
+
+impl<S: Source> Pipeline<S, Identity> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            stage: Identity,
+        }
+    }
+}
+
+impl<S: Source, St: Stage<S::Item>> Pipeline<S, St> {
+    pub fn then<Next: Stage<St::Out>>(self, next: Next) -> Pipeline<S, Chain<St, Next>> {
+        Pipeline {
+            source: self.source,
+            stage: Chain(self.stage, next),
+        }
+    }
+
+    pub fn map<T, F: FnMut(St::Out) -> T>(self, f: F) -> Pipeline<S, Chain<St, Map<F>>> {
+        self.then(Map(f))
+    }
+
+    pub fn filter<F: FnMut(&St::Out) -> bool>(self, f: F) -> Pipeline<S, Chain<St, Filter<F>>> {
+        self.then(Filter(f))
+    }
+
+    pub fn decimate(self, factor: u32) -> Pipeline<S, Chain<St, Decimate>> {
+        self.then(Decimate::new(factor))
+    }
+
+    // Pulls the next value through every stage, waiting on the source until one makes it out
+    pub async fn next(&mut self) -> St::Out {
+        loop {
+            let input = self.source.next().await;
+            if let Some(output) = self.stage.process(input) {
+                return output;
+            }
+        }
+    }
+
+    pub async fn run_into(mut self, mut sink: impl Sink<St::Out>) -> ! {
+        loop {
+            let output = self.next().await;
+            sink.send(output).await;
+        }
+    }
+}
+
+pub struct Sampled<F> {
+    period: TickDuration,
+    next: Option<TickInstant>,
+    read: F,
+}
+
+impl<T, F: FnMut() -> T> Source for Sampled<F> {
+    type Item = T;
+
+    async fn next(&mut self) -> T {
+        let now = Ticker::now();
+        let due = *self.next.get_or_insert(now);
+        if due > now {
+            Timer::delay(due - now).await;
+        }
+        let mut next = due + self.period;
+        let now = Ticker::now();
+        if next <= now {
+            let behind = (now - next).ticks() / self.period.ticks() + 1;
+            warn!("Pipeline source fell behind, skipping {} samples", behind);
+            next += self.period * behind as u32;
+        }
+        self.next = Some(next);
+        (self.read)()
+    }
+}
+
+pub struct FromFn<F>(F);
+
+impl<T, F: AsyncFnMut() -> T> Source for FromFn<F> {
+    type Item = T;
+
+    async fn next(&mut self) -> T {
+        (self.0)().await
+    }
+}
+
+pub struct Identity;
+
+impl<T> Stage<T> for Identity {
+    type Out = T;
+
+    fn process(&mut self, input: T) -> Option<T> {
+        Some(input)
+    }
+}
+
+pub struct Chain<A, B>(A, B);
+
+impl<In, A: Stage<In>, B: Stage<A::Out>> Stage<In> for Chain<A, B> {
+    type Out = B::Out;
+
+    fn process(&mut self, input: In) -> Option<B::Out> {
+        self.0
+            .process(input)
+            .and_then(|value| self.1.process(value))
+    }
+}
+
+pub struct Map<F>(F);
+
+impl<In, T, F: FnMut(In) -> T> Stage<In> for Map<F> {
+    type Out = T;
+
+    fn process(&mut self, input: In) -> Option<T> {
+        Some((self.0)(input))
+    }
+}
+
+pub struct Filter<F>(F);
+
+impl<In, F: FnMut(&In) -> bool> Stage<In> for Filter<F> {
+    type Out = In;
+
+    fn process(&mut self, input: In) -> Option<In> {
+        (self.0)(&input).then_some(input)
+    }
+}
+
+// Keeps one in every `factor` values, starting with the first
+pub struct Decimate {
+    factor: u32,
+    count: u32,
+}
+
+impl Decimate {
+    pub fn new(factor: u32) -> Self {
+        Self {
+            factor: factor.max(1),
+            count: 0,
+        }
+    }
+}
+
+impl<T> Stage<T> for Decimate {
+    type Out = T;
+
+    fn process(&mut self, input: T) -> Option<T> {
+        let keep = self.count == 0;
+        self.count = (self.count + 1) % self.factor;
+        keep.then_some(input)
+    }
+}
+
+// Any of the fixed-point filters can be used as a stage on integer samples
+impl<F: dsp::Filter> Stage<i32> for F {
+    type Out = i32;
+
+    fn process(&mut self, input: i32) -> Option<i32> {
+        Some(self.update(input))
+    }
+}
+
+// Wraps a closure so it can be used as a sink, e.g. to draw each value on the display
+pub struct ForEach<F>(pub F);
+
+impl<T, F: FnMut(T)> Sink<T> for ForEach<F> {
+    async fn send(&mut self, item: T) {
+        (self.0)(item);
+    }
+}
+
+impl<T> Sink<T> for Sender<'_, T> {
+    async fn send(&mut self, item: T) {
+        if let Err(error) = Sender::send(self, item) {
+            warn!("Pipeline sink dropped a value: {}", error);
+        }
+    }
+}
+
+// Writes each value on its own line
+#[cfg(feature = "serial")]
+impl<T: core::fmt::Display> Sink<T> for crate::serial::Serial {
+    async fn send(&mut self, item: T) {
+        use core::fmt::Write;
+
+        let mut line = heapless::String::<32>::new();
+        match writeln!(line, "{item}") {
+            Ok(()) => self.write(line.as_bytes()).await,
+            Err(_) => warn!("Pipeline value is too long for the serial sink"),
+        }
+    }
+}
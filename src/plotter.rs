@@ -0,0 +1,106 @@
+/*
+Streams sampled values over the serial port so they can be plotted live on the host. Sources are
+registered with a name and sampled together every period, and each sample is written either as a
+CSV row (after a header naming the columns) or as teleplot's `>name:timestamp:value` lines.
+*/
+
+use core::fmt::Write;
+
+use snafu::prelude::*;
+
+use crate::{
+    serial::Serial,
+    time::{TickDuration, Ticker, Timer},
+};
+
+const LINE_LENGTH: usize = 128;
+
+pub type Source<'a> = &'a mut dyn FnMut() -> f32;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PlotFormat {
+    Csv,
+    Teleplot,
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PlotterError {
+    #[snafu(display("The plotter can't hold any more sources"))]
+    TooManySources,
+}
+
+pub struct Plotter<'a, const N: usize> {
+    serial: Serial,
+    period: TickDuration,
+    format: PlotFormat,
+    sources: heapless::Vec<(&'static str, Source<'a>), N>,
+}
+
+impl<'a, const N: usize> Plotter<'a, N> {
+    pub fn new(serial: Serial, period: TickDuration, format: PlotFormat) -> Self {
+        Self {
+            serial,
+            period,
+            format,
+            sources: heapless::Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &'static str, source: Source<'a>) -> Result<(), PlotterError> {
+        self.sources
+            .push((name, source))
+            .ok()
+            .context(TooManySourcesSnafu)
+    }
+
+    pub async fn run(&mut self) -> ! {
+        if let PlotFormat::Csv = self.format {
+            let mut header = heapless::String::<LINE_LENGTH>::new();
+            let written = write!(header, "time_ms").and_then(|()| {
+                self.sources
+                    .iter()
+                    .try_for_each(|(name, _)| write!(header, ",{name}"))
+            });
+            self.send(written, &header).await;
+        }
+        // Sample against a fixed schedule, so the time spent writing doesn't add up
+        let mut next = Ticker::now();
+        loop {
+            let millis = next.duration_since_epoch().to_millis();
+            let mut line = heapless::String::<LINE_LENGTH>::new();
+            let written = match self.format {
+                PlotFormat::Csv => write!(line, "{millis}").and_then(|()| {
+                    self.sources
+                        .iter_mut()
+                        .try_for_each(|(_, source)| write!(line, ",{}", source()))
+                }),
+                PlotFormat::Teleplot => self
+                    .sources
+                    .iter_mut()
+                    .try_for_each(|(name, source)| writeln!(line, ">{name}:{millis}:{}", source())),
+            };
+            self.send(written, &line).await;
+            next += self.period;
+            if let Some(remaining) = next.checked_duration_since(Ticker::now()) {
+                Timer::delay(remaining).await;
+            }
+        }
+    }
+
+    async fn send(&mut self, written: core::fmt::Result, line: &str) {
+        if written.is_err() {
+            warn!(
+                "Plot line is longer than {} bytes, dropping it",
+                LINE_LENGTH
+            );
+            return;
+        }
+        self.serial.write(line.as_bytes()).await;
+        // Teleplot lines already end in a newline
+        if let PlotFormat::Csv = self.format {
+            self.serial.write(b"\r\n").await;
+        }
+    }
+}
@@ -0,0 +1,450 @@
+/*
+Async driver for the 2.4 GHz radio, sending datagrams in the micro:bit runtime's packet format so
+it can talk to boards running MakeCode or CODAL. Boards only hear each other when they share a
+frequency and a group, and the group is part of the address, so the radio filters it in hardware.
+It can match up to eight addresses, so a board can listen to several groups at once, like a
+teacher's board hearing every table in a classroom, while sending with the first of them:
+
+    radio.set_groups(&[1, 2, 3]);
+
+The group in each packet's header is checked against them too, so nothing from another group gets
+through while the receiver is switching over. The radio listens whenever it isn't sending, and
+the interrupt queues every good packet.
+`reliable` adds acks, retries and channel hopping on top, for boards that only talk to each other,
+`sync` shares one board's time with the others, and `sniffer` hears every packet on a channel.
+`stats` counts what was sent and received, for each board the reliable layer talks to too.
+*/
+
+use core::{
+    cell::{Cell, RefCell},
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering, compiler_fence},
+    task::Poll,
+};
+
+use critical_section::Mutex;
+use nrf52833_hal::pac::{CLOCK, Interrupt, NVIC, RADIO, interrupt, radio};
+use snafu::prelude::*;
+
+use crate::{
+    config::Priority,
+    time::Ticker,
+    utils::{AtomicWaker, TransferSlot, mpmc::Queue},
+};
+
+pub mod reliable;
+#[cfg(feature = "sniffer")]
+pub mod sniffer;
+pub mod stats;
+pub mod sync;
+
+pub const MAX_PAYLOAD: usize = 32;
+// Version, group and protocol, after the length byte
+const HEADER_SIZE: usize = 3;
+const BUF_SIZE: usize = 1 + HEADER_SIZE + MAX_PAYLOAD;
+const VERSION: u8 = 1;
+const PROTOCOL_DATAGRAM: u8 = 1;
+// "ubit", the base address every micro:bit uses
+const BASE_ADDRESS: u32 = 0x7562_6974;
+const RX_QUEUE_SIZE: usize = 4;
+// Tasks that can wait to send at once without polling
+const TX_WAITERS: usize = 4;
+// The radio matches up to eight addresses
+pub const MAX_GROUPS: usize = 8;
+
+static RX_QUEUE: Queue<Datagram, RX_QUEUE_SIZE> = Queue::new();
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+// Busy while TX_BUF holds a packet that hasn't finished sending
+static TX: TransferSlot<TX_WAITERS> = TransferSlot::new();
+// PREFIX0, PREFIX1 and RXADDRESSES for the interrupt to set before the receiver next starts, so
+// they never change while a packet is being sent
+static ADDRESSES: Mutex<Cell<Option<[u32; 3]>>> = Mutex::new(Cell::new(None));
+// Whether the radio was started to send or to receive, for the interrupt
+static SENDING: AtomicBool = AtomicBool::new(false);
+// The group sent with, which is also the first of GROUPS
+static GROUP: AtomicU8 = AtomicU8::new(0);
+// The groups listened to
+static GROUPS: Mutex<RefCell<heapless::Vec<u8, MAX_GROUPS>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+static FREQUENCY: AtomicU8 = AtomicU8::new(0);
+
+// DMA buffers. The RX buffer belongs to the interrupt, and the TX buffer is only written while
+// TX isn't busy.
+static mut RX_BUF: [u8; BUF_SIZE] = [0; BUF_SIZE];
+static mut TX_BUF: [u8; BUF_SIZE] = [0; BUF_SIZE];
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RadioError {
+    #[snafu(display("A datagram of {len} bytes is longer than the {MAX_PAYLOAD} that fit"))]
+    TooLong { len: usize },
+    #[snafu(display("Board {to} didn't acknowledge after {attempts} attempts"))]
+    NoAck { to: u8, attempts: u8 },
+    #[snafu(display("{} frames are already waiting for acks", reliable::MAX_PENDING))]
+    TooManyPending,
+}
+
+#[derive(Clone)]
+pub struct Datagram {
+    pub group: u8,
+    pub data: heapless::Vec<u8, MAX_PAYLOAD>,
+    // Signal strength in dBm, sampled as the packet arrived
+    pub rssi: i16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RadioConfig {
+    // MHz above 2400, up to 100. The micro:bit runtime defaults to 7.
+    pub frequency: u8,
+    pub group: u8,
+}
+
+impl RadioConfig {
+    pub const fn new() -> Self {
+        Self {
+            frequency: 7,
+            group: 0,
+        }
+    }
+}
+
+impl Default for RadioConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Shared by reference, so one task can send while another receives
+pub struct Radio {
+    _radio: RADIO,
+}
+
+impl Radio {
+    pub fn new(radio: RADIO, nvic: &mut NVIC, priority: Priority, config: RadioConfig) -> Self {
+        check_frequency(config.frequency);
+        start_hfxo();
+        GROUP.store(config.group, Ordering::Relaxed);
+        critical_section::with(|cs| {
+            let mut groups = GROUPS.borrow_ref_mut(cs);
+            groups.clear();
+            // Can't fail, it was just cleared
+            let _ = groups.push(config.group);
+        });
+        FREQUENCY.store(config.frequency, Ordering::Relaxed);
+        // SAFETY: Every value written is in range for its field
+        unsafe {
+            radio.mode.write(|w| w.mode().nrf_1mbit());
+            radio.txpower.write(|w| w.txpower()._0d_bm());
+            radio
+                .frequency
+                .write(|w| w.frequency().bits(config.frequency));
+            radio.pcnf0.write(|w| w.lflen().bits(8));
+            radio.pcnf1.write(|w| {
+                w.maxlen()
+                    .bits((BUF_SIZE - 1) as u8)
+                    .balen()
+                    .bits(4)
+                    .endian()
+                    .little()
+                    .whiteen()
+                    .enabled()
+            });
+            radio.base0.write(|w| w.bits(BASE_ADDRESS));
+            radio.prefix0.write(|w| w.ap0().bits(config.group));
+            radio.txaddress.write(|w| w.txaddress().bits(0));
+            radio.rxaddresses.write(|w| w.addr0().enabled());
+            radio.crccnf.write(|w| w.len().two());
+            radio.crcinit.write(|w| w.crcinit().bits(0xFFFF));
+            radio.crcpoly.write(|w| w.crcpoly().bits(0x11021));
+            radio.datawhiteiv.write(|w| w.datawhiteiv().bits(0x18));
+        }
+        radio.shorts.write(|w| {
+            w.ready_start()
+                .enabled()
+                .end_disable()
+                .enabled()
+                .address_rssistart()
+                .enabled()
+                .disabled_rssistop()
+                .enabled()
+        });
+        radio.intenset.write(|w| w.disabled().set());
+        // SAFETY: The priority is set before the interrupt is unmasked, and the handler only uses
+        // the driver's own statics
+        unsafe {
+            nvic.set_priority(Interrupt::RADIO, priority.bits());
+            NVIC::unmask(Interrupt::RADIO);
+        }
+        start(&radio, false);
+        Self { _radio: radio }
+    }
+
+    // Waits until the datagram has been sent
+    pub async fn send(&self, data: &[u8]) -> Result<(), RadioError> {
+        self.send_packet(PROTOCOL_DATAGRAM, data).await
+    }
+
+    async fn send_packet(&self, protocol: u8, data: &[u8]) -> Result<(), RadioError> {
+        ensure!(data.len() <= MAX_PAYLOAD, TooLongSnafu { len: data.len() });
+        let mut packet = [0; BUF_SIZE];
+        packet[0] = (HEADER_SIZE + data.len()) as u8;
+        packet[1] = VERSION;
+        packet[2] = GROUP.load(Ordering::Relaxed);
+        packet[3] = protocol;
+        packet[1 + HEADER_SIZE..][..data.len()].copy_from_slice(data);
+        // Waits for any other task's packet to go first. Stopping the receiver gets the interrupt
+        // to start sending, and TX is taken in the same critical section, so the interrupt can't
+        // restart the receiver first.
+        TX.transfer(|| {
+            // SAFETY: Nothing is being sent, so the DMA isn't reading the buffer
+            unsafe { (&raw mut TX_BUF).write_volatile(packet) };
+            radio().tasks_disable.write(|w| unsafe { w.bits(1) });
+        })
+        .await;
+        Ok(())
+    }
+
+    pub async fn recv(&self) -> Datagram {
+        poll_fn(|cx| {
+            RX_WAKER.register(cx.waker());
+            RX_QUEUE.dequeue().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    pub fn try_recv(&self) -> Option<Datagram> {
+        RX_QUEUE.dequeue()
+    }
+
+    // The group sent with
+    pub fn group(&self) -> u8 {
+        GROUP.load(Ordering::Relaxed)
+    }
+
+    pub fn groups(&self) -> heapless::Vec<u8, MAX_GROUPS> {
+        critical_section::with(|cs| GROUPS.borrow_ref(cs).clone())
+    }
+
+    // Sends with and listens to only this group
+    pub fn set_group(&self, group: u8) {
+        self.set_groups(&[group]);
+    }
+
+    // Listens to these groups from now on, instead of the ones before, and sends with the first.
+    // While a sniffer is running it keeps the addresses it set, and these take over when it's
+    // dropped.
+    pub fn set_groups(&self, groups: &[u8]) {
+        assert!(
+            (1..=MAX_GROUPS).contains(&groups.len()),
+            "The radio listens to between 1 and {} groups",
+            MAX_GROUPS
+        );
+        critical_section::with(|cs| {
+            // Can't fail, the length was checked
+            *GROUPS.borrow_ref_mut(cs) = heapless::Vec::from_slice(groups).unwrap_or_default();
+            GROUP.store(groups[0], Ordering::Relaxed);
+        });
+        debug!("Listening to {} groups from {}", groups.len(), groups[0]);
+        if !sniffing() {
+            listen_groups();
+        }
+    }
+
+    pub fn frequency(&self) -> u8 {
+        FREQUENCY.load(Ordering::Relaxed)
+    }
+
+    // Moves to another channel. A packet already being sent finishes on the old one.
+    pub fn set_frequency(&self, frequency: u8) {
+        check_frequency(frequency);
+        FREQUENCY.store(frequency, Ordering::Relaxed);
+        critical_section::with(|_| {
+            let radio = radio();
+            // SAFETY: The frequency was checked to be in range
+            radio
+                .frequency
+                .write(|w| unsafe { w.frequency().bits(frequency) });
+            // The receiver only picks the frequency up when it's restarted, which the interrupt
+            // does once it's stopped. When a send is pending, it's already being stopped.
+            if !TX.is_busy() {
+                radio.tasks_disable.write(|w| unsafe { w.bits(1) });
+            }
+        });
+    }
+}
+
+fn check_frequency(frequency: u8) {
+    assert!(
+        frequency <= 100,
+        "Radio frequency {} is out of range",
+        frequency
+    );
+}
+
+fn accepts(group: u8) -> bool {
+    critical_section::with(|cs| GROUPS.borrow_ref(cs).contains(&group))
+}
+
+// Sets the addresses to match, and restarts the receiver so it picks them up. The interrupt sets
+// them once the radio is stopped and any packet being sent has gone, since the packet's address
+// comes from PREFIX0 too.
+fn listen(prefixes: [u8; MAX_GROUPS], enabled: u32) {
+    let [p0, p1] = [0, 4].map(|i| u32::from_le_bytes([0, 1, 2, 3].map(|byte| prefixes[i + byte])));
+    critical_section::with(|cs| {
+        ADDRESSES.borrow(cs).set(Some([p0, p1, enabled]));
+        // When a send is pending, the receiver is already being stopped
+        if !TX.is_busy() {
+            radio().tasks_disable.write(|w| unsafe { w.bits(1) });
+        }
+    });
+}
+
+// Called with the radio disabled, before the receiver starts
+fn set_addresses(radio: &radio::RegisterBlock) {
+    let addresses = critical_section::with(|cs| ADDRESSES.borrow(cs).take());
+    if let Some([p0, p1, enabled]) = addresses {
+        // SAFETY: Any prefix is valid, and only the eight logical addresses' bits are set
+        radio.prefix0.write(|w| unsafe { w.bits(p0) });
+        radio.prefix1.write(|w| unsafe { w.bits(p1) });
+        radio.rxaddresses.write(|w| unsafe { w.bits(enabled) });
+    }
+}
+
+// Matches the addresses of the groups listened to. Sends go to the first, logical address 0.
+fn listen_groups() {
+    let (prefixes, len) = critical_section::with(|cs| {
+        let groups = GROUPS.borrow_ref(cs);
+        let mut prefixes = [0; MAX_GROUPS];
+        prefixes[..groups.len()].copy_from_slice(&groups);
+        (prefixes, groups.len())
+    });
+    listen(prefixes, (1 << len) - 1);
+}
+
+// The radio needs the crystal, since the RC oscillator isn't accurate enough for 2.4 GHz
+fn start_hfxo() {
+    // SAFETY: Only starts the crystal, which nothing else stops
+    let clock = unsafe { &*CLOCK::ptr() };
+    clock.events_hfclkstarted.reset();
+    clock.tasks_hfclkstart.write(|w| unsafe { w.bits(1) });
+    while clock.events_hfclkstarted.read().bits() == 0 {
+        core::hint::spin_loop();
+    }
+}
+
+fn radio() -> &'static radio::RegisterBlock {
+    // SAFETY: The registers are only touched by the driver, which owns RADIO
+    unsafe { &*RADIO::ptr() }
+}
+
+fn start(radio: &radio::RegisterBlock, send: bool) {
+    let buffer = if send {
+        &raw const TX_BUF as u32
+    } else {
+        &raw mut RX_BUF as u32
+    };
+    // SAFETY: Both buffers are static, and only touched by the DMA until the radio is disabled
+    radio.packetptr.write(|w| unsafe { w.bits(buffer) });
+    SENDING.store(send, Ordering::Relaxed);
+    compiler_fence(Ordering::SeqCst);
+    if send {
+        radio.tasks_txen.write(|w| unsafe { w.bits(1) });
+    } else {
+        radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+    }
+}
+
+fn receive(radio: &radio::RegisterBlock) {
+    let received = Ticker::now();
+    // SAFETY: The radio is disabled, so the DMA is done with the buffer
+    let packet = unsafe { (&raw const RX_BUF).read_volatile() };
+    let len = usize::from(packet[0]);
+    if len < HEADER_SIZE || packet[1] != VERSION {
+        debug!("Ignoring a packet that isn't from a micro:bit");
+        return;
+    }
+    let rssi = -i16::from(radio.rssisample.read().rssisample().bits());
+    stats::update_radio(|stats| {
+        stats.received = stats.received.saturating_add(1);
+        stats.rssi.add(rssi);
+    });
+    if !accepts(packet[2]) {
+        debug!("Ignoring a packet for group {}", packet[2]);
+        return;
+    }
+    let payload = &packet[1 + HEADER_SIZE..1 + len];
+    match packet[3] {
+        PROTOCOL_DATAGRAM => {}
+        reliable::PROTOCOL => return reliable::on_frame(payload, rssi),
+        sync::PROTOCOL => return sync::on_beacon(payload, received),
+        protocol => {
+            debug!("Ignoring a packet with protocol {}", protocol);
+            return;
+        }
+    }
+    let datagram = Datagram {
+        group: packet[2],
+        // Can't fail, since the radio stops at the maximum length
+        data: heapless::Vec::from_slice(payload).unwrap_or_default(),
+        rssi,
+    };
+    if RX_QUEUE.enqueue(datagram).is_err() {
+        warn!("Radio receive queue is full, dropping a datagram");
+        stats::update_radio(|stats| stats.dropped = stats.dropped.saturating_add(1));
+    }
+    RX_WAKER.wake();
+}
+
+// Whether the sniffer took the packet
+#[cfg(feature = "sniffer")]
+fn sniff(radio: &radio::RegisterBlock, crc_ok: bool) -> bool {
+    sniffer::on_packet(radio, crc_ok)
+}
+
+#[cfg(feature = "sniffer")]
+fn sniffing() -> bool {
+    sniffer::is_active()
+}
+
+#[cfg(not(feature = "sniffer"))]
+fn sniffing() -> bool {
+    false
+}
+
+#[cfg(not(feature = "sniffer"))]
+fn sniff(_radio: &radio::RegisterBlock, _crc_ok: bool) -> bool {
+    false
+}
+
+#[interrupt]
+fn RADIO() {
+    let radio = radio();
+    if radio.events_disabled.read().bits() == 0 {
+        return;
+    }
+    radio.events_disabled.reset();
+    if SENDING.load(Ordering::Relaxed) {
+        stats::update_radio(|stats| stats.sent = stats.sent.saturating_add(1));
+        TX.finish();
+    } else if radio.events_end.read().bits() != 0 {
+        // Otherwise the receiver was stopped part way, to send
+        let crc_ok = radio.crcstatus.read().crcstatus().is_crcok();
+        if sniff(radio, crc_ok) {
+            // The sniffer has it
+        } else if crc_ok {
+            receive(radio);
+        } else {
+            debug!("Dropping a packet with a bad CRC");
+            stats::update_radio(|stats| stats.crc_errors = stats.crc_errors.saturating_add(1));
+        }
+    }
+    radio.events_end.reset();
+    let send = TX.is_busy();
+    if send {
+        sync::on_send();
+    } else {
+        set_addresses(radio);
+    }
+    start(radio, send);
+}
@@ -0,0 +1,285 @@
+/*
+Reliability layer for links between boards running this crate. Every board has an address, and a
+frame sent to one board is acknowledged by it, and resent until it is, up to a number of retries.
+Frames carry a sequence number, so a resend whose ack got lost isn't delivered twice. Frames to
+`BROADCAST` go to every board in the group, and aren't acknowledged.
+
+With channel hopping, the sender moves to the next channel of the hop list on every attempt, and a
+receiver that hasn't heard anything for a while moves on to the next one too. Sweeping the list
+quickly while receivers dwell on a channel means a sender finds a receiver within one sweep, as
+long as it gets at least as many attempts as there are channels. Boards settle on a channel that
+works, and only move off it once it stops working.
+*/
+
+use core::{cell::Cell, future::poll_fn, task::Poll};
+
+use snafu::prelude::*;
+
+use super::{NoAckSnafu, Radio, RadioError, TooLongSnafu, TooManyPendingSnafu, stats};
+use crate::{
+    time::{TickDuration, timeout},
+    utils::{AtomicWaker, ReplySlots, mpmc::Queue},
+};
+
+// Doesn't clash with the micro:bit runtime's protocols, so MakeCode boards ignore these frames
+pub(super) const PROTOCOL: u8 = 0x52;
+// Kind, sender, receiver and sequence number
+const FRAME_HEADER: usize = 4;
+pub const MAX_PAYLOAD: usize = super::MAX_PAYLOAD - FRAME_HEADER;
+pub const BROADCAST: u8 = 0xFF;
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+// How many senders' last sequence numbers are remembered to drop duplicates
+const MAX_PEERS: usize = 8;
+const FRAME_QUEUE_SIZE: usize = 4;
+// Frames that can be waiting for their acks at once, across every task sending
+pub const MAX_PENDING: usize = 4;
+
+static FRAMES: Queue<Frame, FRAME_QUEUE_SIZE> = Queue::new();
+static FRAME_WAKER: AtomicWaker = AtomicWaker::new();
+// Each frame waiting for its ack, as (from, to, sequence number) of the ack
+static ACKS: ReplySlots<(u8, u8, u8), MAX_PENDING> = ReplySlots::new();
+
+#[derive(Clone)]
+struct Frame {
+    from: u8,
+    to: u8,
+    seq: u8,
+    data: heapless::Vec<u8, MAX_PAYLOAD>,
+    rssi: i16,
+}
+
+#[derive(Clone)]
+pub struct Packet {
+    pub from: u8,
+    pub data: heapless::Vec<u8, MAX_PAYLOAD>,
+    // Signal strength in dBm, sampled as the packet arrived
+    pub rssi: i16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReliableConfig {
+    // This board's address, which can't be `BROADCAST`
+    pub address: u8,
+    // Resends after the first attempt, before giving up
+    pub retries: u8,
+    // How long to wait for each ack
+    pub ack_timeout: TickDuration,
+    // Channels to hop between, in MHz above 2400. Empty to stay on the radio's channel.
+    pub hops: &'static [u8],
+    // How long a receiver stays on a channel without hearing anything before hopping
+    pub dwell: TickDuration,
+}
+
+impl ReliableConfig {
+    pub const fn new(address: u8) -> Self {
+        Self {
+            address,
+            retries: 3,
+            ack_timeout: TickDuration::millis(10),
+            hops: &[],
+            dwell: TickDuration::millis(500),
+        }
+    }
+
+    // Uses this chip's short ID as the address, so boards flashed with the same firmware get
+    // different ones
+    pub fn for_this_device() -> Self {
+        let [low, high] = crate::device::short_id().to_le_bytes();
+        match low ^ high {
+            BROADCAST => Self::new(BROADCAST - 1),
+            address => Self::new(address),
+        }
+    }
+}
+
+impl Default for ReliableConfig {
+    fn default() -> Self {
+        Self::for_this_device()
+    }
+}
+
+// Shared by reference like `Radio`. Acks are sent by `recv`, so a board that should be reachable
+// needs a task waiting in it.
+pub struct ReliableRadio<'a> {
+    radio: &'a Radio,
+    config: ReliableConfig,
+    seq: Cell<u8>,
+    hop: Cell<usize>,
+    // Last sequence number seen from each sender
+    peers: Cell<heapless::LinearMap<u8, u8, MAX_PEERS>>,
+}
+
+impl<'a> ReliableRadio<'a> {
+    pub fn new(radio: &'a Radio, config: ReliableConfig) -> Self {
+        assert!(
+            config.address != BROADCAST,
+            "The broadcast address can't be a board's address"
+        );
+        if let Some(&first) = config.hops.first() {
+            radio.set_frequency(first);
+        }
+        Self {
+            radio,
+            config,
+            seq: Cell::new(0),
+            hop: Cell::new(0),
+            peers: Cell::new(heapless::LinearMap::new()),
+        }
+    }
+
+    // Waits until the receiver has acknowledged the frame. Broadcasts are sent once. Several tasks
+    // can send at once, and each gets its own ack.
+    pub async fn send_to(&self, to: u8, data: &[u8]) -> Result<(), RadioError> {
+        ensure!(data.len() <= MAX_PAYLOAD, TooLongSnafu { len: data.len() });
+        let seq = self.seq.get().wrapping_add(1);
+        self.seq.set(seq);
+        let mut frame = heapless::Vec::<u8, { super::MAX_PAYLOAD }>::new();
+        // Can't fail, since the length was checked
+        let _ = frame.extend_from_slice(&[KIND_DATA, self.config.address, to, seq]);
+        let _ = frame.extend_from_slice(data);
+        if to == BROADCAST {
+            return self.radio.send_packet(PROTOCOL, &frame).await;
+        }
+        // Claimed before the first send, so an ack that comes straight back isn't missed
+        let ack = ACKS
+            .expect((to, self.config.address, seq))
+            .context(TooManyPendingSnafu)?;
+        let attempts = self.config.retries.saturating_add(1);
+        stats::update_link(to, |link| link.sent = link.sent.saturating_add(1));
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                self.hop();
+                stats::update_link(to, |link| link.retries = link.retries.saturating_add(1));
+            }
+            self.radio.send_packet(PROTOCOL, &frame).await?;
+            if timeout(self.config.ack_timeout, ack.wait()).await.is_ok() {
+                stats::update_link(to, |link| link.acked = link.acked.saturating_add(1));
+                return Ok(());
+            }
+            debug!(
+                "No ack from {} for frame {}, attempt {}",
+                to,
+                seq,
+                attempt + 1
+            );
+        }
+        stats::update_link(to, |link| link.failed = link.failed.saturating_add(1));
+        NoAckSnafu { to, attempts }.fail()
+    }
+
+    // Waits for the next new frame for this board, acknowledging it. Hops while nothing arrives.
+    pub async fn recv(&self) -> Packet {
+        loop {
+            let frame = if self.config.hops.is_empty() {
+                next_frame().await
+            } else {
+                match timeout(self.config.dwell, next_frame()).await {
+                    Ok(frame) => frame,
+                    Err(_) => {
+                        self.hop();
+                        continue;
+                    }
+                }
+            };
+            if frame.to != self.config.address && frame.to != BROADCAST {
+                continue;
+            }
+            if frame.to != BROADCAST {
+                // Acked even if it's a duplicate, since the first ack may have been lost. A
+                // `send_to` in another task may be sending too, and the ack waits its turn.
+                let ack = [KIND_ACK, self.config.address, frame.from, frame.seq];
+                if let Err(err) = self.radio.send_packet(PROTOCOL, &ack).await {
+                    warn!(
+                        "Couldn't ack frame {} from {}: {}",
+                        frame.seq, frame.from, err
+                    );
+                }
+            }
+            let duplicate = self.is_duplicate(&frame);
+            stats::update_link(frame.from, |link| {
+                link.rssi.add(frame.rssi);
+                if duplicate {
+                    link.duplicates = link.duplicates.saturating_add(1);
+                } else {
+                    link.received = link.received.saturating_add(1);
+                }
+            });
+            if duplicate {
+                debug!("Dropping resent frame {} from {}", frame.seq, frame.from);
+                continue;
+            }
+            return Packet {
+                from: frame.from,
+                data: frame.data,
+                rssi: frame.rssi,
+            };
+        }
+    }
+
+    fn is_duplicate(&self, frame: &Frame) -> bool {
+        let mut peers = self.peers.take();
+        let duplicate = peers.get(&frame.from) == Some(&frame.seq);
+        if !duplicate && peers.insert(frame.from, frame.seq).is_err() {
+            // Forget an arbitrary peer to make room, which at worst lets one resend through
+            let forget = peers.keys().next().copied();
+            if let Some(forget) = forget {
+                peers.remove(&forget);
+            }
+            let _ = peers.insert(frame.from, frame.seq);
+        }
+        self.peers.set(peers);
+        duplicate
+    }
+
+    fn hop(&self) {
+        let hops = self.config.hops;
+        if hops.is_empty() {
+            return;
+        }
+        let hop = (self.hop.get() + 1) % hops.len();
+        self.hop.set(hop);
+        trace!("Hopping to channel {}", hops[hop]);
+        self.radio.set_frequency(hops[hop]);
+    }
+}
+
+async fn next_frame() -> Frame {
+    poll_fn(|cx| {
+        FRAME_WAKER.register(cx.waker());
+        FRAMES.dequeue().map_or(Poll::Pending, Poll::Ready)
+    })
+    .await
+}
+
+// Called from the radio interrupt with the packet's payload
+pub(super) fn on_frame(payload: &[u8], rssi: i16) {
+    let [kind, from, to, seq, data @ ..] = payload else {
+        debug!("Ignoring a frame that's too short");
+        return;
+    };
+    match *kind {
+        KIND_ACK => {
+            if !ACKS.deliver((*from, *to, *seq)) {
+                debug!("Ignoring an ack for frame {} nobody is waiting for", seq);
+            }
+        }
+        KIND_DATA => {
+            let frame = Frame {
+                from: *from,
+                to: *to,
+                seq: *seq,
+                // Can't fail, since the radio stops at the maximum length
+                data: heapless::Vec::from_slice(data).unwrap_or_default(),
+                rssi,
+            };
+            if FRAMES.enqueue(frame).is_err() {
+                warn!("Radio frame queue is full, dropping a frame");
+                stats::update_radio(|stats| stats.dropped = stats.dropped.saturating_add(1));
+            }
+            FRAME_WAKER.wake();
+        }
+        kind => debug!("Ignoring a frame of kind {}", kind),
+    }
+}
@@ -0,0 +1,169 @@
+/*
+Turns a spare board into a radio sniffer, which hands over every packet heard on its channel,
+including ones with a bad CRC and ones that aren't from a micro:bit, and can stream them over the
+serial port for a host to decode. While it's sniffing, the radio's other users hear nothing.
+
+The radio only takes packets whose address it's been told to match, and the group is part of the
+address, so no radio hears every group at once. It can match eight addresses though, so the
+sniffer listens to up to eight groups at a time, and sweeping through all 256 takes 32 steps.
+
+Each packet goes over the serial port as a frame of
+    0xA5 0x5A, length, timestamp (u64), RSSI (i8), flags, frequency, group, packet, CRC (u16)
+where the length counts everything after it but the CRC, the flags have bit 0 set when the radio's
+CRC was good, the packet is the version, group, protocol and payload as they came off the air, and
+the CRC is the CRC-16 of the bytes the length counts. Numbers are little endian.
+
+    let sniffer = Sniffer::new(&radio, &[0, 1, 2]);
+    sniffer.stream(&mut serial).await;
+*/
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use nrf52833_hal::pac::radio;
+
+pub use super::MAX_GROUPS;
+use super::{BUF_SIZE, RX_BUF, Radio, listen, listen_groups};
+use crate::{
+    crc::crc16,
+    serial::Serial,
+    time::{TickInstant, Ticker},
+    utils::{AtomicWaker, mpmc::Queue},
+};
+
+const QUEUE_SIZE: usize = 8;
+const SYNC: [u8; 2] = [0xA5, 0x5A];
+// Timestamp, RSSI, flags, frequency and group
+const META_SIZE: usize = 12;
+const FLAG_CRC_OK: u8 = 1;
+
+static PACKETS: Queue<Sniffed, QUEUE_SIZE> = Queue::new();
+static WAKER: AtomicWaker = AtomicWaker::new();
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone)]
+pub struct Sniffed {
+    pub received: TickInstant,
+    // Signal strength in dBm, sampled as the packet arrived
+    pub rssi: i16,
+    pub crc_ok: bool,
+    pub frequency: u8,
+    // The group whose address matched
+    pub group: u8,
+    // Everything after the length byte, as received
+    pub data: heapless::Vec<u8, { BUF_SIZE - 1 }>,
+}
+
+impl Sniffed {
+    // The frame it's streamed as, described at the top
+    pub fn to_frame(&self) -> heapless::Vec<u8, { BUF_SIZE + META_SIZE + 4 }> {
+        let mut frame = heapless::Vec::new();
+        // Can't fail, the frame has room for the biggest packet
+        let _ = frame.extend_from_slice(&SYNC);
+        let _ = frame.push((META_SIZE + self.data.len()) as u8);
+        let _ = frame.extend_from_slice(&self.received.ticks().to_le_bytes());
+        let _ = frame.push(self.rssi.clamp(i8::MIN.into(), i8::MAX.into()) as u8);
+        let _ = frame.push(if self.crc_ok { FLAG_CRC_OK } else { 0 });
+        let _ = frame.push(self.frequency);
+        let _ = frame.push(self.group);
+        let _ = frame.extend_from_slice(&self.data);
+        let crc = crc16(&frame[SYNC.len() + 1..]);
+        let _ = frame.extend_from_slice(&crc.to_le_bytes());
+        frame
+    }
+}
+
+// Puts the radio back to normal when dropped
+pub struct Sniffer<'a> {
+    radio: &'a Radio,
+}
+
+impl<'a> Sniffer<'a> {
+    pub fn new(radio: &'a Radio, groups: &[u8]) -> Self {
+        ACTIVE.store(true, Ordering::Release);
+        let sniffer = Self { radio };
+        sniffer.set_groups(groups);
+        sniffer
+    }
+
+    // Listens to these groups from now on, instead of the ones before
+    pub fn set_groups(&self, groups: &[u8]) {
+        assert!(
+            (1..=MAX_GROUPS).contains(&groups.len()),
+            "The sniffer listens to between 1 and {} groups",
+            MAX_GROUPS
+        );
+        let mut prefixes = [0; MAX_GROUPS];
+        prefixes[..groups.len()].copy_from_slice(groups);
+        listen(prefixes, (1 << groups.len()) - 1);
+        debug!("Sniffing {} groups from {}", groups.len(), groups[0]);
+    }
+
+    pub async fn next(&self) -> Sniffed {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            PACKETS.dequeue().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    pub fn try_next(&self) -> Option<Sniffed> {
+        PACKETS.dequeue()
+    }
+
+    // Sends every packet heard over the serial port, framed as described at the top
+    pub async fn stream(&self, serial: &mut Serial) -> ! {
+        info!("Sniffing on {} MHz above 2400", self.radio.frequency());
+        loop {
+            let packet = self.next().await;
+            serial.write(&packet.to_frame()).await;
+        }
+    }
+}
+
+impl Drop for Sniffer<'_> {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::Release);
+        listen_groups();
+        while PACKETS.dequeue().is_some() {}
+    }
+}
+
+pub(super) fn is_active() -> bool {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+// Called from the radio interrupt with every packet received. Returns whether the sniffer took it.
+pub(super) fn on_packet(radio: &radio::RegisterBlock, crc_ok: bool) -> bool {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return false;
+    }
+    let received = Ticker::now();
+    // SAFETY: The radio is disabled, so the DMA is done with the buffer
+    let packet = unsafe { (&raw const RX_BUF).read_volatile() };
+    // A bad length byte can claim more than the radio stored
+    let len = usize::from(packet[0]).min(BUF_SIZE - 1);
+    let matched = radio.rxmatch.read().rxmatch().bits() as usize;
+    let prefixes = if matched < 4 {
+        radio.prefix0.read().bits()
+    } else {
+        radio.prefix1.read().bits()
+    };
+    let sniffed = Sniffed {
+        received,
+        rssi: -i16::from(radio.rssisample.read().rssisample().bits()),
+        crc_ok,
+        frequency: super::FREQUENCY.load(Ordering::Relaxed),
+        group: prefixes.to_le_bytes()[matched % 4],
+        // Can't fail, the length was clamped
+        data: heapless::Vec::from_slice(&packet[1..1 + len]).unwrap_or_default(),
+    };
+    if PACKETS.enqueue(sniffed).is_err() {
+        warn!("Sniffer queue is full, dropping a packet");
+    }
+    WAKER.wake();
+    true
+}
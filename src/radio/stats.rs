@@ -0,0 +1,147 @@
+/*
+Counters for how well the radio is doing, for showing link health or checking loss rates. The
+driver counts every packet the radio sends and receives, and the reliable layer counts frames,
+acks and retries for each board it talks to. They count from boot, or from the last reset.
+*/
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+// Boards whose links are tracked. Once full, new boards aren't counted.
+pub const MAX_LINKS: usize = 8;
+pub const RSSI_BUCKETS: usize = 8;
+// The lowest bucket's top, in dBm, with each bucket above it 10 dBm wide
+const RSSI_FLOOR: i16 = -90;
+const RSSI_STEP: i16 = 10;
+
+// How many packets arrived at each signal strength
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RssiHistogram {
+    // Below -90 dBm, then 10 dBm at a time, up to -30 dBm and above
+    pub buckets: [u32; RSSI_BUCKETS],
+}
+
+impl RssiHistogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; RSSI_BUCKETS],
+        }
+    }
+
+    pub fn add(&mut self, rssi: i16) {
+        let bucket = ((rssi - RSSI_FLOOR).div_euclid(RSSI_STEP) + 1)
+            .clamp(0, RSSI_BUCKETS as i16 - 1) as usize;
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+    }
+
+    // The lowest strength that lands in the bucket, or `None` for the lowest one
+    pub fn bucket_floor(bucket: usize) -> Option<i16> {
+        (bucket > 0).then(|| RSSI_FLOOR + (bucket as i16 - 1) * RSSI_STEP)
+    }
+
+    pub fn total(&self) -> u32 {
+        self.buckets.iter().sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RadioStats {
+    // Every packet, including acks and beacons
+    pub sent: u32,
+    pub received: u32,
+    pub crc_errors: u32,
+    // Received with nowhere to put them, since a queue was full
+    pub dropped: u32,
+    pub rssi: RssiHistogram,
+}
+
+impl RadioStats {
+    pub const fn new() -> Self {
+        Self {
+            sent: 0,
+            received: 0,
+            crc_errors: 0,
+            dropped: 0,
+            rssi: RssiHistogram::new(),
+        }
+    }
+}
+
+// One board's link, as the reliable layer sees it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStats {
+    // Frames sent to the board, however many attempts each took
+    pub sent: u32,
+    pub acked: u32,
+    // Attempts after the first
+    pub retries: u32,
+    // Frames that ran out of retries
+    pub failed: u32,
+    pub received: u32,
+    // Resends of frames already received
+    pub duplicates: u32,
+    pub rssi: RssiHistogram,
+}
+
+impl LinkStats {
+    // Attempts that weren't acked, out of every attempt, in percent
+    pub fn loss_percent(&self) -> u32 {
+        let attempts = u64::from(self.sent) + u64::from(self.retries);
+        if attempts == 0 {
+            return 0;
+        }
+        let lost = attempts.saturating_sub(u64::from(self.acked));
+        (lost * 100 / attempts) as u32
+    }
+}
+
+static RADIO: Mutex<RefCell<RadioStats>> = Mutex::new(RefCell::new(RadioStats::new()));
+static LINKS: Mutex<RefCell<heapless::LinearMap<u8, LinkStats, MAX_LINKS>>> =
+    Mutex::new(RefCell::new(heapless::LinearMap::new()));
+
+pub fn radio() -> RadioStats {
+    critical_section::with(|cs| *RADIO.borrow_ref(cs))
+}
+
+// `None` if nothing has been sent to or heard from the board
+pub fn link(address: u8) -> Option<LinkStats> {
+    critical_section::with(|cs| LINKS.borrow_ref(cs).get(&address).copied())
+}
+
+// Every board with a link, and its stats
+pub fn links() -> heapless::Vec<(u8, LinkStats), MAX_LINKS> {
+    critical_section::with(|cs| {
+        LINKS
+            .borrow_ref(cs)
+            .iter()
+            .map(|(&address, &stats)| (address, stats))
+            .collect()
+    })
+}
+
+pub fn reset() {
+    critical_section::with(|cs| {
+        *RADIO.borrow_ref_mut(cs) = RadioStats::new();
+        LINKS.borrow_ref_mut(cs).clear();
+    });
+}
+
+pub(super) fn update_radio(f: impl FnOnce(&mut RadioStats)) {
+    critical_section::with(|cs| f(&mut RADIO.borrow_ref_mut(cs)));
+}
+
+pub(super) fn update_link(address: u8, f: impl FnOnce(&mut LinkStats)) {
+    critical_section::with(|cs| {
+        let mut links = LINKS.borrow_ref_mut(cs);
+        if !links.contains_key(&address) && links.insert(address, LinkStats::default()).is_err() {
+            return;
+        }
+        if let Some(stats) = links.get_mut(&address) {
+            f(stats);
+        }
+    });
+}
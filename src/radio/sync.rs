@@ -0,0 +1,156 @@
+/*
+Time synchronization between boards over the radio. One board, the leader, broadcasts its own
+`TickInstant` every so often, and every other board in the group learns the offset from its own
+ticks to the leader's, so `synced_now` reads the same on all of them, to within a few ticks.
+
+The time goes into the beacon in the radio interrupt, just before the radio starts sending, and is
+read on the other side as soon as the packet arrives, so neither end's task scheduling gets into
+it. What's left is the radio's ramp-up and the time on air, which are the same for every beacon and
+taken off as a constant. The oscillators still drift apart between beacons, by up to about 40 ppm
+between two crystals, which the beacon period keeps to a tick or so.
+
+A follower steps straight to the first offset it hears, and to any that's far off, and otherwise
+moves a quarter of the way there on each beacon, which smooths out the jitter of single beacons.
+`synced_now` can jump when it steps, so it isn't monotonic the way `Ticker::now` is.
+
+    // On the leader
+    sync::lead(&radio, TickDuration::millis(1000)).await;
+    // Anywhere
+    let now = sync::synced_now();
+*/
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{HEADER_SIZE, Radio, TX_BUF};
+use crate::{
+    time::{PeriodicTimer, TickDuration, TickInstant, Ticker},
+    utils::LockCell,
+};
+
+// Doesn't clash with the micro:bit runtime's protocols or the reliable layer's
+pub(super) const PROTOCOL: u8 = 0x53;
+const BEACON_SIZE: usize = 8;
+// Ramp-up and time on air of a beacon, about 300 µs
+const LATENCY_TICKS: i64 = 10;
+// Further off than this and the offset steps rather than slews, about 1 ms
+const STEP_TICKS: i64 = 32;
+// Beacons missed before a follower counts as out of sync
+const MISSED_BEACONS: u64 = 4;
+
+#[derive(Clone, Copy)]
+struct Sync {
+    // Leader's ticks less this board's
+    offset: i64,
+    // When the last beacon arrived, in this board's ticks, or `None` before the first
+    last_beacon: Option<u64>,
+    // The leader's period, learned from the gap between beacons
+    period: u64,
+}
+
+static SYNC: LockCell<Sync> = LockCell::new(Sync {
+    offset: 0,
+    last_beacon: None,
+    period: 0,
+});
+// The leader ignores beacons, in case another board leads too
+static LEADING: AtomicBool = AtomicBool::new(false);
+
+// The leader's time, or this board's own before any beacon has been heard
+pub fn synced_now() -> TickInstant {
+    let offset = SYNC.with_lock(|sync| sync.get().offset);
+    TickInstant::from_ticks(Ticker::now().ticks().saturating_add_signed(offset))
+}
+
+// Whether a beacon has been heard recently, or this board is the leader
+pub fn is_synced() -> bool {
+    if LEADING.load(Ordering::Relaxed) {
+        return true;
+    }
+    let now = Ticker::now().ticks();
+    SYNC.with_lock(|sync| {
+        let sync = sync.get();
+        sync.last_beacon.is_some_and(|last| {
+            // Before the period is known, the first beacon counts for a second
+            let period = if sync.period == 0 { 32768 } else { sync.period };
+            // A beacon heard since `now` was read is as recent as can be
+            now.saturating_sub(last) <= period * MISSED_BEACONS
+        })
+    })
+}
+
+// How far this board's ticks are behind the leader's
+pub fn offset_ticks() -> i64 {
+    SYNC.with_lock(|sync| sync.get().offset)
+}
+
+// Makes this board the leader, broadcasting its time every period. A shorter period keeps the
+// followers closer, at the cost of the radio's time.
+pub async fn lead(radio: &Radio, period: TickDuration) -> ! {
+    LEADING.store(true, Ordering::Relaxed);
+    SYNC.with_lock(|sync| {
+        sync.set(Sync {
+            offset: 0,
+            last_beacon: None,
+            period: 0,
+        })
+    });
+    info!("Leading time sync every {} ms", period.to_millis());
+    let mut timer = core::pin::pin!(PeriodicTimer::new(period));
+    loop {
+        // The time is filled in by the interrupt, as the beacon goes out
+        if let Err(err) = radio.send_packet(PROTOCOL, &[0; BEACON_SIZE]).await {
+            warn!("Couldn't send a time beacon: {}", err);
+        }
+        timer.as_mut().tick().await;
+    }
+}
+
+// Called from the radio interrupt as it starts sending, to put the time in a beacon
+pub(super) fn on_send() {
+    // SAFETY: The radio is disabled, so the DMA isn't using the buffer, and tasks only write it
+    // while nothing is pending to send
+    let mut packet = unsafe { (&raw const TX_BUF).read_volatile() };
+    if packet[3] != PROTOCOL {
+        return;
+    }
+    let now = Ticker::now().ticks();
+    packet[1 + HEADER_SIZE..][..BEACON_SIZE].copy_from_slice(&now.to_le_bytes());
+    // SAFETY: As above
+    unsafe { (&raw mut TX_BUF).write_volatile(packet) };
+}
+
+// Called from the radio interrupt with a beacon's payload and when it arrived
+pub(super) fn on_beacon(payload: &[u8], received: TickInstant) {
+    if LEADING.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(bytes) = <[u8; BEACON_SIZE]>::try_from(payload) else {
+        debug!("Ignoring a time beacon of the wrong size");
+        return;
+    };
+    let local = received.ticks();
+    let leader = u64::from_le_bytes(bytes) as i64 + LATENCY_TICKS;
+    let measured = leader - local as i64;
+    SYNC.with_lock(|sync| {
+        let mut state = sync.get();
+        let error = measured - state.offset;
+        match state.last_beacon {
+            Some(last) if error.abs() <= STEP_TICKS => {
+                // Rounded, so the last few ticks get corrected too
+                state.offset += (error + 2 * error.signum()) / 4;
+                state.period = local - last;
+            }
+            Some(last) => {
+                debug!("Time sync stepping by {} ticks", error);
+                state.offset = measured;
+                state.period = local - last;
+            }
+            None => {
+                info!("Time synced, offset {} ticks", measured);
+                state.offset = measured;
+            }
+        }
+        state.last_beacon = Some(local);
+        sync.set(state);
+    });
+}
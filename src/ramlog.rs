@@ -0,0 +1,117 @@
+/*
+Log ring in RAM that survives a soft reset, for finding out what happened before a crash in the
+field without a debug probe. It lives in the `.uninit` section, which the runtime doesn't zero at
+boot, and a magic number tells a warm boot (where the contents are from before the reset) from a
+cold one (where they're garbage). It only survives System OFF if `retention::retain_uninit` is
+called before going to sleep. Independent of defmt, so it can be dumped as text over any
+`fmt::Write`, like the serial port.
+*/
+
+use core::{fmt, mem::MaybeUninit};
+
+const SIZE: usize = 4096;
+const MAGIC: u32 = 0x524C_4F47;
+// How much is copied out at a time while dumping, so writers aren't blocked for the whole dump
+const DUMP_CHUNK: usize = 64;
+
+// Only integers, so whatever the RAM held is a valid value
+#[repr(C)]
+struct Ring {
+    magic: u32,
+    // Where the next byte goes
+    head: u32,
+    len: u32,
+    buf: [u8; SIZE],
+}
+
+#[unsafe(link_section = ".uninit.ramlog")]
+static mut RING: MaybeUninit<Ring> = MaybeUninit::uninit();
+
+fn with_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+    critical_section::with(|_| {
+        // SAFETY: Only accessed inside a critical section, and every bit pattern is a valid ring
+        f(unsafe { &mut *(&raw mut RING).cast::<Ring>() })
+    })
+}
+
+// Appends formatted text with `write!`, or use the `ram_log!` macro
+pub struct RamLog;
+
+impl RamLog {
+    // Call once at boot, before anything is logged. Returns whether the contents from before the
+    // reset were kept.
+    pub fn init() -> bool {
+        with_ring(|ring| {
+            let valid =
+                ring.magic == MAGIC && (ring.head as usize) < SIZE && (ring.len as usize) <= SIZE;
+            if !valid {
+                ring.magic = MAGIC;
+                ring.head = 0;
+                ring.len = 0;
+            }
+            valid
+        })
+    }
+
+    // Overwrites the oldest bytes once full
+    pub fn write(bytes: &[u8]) {
+        with_ring(|ring| {
+            for &byte in bytes {
+                ring.buf[ring.head as usize] = byte;
+                ring.head = (ring.head + 1) % SIZE as u32;
+            }
+            ring.len = (ring.len as usize + bytes.len()).min(SIZE) as u32;
+        });
+    }
+
+    pub fn clear() {
+        with_ring(|ring| ring.len = 0);
+    }
+
+    pub fn len() -> usize {
+        with_ring(|ring| ring.len as usize)
+    }
+
+    pub fn is_empty() -> bool {
+        Self::len() == 0
+    }
+
+    // Writes the contents oldest first. Anything that isn't ASCII comes out as '?'.
+    pub fn dump(out: &mut impl fmt::Write) -> fmt::Result {
+        let (start, len) = with_ring(|ring| {
+            (
+                (ring.head + SIZE as u32 - ring.len) as usize % SIZE,
+                ring.len,
+            )
+        });
+        let mut chunk = [0; DUMP_CHUNK];
+        for offset in (0..len as usize).step_by(DUMP_CHUNK) {
+            let count = DUMP_CHUNK.min(len as usize - offset);
+            with_ring(|ring| {
+                for (i, byte) in chunk[..count].iter_mut().enumerate() {
+                    *byte = ring.buf[(start + offset + i) % SIZE];
+                }
+            });
+            for &byte in &chunk[..count] {
+                out.write_char(if byte.is_ascii() { byte as char } else { '?' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for RamLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Self::write(s.as_bytes());
+        Ok(())
+    }
+}
+
+// Like `writeln!` to the RAM log, which can't fail
+#[macro_export]
+macro_rules! ram_log {
+    ($($arg:tt)*) => {{
+        use ::core::fmt::Write as _;
+        let _ = ::core::writeln!($crate::ramlog::RamLog, $($arg)*);
+    }};
+}
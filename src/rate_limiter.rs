@@ -0,0 +1,54 @@
+/*
+Rate limiter for throttling chatty producers against the tick clock. It lets bursts of up to the
+maximum through at once, and after that one per period / max, using the generic cell rate
+algorithm: a single "theoretical arrival time" that each acquire pushes forward.
+*/
+
+use core::cell::Cell;
+
+use crate::time::{TickDuration, TickInstant, Ticker, Timer};
+
+// Shared by reference, so several tasks can draw from the same limit
+pub struct RateLimiter {
+    interval: TickDuration,
+    burst: TickDuration,
+    next: Cell<TickInstant>,
+}
+
+impl RateLimiter {
+    pub fn new(max_per_period: u32, period: TickDuration) -> Self {
+        assert!(max_per_period > 0, "A rate limiter has to allow something");
+        let interval = period / max_per_period;
+        Self {
+            interval,
+            burst: period - interval,
+            next: Cell::new(Ticker::now()),
+        }
+    }
+
+    // The time until the next acquire would be allowed, or None if it would be allowed now
+    fn wait_time(&self, now: TickInstant) -> Option<TickDuration> {
+        let next = self.next.get().max(now);
+        (next - now)
+            .checked_sub(self.burst)
+            .filter(|wait| wait.ticks() > 0)
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let now = Ticker::now();
+        if self.wait_time(now).is_some() {
+            return false;
+        }
+        self.next.set(self.next.get().max(now) + self.interval);
+        true
+    }
+
+    pub async fn acquire(&self) {
+        // Loops since another task could take the slot while this one waits
+        while !self.try_acquire() {
+            if let Some(wait) = self.wait_time(Ticker::now()) {
+                Timer::delay(wait).await;
+            }
+        }
+    }
+}
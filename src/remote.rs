@@ -0,0 +1,370 @@
+/*
+Remote control from the host, in the spirit of Firmata: a script on the computer sends requests
+over the framed serial link, to set pins, read them, drive PWM and draw on the display, and the
+board answers each one. Nothing has to be reflashed to try out a circuit.
+
+A request is a sequence number, a command and its arguments, and the answer is the same sequence
+number, a status, and whatever the command reads. Numbers are little endian.
+
+    command              arguments               answer
+    0x00 ping                                    protocol version
+    0x01 pin mode        pin, mode
+    0x02 digital write   pin, level
+    0x03 digital read    pin                     level
+    0x04 analog read     pin                     reading (u16)
+    0x05 PWM write       channel, duty (u16)
+    0x06 display show    five rows
+    0x07 brightness      brightness
+
+where the modes are 0 for a floating input, 1 for an input with a pull-up and 2 for an output,
+and a PWM duty is a fraction of 65535. The status is 0 when the command worked, and otherwise
+the `Status` that says why not.
+
+The commands go to a `RemoteTarget`, which is the board's edge connector, ADC, PWM channels and
+display on the micro:bit, but can be anything that takes them:
+
+    let mut remote = Remote::new(FramedLink::new(serial), EdgeTarget::new(edge, adc, display));
+    remote.run().await;
+*/
+
+use snafu::prelude::*;
+
+use crate::framing::{ByteLink, FramedLink};
+
+pub const PROTOCOL_VERSION: u8 = 1;
+// Sequence number, status and the longest reading
+const MAX_ANSWER: usize = 4;
+
+const PING: u8 = 0x00;
+const PIN_MODE: u8 = 0x01;
+const DIGITAL_WRITE: u8 = 0x02;
+const DIGITAL_READ: u8 = 0x03;
+const ANALOG_READ: u8 = 0x04;
+const PWM_WRITE: u8 = 0x05;
+const DISPLAY_SHOW: u8 = 0x06;
+const BRIGHTNESS: u8 = 0x07;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinMode {
+    Input,
+    InputPullUp,
+    Output,
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RemoteError {
+    #[snafu(display("There's no pin or channel {pin}"))]
+    NoSuchPin { pin: u8 },
+    #[snafu(display("Pin {pin} isn't in the mode for that"))]
+    WrongMode { pin: u8 },
+    #[snafu(display("The target can't do that"))]
+    NotSupported,
+}
+
+// What comes back in an answer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Status {
+    Ok = 0,
+    UnknownCommand = 1,
+    BadArguments = 2,
+    NoSuchPin = 3,
+    WrongMode = 4,
+    NotSupported = 5,
+}
+
+impl From<RemoteError> for Status {
+    fn from(err: RemoteError) -> Self {
+        match err {
+            RemoteError::NoSuchPin { .. } => Status::NoSuchPin,
+            RemoteError::WrongMode { .. } => Status::WrongMode,
+            RemoteError::NotSupported => Status::NotSupported,
+        }
+    }
+}
+
+// What the commands act on. Anything the target doesn't have is answered as not supported.
+pub trait RemoteTarget {
+    fn pin_mode(&mut self, _pin: u8, _mode: PinMode) -> Result<(), RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    fn digital_write(&mut self, _pin: u8, _high: bool) -> Result<(), RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    fn digital_read(&mut self, _pin: u8) -> Result<bool, RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    // Scaled to the full range of a u16
+    fn analog_read(&mut self, _pin: u8) -> Result<u16, RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    // `duty` is a fraction of `u16::MAX`
+    fn pwm_write(&mut self, _channel: u8, _duty: u16) -> Result<(), RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    fn show(&mut self, _rows: [u8; 5]) -> Result<(), RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+
+    fn set_brightness(&mut self, _brightness: u8) -> Result<(), RemoteError> {
+        NotSupportedSnafu.fail()
+    }
+}
+
+pub struct Remote<L, T> {
+    link: FramedLink<L>,
+    target: T,
+}
+
+impl<L: ByteLink, T: RemoteTarget> Remote<L, T> {
+    pub fn new(link: FramedLink<L>, target: T) -> Self {
+        Self { link, target }
+    }
+
+    pub fn free(self) -> (FramedLink<L>, T) {
+        (self.link, self.target)
+    }
+
+    // Answers requests as they come in
+    pub async fn run(&mut self) -> ! {
+        info!("Remote control listening");
+        loop {
+            let request = self.link.recv().await;
+            let Some(answer) = handle(&mut self.target, &request) else {
+                debug!("Ignoring an empty remote request");
+                continue;
+            };
+            // Can't fail, answers are always short
+            let _ = self.link.send(&answer).await;
+        }
+    }
+}
+
+// The answer to a request, or `None` if it's too short to even have a sequence number
+pub fn handle(
+    target: &mut impl RemoteTarget,
+    request: &[u8],
+) -> Option<heapless::Vec<u8, MAX_ANSWER>> {
+    let (&seq, request) = request.split_first()?;
+    let mut answer = heapless::Vec::new();
+    // Can't fail, there's room for the longest answer
+    let _ = answer.push(seq);
+    let _ = answer.push(Status::Ok as u8);
+    let status = match run_command(target, request) {
+        Ok(data) => {
+            let _ = answer.extend_from_slice(&data);
+            Status::Ok
+        }
+        Err(status) => status,
+    };
+    answer[1] = status as u8;
+    Some(answer)
+}
+
+fn run_command(
+    target: &mut impl RemoteTarget,
+    request: &[u8],
+) -> Result<heapless::Vec<u8, 2>, Status> {
+    let mut data = heapless::Vec::new();
+    match *request {
+        [PING] => {
+            let _ = data.push(PROTOCOL_VERSION);
+        }
+        [PIN_MODE, pin, mode] => {
+            let mode = match mode {
+                0 => PinMode::Input,
+                1 => PinMode::InputPullUp,
+                2 => PinMode::Output,
+                _ => return Err(Status::BadArguments),
+            };
+            target.pin_mode(pin, mode)?;
+        }
+        [DIGITAL_WRITE, pin, level] => target.digital_write(pin, level != 0)?,
+        [DIGITAL_READ, pin] => {
+            let _ = data.push(u8::from(target.digital_read(pin)?));
+        }
+        [ANALOG_READ, pin] => {
+            let _ = data.extend_from_slice(&target.analog_read(pin)?.to_le_bytes());
+        }
+        [PWM_WRITE, channel, low, high] => {
+            target.pwm_write(channel, u16::from_le_bytes([low, high]))?;
+        }
+        [DISPLAY_SHOW, r0, r1, r2, r3, r4] => target.show([r0, r1, r2, r3, r4])?,
+        [BRIGHTNESS, brightness] => target.set_brightness(brightness)?,
+        [
+            PING | PIN_MODE | DIGITAL_WRITE | DIGITAL_READ | ANALOG_READ | PWM_WRITE | DISPLAY_SHOW
+            | BRIGHTNESS,
+            ..,
+        ] => return Err(Status::BadArguments),
+        _ => return Err(Status::UnknownCommand),
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "nrf52833")]
+pub use nrf::EdgeTarget;
+
+#[cfg(feature = "nrf52833")]
+mod nrf {
+    use core::convert::Infallible;
+
+    use embedded_hal::{
+        digital::{InputPin, OutputPin, PinState},
+        pwm::SetDutyCycle,
+    };
+    use nrf52833_hal::gpio::{Input, Level, Output, Pin, PullUp, PushPull};
+    use snafu::OptionExt;
+
+    use super::{
+        NoSuchPinSnafu, NotSupportedSnafu, PinMode, RemoteError, RemoteTarget, WrongModeSnafu,
+    };
+    use crate::{
+        adc::{self, Adc},
+        board::{EdgeConnector, EdgePin},
+        led::DisplayHandle,
+        utils::InfallibleExt,
+    };
+
+    // The edge connector's pads, by the number on the silkscreen
+    const PADS: [u8; 12] = [0, 1, 2, 8, 9, 12, 13, 14, 15, 16, 19, 20];
+
+    // An edge pin in whichever mode the host last asked for
+    enum EdgeMode {
+        Floating(EdgePin),
+        PullUp(Pin<Input<PullUp>>),
+        Output(Pin<Output<PushPull>>),
+    }
+
+    impl EdgeMode {
+        fn into_floating(self) -> EdgePin {
+            match self {
+                EdgeMode::Floating(pin) => pin,
+                EdgeMode::PullUp(pin) => pin.into_floating_input(),
+                EdgeMode::Output(pin) => pin.into_floating_input(),
+            }
+        }
+    }
+
+    pub type PwmChannel<'a> = &'a mut dyn SetDutyCycle<Error = Infallible>;
+
+    // The micro:bit's edge connector, ADC, PWM channels and display. Pins start as floating
+    // inputs.
+    pub struct EdgeTarget<'a, const C: usize> {
+        // By position in `PADS`, only empty while changing mode
+        pins: [Option<EdgeMode>; 12],
+        adc: Adc,
+        pwm: [PwmChannel<'a>; C],
+        display: DisplayHandle<'a>,
+    }
+
+    impl<'a, const C: usize> EdgeTarget<'a, C> {
+        pub fn new(
+            edge: EdgeConnector,
+            adc: Adc,
+            pwm: [PwmChannel<'a>; C],
+            display: DisplayHandle<'a>,
+        ) -> Self {
+            let EdgeConnector {
+                p0,
+                p1,
+                p2,
+                p8,
+                p9,
+                p12,
+                p13,
+                p14,
+                p15,
+                p16,
+                p19,
+                p20,
+            } = edge;
+            let pins = [p0, p1, p2, p8, p9, p12, p13, p14, p15, p16, p19, p20]
+                .map(|pin| Some(EdgeMode::Floating(pin)));
+            Self {
+                pins,
+                adc,
+                pwm,
+                display,
+            }
+        }
+
+        fn pin(&mut self, pad: u8) -> Result<&mut Option<EdgeMode>, RemoteError> {
+            Ok(&mut self.pins[index(pad)?])
+        }
+    }
+
+    fn index(pad: u8) -> Result<usize, RemoteError> {
+        PADS.iter()
+            .position(|&p| p == pad)
+            .context(NoSuchPinSnafu { pin: pad })
+    }
+
+    impl<const C: usize> RemoteTarget for EdgeTarget<'_, C> {
+        fn pin_mode(&mut self, pin: u8, mode: PinMode) -> Result<(), RemoteError> {
+            let slot = self.pin(pin)?;
+            let Some(current) = slot.take() else {
+                return NotSupportedSnafu.fail();
+            };
+            let floating = current.into_floating();
+            *slot = Some(match mode {
+                PinMode::Input => EdgeMode::Floating(floating),
+                PinMode::InputPullUp => EdgeMode::PullUp(floating.into_pullup_input()),
+                PinMode::Output => EdgeMode::Output(floating.into_push_pull_output(Level::Low)),
+            });
+            Ok(())
+        }
+
+        fn digital_write(&mut self, pin: u8, high: bool) -> Result<(), RemoteError> {
+            let Some(EdgeMode::Output(output)) = self.pin(pin)? else {
+                return WrongModeSnafu { pin }.fail();
+            };
+            output.set_state(PinState::from(high)).unwrap_infallible();
+            Ok(())
+        }
+
+        fn digital_read(&mut self, pin: u8) -> Result<bool, RemoteError> {
+            Ok(match self.pin(pin)? {
+                Some(EdgeMode::Floating(input)) => input.is_high().unwrap_infallible(),
+                Some(EdgeMode::PullUp(input)) => input.is_high().unwrap_infallible(),
+                _ => return WrongModeSnafu { pin }.fail(),
+            })
+        }
+
+        fn analog_read(&mut self, pin: u8) -> Result<u16, RemoteError> {
+            let Some(EdgeMode::Floating(input)) = &self.pins[index(pin)?] else {
+                return WrongModeSnafu { pin }.fail();
+            };
+            // Only some pins have an analog input
+            let reading = self.adc.read(input).ok().context(NotSupportedSnafu)?;
+            Ok((reading as u32 * u32::from(u16::MAX) / adc::MAX_READING as u32) as u16)
+        }
+
+        fn pwm_write(&mut self, channel: u8, duty: u16) -> Result<(), RemoteError> {
+            let pwm = self
+                .pwm
+                .get_mut(usize::from(channel))
+                .context(NoSuchPinSnafu { pin: channel })?;
+            pwm.set_duty_cycle_fraction(duty, u16::MAX)
+                .unwrap_infallible();
+            Ok(())
+        }
+
+        fn show(&mut self, rows: [u8; 5]) -> Result<(), RemoteError> {
+            self.display.show(rows.map(|row| row & 0b11111));
+            Ok(())
+        }
+
+        fn set_brightness(&mut self, brightness: u8) -> Result<(), RemoteError> {
+            self.display.set_brightness(brightness);
+            Ok(())
+        }
+    }
+}
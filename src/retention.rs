@@ -0,0 +1,101 @@
+/*
+Which RAM keeps its contents through System OFF. RAM is split into sections that are each powered
+and retained separately, and nothing is retained by default, so deep sleep draws the least current
+but every wake starts from blank RAM. Retaining only the sections holding the state that matters
+keeps most of that saving.
+
+RAM that's retained is still zeroed and initialised at boot if it's in `.bss` or `.data`, so state
+that should survive has to live in `.uninit`, e.g. with `#[unsafe(link_section = ".uninit.name")]`,
+which is what `ramlog` does. `retain_uninit` retains exactly the sections that holds.
+*/
+
+use core::ops::Range;
+
+use nrf52833_hal::pac::{POWER, power};
+
+const RAM_START: usize = 0x2000_0000;
+const RAM_END: usize = 0x2002_0000;
+// RAM0 to RAM7 are 8K blocks of two 4K sections, after which RAM8 has 32K sections
+const SMALL_BLOCKS: usize = 8;
+const SMALL_SECTION: usize = 4 * 1024;
+const LARGE_START: usize = RAM_START + SMALL_BLOCKS * 2 * SMALL_SECTION;
+const LARGE_SECTION: usize = 32 * 1024;
+// The section's retention bit is 16 above its power bit
+const RETENTION_SHIFT: u32 = 16;
+
+unsafe extern "C" {
+    static __suninit: u8;
+    static __euninit: u8;
+}
+
+// The addresses of `.uninit`, as placed by the linker
+pub fn uninit_range() -> Range<usize> {
+    (&raw const __suninit as usize)..(&raw const __euninit as usize)
+}
+
+// Keeps the sections overlapping the range through System OFF, on top of any already kept
+pub fn retain(power: &POWER, range: Range<usize>) {
+    for_each_section(power, range, |block, bit| {
+        // SAFETY: Only sets the retention bit of a section that exists
+        block
+            .powerset
+            .write(|w| unsafe { w.bits(1 << (bit + RETENTION_SHIFT)) });
+    });
+}
+
+pub fn retain_uninit(power: &POWER) {
+    let range = uninit_range();
+    debug!("Retaining RAM from {} to {}", range.start, range.end);
+    retain(power, range);
+}
+
+// Retains nothing, which is how the chip starts
+pub fn release_all(power: &POWER) {
+    for block in blocks(power) {
+        // SAFETY: Only clears retention bits, leaving the sections powered
+        block
+            .powerclr
+            .write(|w| unsafe { w.bits(0xFFFF << RETENTION_SHIFT) });
+    }
+}
+
+fn blocks(power: &POWER) -> [&power::RAM; 9] {
+    [
+        &power.ram0,
+        &power.ram1,
+        &power.ram2,
+        &power.ram3,
+        &power.ram4,
+        &power.ram5,
+        &power.ram6,
+        &power.ram7,
+        &power.ram8,
+    ]
+}
+
+// Calls `f` with the block and section number of every section overlapping the range
+fn for_each_section(power: &POWER, range: Range<usize>, mut f: impl FnMut(&power::RAM, u32)) {
+    let blocks = blocks(power);
+    let start = range.start.max(RAM_START);
+    let end = range.end.min(RAM_END);
+    let mut address = start;
+    while address < end {
+        let (block, section, size) = if address < LARGE_START {
+            let offset = address - RAM_START;
+            (
+                offset / (2 * SMALL_SECTION),
+                offset / SMALL_SECTION % 2,
+                SMALL_SECTION,
+            )
+        } else {
+            (
+                SMALL_BLOCKS,
+                (address - LARGE_START) / LARGE_SECTION,
+                LARGE_SECTION,
+            )
+        };
+        f(blocks[block], section as u32);
+        // On to the start of the next section
+        address = (address / size + 1) * size;
+    }
+}
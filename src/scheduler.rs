@@ -0,0 +1,92 @@
+/*
+Runs many small periodic jobs from one task and one timer, which is cheaper than giving each its own
+task slot. A job is a callback with a period and a phase, the offset of its first run from when the
+scheduler starts, so jobs with the same period can be spread out. Jobs that fall behind skip the
+runs they missed rather than running several times in a row.
+*/
+
+use snafu::prelude::*;
+
+use crate::time::{TickDuration, TickInstant, Ticker, Timer};
+
+// Gets the time the run was due
+pub type Job<'a> = &'a mut dyn FnMut(TickInstant);
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SchedulerError {
+    #[snafu(display("The scheduler can't hold any more jobs"))]
+    TooManyJobs,
+}
+
+struct Entry<'a> {
+    name: &'static str,
+    period: TickDuration,
+    phase: TickDuration,
+    next: TickInstant,
+    job: Job<'a>,
+}
+
+pub struct Scheduler<'a, const N: usize> {
+    jobs: heapless::Vec<Entry<'a>, N>,
+}
+
+impl<'a, const N: usize> Scheduler<'a, N> {
+    pub const fn new() -> Self {
+        Self {
+            jobs: heapless::Vec::new(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        period: TickDuration,
+        phase: TickDuration,
+        job: Job<'a>,
+    ) -> Result<(), SchedulerError> {
+        self.jobs
+            .push(Entry {
+                name,
+                period,
+                phase,
+                next: TickInstant::from_ticks(0),
+                job,
+            })
+            .ok()
+            .context(TooManyJobsSnafu)
+    }
+
+    pub async fn run(&mut self) -> ! {
+        let start = Ticker::now();
+        for entry in &mut self.jobs {
+            entry.next = start + entry.phase;
+        }
+        loop {
+            let Some(next) = self.jobs.iter().map(|entry| entry.next).min() else {
+                // Nothing to run, ever
+                core::future::pending::<()>().await;
+                continue;
+            };
+            if let Some(wait) = next.checked_duration_since(Ticker::now()) {
+                Timer::delay(wait).await;
+            }
+            let now = Ticker::now();
+            for entry in self.jobs.iter_mut().filter(|entry| entry.next <= now) {
+                (entry.job)(entry.next);
+                entry.next += entry.period;
+                if entry.next <= now {
+                    let behind = (now - entry.next).ticks() / entry.period.ticks() + 1;
+                    warn!("Job {} fell behind, skipping {} runs", entry.name, behind);
+                    entry.next += entry.period * behind as u32;
+                }
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for Scheduler<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
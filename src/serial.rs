@@ -0,0 +1,183 @@
+/*
+Async driver for UARTE0. The interrupt moves each received byte into a queue, so nothing is lost
+while no task is reading, and then starts the next one byte DMA transfer. Writes are copied
+through a RAM buffer, since EasyDMA can't read from flash.
+*/
+
+use core::{
+    fmt,
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering, compiler_fence},
+    task::Poll,
+};
+
+use embedded_hal::digital::OutputPin;
+use nrf52833_hal::{
+    pac::{Interrupt, NVIC, UARTE0, interrupt, uarte0},
+    uarte::{Baudrate, Pins},
+};
+
+use crate::{
+    config::Priority,
+    utils::{AtomicWaker, InfallibleExt, mpmc::Queue},
+};
+
+const RX_QUEUE_SIZE: usize = 64;
+const TX_CHUNK_SIZE: usize = 32;
+
+static RX_QUEUE: Queue<u8, RX_QUEUE_SIZE> = Queue::new();
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+static TX_WAKER: AtomicWaker = AtomicWaker::new();
+static TX_BUSY: AtomicBool = AtomicBool::new(false);
+
+// DMA buffers. The RX buffer belongs to the interrupt, and the TX buffer is only written while
+// TX_BUSY is clear.
+static mut RX_BUF: [u8; 1] = [0];
+static mut TX_BUF: [u8; TX_CHUNK_SIZE] = [0; TX_CHUNK_SIZE];
+
+pub struct Serial {
+    _uarte: UARTE0,
+    _pins: Pins,
+}
+
+impl Serial {
+    pub fn new(
+        uarte: UARTE0,
+        mut pins: Pins,
+        baudrate: Baudrate,
+        nvic: &mut NVIC,
+        priority: Priority,
+    ) -> Self {
+        // SAFETY: Only sets the pin numbers, which come from pins this driver owns
+        uarte.psel.rxd.write(|w| {
+            unsafe { w.bits(pins.rxd.psel_bits()) };
+            w.connect().connected()
+        });
+        pins.txd.set_high().unwrap_infallible();
+        uarte.psel.txd.write(|w| {
+            unsafe { w.bits(pins.txd.psel_bits()) };
+            w.connect().connected()
+        });
+        uarte
+            .config
+            .write(|w| w.hwfc().disabled().parity().excluded());
+        uarte.baudrate.write(|w| w.baudrate().variant(baudrate));
+        uarte.enable.write(|w| w.enable().enabled());
+        uarte.intenset.write(|w| w.endrx().set().endtx().set());
+        start_rx(&uarte);
+        // SAFETY: The priority is set before the interrupt is unmasked, and the handler only uses
+        // the driver's own statics
+        unsafe {
+            nvic.set_priority(Interrupt::UARTE0_UART0, priority.bits());
+            NVIC::unmask(Interrupt::UARTE0_UART0);
+        }
+        Self {
+            _uarte: uarte,
+            _pins: pins,
+        }
+    }
+
+    pub async fn read_byte(&mut self) -> u8 {
+        poll_fn(|cx| {
+            RX_WAKER.register(cx.waker());
+            RX_QUEUE.dequeue().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(TX_CHUNK_SIZE) {
+            // A write that was cancelled part way may still be sending
+            poll_fn(|cx| {
+                TX_WAKER.register(cx.waker());
+                if TX_BUSY.load(Ordering::Acquire) {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+            start_tx(chunk);
+        }
+    }
+
+    // Spins until the bytes are sent, for when there's no task to await in
+    pub fn write_blocking(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(TX_CHUNK_SIZE) {
+            while TX_BUSY.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            start_tx(chunk);
+        }
+        while TX_BUSY.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// Blocking, so it can be used from synchronous code like the shell's command handlers
+impl fmt::Write for Serial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_blocking(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn uarte() -> &'static uarte0::RegisterBlock {
+    // SAFETY: The registers are only touched by the driver, which owns UARTE0
+    unsafe { &*UARTE0::ptr() }
+}
+
+fn start_rx(uarte: &uarte0::RegisterBlock) {
+    // SAFETY: The buffer is static, and only read by the interrupt once the transfer has ended
+    uarte
+        .rxd
+        .ptr
+        .write(|w| unsafe { w.ptr().bits(&raw mut RX_BUF as u32) });
+    uarte.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(1) });
+    compiler_fence(Ordering::SeqCst);
+    uarte.tasks_startrx.write(|w| unsafe { w.bits(1) });
+}
+
+fn start_tx(chunk: &[u8]) {
+    let uarte = uarte();
+    // SAFETY: The previous transfer has finished, so the DMA isn't reading the buffer
+    unsafe {
+        core::ptr::copy_nonoverlapping(chunk.as_ptr(), (&raw mut TX_BUF).cast(), chunk.len())
+    };
+    TX_BUSY.store(true, Ordering::Release);
+    uarte
+        .txd
+        .ptr
+        .write(|w| unsafe { w.ptr().bits(&raw const TX_BUF as u32) });
+    uarte
+        .txd
+        .maxcnt
+        .write(|w| unsafe { w.maxcnt().bits(chunk.len() as u16) });
+    compiler_fence(Ordering::SeqCst);
+    uarte.tasks_starttx.write(|w| unsafe { w.bits(1) });
+}
+
+#[interrupt]
+fn UARTE0_UART0() {
+    let uarte = uarte();
+    if uarte.events_endrx.read().bits() != 0 {
+        uarte.events_endrx.reset();
+        compiler_fence(Ordering::SeqCst);
+        if uarte.rxd.amount.read().bits() == 1 {
+            // SAFETY: The transfer has ended, so the DMA is done with the buffer
+            let byte = unsafe { (&raw const RX_BUF).cast::<u8>().read_volatile() };
+            if RX_QUEUE.enqueue(byte).is_err() {
+                warn!("Serial receive queue is full, dropping a byte");
+            }
+            RX_WAKER.wake();
+        }
+        start_rx(uarte);
+    }
+    if uarte.events_endtx.read().bits() != 0 {
+        uarte.events_endtx.reset();
+        uarte.tasks_stoptx.write(|w| unsafe { w.bits(1) });
+        TX_BUSY.store(false, Ordering::Release);
+        TX_WAKER.wake();
+    }
+}
@@ -0,0 +1,151 @@
+/*
+Hobby servos on a PWM channel, which has to run at 50 Hz: the width of each pulse sets the angle,
+from about 0.5 ms at one end to 2.5 ms at the other. `move_to` glides to an angle over a duration
+with an easing curve, updating the pulse once per PWM period, since the servo can't see anything
+faster.
+
+    servo.move_to(90, TickDuration::millis(800), Easing::Cubic).await;
+*/
+
+use core::{convert::Infallible, pin::pin};
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::{
+    dsp::ONE,
+    time::{PeriodicTimer, TickDuration},
+    utils::InfallibleExt,
+};
+
+const PERIOD_US: u32 = 20_000;
+const UPDATE_PERIOD: TickDuration = TickDuration::micros(PERIOD_US as u64);
+
+// How the position moves between the start and the end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Easing {
+    // Constant speed, starting and stopping abruptly
+    Linear,
+    // Speeds up then slows down
+    Quadratic,
+    // Like quadratic, but gentler at the ends and faster in the middle
+    Cubic,
+}
+
+impl Easing {
+    // Maps progress through the move to progress along the path, both from 0 to `dsp::ONE`
+    pub fn apply(self, t: i32) -> i32 {
+        let t = t.clamp(0, ONE);
+        // The curves are symmetric, so the second half mirrors the first
+        let (t, mirrored) = if t <= ONE / 2 {
+            (t, false)
+        } else {
+            (ONE - t, true)
+        };
+        let t = i64::from(t);
+        let one = i64::from(ONE);
+        let eased = match self {
+            Easing::Linear => t,
+            Easing::Quadratic => 2 * t * t / one,
+            Easing::Cubic => 4 * t * t * t / (one * one),
+        } as i32;
+        if mirrored { ONE - eased } else { eased }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServoConfig {
+    // Pulse width at 0 degrees
+    pub min_pulse_us: u32,
+    // Pulse width at `max_angle`
+    pub max_pulse_us: u32,
+    pub max_angle: u16,
+}
+
+impl ServoConfig {
+    pub const fn new() -> Self {
+        Self {
+            min_pulse_us: 500,
+            max_pulse_us: 2500,
+            max_angle: 180,
+        }
+    }
+}
+
+impl Default for ServoConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Servo<P> {
+    pwm: P,
+    config: ServoConfig,
+    pulse_us: u32,
+}
+
+impl<P: SetDutyCycle<Error = Infallible>> Servo<P> {
+    // Moves straight to `angle`, since there's no knowing where the servo is to start with
+    pub fn new(pwm: P, config: ServoConfig, angle: u16) -> Self {
+        assert!(
+            config.min_pulse_us < config.max_pulse_us && config.max_pulse_us < PERIOD_US,
+            "The pulse widths must be increasing and fit in the period"
+        );
+        let mut servo = Self {
+            pwm,
+            config,
+            pulse_us: config.min_pulse_us,
+        };
+        servo.set_angle(angle);
+        servo
+    }
+
+    // In degrees, clamped to `max_angle`. Rounded down when the servo is between angles.
+    pub fn angle(&self) -> u16 {
+        let ServoConfig {
+            min_pulse_us,
+            max_pulse_us,
+            max_angle,
+        } = self.config;
+        ((self.pulse_us - min_pulse_us) * u32::from(max_angle) / (max_pulse_us - min_pulse_us))
+            as u16
+    }
+
+    pub fn set_angle(&mut self, angle: u16) {
+        let pulse = self.pulse_for(angle);
+        self.set_pulse(pulse);
+    }
+
+    // Glides from the current angle to `angle`, taking `duration`
+    pub async fn move_to(&mut self, angle: u16, duration: TickDuration, easing: Easing) {
+        let (start, end) = (self.pulse_us as i32, self.pulse_for(angle) as i32);
+        let steps = (duration.ticks() / UPDATE_PERIOD.ticks()).max(1) as i32;
+        let mut timer = pin!(PeriodicTimer::new(UPDATE_PERIOD));
+        for step in 1..=steps {
+            timer.as_mut().tick().await;
+            let progress = easing.apply(step * ONE / steps);
+            let pulse =
+                start + ((i64::from(end - start) * i64::from(progress)) / i64::from(ONE)) as i32;
+            self.set_pulse(pulse as u32);
+        }
+    }
+
+    fn pulse_for(&self, angle: u16) -> u32 {
+        let ServoConfig {
+            min_pulse_us,
+            max_pulse_us,
+            max_angle,
+        } = self.config;
+        let angle = u32::from(angle.min(max_angle));
+        min_pulse_us + (max_pulse_us - min_pulse_us) * angle / u32::from(max_angle)
+    }
+
+    fn set_pulse(&mut self, pulse_us: u32) {
+        self.pulse_us = pulse_us;
+        let max = u32::from(self.pwm.max_duty_cycle());
+        self.pwm
+            .set_duty_cycle((max * pulse_us / PERIOD_US) as u16)
+            .unwrap_infallible();
+    }
+}
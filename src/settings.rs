@@ -0,0 +1,248 @@
+/*
+Settings store, which keeps small values by key in flash so they survive resets and reflashing,
+like calibration or the radio group. Writes append a new record rather than overwriting, since
+flash can only be erased a page at a time, and the latest record for a key wins. When the region
+fills up, the latest value of each key is kept in RAM while the region is erased and written back.
+A reset in the middle of that loses the settings, which then read as missing.
+
+Each record is a header word holding the key, the length and a checksum, followed by the value,
+padded out to whole words since the NVMC writes words. On the board, the flash comes from
+`FlashRegions`, which owns the NVMC:
+
+    let mut settings = Settings::new(FlashRegions::new(board.spare.nvmc).settings);
+*/
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use snafu::prelude::*;
+
+use crate::crc::Crc16;
+
+const HEADER_SIZE: usize = 4;
+// Erased flash, so a key that can't be used
+const ERASED_KEY: u16 = 0xFFFF;
+pub const MAX_VALUE: usize = 64;
+// Different keys kept through a compaction
+pub const MAX_KEYS: usize = 16;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SettingsError {
+    #[snafu(display("The value is longer than the store takes"))]
+    TooLong,
+    #[snafu(display("The key is reserved"))]
+    ReservedKey,
+    #[snafu(display("There are too many keys to keep when compacting"))]
+    TooManyKeys,
+    #[snafu(display("The settings don't fit in the flash region"))]
+    Full,
+    #[snafu(display("The flash controller rejected the operation"))]
+    Flash,
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    key: u16,
+    len: usize,
+    check: u8,
+}
+
+impl Header {
+    fn from_word(word: [u8; HEADER_SIZE]) -> Self {
+        Self {
+            key: u16::from_le_bytes([word[0], word[1]]),
+            len: usize::from(word[2]),
+            check: word[3],
+        }
+    }
+
+    fn to_word(self) -> [u8; HEADER_SIZE] {
+        let [key_lo, key_hi] = self.key.to_le_bytes();
+        [key_lo, key_hi, self.len as u8, self.check]
+    }
+
+    // The header and value, padded to whole words
+    fn record_size(&self) -> usize {
+        HEADER_SIZE + self.len.next_multiple_of(HEADER_SIZE)
+    }
+}
+
+// The low bits of the key and value's CRC-16, never all ones, so a record with erased flash after
+// its key can't check out
+fn checksum(key: u16, value: &[u8]) -> u8 {
+    let mut crc = Crc16::new();
+    crc.update(&key.to_le_bytes());
+    crc.update(value);
+    crc.finish() as u8 & 0x7F
+}
+
+pub struct Settings<F> {
+    flash: F,
+    // Bytes of the region used so far
+    used: usize,
+}
+
+impl<F: NorFlash + ReadNorFlash> Settings<F> {
+    // Finds the end of the records already stored. A record that doesn't check out, from a reset
+    // part way through a write, ends the store there, and what's left of it is compacted away so
+    // the next record goes onto erased flash.
+    pub fn new(flash: F) -> Self {
+        let mut settings = Self { flash, used: 0 };
+        while let Some(header) = settings.header_at(settings.used) {
+            settings.used += header.record_size();
+        }
+        debug!("Settings use {} bytes", settings.used);
+        if !settings.is_erased_from(settings.used) {
+            warn!("Settings have a torn record, compacting");
+            if settings.compact(ERASED_KEY).is_err() {
+                error!("Couldn't compact the settings");
+            }
+        }
+        settings
+    }
+
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    // Copies the latest value for the key into `buf`, returning its length, or `None` if the key
+    // was never written. `buf` has to be long enough for the value.
+    pub fn read(&mut self, key: u16, buf: &mut [u8]) -> Result<Option<usize>, SettingsError> {
+        let Some((offset, header)) = self.records().filter(|(_, h)| h.key == key).last() else {
+            return Ok(None);
+        };
+        let buf = buf.get_mut(..header.len).context(TooLongSnafu)?;
+        self.flash
+            .read((offset + HEADER_SIZE) as u32, buf)
+            .ok()
+            .context(FlashSnafu)?;
+        Ok(Some(header.len))
+    }
+
+    // Skips the write if the value hasn't changed, to save wearing the flash
+    pub fn write(&mut self, key: u16, value: &[u8]) -> Result<(), SettingsError> {
+        ensure!(key != ERASED_KEY, ReservedKeySnafu);
+        ensure!(value.len() <= MAX_VALUE, TooLongSnafu);
+        let mut current = [0; MAX_VALUE];
+        if let Some(len) = self.read(key, &mut current)?
+            && current[..len] == *value
+        {
+            return Ok(());
+        }
+        let header = Header {
+            key,
+            len: value.len(),
+            check: checksum(key, value),
+        };
+        if self.used + header.record_size() > self.flash.capacity() {
+            self.compact(key)?;
+            ensure!(
+                self.used + header.record_size() <= self.flash.capacity(),
+                FullSnafu
+            );
+        }
+        self.append(header, value)
+    }
+
+    // Forgets every setting
+    pub fn erase(&mut self) -> Result<(), SettingsError> {
+        let capacity = self.flash.capacity() as u32;
+        self.flash.erase(0, capacity).ok().context(FlashSnafu)?;
+        self.used = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, header: Header, value: &[u8]) -> Result<(), SettingsError> {
+        // The value first, so a reset part way through leaves a header that doesn't check out
+        let mut padded = [0xFF; MAX_VALUE.next_multiple_of(HEADER_SIZE)];
+        padded[..value.len()].copy_from_slice(value);
+        let data = &padded[..value.len().next_multiple_of(HEADER_SIZE)];
+        let offset = self.used;
+        self.flash
+            .write((offset + HEADER_SIZE) as u32, data)
+            .ok()
+            .context(FlashSnafu)?;
+        self.flash
+            .write(offset as u32, &header.to_word())
+            .ok()
+            .context(FlashSnafu)?;
+        self.used += header.record_size();
+        Ok(())
+    }
+
+    // Rewrites the latest value of every key but `replacing`, which is about to be written anyway
+    fn compact(&mut self, replacing: u16) -> Result<(), SettingsError> {
+        let mut keys: heapless::Vec<u16, MAX_KEYS> = heapless::Vec::new();
+        for (_, header) in self.records() {
+            if header.key != replacing && !keys.contains(&header.key) {
+                keys.push(header.key).ok().context(TooManyKeysSnafu)?;
+            }
+        }
+        let mut keep: heapless::Vec<(u16, heapless::Vec<u8, MAX_VALUE>), MAX_KEYS> =
+            heapless::Vec::new();
+        for key in keys {
+            let mut value = [0; MAX_VALUE];
+            let len = self.read(key, &mut value)?.unwrap_or(0);
+            // Can't fail, there are as many slots as keys and the value fits
+            let _ = keep.push((
+                key,
+                heapless::Vec::from_slice(&value[..len]).unwrap_or_default(),
+            ));
+        }
+        info!("Compacting settings down to {} keys", keep.len());
+        self.erase()?;
+        for (key, value) in &keep {
+            let header = Header {
+                key: *key,
+                len: value.len(),
+                check: checksum(*key, value),
+            };
+            self.append(header, value)?;
+        }
+        Ok(())
+    }
+
+    // The offset and header of every record, oldest first
+    fn records(&mut self) -> impl Iterator<Item = (usize, Header)> + '_ {
+        let used = self.used;
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            if offset >= used {
+                return None;
+            }
+            let header = self.header_at(offset)?;
+            let record = (offset, header);
+            offset += header.record_size();
+            Some(record)
+        })
+    }
+
+    fn is_erased_from(&mut self, offset: usize) -> bool {
+        let mut word = [0; HEADER_SIZE];
+        (offset..self.flash.capacity())
+            .step_by(HEADER_SIZE)
+            .all(|offset| {
+                self.flash.read(offset as u32, &mut word).is_ok() && word == [0xFF; HEADER_SIZE]
+            })
+    }
+
+    // The record at the offset, if there's a whole one there that checks out
+    fn header_at(&mut self, offset: usize) -> Option<Header> {
+        let mut word = [0; HEADER_SIZE];
+        if offset + HEADER_SIZE > self.flash.capacity() {
+            return None;
+        }
+        self.flash.read(offset as u32, &mut word).ok()?;
+        let header = Header::from_word(word);
+        if header.key == ERASED_KEY
+            || header.len > MAX_VALUE
+            || offset + header.record_size() > self.flash.capacity()
+        {
+            return None;
+        }
+        let mut value = [0; MAX_VALUE];
+        self.flash
+            .read((offset + HEADER_SIZE) as u32, &mut value[..header.len])
+            .ok()?;
+        (checksum(header.key, &value[..header.len]) == header.check).then_some(header)
+    }
+}
@@ -0,0 +1,117 @@
+/*
+A small command shell over the serial port, for poking at the running system from a terminal.
+Commands are registered with a name and a handler, and the line can be edited with backspace and
+cleared with ctrl-c. Handlers write their output straight to the port, blocking until it's sent.
+*/
+
+use core::{fmt, fmt::Write, str::SplitWhitespace};
+
+use snafu::prelude::*;
+
+use crate::serial::Serial;
+
+const LINE_LENGTH: usize = 80;
+const PROMPT: &[u8] = b"> ";
+
+pub type Args<'l> = SplitWhitespace<'l>;
+pub type Handler<'a> = &'a mut dyn FnMut(Args<'_>, &mut dyn Write) -> fmt::Result;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShellError {
+    #[snafu(display("The shell can't hold any more commands"))]
+    TooManyCommands,
+}
+
+struct Command<'a> {
+    name: &'static str,
+    help: &'static str,
+    handler: Handler<'a>,
+}
+
+pub struct Shell<'a, const N: usize> {
+    serial: Serial,
+    commands: heapless::Vec<Command<'a>, N>,
+}
+
+impl<'a, const N: usize> Shell<'a, N> {
+    pub fn new(serial: Serial) -> Self {
+        Self {
+            serial,
+            commands: heapless::Vec::new(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        handler: Handler<'a>,
+    ) -> Result<(), ShellError> {
+        self.commands
+            .push(Command {
+                name,
+                help,
+                handler,
+            })
+            .ok()
+            .context(TooManyCommandsSnafu)
+    }
+
+    pub async fn run(&mut self) -> ! {
+        let mut line = heapless::String::<LINE_LENGTH>::new();
+        self.serial.write(PROMPT).await;
+        loop {
+            match self.serial.read_byte().await {
+                b'\r' => {
+                    self.serial.write(b"\r\n").await;
+                    self.execute(&line);
+                    line.clear();
+                    self.serial.write(PROMPT).await;
+                }
+                // Backspace or delete
+                0x08 | 0x7F if !line.is_empty() => {
+                    line.pop();
+                    self.serial.write(b"\x08 \x08").await;
+                }
+                // Ctrl-c
+                0x03 => {
+                    line.clear();
+                    self.serial.write(b"^C\r\n").await;
+                    self.serial.write(PROMPT).await;
+                }
+                byte @ 0x20..=0x7E => match line.push(char::from(byte)) {
+                    Ok(()) => self.serial.write(&[byte]).await,
+                    // Ring the bell when the line is full
+                    Err(_) => self.serial.write(b"\x07").await,
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) {
+        let mut args = line.split_whitespace();
+        let Some(name) = args.next() else {
+            return;
+        };
+        let serial = &mut self.serial;
+        let result = if name == "help" {
+            self.commands
+                .iter()
+                .try_for_each(|command| write!(serial, "{}\t{}\r\n", command.name, command.help))
+        } else {
+            match self
+                .commands
+                .iter_mut()
+                .find(|command| command.name == name)
+            {
+                Some(command) => (command.handler)(args, serial),
+                None => write!(serial, "Unknown command {name}, try help\r\n"),
+            }
+        };
+        if result.is_err() {
+            warn!("Shell command failed to write its output");
+        }
+    }
+}
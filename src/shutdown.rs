@@ -0,0 +1,239 @@
+/*
+Flushing state before the power goes. Subsystems that buffer writes, like the settings store or a
+logger, register a hook and wait on it alongside their work. When the supply starts to fail (the
+power-fail comparator's POFWARN) or the device is about to go into System OFF, `run` asks every
+hook to flush and waits for them, but only up to a deadline, since a browning out supply won't
+wait.
+
+    static HOOKS: ShutdownHooks<4> = ShutdownHooks::new();
+    // In the settings task
+    HOOKS.register("settings")?.flush_on_shutdown(async || settings.flush().await).await;
+    // In the task that powers down
+    pof_warning.wait().await;
+    HOOKS.run(TickDuration::millis(5)).await;
+
+A hook counts as finished once it's dropped, so a task that stops early never holds up shutdown.
+*/
+
+use core::{cell::RefCell, future::poll_fn, task::Poll};
+
+use critical_section::Mutex;
+use snafu::prelude::*;
+
+use crate::{
+    cancel::CancellationToken,
+    time::{TickDuration, timeout},
+    utils::AtomicWaker,
+};
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShutdownError {
+    #[snafu(display("There's no room for another shutdown hook"))]
+    TooManyHooks,
+}
+
+struct Hook {
+    name: &'static str,
+    finished: bool,
+}
+
+// Up to N hooks, registered for the life of the program
+pub struct ShutdownHooks<const N: usize> {
+    requested: CancellationToken,
+    hooks: Mutex<RefCell<heapless::Vec<Hook, N>>>,
+    // The task in `run`
+    waiting: AtomicWaker,
+}
+
+impl<const N: usize> ShutdownHooks<N> {
+    pub const fn new() -> Self {
+        Self {
+            requested: CancellationToken::new(),
+            hooks: Mutex::new(RefCell::new(heapless::Vec::new())),
+            waiting: AtomicWaker::new(),
+        }
+    }
+
+    pub fn register(&self, name: &'static str) -> Result<ShutdownHook<'_, N>, ShutdownError> {
+        let index = critical_section::with(|cs| {
+            let mut hooks = self.hooks.borrow_ref_mut(cs);
+            hooks
+                .push(Hook {
+                    name,
+                    finished: false,
+                })
+                .ok()
+                .context(TooManyHooksSnafu)?;
+            Ok(hooks.len() - 1)
+        })?;
+        Ok(ShutdownHook { hooks: self, index })
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.is_cancelled()
+    }
+
+    // Asks every hook to flush, and waits until they all have or the deadline passes. Returns
+    // whether they all finished in time.
+    pub async fn run(&self, deadline: TickDuration) -> bool {
+        info!("Shutting down");
+        self.requested.cancel();
+        let finished = timeout(
+            deadline,
+            poll_fn(|cx| {
+                self.waiting.register(cx.waker());
+                if self.all_finished() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            }),
+        )
+        .await
+        .is_ok();
+        if !finished {
+            critical_section::with(|cs| {
+                for hook in self
+                    .hooks
+                    .borrow_ref(cs)
+                    .iter()
+                    .filter(|hook| !hook.finished)
+                {
+                    warn!("Shutdown hook {} didn't finish in time", hook.name);
+                }
+            });
+        }
+        finished
+    }
+
+    fn all_finished(&self) -> bool {
+        critical_section::with(|cs| self.hooks.borrow_ref(cs).iter().all(|hook| hook.finished))
+    }
+
+    fn finish(&self, index: usize) {
+        critical_section::with(|cs| {
+            if let Some(hook) = self.hooks.borrow_ref_mut(cs).get_mut(index) {
+                hook.finished = true;
+            }
+        });
+        self.waiting.wake();
+    }
+}
+
+impl<const N: usize> Default for ShutdownHooks<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Held by the subsystem that has to flush. Dropping it says the flush is done.
+pub struct ShutdownHook<'a, const N: usize> {
+    hooks: &'a ShutdownHooks<N>,
+    index: usize,
+}
+
+impl<const N: usize> ShutdownHook<'_, N> {
+    // Waits until shutdown is requested, to select on alongside the subsystem's work
+    pub async fn requested(&self) {
+        self.hooks.requested.cancelled().await;
+    }
+
+    // Waits until shutdown is requested, flushes and finishes
+    pub async fn flush_on_shutdown(self, flush: impl AsyncFnOnce()) {
+        self.requested().await;
+        flush().await;
+    }
+}
+
+impl<const N: usize> Drop for ShutdownHook<'_, N> {
+    fn drop(&mut self) {
+        self.hooks.finish(self.index);
+    }
+}
+
+#[cfg(feature = "nrf52833")]
+pub use nrf::PofWarning;
+
+#[cfg(feature = "nrf52833")]
+mod nrf {
+    use core::{
+        future::poll_fn,
+        sync::atomic::{AtomicBool, Ordering},
+        task::Poll,
+    };
+
+    use nrf52833_hal::pac::{Interrupt, NVIC, POWER, interrupt};
+
+    use crate::{config::Priority, utils::AtomicWaker};
+
+    // The comparator's lowest and highest thresholds, in 100 mV steps
+    const MIN_THRESHOLD_MV: u16 = 1700;
+    const MAX_THRESHOLD_MV: u16 = 2800;
+    // The register value for the lowest threshold
+    const MIN_THRESHOLD_BITS: u16 = 4;
+
+    static WARNING_WAKER: AtomicWaker = AtomicWaker::new();
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    // The power-fail comparator, which warns when the supply drops below a threshold, before the
+    // brown-out reset. Claims the POWER_CLOCK interrupt.
+    pub struct PofWarning {
+        _private: (),
+    }
+
+    impl PofWarning {
+        // The threshold is rounded down to 100 mV, from 1.7 V to 2.8 V
+        pub fn new(power: &POWER, nvic: &mut NVIC, priority: Priority, threshold_mv: u16) -> Self {
+            let bits = (threshold_mv.clamp(MIN_THRESHOLD_MV, MAX_THRESHOLD_MV) - MIN_THRESHOLD_MV)
+                / 100
+                + MIN_THRESHOLD_BITS;
+            // SAFETY: The threshold is one of the valid settings
+            power
+                .pofcon
+                .write(|w| unsafe { w.threshold().bits(bits as u8) }.pof().enabled());
+            // SAFETY: The priority is set before the interrupt is unmasked, and the handler only
+            // uses this module's statics
+            unsafe {
+                nvic.set_priority(Interrupt::POWER_CLOCK, priority.bits());
+                NVIC::unmask(Interrupt::POWER_CLOCK);
+            }
+            Self { _private: () }
+        }
+
+        // Waits for the supply to drop below the threshold
+        pub async fn wait(&mut self) {
+            let power = power();
+            WARNED.store(false, Ordering::Relaxed);
+            power.events_pofwarn.reset();
+            power.intenset.write(|w| w.pofwarn().set());
+            poll_fn(|cx| {
+                WARNING_WAKER.register(cx.waker());
+                if WARNED.load(Ordering::Acquire) {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+            warn!("Supply voltage is failing");
+        }
+    }
+
+    fn power() -> &'static nrf52833_hal::pac::power::RegisterBlock {
+        // SAFETY: Only the POFWARN event and interrupt are touched, which nothing else uses
+        unsafe { &*POWER::ptr() }
+    }
+
+    #[interrupt]
+    fn POWER_CLOCK() {
+        let power = power();
+        if power.events_pofwarn.read().bits() != 0 {
+            power.events_pofwarn.reset();
+            // The warning keeps firing while the supply stays low
+            power.intenclr.write(|w| w.pofwarn().clear());
+            WARNED.store(true, Ordering::Release);
+            WARNING_WAKER.wake();
+        }
+    }
+}
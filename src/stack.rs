@@ -0,0 +1,65 @@
+/*
+Stack usage instrumentation. cortex-m-rt paints everything between the end of .bss and the top of
+RAM before main runs, and any word the stack has touched since then no longer holds the paint. The
+lowest touched word is the high water mark.
+
+The Cortex-M4 has no MSPLIM register to fault on overflow, so `watch` polls the paint instead and
+panics once the stack comes within a reserve of running into .bss.
+*/
+
+use core::ptr::{addr_of, read_volatile};
+
+use cortex_m_rt::STACK_PAINT_VALUE;
+
+use crate::time::{TickDuration, Timer};
+
+unsafe extern "C" {
+    // Top of the stack (the initial SP) and the lowest address it may grow down to
+    static _stack_start: u32;
+    static _stack_end: u32;
+}
+
+fn stack_bounds() -> (usize, usize) {
+    (
+        addr_of!(_stack_end) as usize,
+        addr_of!(_stack_start) as usize,
+    )
+}
+
+// Total bytes available to the stack
+pub fn stack_size() -> usize {
+    let (end, start) = stack_bounds();
+    start - end
+}
+
+// The most bytes the stack has ever used since boot
+pub fn stack_high_water_mark() -> usize {
+    let (end, start) = stack_bounds();
+    let mut addr = end;
+    // SAFETY: Everything between the stack bounds is RAM owned by the stack, and is only read
+    while addr < start && unsafe { read_volatile(addr as *const u32) } == STACK_PAINT_VALUE {
+        addr += size_of::<u32>();
+    }
+    start - addr
+}
+
+// Checks the high water mark every `period`, and panics if fewer than `reserve` bytes of stack
+// have never been touched. Meant to be run as its own task.
+pub async fn watch(period: TickDuration, reserve: usize) -> ! {
+    let size = stack_size();
+    let mut last = 0;
+    loop {
+        let used = stack_high_water_mark();
+        if used > last {
+            debug!("Stack high water mark is {} of {} bytes", used, size);
+            last = used;
+        }
+        if used + reserve > size {
+            panic!(
+                "Stack used {} of {} bytes, leaving less than {} in reserve",
+                used, size, reserve
+            );
+        }
+        Timer::delay(period).await;
+    }
+}
@@ -0,0 +1,86 @@
+/*
+Starvation watchdog for the cooperative executor. A task only gives up the CPU when it awaits, so
+one that does too much work between awaits holds up every other task. The executor times every
+poll, keeps the longest one, and reports any that takes longer than the threshold, by logging a
+warning or by calling a policy set by the application (e.g. to count them, or to reset).
+*/
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    time::{TickDuration, TickInstant, Ticker},
+    utils::LockCell,
+};
+
+// Called with the task and how long its poll took
+pub type StarvationPolicy = fn(usize, TickDuration);
+
+// 10 ms
+static THRESHOLD: AtomicU32 = AtomicU32::new(328);
+static LONGEST: LockCell<Option<(usize, TickDuration)>> = LockCell::new(None);
+static POLICY: LockCell<Option<StarvationPolicy>> = LockCell::new(None);
+
+pub struct StarvationWatchdog;
+
+impl StarvationWatchdog {
+    pub fn set_threshold(threshold: TickDuration) {
+        THRESHOLD.store(
+            u32::try_from(threshold.ticks()).unwrap_or(u32::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    // Replaces the warning with the policy
+    pub fn set_policy(policy: StarvationPolicy) {
+        POLICY.with_lock(|cell| cell.set(Some(policy)));
+    }
+
+    // The task with the longest poll so far, and how long that took
+    pub fn longest_poll() -> Option<(usize, TickDuration)> {
+        LONGEST.with_lock(Cell::get)
+    }
+
+    pub fn reset_longest() {
+        LONGEST.with_lock(|cell| cell.set(None));
+    }
+}
+
+// Times a poll, from when the executor creates it until it drops it after the poll
+pub(crate) struct PollTimer {
+    task: usize,
+    start: TickInstant,
+}
+
+impl PollTimer {
+    pub(crate) fn start(task: usize) -> Self {
+        Self {
+            task,
+            start: Ticker::now(),
+        }
+    }
+}
+
+impl Drop for PollTimer {
+    fn drop(&mut self) {
+        let took = Ticker::now() - self.start;
+        LONGEST.with_lock(|cell| {
+            if cell.get().is_none_or(|(_, longest)| took > longest) {
+                cell.set(Some((self.task, took)));
+            }
+        });
+        if took.ticks() <= u64::from(THRESHOLD.load(Ordering::Relaxed)) {
+            return;
+        }
+        match POLICY.with_lock(Cell::get) {
+            Some(policy) => policy(self.task, took),
+            None => warn!(
+                "Task {} held the CPU for {} ticks without yielding",
+                self.task,
+                took.ticks()
+            ),
+        }
+    }
+}
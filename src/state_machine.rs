@@ -0,0 +1,89 @@
+/*
+Plumbing for tasks that are written as state machines. The state enum says how long each state
+may last, and the machine races the task's input against that timeout and hands whichever comes
+first to the task's transition handler.
+*/
+
+use futures::{FutureExt, select_biased};
+
+use crate::time::{TickDuration, TickInstant, Ticker, Timer};
+
+pub trait State: Copy + PartialEq {
+    // How long the machine can stay in this state before it gets `Event::Timeout`
+    fn timeout(&self) -> Option<TickDuration> {
+        None
+    }
+}
+
+pub enum Event<E> {
+    Input(E),
+    Timeout,
+}
+
+pub enum Transition<S> {
+    Stay,
+    // Going to the current state again restarts its timeout
+    To(S),
+}
+
+pub struct StateMachine<S> {
+    state: S,
+    entered_at: TickInstant,
+}
+
+impl<S: State> StateMachine<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            state: initial,
+            entered_at: Ticker::now(),
+        }
+    }
+
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    pub fn time_in_state(&self) -> TickDuration {
+        Ticker::now() - self.entered_at
+    }
+
+    pub fn transition(&mut self, next: S) {
+        self.state = next;
+        self.entered_at = Ticker::now();
+    }
+
+    // Waits for the input, or for the current state to time out. The timeout counts from when the
+    // state was entered, not from this call.
+    pub async fn next_event<E>(&self, input: impl Future<Output = E>) -> Event<E> {
+        let Some(timeout) = self.state.timeout() else {
+            return Event::Input(input.await);
+        };
+        let remaining = match (self.entered_at + timeout).checked_duration_since(Ticker::now()) {
+            Some(remaining) if remaining.ticks() > 0 => remaining,
+            _ => return Event::Timeout,
+        };
+        select_biased! {
+            input = input.fuse() => Event::Input(input),
+            () = Timer::delay(remaining).fuse() => Event::Timeout,
+        }
+    }
+
+    // Runs the machine forever, taking input from `input` and passing each event to `handler`
+    // along with the current state
+    pub async fn run<E>(
+        &mut self,
+        mut input: impl AsyncFnMut() -> E,
+        mut handler: impl AsyncFnMut(S, Event<E>) -> Transition<S>,
+    ) -> ! {
+        loop {
+            let event = self.next_event(input()).await;
+            let timed_out = matches!(event, Event::Timeout);
+            match handler(self.state, event).await {
+                Transition::To(next) => self.transition(next),
+                // Otherwise the state would time out again straight away
+                Transition::Stay if timed_out => self.transition(self.state),
+                Transition::Stay => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+/*
+Supervises worker tasks that are meant to check in regularly. A worker that stays silent for longer
+than the timeout is escalated a step at a time: first it's logged, after twice the timeout the
+indicator LED starts blinking an error pattern, and after three times the timeout the supervisor
+stops petting the watchdog so the board resets. A worker that checks in again is back in good
+standing, unless the reset has already started.
+*/
+
+use core::cell::RefCell;
+
+use embedded_hal::digital::{OutputPin, PinState};
+use nrf52833_hal::wdt::{WatchdogHandle, handles::HdlN};
+use snafu::prelude::*;
+
+use crate::{
+    time::{TickDuration, TickInstant, Ticker, Timer},
+    utils::InfallibleExt,
+};
+
+// How many times per timeout the workers are checked
+const CHECKS_PER_TIMEOUT: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Escalation {
+    Healthy,
+    Logged,
+    Indicating,
+    Resetting,
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SupervisorError {
+    #[snafu(display("The supervisor can't watch any more workers"))]
+    TooManyWorkers,
+}
+
+struct Worker {
+    name: &'static str,
+    last_check_in: TickInstant,
+    escalation: Escalation,
+}
+
+pub struct Supervisor<const N: usize> {
+    timeout: TickDuration,
+    workers: RefCell<heapless::Vec<Worker, N>>,
+}
+
+impl<const N: usize> Supervisor<N> {
+    pub const fn new(timeout: TickDuration) -> Self {
+        Self {
+            timeout,
+            workers: RefCell::new(heapless::Vec::new()),
+        }
+    }
+
+    pub fn register(&self, name: &'static str) -> Result<Heartbeat<'_, N>, SupervisorError> {
+        let mut workers = self.workers.borrow_mut();
+        let index = workers.len();
+        workers
+            .push(Worker {
+                name,
+                last_check_in: Ticker::now(),
+                escalation: Escalation::Healthy,
+            })
+            .ok()
+            .context(TooManyWorkersSnafu)?;
+        Ok(Heartbeat {
+            supervisor: self,
+            index,
+        })
+    }
+
+    // The worst escalation of any worker
+    pub fn escalation(&self) -> Escalation {
+        self.workers
+            .borrow()
+            .iter()
+            .map(|worker| worker.escalation)
+            .max()
+            .unwrap_or(Escalation::Healthy)
+    }
+
+    // The watchdog's timeout has to be longer than a quarter of the supervisor's, which is how
+    // often it gets petted
+    pub async fn run<P>(&self, mut watchdog: WatchdogHandle<HdlN>, mut indicator: P) -> !
+    where
+        P: OutputPin<Error = core::convert::Infallible>,
+    {
+        let mut indicator_on = false;
+        loop {
+            Timer::delay(self.timeout / CHECKS_PER_TIMEOUT).await;
+            let escalation = self.check();
+            if escalation < Escalation::Resetting {
+                watchdog.pet();
+            }
+            // Blinks while any worker is at least that far gone
+            indicator_on = escalation >= Escalation::Indicating && !indicator_on;
+            indicator
+                .set_state(PinState::from(indicator_on))
+                .unwrap_infallible();
+        }
+    }
+
+    fn check(&self) -> Escalation {
+        let now = Ticker::now();
+        for worker in self.workers.borrow_mut().iter_mut() {
+            // Once the reset has started there's no going back
+            if worker.escalation == Escalation::Resetting {
+                continue;
+            }
+            let silent = now - worker.last_check_in;
+            let escalation = if silent > self.timeout * 3 {
+                Escalation::Resetting
+            } else if silent > self.timeout * 2 {
+                Escalation::Indicating
+            } else if silent > self.timeout {
+                Escalation::Logged
+            } else {
+                Escalation::Healthy
+            };
+            if escalation > worker.escalation {
+                match escalation {
+                    Escalation::Resetting => error!(
+                        "Worker {} silent for {} ticks, letting the watchdog reset",
+                        worker.name,
+                        silent.ticks()
+                    ),
+                    _ => warn!("Worker {} silent for {} ticks", worker.name, silent.ticks()),
+                }
+            }
+            worker.escalation = escalation;
+        }
+        self.escalation()
+    }
+}
+
+// Held by a worker to check in with its supervisor
+pub struct Heartbeat<'a, const N: usize> {
+    supervisor: &'a Supervisor<N>,
+    index: usize,
+}
+
+impl<const N: usize> Heartbeat<'_, N> {
+    pub fn check_in(&self) {
+        if let Some(worker) = self.supervisor.workers.borrow_mut().get_mut(self.index) {
+            worker.last_check_in = Ticker::now();
+        }
+    }
+}
@@ -0,0 +1,264 @@
+/*
+Structured telemetry to a host, for builds without a debug probe where defmt has nowhere to go.
+Tasks emit records of named fields, which queue up until the telemetry task sends them over the
+framed serial link, one record to a frame, for a dashboard on the host to decode and plot:
+
+    static TELEMETRY: Telemetry<8> = Telemetry::new();
+    // In any task
+    let mut record = Record::now("motor")?;
+    record.field("rpm", rpm)?.field("stalled", false)?;
+    let _ = TELEMETRY.emit(record);
+    // In the telemetry task
+    TELEMETRY.run(&mut FramedLink::new(serial)).await;
+
+A record is encoded as MessagePack, so the host can decode it with any MessagePack library: an
+array of the record's name, its timestamp in microseconds and a map of its fields, as in
+`["motor", 1500000, {"rpm": 1200, "stalled": false}]`. Records are dropped rather than waited on
+when the queue is full, so telemetry never holds up the task reporting it.
+*/
+
+use portable_atomic::{AtomicU32, Ordering};
+use snafu::prelude::*;
+
+use crate::{
+    channel::mpsc::Mpsc,
+    framing::{ByteLink, FramedLink},
+};
+
+// The most an encoded record takes, which is well under a frame
+pub const MAX_RECORD: usize = 64;
+// A fixmap holds at most 15 entries
+pub const MAX_FIELDS: u8 = 15;
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TelemetryError {
+    #[snafu(display("The record doesn't fit in {MAX_RECORD} bytes"))]
+    RecordTooLong,
+    #[snafu(display("A record can have at most {MAX_FIELDS} fields"))]
+    TooManyFields,
+    #[snafu(display("The telemetry queue is full, dropping the record"))]
+    QueueFull,
+}
+
+// A field's value, made with `into` from the usual types
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f32),
+    Str(&'a str),
+}
+
+impl From<bool> for Value<'_> {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<f32> for Value<'_> {
+    fn from(value: f32) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self {
+        Value::Str(value)
+    }
+}
+
+macro_rules! value_from {
+    ($variant:ident, $wide:ty, $($ty:ty),+) => {
+        $(
+            impl From<$ty> for Value<'_> {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(<$wide>::from(value))
+                }
+            }
+        )+
+    };
+}
+
+value_from!(Int, i64, i8, i16, i32, i64);
+value_from!(Uint, u64, u8, u16, u32, u64);
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    encoded: heapless::Vec<u8, MAX_RECORD>,
+    // Where the map header is, to count the fields into
+    fields_at: usize,
+    fields: u8,
+}
+
+impl Record {
+    pub fn new(name: &str, timestamp_us: u64) -> Result<Self, TelemetryError> {
+        let mut record = Self {
+            encoded: heapless::Vec::new(),
+            fields_at: 0,
+            fields: 0,
+        };
+        // A fixarray of 3
+        record.push(&[0x93])?;
+        record.encode(Value::Str(name))?;
+        record.encode(Value::Uint(timestamp_us))?;
+        record.fields_at = record.encoded.len();
+        // An empty fixmap, counted up as fields are added
+        record.push(&[0x80])?;
+        Ok(record)
+    }
+
+    // Stamped with the ticker's time
+    #[cfg(any(feature = "nrf52833", feature = "std"))]
+    pub fn now(name: &str) -> Result<Self, TelemetryError> {
+        Self::new(
+            name,
+            crate::time::Ticker::now()
+                .duration_since_epoch()
+                .to_micros(),
+        )
+    }
+
+    // Leaves the record as it was if the field doesn't fit
+    pub fn field<'a>(
+        &mut self,
+        key: &str,
+        value: impl Into<Value<'a>>,
+    ) -> Result<&mut Self, TelemetryError> {
+        ensure!(self.fields < MAX_FIELDS, TooManyFieldsSnafu);
+        let len = self.encoded.len();
+        if let Err(err) = self
+            .encode(Value::Str(key))
+            .and_then(|()| self.encode(value.into()))
+        {
+            self.encoded.truncate(len);
+            return Err(err);
+        }
+        self.fields += 1;
+        self.encoded[self.fields_at] = 0x80 | self.fields;
+        Ok(self)
+    }
+
+    pub fn fields(&self) -> u8 {
+        self.fields
+    }
+
+    // The MessagePack encoding, as sent
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.encoded
+    }
+
+    fn encode(&mut self, value: Value<'_>) -> Result<(), TelemetryError> {
+        match value {
+            Value::Bool(false) => self.push(&[0xC2]),
+            Value::Bool(true) => self.push(&[0xC3]),
+            Value::Uint(value) => self.encode_uint(value),
+            Value::Int(value) => match u64::try_from(value) {
+                Ok(value) => self.encode_uint(value),
+                Err(_) => self.encode_negative(value),
+            },
+            Value::Float(value) => {
+                self.push(&[0xCA])?;
+                self.push(&value.to_be_bytes())
+            }
+            Value::Str(value) => {
+                let len = value.len();
+                if len < 32 {
+                    self.push(&[0xA0 | len as u8])?;
+                } else {
+                    let len = u8::try_from(len).ok().context(RecordTooLongSnafu)?;
+                    self.push(&[0xD9, len])?;
+                }
+                self.push(value.as_bytes())
+            }
+        }
+    }
+
+    // In the fewest bytes that hold it
+    fn encode_uint(&mut self, value: u64) -> Result<(), TelemetryError> {
+        if value < 0x80 {
+            self.push(&[value as u8])
+        } else if let Ok(value) = u8::try_from(value) {
+            self.push(&[0xCC, value])
+        } else if let Ok(value) = u16::try_from(value) {
+            self.push(&[0xCD])?;
+            self.push(&value.to_be_bytes())
+        } else if let Ok(value) = u32::try_from(value) {
+            self.push(&[0xCE])?;
+            self.push(&value.to_be_bytes())
+        } else {
+            self.push(&[0xCF])?;
+            self.push(&value.to_be_bytes())
+        }
+    }
+
+    fn encode_negative(&mut self, value: i64) -> Result<(), TelemetryError> {
+        if value >= -32 {
+            self.push(&[value as u8])
+        } else if let Ok(value) = i8::try_from(value) {
+            self.push(&[0xD0, value as u8])
+        } else if let Ok(value) = i16::try_from(value) {
+            self.push(&[0xD1])?;
+            self.push(&value.to_be_bytes())
+        } else if let Ok(value) = i32::try_from(value) {
+            self.push(&[0xD2])?;
+            self.push(&value.to_be_bytes())
+        } else {
+            self.push(&[0xD3])?;
+            self.push(&value.to_be_bytes())
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), TelemetryError> {
+        self.encoded
+            .extend_from_slice(bytes)
+            .ok()
+            .context(RecordTooLongSnafu)
+    }
+}
+
+// Queues up to N records for the telemetry task. It can live in a static, so any task can emit.
+pub struct Telemetry<const N: usize> {
+    queue: Mpsc<Record, N>,
+    dropped: AtomicU32,
+}
+
+impl<const N: usize> Telemetry<N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: Mpsc::new(),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    // Queues the record without waiting, or drops it if the queue is full
+    pub fn emit(&self, record: Record) -> Result<(), TelemetryError> {
+        if self.queue.send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return QueueFullSnafu.fail();
+        }
+        Ok(())
+    }
+
+    // Records dropped for a full queue, which a dashboard can report as a field of its own
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    // Sends records as they're emitted, one to a frame
+    pub async fn run<L: ByteLink>(&self, link: &mut FramedLink<L>) -> ! {
+        info!("Telemetry streaming");
+        loop {
+            let record = self.queue.recv().await;
+            // Can't fail, a record is shorter than a frame's payload
+            let _ = link.send(record.as_bytes()).await;
+        }
+    }
+}
+
+impl<const N: usize> Default for Telemetry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,75 @@
+/*
+The die temperature sensor, and temperature compensation of the tick drift. The RC oscillator
+drifts with temperature even after calibration, so on a board without a 32 kHz crystal, timestamps
+taken hours apart can be seconds off. Given a table of how far the oscillator is off at each
+temperature, measured against a reference for the particular board or batch, `compensate_drift`
+keeps the ticker's drift correction up to date as the temperature changes.
+*/
+
+use nrf52833_hal::pac::TEMP;
+
+use crate::time::{TickDuration, Ticker, Timer};
+
+pub struct Temperature {
+    temp: TEMP,
+}
+
+impl Temperature {
+    pub fn new(temp: TEMP) -> Self {
+        Self { temp }
+    }
+
+    // In quarter degrees Celsius. A measurement takes about 36 us, so it's waited for.
+    pub fn read_quarters(&mut self) -> i32 {
+        self.temp.events_datardy.reset();
+        self.temp.tasks_start.write(|w| unsafe { w.bits(1) });
+        while self.temp.events_datardy.read().bits() == 0 {
+            core::hint::spin_loop();
+        }
+        self.temp.events_datardy.reset();
+        self.temp.temp.read().bits() as i32
+    }
+
+    pub fn read_celsius(&mut self) -> i32 {
+        self.read_quarters().div_euclid(4)
+    }
+}
+
+// Drift in ppm at a temperature in degrees Celsius, positive when the oscillator runs fast.
+// Sorted by temperature.
+pub type DriftTable = [(i8, i32)];
+
+// Linear between the points, and flat beyond the ends of the table
+pub fn drift_at(table: &DriftTable, quarters: i32) -> i32 {
+    let Some(&(first_celsius, first_ppm)) = table.first() else {
+        return 0;
+    };
+    if quarters <= i32::from(first_celsius) * 4 {
+        return first_ppm;
+    }
+    for pair in table.windows(2) {
+        let [(low_celsius, low_ppm), (high_celsius, high_ppm)] = [pair[0], pair[1]];
+        let (low, high) = (i32::from(low_celsius) * 4, i32::from(high_celsius) * 4);
+        if quarters <= high {
+            return low_ppm + (high_ppm - low_ppm) * (quarters - low) / (high - low).max(1);
+        }
+    }
+    table.last().map_or(0, |&(_, ppm)| ppm)
+}
+
+// Measures the temperature every period, and sets the ticker's drift correction from the table
+pub async fn compensate_drift(
+    mut temperature: Temperature,
+    table: &'static DriftTable,
+    period: TickDuration,
+) -> ! {
+    loop {
+        let quarters = temperature.read_quarters();
+        let ppm = drift_at(table, quarters);
+        if ppm != Ticker::drift_ppm() {
+            debug!("Drift is {} ppm at {} quarter degrees", ppm, quarters);
+            Ticker::set_drift_ppm(ppm);
+        }
+        Timer::delay(period).await;
+    }
+}
@@ -1,156 +1,183 @@
 use core::{
-    marker::PhantomPinned,
+    future::poll_fn,
     pin::{Pin, pin},
-    task::{Context, Poll, Waker},
+    task::{Context, Poll},
 };
 
 use fugit::{Duration, Instant};
+use futures::{FutureExt, select_biased};
 pub type TickInstant = Instant<u64, 1, 32768>;
 pub type TickDuration = Duration<u64, 1, 32768>;
-use nrf52833_hal::{
-    Rtc,
-    pac::{NVIC, RTC0, interrupt},
-    rtc::{RtcCompareReg, RtcInterrupt},
-};
 use snafu::prelude::*;
 
-use intrusive_collections::{KeyAdapter, RBTree, RBTreeAtomicLink, UnsafeRef, intrusive_adapter};
-
-use crate::utils::{LockCell, LockMut};
+use crate::utils::LockCell;
+#[cfg(not(feature = "nrf52833"))]
+use crate::utils::LockMut;
+#[cfg(feature = "nrf52833")]
+use crate::utils::PriorityLock;
+
+mod drift;
+mod queue;
+#[cfg(feature = "metrics")]
+pub use queue::QueueMetrics;
+use queue::{TimerNode, TimerQueue};
+
+// The driver backs the ticker with a monotonic tick count and a single alarm
+#[cfg(feature = "nrf52833")]
+mod rtc;
+#[cfg(feature = "nrf52833")]
+use rtc::TickDriver;
+
+#[cfg(all(feature = "std", not(feature = "mock-time")))]
+mod host;
+#[cfg(all(feature = "std", not(feature = "mock-time")))]
+use host::TickDriver;
+
+#[cfg(feature = "mock-time")]
+mod mock;
+#[cfg(feature = "mock-time")]
+pub use mock::MockTicker;
+#[cfg(feature = "mock-time")]
+use mock::TickDriver;
+
+#[cfg(feature = "embassy-time-driver")]
+mod embassy_driver;
 
 pub struct Timer {
-    // SAFETY: Never access this through a mutable reference
-    inner: TimerInner,
-    _pin: PhantomPinned,
-}
-
-struct TimerQueue {
-    timers: RBTree<TimerAdapter>,
-}
-
-intrusive_adapter!(TimerAdapter = UnsafeRef<TimerInner>: TimerInner { link: RBTreeAtomicLink } );
-
-impl<'a> KeyAdapter<'a> for TimerAdapter {
-    type Key = u64;
-    fn get_key(&self, value: &'a TimerInner) -> Self::Key {
-        value.end_time.ticks()
-    }
+    // Structurally pinned, see `node`
+    node: TimerNode,
+    state: LockCell<TimerState>,
 }
 
-impl TimerQueue {
-    fn new() -> Self {
+impl Timer {
+    fn new(duration: TickDuration) -> Self {
         Self {
-            timers: RBTree::new(TimerAdapter::new()),
+            node: TimerNode::new(Ticker::now() + duration),
+            state: LockCell::new(TimerState::Init),
         }
     }
 
-    fn insert_timer(&mut self, timer: &Timer) {
-        /*
-           SAFETY:
-           UnsafeRef is safe if the object it is pointing to is not moved, dropped, or accessed through a mutable reference during the UnsafeRef's lifetime.
-           - The API for the timer atomatically pins the value to avoid moving
-           - The timer never exposes any functions to mutably alter the TimerInner, and the TimerInner is itself never accessed mutably
-           - When the timer is dropped, it is first removed from the linked list
-        */
-        let timer_ref = unsafe { UnsafeRef::from_raw(&timer.inner) };
-        self.timers.insert(timer_ref);
-    }
-
-    fn remove_timer(&mut self, timer: &Timer) {
-        if timer.inner.link.is_linked() {
-            // SAFETY
-            // Since there is only one static timer queue in this module, then we know the timer must be a part of it
-            let mut cursor = unsafe { self.timers.cursor_mut_from_ptr(&timer.inner) };
-            cursor.remove();
-        }
+    pub async fn delay(duration: TickDuration) {
+        let timer = pin!(Self::new(duration));
+        timer.await;
     }
 
-    fn peek_earliest(&self) -> Option<&TimerInner> {
-        self.timers.front().get()
+    fn node(self: Pin<&Self>) -> Pin<&TimerNode> {
+        // SAFETY: The node is never moved out of the timer, and the timer has no drop of its own
+        unsafe { self.map_unchecked(|timer| &timer.node) }
     }
 
-    fn pop_earliest(&mut self) -> Option<UnsafeRef<TimerInner>> {
-        if self.timers.is_empty() {
-            None
-        } else {
-            let mut cursor = self.timers.front_mut();
-            let timer_ref = cursor.remove();
-            timer_ref
-        }
+    fn is_ready(&self) -> bool {
+        Ticker::now() >= self.node.end_time()
     }
 }
 
-// SAFETY
-// Must not be moved, dropped, or accessed through a mutable reference as long as at least one UnsafeRef is pointing to it
-struct TimerInner {
-    end_time: TickInstant,
-    state: LockCell<TimerState>,
-    waker: LockCell<Option<Waker>>,
-    link: RBTreeAtomicLink,
-    _pin: PhantomPinned,
+// Fires every period against a fixed schedule, reusing its queue node instead of building a new
+// timer each time. Pin it once, then await `tick` in a loop.
+pub struct PeriodicTimer {
+    // Structurally pinned, see `node`
+    node: TimerNode,
+    period: TickDuration,
 }
 
-impl Timer {
-    fn new(duration: TickDuration) -> Self {
-        let end_time = Ticker::now() + duration;
+impl PeriodicTimer {
+    // The first tick is a period from now
+    pub fn new(period: TickDuration) -> Self {
+        assert!(period.ticks() > 0, "The period can't be zero");
         Self {
-            inner: TimerInner {
-                end_time,
-                state: LockCell::new(TimerState::Init),
-                waker: LockCell::new(None),
-                link: RBTreeAtomicLink::new(),
-                _pin: PhantomPinned,
-            },
-            _pin: PhantomPinned,
+            node: TimerNode::new(Ticker::now() + period),
+            period,
         }
     }
 
-    pub async fn delay(duration: TickDuration) {
-        // Garuntees that the value can't be moved (for the unsaferef)
-        let timer = pin!(Self::new(duration));
-        timer.await;
+    pub fn period(&self) -> TickDuration {
+        self.period
     }
 
-    fn is_ready(&self) -> bool {
-        Ticker::now() >= self.inner.end_time
+    fn node(self: Pin<&Self>) -> Pin<&TimerNode> {
+        // SAFETY: The node is never moved out of the timer, and the timer has no drop of its own
+        unsafe { self.map_unchecked(|timer| &timer.node) }
     }
 
-    fn add_to_queue(&self, waker: &Waker) {
-        TICKER.with_lock(|ticker| {
-            // Only add if not already in the queue
-            if !self.inner.link.is_linked() {
-                ticker.deadlines.insert_timer(self);
-                self.inner
-                    .waker
-                    .with_lock(|waker_cell| waker_cell.replace(Some(waker.clone())));
-                // Update if this is now the earliest
-                if let Some(latest) = ticker.deadlines.peek_earliest() {
-                    set_deadline(&latest.end_time, &mut ticker.rtc0);
+    // Waits for the next deadline. If ticks were missed, it warns and skips them, so the deadlines
+    // stay on the schedule.
+    pub async fn tick(self: Pin<&mut Self>) {
+        let timer = self.into_ref();
+        poll_fn(|cx| {
+            let node = timer.node();
+            let now = Ticker::now();
+            let deadline = node.end_time();
+            if now < deadline {
+                node.set_waker(cx.waker());
+                if !node.is_queued() {
+                    TICKER.with_lock(|ticker| ticker.insert(node));
                 }
+                return Poll::Pending;
             }
-        });
-    }
-
-    fn remove_from_queue(&self) {
-        TICKER.with_lock(|ticker| {
-            if self.inner.link.is_linked() {
-                ticker.deadlines.remove_timer(self);
-                // Update in case we removed the first timer
-                if let Some(earliest) = ticker.deadlines.peek_earliest() {
-                    set_deadline(&earliest.end_time, &mut ticker.rtc0);
-                }
+            if node.is_queued() {
+                TICKER.with_lock(|ticker| ticker.remove(&node));
+            }
+            let period = timer.period;
+            let mut next = deadline + period;
+            if next <= now {
+                let behind = (now - next).ticks() / period.ticks() + 1;
+                warn!("Periodic timer fell behind, skipping {} ticks", behind);
+                next += period * behind as u32;
             }
+            node.set_end_time(next);
+            Poll::Ready(())
         })
+        .await;
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[snafu(display("The timeout elapsed"))]
+pub struct Elapsed;
+
+// Runs the future until it finishes or the duration is up, whichever is first
+pub async fn timeout<T>(
+    duration: TickDuration,
+    future: impl Future<Output = T>,
+) -> Result<T, Elapsed> {
+    select_biased! {
+        output = future.fuse() => Ok(output),
+        () = Timer::delay(duration).fuse() => Err(Elapsed),
     }
 }
 
-impl Drop for Timer {
-    fn drop(&mut self) {
-        // Remove from queue when dropped to adhere to UnsafeCell's safety requirements
-        self.remove_from_queue();
+// Below this a delay busy waits, since a timer can't be shorter than a tick of about 31 µs, and
+// waking the task adds more on top
+pub const BUSY_WAIT_LIMIT_US: u32 = 100;
+
+// Waits at least `micros`, e.g. between the bits of a bit-banged protocol. Short waits busy wait,
+// which is accurate but holds up every other task, and longer ones yield to them on a timer.
+pub async fn delay_us(micros: u32) {
+    if micros < BUSY_WAIT_LIMIT_US {
+        busy_wait_us(micros);
+        return;
+    }
+    // Rounded up, plus a tick, since the tick the timer starts in is already partly over
+    Timer::delay(TickDuration::micros_at_least(u64::from(micros)) + TickDuration::from_ticks(1))
+        .await;
+}
+
+// Counts cycles in a loop, so it needs no timer or DWT setup
+#[cfg(feature = "nrf52833")]
+fn busy_wait_us(micros: u32) {
+    cortex_m::asm::delay(micros * crate::cycles::PER_MICRO);
+}
+
+#[cfg(not(feature = "nrf52833"))]
+fn busy_wait_us(micros: u32) {
+    let start = std::time::Instant::now();
+    while start.elapsed() < std::time::Duration::from_micros(u64::from(micros)) {
+        core::hint::spin_loop();
     }
 }
 
+#[derive(Clone, Copy)]
 enum TimerState {
     Wait,
     Init,
@@ -160,21 +187,25 @@ impl Future for Timer {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let state = self
-            .inner
-            .state
-            .with_lock(|cell| cell.replace(TimerState::Wait));
+        let state = self.state.with_lock(|cell| cell.replace(TimerState::Wait));
         match state {
             TimerState::Init => {
-                self.add_to_queue(cx.waker());
-                self.inner
-                    .state
-                    .with_lock(|cell| cell.set(TimerState::Wait));
+                let node = self.as_ref().node();
+                // Only add if not already in the queue
+                if !node.is_queued() {
+                    node.set_waker(cx.waker());
+                    TICKER.with_lock(|ticker| ticker.insert(node));
+                }
                 Poll::Pending
             }
             TimerState::Wait => {
+                // The future may be polled with a different waker than last time (e.g. after being
+                // moved between combinators), so register the current one before checking
+                self.node.set_waker(cx.waker());
                 if self.is_ready() {
-                    self.remove_from_queue();
+                    if self.node.is_queued() {
+                        TICKER.with_lock(|ticker| ticker.remove(&self.node));
+                    }
                     Poll::Ready(())
                 } else {
                     Poll::Pending
@@ -184,84 +215,104 @@ impl Future for Timer {
     }
 }
 
+// Only the RTC interrupt shares the ticker with the tasks, so only it needs masking
+#[cfg(feature = "nrf52833")]
+static TICKER: PriorityLock<Ticker> = PriorityLock::new();
+#[cfg(not(feature = "nrf52833"))]
 static TICKER: LockMut<Ticker> = LockMut::new();
 
 pub struct Ticker {
-    rtc0: Rtc<RTC0>,
-    overflow_count: u32,
+    driver: TickDriver,
     deadlines: TimerQueue,
 }
 
-#[derive(Debug, Snafu)]
-pub enum TimerError {
-    #[snafu(display("The deadline {ticks} is too big for the internal counter"))]
-    DeadlineTooLarge { ticks: u64 },
-}
-
 impl Ticker {
-    pub fn init(rtc0: RTC0, nvic: &mut NVIC) {
-        // SAFETY: Can never return an error since prescalar 0 never returns an error
-        #[allow(clippy::unwrap_used)]
-        let mut rtc0 = Rtc::new(rtc0, 0).unwrap();
-        rtc0.enable_counter();
-
-        // Enable overflow interrupt
-        rtc0.enable_event(RtcInterrupt::Overflow);
-        rtc0.enable_interrupt(RtcInterrupt::Overflow, Some(nvic));
-
-        // Enable compare interrupt
-        rtc0.enable_event(RtcInterrupt::Compare0);
-        rtc0.enable_interrupt(RtcInterrupt::Compare0, Some(nvic));
-
-        // Init
-        TICKER.init(Self {
-            overflow_count: 0,
-            rtc0,
+    fn new(driver: TickDriver) -> Self {
+        Self {
+            driver,
             deadlines: TimerQueue::new(),
-        });
+        }
+    }
+
+    #[cfg(not(feature = "nrf52833"))]
+    fn init_with(driver: TickDriver) {
+        drift::reset();
+        TICKER.init(Self::new(driver));
     }
 
+    // Corrected for the drift set with `set_drift_ppm`
     pub fn now() -> TickInstant {
-        let ticks = TICKER.with_lock(|ticker| {
-            let counter = ticker.rtc0.get_counter();
-            let overflow = ticker.overflow_count;
-            (u64::from(overflow) << 24) | u64::from(counter)
+        TickInstant::from_ticks(drift::corrected(TickDriver::now()))
+    }
+
+    // Corrects time for a tick source running `ppm` parts per million fast (or slow, when
+    // negative), from now on. Time already passed keeps the correction it had.
+    pub fn set_drift_ppm(ppm: i32) {
+        TICKER.with_lock(|ticker| {
+            drift::set_ppm(ticker.driver.ticks(), ppm);
+            // The alarm was set in raw ticks for the old drift
+            if let Some(earliest) = ticker.deadlines.peek_earliest() {
+                ticker.set_alarm(earliest.end_time());
+            }
         });
-        TickInstant::from_ticks(ticks)
     }
-}
 
-fn set_deadline(deadline: &TickInstant, rtc0: &mut Rtc<RTC0>) {
-    let deadline_low = (deadline.ticks() & 0x00FF_FFFF) as u32;
-    rtc0.set_compare(RtcCompareReg::Compare0, deadline_low)
-        .unwrap();
-}
+    pub fn drift_ppm() -> i32 {
+        drift::ppm()
+    }
 
-#[interrupt]
-fn RTC0() {
-    TICKER.with_lock(handle_rtc0_interrupt);
-}
+    // What the timer queue has done since boot, or since the last reset
+    #[cfg(feature = "metrics")]
+    pub fn queue_metrics() -> QueueMetrics {
+        TICKER.with_lock(|ticker| ticker.deadlines.metrics())
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn reset_queue_metrics() {
+        TICKER.with_lock(|ticker| ticker.deadlines.reset_metrics());
+    }
+
+    fn ticks(&self) -> u64 {
+        drift::corrected(self.driver.ticks())
+    }
 
-// TODO: I believe this is unsound, since it does not collect all the pending deadlines, only one.
-fn handle_rtc0_interrupt(ticker: &mut Ticker) {
-    let rtc0 = &mut ticker.rtc0;
-    if rtc0.is_event_triggered(RtcInterrupt::Overflow) {
-        rtc0.reset_event(RtcInterrupt::Overflow);
-        ticker.overflow_count += 1;
+    fn set_alarm(&mut self, deadline: TickInstant) {
+        let raw = TickInstant::from_ticks(drift::raw(deadline.ticks()));
+        self.driver.set_alarm(&raw);
     }
-    if rtc0.is_event_triggered(RtcInterrupt::Compare0) {
-        rtc0.reset_event(RtcInterrupt::Compare0);
-        let latest = ticker
-            .deadlines
-            .pop_earliest()
-            .expect("No deadline available on interrupt");
-        if let Some(pending_deadline) = ticker.deadlines.peek_earliest() {
-            set_deadline(&pending_deadline.end_time, rtc0);
+
+    fn insert(&mut self, node: Pin<&TimerNode>) {
+        self.deadlines.insert(node);
+        // Update if this is now the earliest
+        if let Some(earliest) = self.deadlines.peek_earliest() {
+            self.set_alarm(earliest.end_time());
+        }
+    }
+
+    fn remove(&mut self, node: &TimerNode) {
+        self.deadlines.remove(node);
+        // Update in case we removed the first timer
+        if let Some(earliest) = self.deadlines.peek_earliest() {
+            self.set_alarm(earliest.end_time());
+        }
+    }
+
+    // Called by the driver when the alarm goes off. Wakes every timer that is due, since several
+    // can share a deadline, and the alarm can fire early when the deadline is more than a counter
+    // period away.
+    fn on_alarm(&mut self) {
+        let now = self.ticks();
+        while let Some(earliest) = self.deadlines.peek_earliest() {
+            if earliest.end_time().ticks() > now {
+                self.set_alarm(earliest.end_time());
+                break;
+            }
+            let waker = earliest.take_waker();
+            self.deadlines.pop_earliest();
+            match waker {
+                Some(waker) => waker.wake(),
+                None => warn!("Timer does not have an associated waker"),
+            }
         }
-        latest
-            .waker
-            .with_lock(|cell| cell.replace(None))
-            .expect("Timer does not have an associated waker")
-            .wake();
     }
 }
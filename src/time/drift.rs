@@ -0,0 +1,98 @@
+/*
+Software correction for a tick source that runs fast or slow, like the RC oscillator, which can be
+hundreds of ppm off and moves with temperature. The raw ticks are scaled by the drift from a base
+point, which moves to the current time whenever the drift changes, so corrected time stays
+continuous and monotonic. Only time from the ticker is corrected, the driver keeps counting raw
+ticks, so alarms are converted back before they're set.
+*/
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::utils::LockCell;
+
+const MILLION: i64 = 1_000_000;
+// Beyond this the oscillator is broken rather than drifting
+const MAX_PPM: i32 = 100_000;
+
+#[derive(Clone, Copy)]
+struct Drift {
+    base_raw: u64,
+    base_corrected: u64,
+    // How fast the tick source runs, positive when it's fast
+    ppm: i32,
+}
+
+static DRIFT: LockCell<Drift> = LockCell::new(Drift {
+    base_raw: 0,
+    base_corrected: 0,
+    ppm: 0,
+});
+// Skips the lock while no drift has ever been set
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+impl Drift {
+    fn corrected(&self, raw: u64) -> u64 {
+        let elapsed = raw.saturating_sub(self.base_raw) as i64;
+        let scaled = elapsed * (MILLION - i64::from(self.ppm)) / MILLION;
+        self.base_corrected + scaled as u64
+    }
+
+    // Rounds up, so an alarm never goes off before the corrected deadline
+    fn raw(&self, corrected: u64) -> u64 {
+        let Some(elapsed) = corrected.checked_sub(self.base_corrected) else {
+            return self
+                .base_raw
+                .saturating_sub(self.base_corrected - corrected);
+        };
+        // Always positive, since the drift is limited
+        let rate = (MILLION - i64::from(self.ppm)) as u64;
+        self.base_raw + (elapsed * MILLION as u64).div_ceil(rate)
+    }
+}
+
+pub(super) fn corrected(raw: u64) -> u64 {
+    if !ENABLED.load(Ordering::Acquire) {
+        return raw;
+    }
+    DRIFT.with_lock(|drift| drift.get().corrected(raw))
+}
+
+pub(super) fn raw(corrected: u64) -> u64 {
+    if !ENABLED.load(Ordering::Acquire) {
+        return corrected;
+    }
+    DRIFT.with_lock(|drift| drift.get().raw(corrected))
+}
+
+pub(super) fn set_ppm(raw_now: u64, ppm: i32) {
+    assert!(
+        ppm.abs() <= MAX_PPM,
+        "A drift of {} ppm is out of range",
+        ppm
+    );
+    DRIFT.with_lock(|drift| {
+        let old = drift.get();
+        drift.set(Drift {
+            base_raw: raw_now,
+            base_corrected: old.corrected(raw_now),
+            ppm,
+        });
+    });
+    ENABLED.store(true, Ordering::Release);
+}
+
+pub(super) fn ppm() -> i32 {
+    DRIFT.with_lock(|drift| drift.get().ppm)
+}
+
+// Back to raw time, for when the ticker is reinitialised and its count restarts
+pub(super) fn reset() {
+    ENABLED.store(false, Ordering::Release);
+    DRIFT.with_lock(|drift| {
+        drift.set(Drift {
+            base_raw: 0,
+            base_corrected: 0,
+            ppm: 0,
+        })
+    });
+}
@@ -0,0 +1,71 @@
+/*
+Implements embassy's time driver on top of the Ticker, so drivers written against `embassy_time`
+can run on this executor. Embassy timers get their own RTC compare channel (Compare1) and timer
+queue, so they don't interfere with our own `Timer`s.
+*/
+
+use core::{cell::RefCell, task::Waker};
+
+use critical_section::Mutex;
+use embassy_time_driver::{Driver, time_driver_impl};
+use embassy_time_queue_utils::queue_generic::ConstGenericQueue;
+use nrf52833_hal::rtc::RtcCompareReg;
+
+use super::{TICKER, Ticker};
+
+const QUEUE_SIZE: usize = 16;
+// The RTC won't fire a compare event set less than 2 ticks ahead of the counter
+const MIN_ALARM_TICKS: u64 = 2;
+
+type EmbassyQueue = ConstGenericQueue<QUEUE_SIZE>;
+
+struct TickerDriver {
+    queue: Mutex<RefCell<EmbassyQueue>>,
+}
+
+time_driver_impl!(static DRIVER: TickerDriver = TickerDriver {
+    queue: Mutex::new(RefCell::new(EmbassyQueue::new())),
+});
+
+impl Driver for TickerDriver {
+    fn now(&self) -> u64 {
+        // Doesn't need the ticker, so works even before it's initialized
+        Ticker::now().ticks()
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        critical_section::with(|cs| {
+            let mut queue = self.queue.borrow_ref_mut(cs);
+            if queue.schedule_wake(at, waker) {
+                TICKER.with_lock(|ticker| arm_alarm(ticker, &mut queue));
+            }
+        });
+    }
+}
+
+// Called from the RTC0 interrupt when the embassy alarm fires
+pub(super) fn on_alarm(ticker: &mut Ticker) {
+    critical_section::with(|cs| arm_alarm(ticker, &mut DRIVER.queue.borrow_ref_mut(cs)));
+}
+
+// Wakes all of the expired timers, and sets the alarm for the next one
+fn arm_alarm(ticker: &mut Ticker, queue: &mut EmbassyQueue) {
+    loop {
+        let now = ticker.ticks();
+        let next = queue.next_expiration(now);
+        if next == u64::MAX {
+            return;
+        }
+        // Too close to set an alarm for, so spin until it expires instead
+        if next > now + MIN_ALARM_TICKS {
+            let next_low = (super::drift::raw(next) & 0x00FF_FFFF) as u32;
+            #[allow(clippy::unwrap_used)] // The value is masked to the counter width
+            ticker
+                .driver
+                .rtc0
+                .set_compare(RtcCompareReg::Compare1, next_low)
+                .unwrap();
+            return;
+        }
+    }
+}
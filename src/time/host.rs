@@ -0,0 +1,90 @@
+/*
+Host time driver for the `std` simulation. The tick count comes from `std::time`, and a background
+thread stands in for the RTC compare interrupt.
+*/
+
+use std::{
+    sync::{Condvar, Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{TICKER, TickInstant, Ticker};
+
+const TICK_HZ: u128 = 32_768;
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// Shared with the alarm thread. The start time is the current driver's, so the thread follows the
+// ticker when it's initialised again.
+static ALARM: Mutex<Alarm> = Mutex::new(Alarm {
+    start: None,
+    deadline: None,
+});
+static ALARM_CHANGED: Condvar = Condvar::new();
+static ALARM_THREAD: Once = Once::new();
+
+struct Alarm {
+    start: Option<Instant>,
+    deadline: Option<u64>,
+}
+
+pub(super) struct TickDriver {
+    start: Instant,
+}
+
+impl Ticker {
+    pub fn init() {
+        let start = Instant::now();
+        Self::init_with(TickDriver { start });
+        *ALARM.lock().unwrap() = Alarm {
+            start: Some(start),
+            deadline: None,
+        };
+        ALARM_CHANGED.notify_one();
+        ALARM_THREAD.call_once(|| {
+            thread::spawn(alarm_thread);
+        });
+    }
+}
+
+impl TickDriver {
+    pub(super) fn now() -> u64 {
+        TICKER.with_lock(|ticker| ticker.driver.ticks())
+    }
+
+    pub(super) fn ticks(&self) -> u64 {
+        ticks_since(self.start)
+    }
+
+    pub(super) fn set_alarm(&mut self, deadline: &TickInstant) {
+        ALARM.lock().unwrap().deadline = Some(deadline.ticks());
+        ALARM_CHANGED.notify_one();
+    }
+}
+
+fn ticks_since(start: Instant) -> u64 {
+    (start.elapsed().as_nanos() * TICK_HZ / NANOS_PER_SEC) as u64
+}
+
+fn alarm_thread() -> ! {
+    let mut alarm = ALARM.lock().unwrap();
+    loop {
+        match (alarm.start, alarm.deadline) {
+            (Some(start), Some(deadline)) => {
+                let now = ticks_since(start);
+                if now >= deadline {
+                    alarm.deadline = None;
+                    // The ticker sets the next alarm itself, so don't hold onto it
+                    drop(alarm);
+                    TICKER.with_lock(Ticker::on_alarm);
+                    alarm = ALARM.lock().unwrap();
+                } else {
+                    let remaining = u128::from(deadline - now) * NANOS_PER_SEC;
+                    let timeout = Duration::from_nanos(remaining.div_ceil(TICK_HZ) as u64);
+                    alarm = ALARM_CHANGED.wait_timeout(alarm, timeout).unwrap().0;
+                }
+            }
+            _ => alarm = ALARM_CHANGED.wait(alarm).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/*
+Mock time driver for deterministic tests. It emulates the RTC, a 24 bit counter with an overflow
+count and a compare register holding the low 24 bits of the alarm, but time only moves when the
+test advances it.
+*/
+
+use std::sync::{Mutex, MutexGuard};
+
+use super::{TICKER, TickDuration, TickInstant, Ticker};
+
+const COUNTER_MASK: u32 = 0x00FF_FFFF;
+const COUNTER_PERIOD: u64 = 1 << 24;
+
+// The ticker is a global, so tests using it can't run in parallel
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+pub(super) struct TickDriver {
+    counter: u32,
+    overflow_count: u32,
+    compare: Option<u32>,
+}
+
+impl TickDriver {
+    pub(super) fn now() -> u64 {
+        TICKER.with_lock(|ticker| ticker.driver.ticks())
+    }
+
+    pub(super) fn ticks(&self) -> u64 {
+        (u64::from(self.overflow_count) << 24) | u64::from(self.counter)
+    }
+
+    pub(super) fn set_alarm(&mut self, deadline: &TickInstant) {
+        self.compare = Some((deadline.ticks() & u64::from(COUNTER_MASK)) as u32);
+    }
+
+    // Ticks until the counter next equals the compare register. The RTC only fires on a
+    // transition, so a compare equal to the counter takes a whole period.
+    fn ticks_to_compare(&self) -> Option<u64> {
+        self.compare.map(|compare| {
+            match u64::from(compare.wrapping_sub(self.counter) & COUNTER_MASK) {
+                0 => COUNTER_PERIOD,
+                ticks => ticks,
+            }
+        })
+    }
+}
+
+pub struct MockTicker;
+
+impl MockTicker {
+    // Resets the ticker to zero, with no timers and no alarm
+    pub fn init() {
+        Self::init_at(TickInstant::from_ticks(0));
+    }
+
+    // Holds the ticker for a test, resetting it to zero. Any other test taking it waits until the
+    // guard is dropped.
+    pub fn lock() -> MutexGuard<'static, ()> {
+        Self::lock_at(TickInstant::from_ticks(0))
+    }
+
+    pub fn lock_at(now: TickInstant) -> MutexGuard<'static, ()> {
+        // A test that failed while holding it has left nothing the reset won't clear
+        let guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        Self::init_at(now);
+        guard
+    }
+
+    pub fn init_at(now: TickInstant) {
+        Ticker::init_with(TickDriver {
+            counter: (now.ticks() & u64::from(COUNTER_MASK)) as u32,
+            overflow_count: (now.ticks() >> 24) as u32,
+            compare: None,
+        });
+    }
+
+    // Moves time forward, firing the alarm interrupt whenever the counter passes the compare
+    // register, like the RTC would
+    pub fn advance(duration: TickDuration) {
+        let mut remaining = duration.ticks();
+        while remaining > 0 {
+            let fired = TICKER.with_lock(|ticker| {
+                let driver = &mut ticker.driver;
+                let to_overflow = COUNTER_PERIOD - u64::from(driver.counter);
+                let to_compare = driver.ticks_to_compare().unwrap_or(u64::MAX);
+                let step = remaining.min(to_overflow).min(to_compare);
+                remaining -= step;
+                if step == to_overflow {
+                    driver.counter = 0;
+                    driver.overflow_count += 1;
+                } else {
+                    driver.counter += step as u32;
+                }
+                step == to_compare
+            });
+            if fired {
+                Self::fire_alarm();
+            }
+        }
+    }
+
+    // Runs the alarm interrupt handler, whether or not the alarm is due
+    pub fn fire_alarm() {
+        TICKER.with_lock(Ticker::on_alarm);
+    }
+
+    // The value in the emulated compare register, if an alarm has been set
+    pub fn compare() -> Option<u32> {
+        TICKER.with_lock(|ticker| ticker.driver.compare)
+    }
+}
@@ -0,0 +1,179 @@
+/*
+Intrusive queue of timer deadlines, sorted by deadline. The nodes live inside the timer futures
+rather than in the queue, so any number of timers can wait without allocating.
+
+Nodes can only be inserted while pinned, and a node unlinks itself when it's dropped. Pinning
+guarantees the node stays put until its drop runs, so the queue never holds a dangling node, and
+nodes are only ever accessed through shared references.
+
+With the `metrics` feature, the queue counts what's done to it, to check it isn't what's adding
+latency. The scan length of an insert is how many nodes its deadline was compared against on the
+way down the tree.
+*/
+
+use core::{cell::Cell, marker::PhantomPinned, pin::Pin, task::Waker};
+
+use intrusive_collections::{KeyAdapter, RBTree, RBTreeAtomicLink, UnsafeRef, intrusive_adapter};
+
+use super::{TICKER, TickInstant};
+use crate::utils::LockCell;
+
+// Deadlines looked up since the count was last taken, which is once per comparison during an insert
+#[cfg(feature = "metrics")]
+static KEY_LOOKUPS: portable_atomic::AtomicU32 = portable_atomic::AtomicU32::new(0);
+
+pub(super) struct TimerNode {
+    // Only changed while the node isn't queued, since it's the node's key in the queue
+    end_time: LockCell<TickInstant>,
+    waker: LockCell<Option<Waker>>,
+    link: RBTreeAtomicLink,
+    _pin: PhantomPinned,
+}
+
+impl TimerNode {
+    pub(super) const fn new(end_time: TickInstant) -> Self {
+        Self {
+            end_time: LockCell::new(end_time),
+            waker: LockCell::new(None),
+            link: RBTreeAtomicLink::new(),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub(super) fn end_time(&self) -> TickInstant {
+        self.end_time.with_lock(Cell::get)
+    }
+
+    pub(super) fn set_end_time(&self, end_time: TickInstant) {
+        debug_assert!(!self.is_queued(), "A queued timer's deadline can't change");
+        self.end_time.with_lock(|cell| cell.set(end_time));
+    }
+
+    pub(super) fn is_queued(&self) -> bool {
+        self.link.is_linked()
+    }
+
+    pub(super) fn set_waker(&self, waker: &Waker) {
+        self.waker
+            .with_lock(|cell| cell.replace(Some(waker.clone())));
+    }
+
+    pub(super) fn take_waker(&self) -> Option<Waker> {
+        self.waker.with_lock(|cell| cell.replace(None))
+    }
+}
+
+impl Drop for TimerNode {
+    fn drop(&mut self) {
+        if self.is_queued() {
+            TICKER.with_lock(|ticker| ticker.remove(self));
+        }
+    }
+}
+
+intrusive_adapter!(NodeAdapter = UnsafeRef<TimerNode>: TimerNode { link: RBTreeAtomicLink });
+
+impl<'a> KeyAdapter<'a> for NodeAdapter {
+    type Key = u64;
+    fn get_key(&self, node: &'a TimerNode) -> Self::Key {
+        #[cfg(feature = "metrics")]
+        KEY_LOOKUPS.fetch_add(1, portable_atomic::Ordering::Relaxed);
+        node.end_time().ticks()
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueMetrics {
+    pub inserts: u32,
+    // Timers dropped or reset before their deadline
+    pub removes: u32,
+    // Timers woken by the alarm
+    pub fired: u32,
+    // Timers waiting now
+    pub len: u32,
+    pub max_len: u32,
+    // The most nodes a single insert was compared against
+    pub max_insert_scan: u32,
+}
+
+pub(super) struct TimerQueue {
+    timers: RBTree<NodeAdapter>,
+    #[cfg(feature = "metrics")]
+    metrics: QueueMetrics,
+}
+
+impl TimerQueue {
+    pub(super) fn new() -> Self {
+        Self {
+            timers: RBTree::new(NodeAdapter::new()),
+            #[cfg(feature = "metrics")]
+            metrics: QueueMetrics::default(),
+        }
+    }
+
+    pub(super) fn insert(&mut self, node: Pin<&TimerNode>) {
+        #[cfg(feature = "metrics")]
+        KEY_LOOKUPS.store(0, portable_atomic::Ordering::Relaxed);
+        // SAFETY: The node is pinned, so it stays valid at this address until it's dropped, and
+        // dropping it unlinks it first. It's never accessed through a mutable reference.
+        self.timers
+            .insert(unsafe { UnsafeRef::from_raw(node.get_ref()) });
+        #[cfg(feature = "metrics")]
+        {
+            // The new node's own deadline is looked up once too
+            let scan = KEY_LOOKUPS
+                .load(portable_atomic::Ordering::Relaxed)
+                .saturating_sub(1);
+            let metrics = &mut self.metrics;
+            metrics.inserts = metrics.inserts.saturating_add(1);
+            metrics.len += 1;
+            metrics.max_len = metrics.max_len.max(metrics.len);
+            metrics.max_insert_scan = metrics.max_insert_scan.max(scan);
+        }
+    }
+
+    pub(super) fn remove(&mut self, node: &TimerNode) {
+        if node.is_queued() {
+            // SAFETY: The ticker holds the only queue, so a linked node must be in this one
+            unsafe { self.timers.cursor_mut_from_ptr(node) }.remove();
+            #[cfg(feature = "metrics")]
+            {
+                self.metrics.removes = self.metrics.removes.saturating_add(1);
+                self.metrics.len -= 1;
+            }
+        }
+    }
+
+    pub(super) fn peek_earliest(&self) -> Option<&TimerNode> {
+        self.timers.front().get()
+    }
+
+    // Unlinks the earliest node. Its waker has to be taken beforehand through `peek_earliest`,
+    // since once it's unlinked its owner is free to drop it.
+    pub(super) fn pop_earliest(&mut self) {
+        #[cfg(feature = "metrics")]
+        if self.timers.front_mut().remove().is_some() {
+            self.metrics.fired = self.metrics.fired.saturating_add(1);
+            self.metrics.len -= 1;
+        }
+        #[cfg(not(feature = "metrics"))]
+        self.timers.front_mut().remove();
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn metrics(&self) -> QueueMetrics {
+        self.metrics
+    }
+
+    // Clears the counts and maximums, but not the timers still waiting
+    #[cfg(feature = "metrics")]
+    pub(super) fn reset_metrics(&mut self) {
+        self.metrics = QueueMetrics {
+            len: self.metrics.len,
+            max_len: self.metrics.len,
+            ..QueueMetrics::default()
+        };
+    }
+}
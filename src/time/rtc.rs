@@ -0,0 +1,113 @@
+use core::sync::atomic::{AtomicU32, Ordering, compiler_fence};
+
+use nrf52833_hal::{
+    Rtc,
+    pac::{Interrupt, NVIC, RTC0, interrupt},
+    rtc::{RtcCompareReg, RtcInterrupt},
+};
+
+use super::{TICKER, TickInstant, Ticker};
+use crate::config::Priority;
+
+const HALF_PERIOD: u32 = 0x0080_0000;
+
+// Half counter periods since init, bumped by the overflow and by Compare2 halfway through. This
+// lets the tick count be read without any lock (see `TickDriver::now`).
+static PERIOD: AtomicU32 = AtomicU32::new(0);
+
+pub(super) struct TickDriver {
+    pub(super) rtc0: Rtc<RTC0>,
+}
+
+impl Ticker {
+    // The ticker lock only masks interrupts up to `priority`
+    pub fn init(rtc0: RTC0, nvic: &mut NVIC, priority: Priority) {
+        // SAFETY: Can never return an error since prescalar 0 never returns an error
+        #[allow(clippy::unwrap_used)]
+        let mut rtc0 = Rtc::new(rtc0, 0).unwrap();
+        rtc0.clear_counter();
+        PERIOD.store(0, Ordering::Relaxed);
+        super::drift::reset();
+        // SAFETY: Set before the interrupt is enabled, so nothing is relying on the old priority
+        unsafe { nvic.set_priority(Interrupt::RTC0, priority.bits()) };
+
+        // Enable overflow interrupt
+        rtc0.enable_event(RtcInterrupt::Overflow);
+        rtc0.enable_interrupt(RtcInterrupt::Overflow, Some(nvic));
+
+        // Enable the half period interrupt
+        #[allow(clippy::unwrap_used)] // Fits in the counter
+        rtc0.set_compare(RtcCompareReg::Compare2, HALF_PERIOD)
+            .unwrap();
+        rtc0.enable_event(RtcInterrupt::Compare2);
+        rtc0.enable_interrupt(RtcInterrupt::Compare2, Some(nvic));
+
+        // Enable compare interrupt
+        rtc0.enable_event(RtcInterrupt::Compare0);
+        rtc0.enable_interrupt(RtcInterrupt::Compare0, Some(nvic));
+
+        // Embassy timers get their own compare channel
+        #[cfg(feature = "embassy-time-driver")]
+        {
+            rtc0.enable_event(RtcInterrupt::Compare1);
+            rtc0.enable_interrupt(RtcInterrupt::Compare1, Some(nvic));
+        }
+
+        rtc0.enable_counter();
+        TICKER.init(Self::new(TickDriver { rtc0 }), priority);
+    }
+}
+
+impl TickDriver {
+    pub(super) fn now() -> u64 {
+        let period = PERIOD.load(Ordering::Acquire);
+        compiler_fence(Ordering::Acquire);
+        // SAFETY: Reading the counter has no side effects
+        let counter = unsafe { (*RTC0::ptr()).counter.read().bits() };
+        // The parity of the period says which half the counter should be in. If the counter has
+        // crossed into the next half before the interrupt bumped the period, the xor carries it
+        // into the next period rather than back to the start of this one.
+        (u64::from(period) << 23) + u64::from(counter ^ ((period & 1) << 23))
+    }
+
+    pub(super) fn ticks(&self) -> u64 {
+        Self::now()
+    }
+
+    pub(super) fn set_alarm(&mut self, deadline: &TickInstant) {
+        let deadline_low = (deadline.ticks() & 0x00FF_FFFF) as u32;
+        self.rtc0
+            .set_compare(RtcCompareReg::Compare0, deadline_low)
+            .unwrap();
+    }
+}
+
+#[interrupt]
+fn RTC0() {
+    TICKER.with_lock(handle_rtc0_interrupt);
+}
+
+fn handle_rtc0_interrupt(ticker: &mut Ticker) {
+    let rtc0 = &mut ticker.driver.rtc0;
+    if rtc0.is_event_triggered(RtcInterrupt::Overflow) {
+        rtc0.reset_event(RtcInterrupt::Overflow);
+        PERIOD.fetch_add(1, Ordering::Release);
+    }
+    if rtc0.is_event_triggered(RtcInterrupt::Compare2) {
+        rtc0.reset_event(RtcInterrupt::Compare2);
+        PERIOD.fetch_add(1, Ordering::Release);
+    }
+    if rtc0.is_event_triggered(RtcInterrupt::Compare0) {
+        rtc0.reset_event(RtcInterrupt::Compare0);
+        ticker.on_alarm();
+    }
+    #[cfg(feature = "embassy-time-driver")]
+    if ticker
+        .driver
+        .rtc0
+        .is_event_triggered(RtcInterrupt::Compare1)
+    {
+        ticker.driver.rtc0.reset_event(RtcInterrupt::Compare1);
+        super::embassy_driver::on_alarm(ticker);
+    }
+}
@@ -1,34 +1,102 @@
-use core::{cell::Cell, task::Waker};
+/*
+Lock free waker slot, shared between a task registering itself and an interrupt waking it. The same
+algorithm as the futures crate: a state word tracks whether a register or wake is in progress, and
+whichever side comes second makes sure the wake isn't lost.
+*/
 
-use critical_section::{CriticalSection, Mutex};
+use core::task::Waker;
+
+use super::sync::{AtomicUsize, Ordering, UnsafeCell};
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
 
 pub struct AtomicWaker {
-    inner: Mutex<Cell<Option<Waker>>>,
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
 }
 
+// SAFETY: The waker is only accessed by whoever moved the state out of WAITING
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
 impl AtomicWaker {
+    #[cfg(not(loom))]
     pub const fn new() -> Self {
         Self {
-            inner: Mutex::new(Cell::new(None)),
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
         }
     }
 
-    pub fn register(&self, cs: CriticalSection, waker: &Waker) {
-        let cell = self.inner.borrow(cs);
-        let prev_value = cell.replace(None);
-        cell.set(match prev_value {
-            Some(prev_value) if prev_value.will_wake(waker) => Some(prev_value),
-            _ => Some(waker.clone()),
-        });
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
     }
 
-    pub fn wake(&self) {
-        critical_section::with(|cs| self.wake_with_cs(cs));
+    pub fn register(&self, waker: &Waker) {
+        match self
+            .state
+            .compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+            .unwrap_or_else(|state| state)
+        {
+            WAITING => {
+                // SAFETY: We hold the REGISTERING lock
+                self.waker.with_mut(|slot| unsafe {
+                    match &*slot {
+                        Some(prev) if prev.will_wake(waker) => {}
+                        _ => *slot = Some(waker.clone()),
+                    }
+                });
+                if let Err(state) = self.state.compare_exchange(
+                    REGISTERING,
+                    WAITING,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    // A wake came in while registering, so it's up to us to deliver it
+                    debug_assert_eq!(state, REGISTERING | WAKING);
+                    // SAFETY: The waker couldn't take the slot, since we still hold the lock
+                    let waker = self.waker.with_mut(|slot| unsafe { (*slot).take() });
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            // A wake is in progress and may miss the new waker, so wake it straight away
+            WAKING => waker.wake_by_ref(),
+            // Registered concurrently from somewhere else, which isn't supported, so do nothing
+            _ => {}
+        }
     }
 
-    pub fn wake_with_cs(&self, cs: CriticalSection) {
-        if let Some(waker) = self.inner.borrow(cs).replace(None) {
-            waker.wake_by_ref();
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                // SAFETY: We hold the WAKING lock
+                let waker = self.waker.with_mut(|slot| unsafe { (*slot).take() });
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            }
+            // Registering will see the WAKING bit and wake the task itself
+            _ => None,
         }
     }
+
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -1,6 +1,11 @@
 use core::cell::{Cell, RefCell};
 
 use critical_section::Mutex;
+#[cfg(feature = "nrf52833")]
+use {
+    crate::config::Priority,
+    core::sync::atomic::{AtomicU8, Ordering},
+};
 
 pub struct LockCell<T> {
     inner: Mutex<Cell<T>>,
@@ -42,4 +47,78 @@ impl<T> LockMut<T> {
                 .expect("Please initialize the LockMut first"))
         })
     }
+
+    // Like with_lock, but returns None instead of panicking if it hasn't been initialized
+    pub fn try_with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        critical_section::with(|cs| self.inner.borrow_ref_mut(cs).as_mut().map(f))
+    }
+}
+
+impl<T> Default for LockMut<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Like LockMut, but only masks interrupts up to a ceiling priority (by raising BASEPRI) rather
+// than all of them, so more urgent interrupts still run while it's held. The ceiling must be the
+// priority of the most urgent interrupt that uses the value.
+#[cfg(feature = "nrf52833")]
+pub struct PriorityLock<T> {
+    ceiling: AtomicU8,
+    inner: RefCell<Option<T>>,
+}
+
+// SAFETY: Everything that can access the value runs at or below the ceiling, and that is masked
+// while the value is borrowed
+#[cfg(feature = "nrf52833")]
+unsafe impl<T: Send> Sync for PriorityLock<T> {}
+
+#[cfg(feature = "nrf52833")]
+impl<T> PriorityLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            ceiling: AtomicU8::new(0),
+            inner: RefCell::new(None),
+        }
+    }
+
+    pub fn init(&self, val: T, ceiling: Priority) {
+        critical_section::with(|_| {
+            self.ceiling.store(ceiling.bits(), Ordering::Relaxed);
+            self.inner.replace(Some(val));
+        });
+    }
+
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        self.lock(|inner| {
+            f(inner
+                .borrow_mut()
+                .as_mut()
+                .expect("Please initialize the PriorityLock first"))
+        })
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&RefCell<Option<T>>) -> R) -> R {
+        use cortex_m::register::{basepri, basepri_max};
+        let ceiling = self.ceiling.load(Ordering::Relaxed);
+        // A BASEPRI of zero masks nothing, so the most urgent level needs a full critical section
+        if ceiling == 0 {
+            return critical_section::with(|_| f(&self.inner));
+        }
+        let previous = basepri::read();
+        basepri_max::write(ceiling);
+        let result = f(&self.inner);
+        // SAFETY: Only puts back the mask that was there before, which can't unmask anything
+        // that was masked before the lock was taken
+        unsafe { basepri::write(previous) };
+        result
+    }
+}
+
+#[cfg(feature = "nrf52833")]
+impl<T> Default for PriorityLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -6,3 +6,19 @@ pub use infallible::*;
 
 pub mod lockmut;
 pub use lockmut::*;
+
+pub mod mpmc;
+
+pub mod reply_slots;
+pub use reply_slots::*;
+
+pub(crate) mod sync;
+
+pub mod transfer_slot;
+pub use transfer_slot::*;
+
+pub mod waiter_list;
+pub use waiter_list::*;
+
+pub mod waker_slots;
+pub use waker_slots::*;
@@ -0,0 +1,148 @@
+/*
+Bounded lock free multi producer multi consumer queue (Dmitry Vyukov's algorithm). Every slot has a
+sequence number which says whether it is ready to be written to or read from for the current lap
+around the buffer, so producers and consumers only ever contend on the positions.
+*/
+
+use core::mem::MaybeUninit;
+
+use super::sync::{AtomicUsize, Ordering, UnsafeCell};
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    #[cfg(not(loom))]
+    const fn new(sequence: usize) -> Self {
+        Self {
+            sequence: AtomicUsize::new(sequence),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    #[cfg(loom)]
+    fn new(sequence: usize) -> Self {
+        Self {
+            sequence: AtomicUsize::new(sequence),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+// N must be a power of two
+pub struct Queue<T, const N: usize> {
+    buffer: [Slot<T>; N],
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+}
+
+// SAFETY: A slot's data is only accessed by the one producer or consumer that claimed its position
+unsafe impl<T: Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T, const N: usize> Queue<T, N> {
+    const MASK: usize = {
+        assert!(
+            N.is_power_of_two(),
+            "The queue capacity must be a power of two"
+        );
+        N - 1
+    };
+
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        let mut buffer = [const { Slot::new(0) }; N];
+        let mut i = 0;
+        while i < N {
+            buffer[i] = Slot::new(i);
+            i += 1;
+        }
+        Self {
+            buffer,
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            buffer: core::array::from_fn(Slot::new),
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+        }
+    }
+
+    // Gives the item back if the queue is full
+    pub fn enqueue(&self, item: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.buffer[pos & Self::MASK];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos) as isize).signum() {
+                // The slot is free for this lap, try to claim it
+                0 => match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // The slot still holds an item from the previous lap
+                -1 => return Err(item),
+                // Another producer claimed it first
+                _ => pos = self.enqueue_pos.load(Ordering::Relaxed),
+            }
+        };
+        // SAFETY: Claiming the position gives exclusive access to the slot until the sequence is
+        // bumped
+        slot.data.with_mut(|data| unsafe { (*data).write(item) });
+        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.buffer[pos & Self::MASK];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos.wrapping_add(1)) as isize).signum() {
+                // The slot has been written for this lap, try to claim it
+                0 => match self.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // Nothing has been written to it yet
+                -1 => return None,
+                // Another consumer claimed it first
+                _ => pos = self.dequeue_pos.load(Ordering::Relaxed),
+            }
+        };
+        // SAFETY: The producer finished writing before bumping the sequence, and claiming the
+        // position gives exclusive access to the slot
+        let item = slot.data.with(|data| unsafe { (*data).assume_init_read() });
+        slot.sequence
+            .store(pos.wrapping_add(Self::MASK + 1), Ordering::Release);
+        Some(item)
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,115 @@
+/*
+Slots for tasks waiting on replies to their own requests, like a radio frame's ack, when several
+tasks can have a request out at once. Each task claims a slot keyed by what its reply will carry
+before sending the request, so a reply that comes straight back isn't missed, and the interrupt
+hands each reply to the slot with its key. A reply nobody is waiting for is dropped without
+touching anyone else's:
+
+    static ACKS: ReplySlots<(u8, u8), 4> = ReplySlots::new();
+    // In the task
+    let ack = ACKS.expect((to, seq)).ok_or(Busy)?;
+    send(frame).await;
+    ack.wait().await;
+    // In the interrupt
+    ACKS.deliver((from, seq));
+*/
+
+use core::{
+    cell::RefCell,
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use critical_section::Mutex;
+
+pub struct ReplySlots<K, const N: usize> {
+    slots: Mutex<RefCell<[Option<Slot<K>>; N]>>,
+}
+
+struct Slot<K> {
+    key: K,
+    replied: bool,
+    waker: Option<Waker>,
+}
+
+impl<K: Copy + PartialEq, const N: usize> ReplySlots<K, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Mutex::new(RefCell::new([const { None }; N])),
+        }
+    }
+
+    // Claims a slot for the reply with this key, or `None` if every slot is taken. The slot is
+    // freed when the returned `Expected` is dropped.
+    pub fn expect(&self, key: K) -> Option<Expected<'_, K, N>> {
+        critical_section::with(|cs| {
+            let mut slots = self.slots.borrow_ref_mut(cs);
+            let index = slots.iter().position(Option::is_none)?;
+            slots[index] = Some(Slot {
+                key,
+                replied: false,
+                waker: None,
+            });
+            Some(Expected { slots: self, index })
+        })
+    }
+
+    // Hands the reply to whoever is waiting for it, usually from the interrupt. Returns whether
+    // anyone was.
+    pub fn deliver(&self, key: K) -> bool {
+        let waker = critical_section::with(|cs| {
+            let mut slots = self.slots.borrow_ref_mut(cs);
+            let slot = slots.iter_mut().flatten().find(|slot| slot.key == key)?;
+            slot.replied = true;
+            Some(slot.waker.take())
+        });
+        match waker {
+            Some(waker) => {
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<K: Copy + PartialEq, const N: usize> Default for ReplySlots<K, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Expected<'a, K, const N: usize> {
+    slots: &'a ReplySlots<K, N>,
+    index: usize,
+}
+
+impl<K, const N: usize> Expected<'_, K, N> {
+    // Waits for the reply, or returns straight away if it already came. Can be waited on again
+    // after a timeout, and a reply that came in between still counts.
+    pub async fn wait(&self) {
+        poll_fn(|cx| {
+            critical_section::with(|cs| {
+                let mut slots = self.slots.slots.borrow_ref_mut(cs);
+                // Can't be empty, the slot is only freed on drop
+                let Some(slot) = slots[self.index].as_mut() else {
+                    return Poll::Ready(());
+                };
+                if slot.replied {
+                    return Poll::Ready(());
+                }
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            })
+        })
+        .await;
+    }
+}
+
+impl<K, const N: usize> Drop for Expected<'_, K, N> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| self.slots.slots.borrow_ref_mut(cs)[self.index] = None);
+    }
+}
@@ -0,0 +1,33 @@
+/*
+Atomics and cells used by the concurrency primitives. Under `--cfg loom` they come from loom so the
+primitives can be model checked on the host, otherwise from portable-atomic so they also work on
+targets without native compare and swap.
+*/
+
+#[cfg(loom)]
+pub(crate) use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+#[cfg(not(loom))]
+pub(crate) use portable_atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Mirrors loom's UnsafeCell API, so that loom can track every access
+#[cfg(not(loom))]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) const fn new(data: T) -> Self {
+        Self(core::cell::UnsafeCell::new(data))
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
@@ -0,0 +1,106 @@
+/*
+One transfer at a time for any number of tasks sharing a peripheral, like the radio, which only
+sends one packet at once. A task waits for its turn, starts its transfer, and waits for that
+transfer to finish, which the interrupt says it has with `finish`:
+
+    static TX: TransferSlot<4> = TransferSlot::new();
+    // In the task
+    TX.transfer(|| start_sending(packet)).await;
+    // In the interrupt
+    TX.finish();
+
+Every waiting task is woken when a transfer finishes, since any of them may be next. Each transfer
+is counted, so a task knows its own has finished even if another task has started the next one by
+the time it's polled.
+*/
+
+use core::{cell::RefCell, future::poll_fn, task::Poll};
+
+use critical_section::Mutex;
+
+use super::WaiterList;
+
+pub struct TransferSlot<const N: usize> {
+    inner: Mutex<RefCell<Inner>>,
+    waiting: WaiterList<N>,
+}
+
+struct Inner {
+    busy: bool,
+    // Bumped as each transfer finishes
+    finished: u32,
+}
+
+impl<const N: usize> TransferSlot<N> {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                busy: false,
+                finished: 0,
+            })),
+            waiting: WaiterList::new(),
+        }
+    }
+
+    // Whether a transfer has started and not yet finished
+    pub fn is_busy(&self) -> bool {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).busy)
+    }
+
+    // Waits for the transfer in progress, if any, then calls `start` to start this one, and waits
+    // for it to finish. `start` runs in a critical section, so an interrupt can't see the slot
+    // taken before the transfer is set up. If the future is dropped once the transfer has
+    // started, the transfer still carries on until it's finished.
+    pub async fn transfer(&self, start: impl FnOnce()) {
+        let mut start = Some(start);
+        let ticket = poll_fn(|cx| {
+            critical_section::with(|cs| {
+                let mut inner = self.inner.borrow_ref_mut(cs);
+                if inner.busy {
+                    self.waiting.register(cx);
+                    return Poll::Pending;
+                }
+                inner.busy = true;
+                if let Some(start) = start.take() {
+                    start();
+                }
+                Poll::Ready(inner.finished)
+            })
+        })
+        .await;
+        poll_fn(|cx| {
+            critical_section::with(|cs| {
+                let inner = self.inner.borrow_ref(cs);
+                if inner.finished != ticket {
+                    return Poll::Ready(());
+                }
+                self.waiting.register(cx);
+                Poll::Pending
+            })
+        })
+        .await;
+    }
+
+    // Called when the transfer in progress has finished, usually from the interrupt. Does
+    // nothing if none is.
+    pub fn finish(&self) {
+        let finished = critical_section::with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            if !inner.busy {
+                return false;
+            }
+            inner.busy = false;
+            inner.finished = inner.finished.wrapping_add(1);
+            true
+        });
+        if finished {
+            self.waiting.wake_all();
+        }
+    }
+}
+
+impl<const N: usize> Default for TransferSlot<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,62 @@
+/*
+A list of tasks waiting for the same thing, like a value to be sent or a token to be cancelled,
+where each of them needs waking when it happens rather than just the latest to register. A task
+registers each time it finds it has to wait, and whoever makes the change wakes the lot:
+
+    static WAITING: WaiterList<8> = WaiterList::new();
+    // In the future
+    if ready() {
+        return Poll::Ready(());
+    }
+    WAITING.register(cx);
+    // Wherever it becomes ready
+    WAITING.wake_all();
+
+Registering in the same critical section as the check means a wake can't slip in between. With
+more than N tasks waiting, the extra ones fall back to polling again straight away.
+*/
+
+use core::{
+    cell::RefCell,
+    task::{Context, Waker},
+};
+
+use critical_section::Mutex;
+
+pub struct WaiterList<const N: usize> {
+    waiting: Mutex<RefCell<heapless::Vec<Waker, N>>>,
+}
+
+impl<const N: usize> WaiterList<N> {
+    pub const fn new() -> Self {
+        Self {
+            waiting: Mutex::new(RefCell::new(heapless::Vec::new())),
+        }
+    }
+
+    // Registering again with the same waker doesn't take another place
+    pub fn register(&self, cx: &mut Context<'_>) {
+        critical_section::with(|cs| {
+            let mut waiting = self.waiting.borrow_ref_mut(cs);
+            if !waiting.iter().any(|waiter| waiter.will_wake(cx.waker()))
+                && waiting.push(cx.waker().clone()).is_err()
+            {
+                cx.waker().wake_by_ref();
+            }
+        });
+    }
+
+    // The wakers are taken in a critical section and woken outside it
+    pub fn wake_all(&self) {
+        let waiting = critical_section::with(|cs| self.waiting.take(cs));
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+impl<const N: usize> Default for WaiterList<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
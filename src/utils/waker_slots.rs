@@ -0,0 +1,81 @@
+/*
+A fixed array of waker slots, one per channel of a peripheral, for drivers whose interrupt serves
+several waiting tasks, like the GPIOTE channels. Each task registers in its channel's slot, and the
+interrupt wakes the slots whose events fired:
+
+    static WAKERS: WakerSlotArray<8> = WakerSlotArray::new();
+    // In the future
+    WAKERS.register(channel, cx.waker());
+    // In the interrupt
+    WAKERS.wake(channel);
+*/
+
+use core::task::Waker;
+
+use super::AtomicWaker;
+
+pub struct WakerSlotArray<const N: usize> {
+    slots: [AtomicWaker; N],
+}
+
+impl<const N: usize> WakerSlotArray<N> {
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { AtomicWaker::new() }; N],
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| AtomicWaker::new()),
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    // Each slot holds one task, as with `AtomicWaker`. Panics if there's no such slot.
+    pub fn register(&self, index: usize, waker: &Waker) {
+        self.slots[index].register(waker);
+    }
+
+    // Does nothing if there's no such slot, since it's usually called from an interrupt
+    pub fn wake(&self, index: usize) {
+        if let Some(slot) = self.slots.get(index) {
+            slot.wake();
+        }
+    }
+
+    pub fn take(&self, index: usize) -> Option<Waker> {
+        self.slots.get(index).and_then(AtomicWaker::take)
+    }
+
+    // Wakes the slots with their bit set, slot 0 in the lowest bit, as in an interrupt status
+    // register
+    pub fn wake_mask(&self, mask: u32) {
+        for (index, slot) in self.slots.iter().enumerate().take(32) {
+            if mask & (1 << index) != 0 {
+                slot.wake();
+            }
+        }
+    }
+
+    pub fn wake_all(&self) {
+        for slot in &self.slots {
+            slot.wake();
+        }
+    }
+}
+
+impl<const N: usize> Default for WakerSlotArray<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+/*
+Deferred work for interrupt handlers. A handler should do as little as it can and leave the rest to
+a task, which otherwise means a static, a waker and a flag for every interrupt. Instead, handlers
+push commands (an enum, or a plain `fn()`) onto a work queue, and a task dedicated to it drains the
+queue in thread mode. Pushing is lock free, so it works at any interrupt priority.
+*/
+
+use core::{future::poll_fn, task::Poll};
+
+use portable_atomic::{AtomicUsize, Ordering};
+
+use crate::utils::{AtomicWaker, mpmc::Queue};
+
+// Work that's just a function to call
+pub type Work = fn();
+
+// N must be a power of two
+pub struct IsrWorkQueue<T, const N: usize> {
+    queue: Queue<T, N>,
+    waker: AtomicWaker,
+    dropped: AtomicUsize,
+}
+
+impl<T, const N: usize> IsrWorkQueue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: Queue::new(),
+            waker: AtomicWaker::new(),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    // Gives the work back if the queue is full, after counting it as dropped
+    pub fn push(&self, work: T) -> Result<(), T> {
+        let pushed = self.queue.enqueue(work);
+        if pushed.is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.waker.wake();
+        pushed
+    }
+
+    // How many pushes failed because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub async fn next(&self) -> T {
+        poll_fn(|cx| {
+            self.waker.register(cx.waker());
+            self.queue.dequeue().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+
+    // Handles the work as it comes in, for running as its own task
+    pub async fn run(&self, mut handle: impl FnMut(T)) -> ! {
+        loop {
+            handle(self.next().await);
+        }
+    }
+}
+
+impl<const N: usize> IsrWorkQueue<Work, N> {
+    pub async fn run_work(&self) -> ! {
+        self.run(|work| work()).await
+    }
+}
+
+impl<T, const N: usize> Default for IsrWorkQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::{
+    alarm::{self, ALARM_HZ, Alarm, Tone},
+    cancel::{CancellationToken, Cancelled},
+    time::{MockTicker, TickDuration},
+};
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+// Counts the rising edges, one per cycle of the tone
+#[derive(Clone, Default)]
+struct FakePin {
+    high: Rc<Cell<bool>>,
+    cycles: Rc<Cell<u32>>,
+}
+
+impl ErrorType for FakePin {
+    type Error = Infallible;
+}
+
+impl OutputPin for FakePin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        if !self.high.get() {
+            self.cycles.set(self.cycles.get() + 1);
+        }
+        self.high.set(true);
+        Ok(())
+    }
+}
+
+#[test]
+fn beep_code_counts_out_beeps() {
+    let tones: Vec<_> = alarm::beep_code(3).collect();
+    let beeps = tones.iter().filter(|tone| tone.hz != 0).count();
+    assert_eq!(beeps, 3);
+    assert_eq!(tones.len(), 6);
+    assert_eq!(tones.last().unwrap().hz, 0);
+}
+
+#[test]
+fn sos_is_three_short_three_long_three_short() {
+    let sounded: Vec<_> = alarm::sos()
+        .filter(|tone| tone.hz != 0)
+        .map(|tone| tone.duration)
+        .collect();
+    let (dit, dah) = (TickDuration::millis(100), TickDuration::millis(100) * 3);
+    assert_eq!(sounded, [dit, dit, dit, dah, dah, dah, dit, dit, dit]);
+}
+
+#[test]
+fn rising_alert_goes_up() {
+    let pitches: Vec<_> = alarm::rising()
+        .map(|tone| tone.hz)
+        .filter(|&hz| hz != 0)
+        .collect();
+    assert!(pitches.is_sorted());
+    assert!(pitches.first() < pitches.last());
+}
+
+#[test]
+fn play_sounds_each_tone_then_stops() {
+    let _guard = MockTicker::lock();
+    let pin = FakePin::default();
+    let mut alarm = Alarm::new(pin.clone());
+    let token = CancellationToken::new();
+    let tones = [
+        Tone::new(ALARM_HZ, TickDuration::millis(10)),
+        Tone::rest(TickDuration::millis(10)),
+        Tone::new(ALARM_HZ, TickDuration::millis(10)),
+    ];
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut play = pin!(alarm.play(tones, &token));
+    while play.as_mut().poll(&mut cx).is_pending() {
+        MockTicker::advance(TickDuration::from_ticks(1));
+    }
+    // 2 kHz rounds to 8 ticks a half period, so 20 cycles in each tone
+    assert_eq!(pin.cycles.get(), 40);
+    assert!(!pin.high.get());
+}
+
+#[test]
+fn cancelling_silences_the_speaker() {
+    let _guard = MockTicker::lock();
+    let pin = FakePin::default();
+    let mut alarm = Alarm::new(pin.clone());
+    let token = CancellationToken::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut play = pin!(alarm.play(alarm::rising().cycle(), &token));
+    // Stop half way through a cycle, with the speaker on
+    while !pin.high.get() {
+        assert!(play.as_mut().poll(&mut cx).is_pending());
+        MockTicker::advance(TickDuration::from_ticks(1));
+    }
+    token.cancel();
+    assert!(matches!(
+        play.as_mut().poll(&mut cx),
+        Poll::Ready(Err(Cancelled))
+    ));
+    assert!(!pin.high.get());
+}
@@ -0,0 +1,54 @@
+use std::{
+    pin::pin,
+    task::{Context, Waker},
+};
+
+use async_fluid::cancel::{CancellationToken, Cancelled};
+
+#[test]
+fn cancelling_stops_every_waiter() {
+    static TOKEN: CancellationToken = CancellationToken::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut first = pin!(TOKEN.cancelled());
+    let mut second = pin!(TOKEN.run(std::future::pending::<()>()));
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+    TOKEN.cancel();
+    assert!(TOKEN.is_cancelled());
+    assert!(first.as_mut().poll(&mut cx).is_ready());
+    assert!(matches!(
+        second.as_mut().poll(&mut cx),
+        std::task::Poll::Ready(Err(Cancelled))
+    ));
+}
+
+#[test]
+fn run_finishes_when_not_cancelled() {
+    let token = CancellationToken::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(token.run(async { 5 }));
+    assert!(matches!(
+        run.as_mut().poll(&mut cx),
+        std::task::Poll::Ready(Ok(5))
+    ));
+}
+
+#[test]
+fn reset_token_can_be_reused() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let mut cx = Context::from_waker(Waker::noop());
+    {
+        let mut run = pin!(token.run(async { 5 }));
+        assert!(matches!(
+            run.as_mut().poll(&mut cx),
+            std::task::Poll::Ready(Err(Cancelled))
+        ));
+    }
+    token.reset();
+    let mut run = pin!(token.run(async { 5 }));
+    assert!(matches!(
+        run.as_mut().poll(&mut cx),
+        std::task::Poll::Ready(Ok(5))
+    ));
+}
@@ -0,0 +1,158 @@
+use std::{
+    pin::pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use async_fluid::channel::{
+    Channel, ChannelError, OverflowPolicy, mpsc::Mpsc, spsc::Spsc, watch::Watch,
+};
+
+mod common;
+
+use common::block_on;
+
+#[test]
+fn overwrite_keeps_the_latest_value() {
+    let channel = Channel::new();
+    channel.send(1).unwrap();
+    channel.send(2).unwrap();
+    assert_eq!(channel.recv(), Some(2));
+    assert_eq!(channel.recv(), None);
+    assert_eq!(channel.dropped(), 1);
+}
+
+#[test]
+fn reject_keeps_the_unread_value() {
+    let channel = Channel::with_policy(OverflowPolicy::Reject);
+    channel.send(1).unwrap();
+    assert!(matches!(channel.send(2), Err(ChannelError::Full)));
+    assert_eq!(channel.recv(), Some(1));
+    channel.send(3).unwrap();
+    assert_eq!(channel.recv(), Some(3));
+    assert_eq!(channel.dropped(), 1);
+}
+
+#[test]
+fn spsc_keeps_order_when_wrapping() {
+    let ring = Spsc::<u32, 2>::new();
+    let mut producer = ring.producer().unwrap();
+    let mut consumer = ring.consumer().unwrap();
+    for item in 0..5 {
+        producer.enqueue(item).unwrap();
+        assert_eq!(consumer.dequeue(), Some(item));
+    }
+    producer.enqueue(5).unwrap();
+    producer.enqueue(6).unwrap();
+    assert_eq!(producer.enqueue(7), Err(7));
+    assert_eq!(ring.len(), 2);
+    assert_eq!(consumer.dequeue(), Some(5));
+    assert_eq!(consumer.dequeue(), Some(6));
+    assert_eq!(consumer.dequeue(), None);
+}
+
+#[test]
+fn spsc_sides_can_only_be_taken_once() {
+    let ring = Spsc::<u32, 4>::new();
+    assert!(ring.producer().is_some());
+    assert!(ring.producer().is_none());
+    assert!(ring.consumer().is_some());
+    assert!(ring.consumer().is_none());
+}
+
+#[test]
+fn receiver_sees_disconnect_after_last_value() {
+    let channel = Channel::new();
+    let first = channel.get_sender();
+    let second = first.clone();
+    first.send(1).unwrap();
+    drop(first);
+    assert!(!channel.is_disconnected());
+    drop(second);
+    assert!(channel.is_disconnected());
+    let mut recv = channel.get_recv();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(matches!(
+        pin!(recv.recv()).poll(&mut cx),
+        Poll::Ready(Ok(1))
+    ));
+    assert!(matches!(
+        pin!(recv.recv()).poll(&mut cx),
+        Poll::Ready(Err(ChannelError::Disconnected))
+    ));
+}
+
+#[test]
+fn watch_receivers_see_only_the_latest_value() {
+    static WATCH: Watch<u8> = Watch::new();
+    let mut receiver = WATCH.receiver();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(pin!(receiver.changed()).poll(&mut cx).is_pending());
+    WATCH.send(1);
+    WATCH.send(2);
+    assert_eq!(pin!(receiver.changed()).poll(&mut cx), Poll::Ready(2));
+    assert!(pin!(receiver.changed()).poll(&mut cx).is_pending());
+    assert_eq!(receiver.get(), Some(2));
+}
+
+#[test]
+fn late_watch_receivers_get_the_current_value() {
+    let watch = Watch::new_with(7);
+    let mut receiver = watch.receiver();
+    assert_eq!(receiver.try_changed(), Some(7));
+    assert_eq!(receiver.try_changed(), None);
+}
+
+#[test]
+fn watch_modify_only_wakes_on_change() {
+    let watch = Watch::new_with(3);
+    let mut receiver = watch.receiver();
+    receiver.try_changed();
+    watch.send_modify(|_| false);
+    assert_eq!(receiver.try_changed(), None);
+    watch.send_modify(|value| {
+        *value = value.map(|value| value + 1);
+        true
+    });
+    assert_eq!(receiver.try_changed(), Some(4));
+}
+
+// Each value is only sent once the last was seen, so a wake lost between the receiver's check and
+// its registering leaves it waiting for good
+#[test]
+fn watch_receivers_dont_miss_sends_from_another_thread() {
+    static WATCH: Watch<u32> = Watch::new();
+    static SEEN: AtomicU32 = AtomicU32::new(0);
+    let sender = thread::spawn(|| {
+        for value in 1..=1000 {
+            WATCH.send(value);
+            while SEEN.load(Ordering::Acquire) != value {
+                thread::yield_now();
+            }
+        }
+    });
+    let mut receiver = WATCH.receiver();
+    while SEEN.load(Ordering::Relaxed) != 1000 {
+        SEEN.store(block_on(receiver.changed()), Ordering::Release);
+    }
+    sender.join().unwrap();
+}
+
+#[test]
+fn mpsc_keeps_every_value_in_order() {
+    static QUEUE: Mpsc<u8, 2> = Mpsc::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    {
+        let mut recv = pin!(QUEUE.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+    }
+    QUEUE.send(1).unwrap();
+    QUEUE.send(2).unwrap();
+    assert!(matches!(QUEUE.send(3), Err(ChannelError::Full)));
+    assert_eq!(QUEUE.len(), 2);
+    let mut recv = pin!(QUEUE.recv());
+    assert_eq!(recv.as_mut().poll(&mut cx), Poll::Ready(1));
+    assert_eq!(QUEUE.try_recv(), Some(2));
+    assert!(QUEUE.is_empty());
+}
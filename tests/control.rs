@@ -0,0 +1,90 @@
+use std::{
+    cell::Cell,
+    pin::pin,
+    task::{Context, Waker},
+};
+
+use async_fluid::{
+    control::{ControlLoop, Pid, PidGains},
+    dsp::ONE,
+    time::{MockTicker, TickDuration},
+};
+
+fn gains(kp: i32, ki: i32, kd: i32) -> PidGains {
+    PidGains { kp, ki, kd }
+}
+
+#[test]
+fn proportional_output_follows_the_error() {
+    let mut pid = Pid::new(gains(ONE / 2, 0, 0), -100, 100);
+    pid.set_setpoint(50);
+    assert_eq!(pid.update(10), 20);
+    assert_eq!(pid.update(60), -5);
+}
+
+#[test]
+fn output_is_clamped() {
+    let mut pid = Pid::new(gains(ONE * 10, 0, 0), -100, 100);
+    pid.set_setpoint(50);
+    assert_eq!(pid.update(0), 100);
+    assert_eq!(pid.update(100), -100);
+}
+
+#[test]
+fn integral_accumulates_and_does_not_wind_up() {
+    let mut pid = Pid::new(gains(0, ONE, 0), -100, 100);
+    pid.set_setpoint(40);
+    assert_eq!(pid.update(0), 40);
+    assert_eq!(pid.update(0), 80);
+    for _ in 0..100 {
+        assert_eq!(pid.update(0), 100);
+    }
+    // Unwinds as soon as the error changes sign, rather than after the 100 saturated samples
+    assert_eq!(pid.update(60), 80);
+}
+
+#[test]
+fn derivative_acts_on_the_input_not_the_setpoint() {
+    let mut pid = Pid::new(gains(0, 0, ONE), -100, 100);
+    assert_eq!(pid.update(10), 0);
+    pid.set_setpoint(50);
+    assert_eq!(pid.update(10), 0);
+    assert_eq!(pid.update(15), -5);
+}
+
+#[test]
+fn reset_forgets_the_integral() {
+    let mut pid = Pid::new(gains(0, ONE, 0), -100, 100);
+    pid.set_setpoint(10);
+    pid.update(0);
+    pid.reset();
+    pid.set_setpoint(0);
+    assert_eq!(pid.update(0), 0);
+}
+
+#[test]
+fn control_loop_steps_every_period() {
+    let _guard = MockTicker::lock();
+    let input = Cell::new(0);
+    let outputs = Cell::new(0);
+    let mut pid = Pid::new(gains(ONE, 0, 0), -100, 100);
+    pid.set_setpoint(30);
+    let mut control = ControlLoop::new(
+        pid,
+        TickDuration::from_ticks(10),
+        || input.get(),
+        |output| {
+            input.set(input.get() + output / 2);
+            outputs.set(outputs.get() + 1);
+        },
+    );
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(control.run());
+    for _ in 0..50 {
+        assert!(run.as_mut().poll(&mut cx).is_pending());
+        MockTicker::advance(TickDuration::from_ticks(1));
+    }
+    // At ticks 0, 10, 20, 30 and 40
+    assert_eq!(outputs.get(), 5);
+    assert_eq!(input.get(), 29);
+}
@@ -0,0 +1,32 @@
+use async_fluid::crc::{Crc16, Crc32, crc8, crc16, crc32};
+
+// The standard check input for CRC catalogues
+const CHECK: &[u8] = b"123456789";
+
+#[test]
+fn crcs_match_their_check_values() {
+    assert_eq!(crc32(CHECK), 0xCBF4_3926);
+    assert_eq!(crc16(CHECK), 0x29B1);
+    assert_eq!(crc8(CHECK), 0xA1);
+    assert_eq!(crc32(&[]), 0);
+    assert_eq!(crc16(&[]), 0xFFFF);
+}
+
+#[test]
+fn crcs_carry_over_pieces() {
+    let mut crc32 = Crc32::new();
+    let mut crc16 = Crc16::new();
+    for piece in CHECK.chunks(4) {
+        crc32.update(piece);
+        crc16.update(piece);
+    }
+    assert_eq!(crc32.finish(), 0xCBF4_3926);
+    assert_eq!(crc16.finish(), 0x29B1);
+}
+
+#[test]
+fn crc8_comes_out_zero_with_its_crc_appended() {
+    let mut data = CHECK.to_vec();
+    data.push(crc8(CHECK));
+    assert_eq!(crc8(&data), 0);
+}
@@ -0,0 +1,120 @@
+use std::{
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::dsp::{
+    Biquad, BiquadCoefficients, DcBlocker, Ema, Filter, Goertzel, MovingAverage, ONE, ToneDetector,
+};
+
+fn run(filter: &mut impl Filter, samples: impl IntoIterator<Item = i32>) -> Vec<i32> {
+    samples
+        .into_iter()
+        .map(|sample| filter.update(sample))
+        .collect()
+}
+
+#[test]
+fn moving_average_averages_a_partial_window() {
+    let outputs = run(&mut MovingAverage::<3>::new(), [3, 6, 9, 12, -30]);
+    assert_eq!(outputs, [3, 4, 6, 9, -3]);
+}
+
+#[test]
+fn ema_starts_at_the_first_sample_and_converges() {
+    let mut ema = Ema::new(2);
+    let outputs = run(&mut ema, [100, 200, 200, 200]);
+    assert_eq!(outputs, [100, 125, 144, 158]);
+    let settled = run(&mut ema, [200; 100]);
+    assert_eq!(settled.last(), Some(&200));
+
+    ema.reset();
+    assert_eq!(ema.update(-50), -50);
+}
+
+#[test]
+fn dc_blocker_removes_an_offset() {
+    let mut blocker = DcBlocker::new(3);
+    let outputs = run(
+        &mut blocker,
+        (0..200).map(|i| 1000 + if i % 2 == 0 { 10 } else { -10 }),
+    );
+    assert_eq!(outputs[0], 0);
+    assert!(outputs[190..].iter().all(|output| output.abs() <= 11));
+}
+
+#[test]
+fn biquad_applies_its_coefficients() {
+    // y[n] = 0.5 x[n] + 0.5 y[n-1], a one-pole low pass with unity DC gain
+    let mut low_pass = Biquad::new(BiquadCoefficients {
+        b0: ONE / 2,
+        b1: 0,
+        b2: 0,
+        a1: -ONE / 2,
+        a2: 0,
+    });
+    let outputs = run(&mut low_pass, [1000, 1000, 1000, 1000]);
+    assert_eq!(outputs, [500, 750, 875, 938]);
+
+    low_pass.reset();
+    assert_eq!(low_pass.update(1000), 500);
+}
+
+fn tone(hz: f32, amplitude: f32, sample_rate: f32, len: usize) -> Vec<i16> {
+    (0..len)
+        .map(|i| {
+            (amplitude * (2.0 * std::f32::consts::PI * hz * i as f32 / sample_rate).sin()) as i16
+        })
+        .collect()
+}
+
+#[test]
+fn goertzel_measures_the_target_tone_only() {
+    let mut goertzel = Goertzel::new(1000.0, 8000.0, 200);
+    let power = goertzel
+        .process(&tone(1000.0, 1000.0, 8000.0, 200))
+        .unwrap();
+    assert!((power.sqrt() - 1000.0).abs() < 10.0, "power {power}");
+
+    let off_target = goertzel
+        .process(&tone(2000.0, 1000.0, 8000.0, 200))
+        .unwrap();
+    assert!(off_target.sqrt() < 10.0, "power {off_target}");
+}
+
+#[test]
+fn goertzel_blocks_span_buffers() {
+    let mut goertzel = Goertzel::new(1000.0, 8000.0, 200);
+    let samples = tone(1000.0, 500.0, 8000.0, 200);
+    assert_eq!(goertzel.process(&samples[..150]), None);
+    let power = goertzel.process(&samples[150..]).unwrap();
+    assert!((power.sqrt() - 500.0).abs() < 10.0, "power {power}");
+}
+
+// A buffer of several blocks with the tone only in the first
+#[test]
+fn goertzel_reports_the_loudest_block() {
+    let mut goertzel = Goertzel::new(1000.0, 8000.0, 64);
+    let mut samples = tone(1000.0, 400.0, 8000.0, 64);
+    samples.extend(tone(1000.0, 0.0, 8000.0, 128));
+    let power = goertzel.process(&samples).unwrap();
+    assert!((power.sqrt() - 400.0).abs() < 10.0, "power {power}");
+}
+
+#[test]
+fn tone_detector_waits_for_the_threshold() {
+    let mut detector = ToneDetector::new(Goertzel::new(1000.0, 8000.0, 64), 300);
+    let mut reads = 0;
+    let mut buffer = [0; 64];
+    let wait = detector.wait(&mut buffer, async |buffer: &mut [i16; 64]| {
+        reads += 1;
+        // Quiet at first, then loud enough
+        let amplitude = if reads < 3 { 100.0 } else { 400.0 };
+        buffer.copy_from_slice(&tone(1000.0, amplitude, 8000.0, 64));
+    });
+    let power = pin!(wait)
+        .poll(&mut Context::from_waker(Waker::noop()))
+        .map(f32::sqrt);
+    assert!(matches!(power, Poll::Ready(amplitude) if (amplitude - 400.0).abs() < 10.0));
+    assert_eq!(reads, 3);
+}
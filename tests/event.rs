@@ -0,0 +1,54 @@
+use async_fluid::{channel::broadcast::Broadcast, event::EventBus, events};
+
+#[derive(Clone, Debug, PartialEq)]
+struct ButtonEvent(u8);
+
+#[derive(Clone, Debug, PartialEq)]
+struct TimerTick(u32);
+
+events! {
+    enum AppEvent {
+        Button(ButtonEvent),
+        Tick(TimerTick),
+    }
+}
+
+#[test]
+fn every_subscriber_sees_every_value() {
+    let broadcast = Broadcast::<u32, 4>::new();
+    let mut first = broadcast.subscribe();
+    let mut second = broadcast.subscribe();
+    broadcast.send(1);
+    broadcast.send(2);
+    assert_eq!(first.try_recv(), Some(1));
+    assert_eq!(first.try_recv(), Some(2));
+    assert_eq!(first.try_recv(), None);
+    assert_eq!(second.try_recv(), Some(1));
+    assert_eq!(second.try_recv(), Some(2));
+}
+
+#[test]
+fn lagging_subscriber_skips_to_oldest_kept_value() {
+    let broadcast = Broadcast::<u32, 2>::new();
+    let mut subscriber = broadcast.subscribe();
+    for value in 0..5 {
+        broadcast.send(value);
+    }
+    assert_eq!(subscriber.try_recv(), Some(3));
+    assert_eq!(subscriber.try_recv(), Some(4));
+    assert_eq!(subscriber.missed(), 3);
+}
+
+#[test]
+fn subscribers_only_see_their_event_type() {
+    let bus = EventBus::<AppEvent, 4>::new();
+    let mut buttons = bus.subscribe::<ButtonEvent>();
+    let mut ticks = bus.subscribe::<TimerTick>();
+    bus.publish(TimerTick(1));
+    bus.publish(ButtonEvent(7));
+    bus.publish(TimerTick(2));
+    assert_eq!(buttons.try_recv(), Some(ButtonEvent(7)));
+    assert_eq!(buttons.try_recv(), None);
+    assert_eq!(ticks.try_recv(), Some(TimerTick(1)));
+    assert_eq!(ticks.try_recv(), Some(TimerTick(2)));
+}
@@ -0,0 +1,115 @@
+// The executor isn't built under loom
+#![cfg(not(loom))]
+
+use std::{
+    cell::Cell,
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use async_fluid::{
+    executor::{Executor, ExecutorHarness},
+    pin_array,
+};
+
+// Stays pending for `polls` polls, leaving its waker in `waker` for the test to wake it with
+async fn pending_for(polls: usize, waker: &Cell<Option<Waker>>) {
+    let mut left = polls;
+    poll_fn(|cx| {
+        if left == 0 {
+            return Poll::Ready(());
+        }
+        left -= 1;
+        waker.set(Some(cx.waker().clone()));
+        Poll::Pending
+    })
+    .await
+}
+
+#[test]
+fn tasks_start_in_order_and_wait_to_be_woken() {
+    let wakers: [Cell<Option<Waker>>; 3] = Default::default();
+    let tasks = pin_array!(
+        pending_for(2, &wakers[0]),
+        pending_for(2, &wakers[1]),
+        pending_for(2, &wakers[2]),
+    );
+    let mut harness = ExecutorHarness::new(tasks);
+    assert_eq!(harness.run_until_idle(), [0, 1, 2]);
+    assert_eq!(harness.step(), None);
+    harness.wake(2);
+    harness.wake(0);
+    assert_eq!(harness.run_until_idle(), [2, 0]);
+}
+
+#[test]
+fn wakes_from_an_interrupt_land_between_polls() {
+    let wakers: [Cell<Option<Waker>>; 2] = Default::default();
+    let tasks = pin_array!(pending_for(3, &wakers[0]), pending_for(3, &wakers[1]),);
+    let mut harness = ExecutorHarness::new(tasks);
+    assert_eq!(harness.step(), Some(0));
+    // Task 0 is woken before task 1 has had its first poll, so it queues behind it
+    harness.interrupt(|| Executor::wake_task(0));
+    assert_eq!(harness.step(), Some(1));
+    harness.interrupt(|| {
+        if let Some(waker) = wakers[1].take() {
+            waker.wake();
+        }
+    });
+    assert_eq!(harness.run_until_idle(), [0, 1]);
+    assert_eq!(harness.polls(), [0, 1, 0, 1]);
+}
+
+#[test]
+fn duplicate_wakes_queue_the_task_once() {
+    let waker = Cell::new(None);
+    let tasks = pin_array!(pending_for(5, &waker));
+    let mut harness = ExecutorHarness::new(tasks);
+    assert_eq!(harness.run_until_idle(), [0]);
+    harness.wake(0);
+    harness.wake(0);
+    assert_eq!(harness.run_until_idle(), [0]);
+}
+
+#[test]
+fn a_busy_task_cant_crowd_out_the_others() {
+    let wakers: [Cell<Option<Waker>>; 3] = Default::default();
+    let tasks = pin_array!(
+        pending_for(5, &wakers[0]),
+        pending_for(5, &wakers[1]),
+        pending_for(5, &wakers[2]),
+    );
+    let mut harness = ExecutorHarness::new(tasks);
+    harness.run_until_idle();
+    // Far more wakes than the ready queue has room for
+    for _ in 0..100 {
+        harness.interrupt(|| Executor::wake_task(0));
+    }
+    harness.wake(2);
+    harness.wake(1);
+    assert_eq!(harness.run_until_idle(), [0, 2, 1]);
+}
+
+#[test]
+fn finished_tasks_are_not_polled_again() {
+    let waker = Cell::new(None);
+    let tasks = pin_array!(pending_for(1, &waker), async {});
+    let mut harness = ExecutorHarness::new(tasks);
+    assert_eq!(harness.run_until_idle(), [0, 1]);
+    assert!(harness.is_finished(1));
+    assert!(!harness.is_finished(0));
+    harness.wake(1);
+    harness.wake(0);
+    assert_eq!(harness.run_until_idle(), [0]);
+    assert!(harness.is_finished(0));
+}
+
+#[test]
+fn wakes_for_unknown_tasks_are_ignored() {
+    let tasks = pin_array!(async {});
+    let mut harness = ExecutorHarness::new(tasks);
+    assert_eq!(harness.run_until_idle(), [0]);
+    harness.wake(5);
+    harness.wake(1000);
+    assert_eq!(harness.step(), None);
+}
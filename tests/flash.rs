@@ -0,0 +1,154 @@
+use std::{
+    convert::Infallible,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use async_fluid::{
+    flash::{PartialErase, QuietWindows, ScheduledFlash},
+    time::{MockTicker, TickDuration},
+};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use futures::{FutureExt, task::noop_waker};
+
+// Flash that records how many operations it's done
+struct FakeFlash {
+    bytes: Vec<u8>,
+    writes: usize,
+    steps: usize,
+}
+
+impl FakeFlash {
+    fn new() -> Self {
+        Self {
+            bytes: vec![0; 256],
+            writes: 0,
+            steps: 0,
+        }
+    }
+}
+
+impl ErrorType for FakeFlash {
+    type Error = Infallible;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Infallible> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 128;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Infallible> {
+        self.bytes[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Infallible> {
+        self.writes += 1;
+        let offset = offset as usize;
+        self.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl PartialErase for FakeFlash {
+    const STEP_TIME: TickDuration = TickDuration::millis(2);
+    const STEPS: u32 = 3;
+
+    fn erase_step(&mut self, page_offset: u32) -> Result<(), Infallible> {
+        self.steps += 1;
+        if self.steps.is_multiple_of(Self::STEPS as usize) {
+            let page = page_offset as usize;
+            self.bytes[page..page + Self::ERASE_SIZE].fill(0xFF);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn work_waits_for_a_window() {
+    let _guard = MockTicker::lock();
+    let quiet = QuietWindows::new();
+    let mut flash = ScheduledFlash::new(FakeFlash::new(), &quiet);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    {
+        let mut write = pin!(flash.write(0, &[1; 64]));
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        assert!(quiet.is_wanted());
+        MockTicker::advance(TickDuration::millis(5));
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+
+        // Time doesn't pass while the mock flash writes, so both chunks fit in the window
+        let mut window = pin!(quiet.quiet_for(TickDuration::millis(3)));
+        assert!(window.as_mut().poll(&mut cx).is_pending());
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+        MockTicker::advance(TickDuration::millis(3));
+        assert!(window.as_mut().poll(&mut cx).is_ready());
+    }
+    assert!(!quiet.is_wanted());
+    let flash = flash.free();
+    assert_eq!(flash.writes, 2);
+    assert_eq!(flash.bytes[..64], [1; 64]);
+}
+
+#[test]
+fn windows_too_short_dont_count() {
+    let _guard = MockTicker::lock();
+    let quiet = QuietWindows::new();
+    let mut flash = ScheduledFlash::new(FakeFlash::new(), &quiet);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut erase = pin!(flash.erase(0, 128));
+    assert!(erase.as_mut().poll(&mut cx).is_pending());
+    let mut window = pin!(quiet.quiet_for(TickDuration::millis(1)));
+    assert!(window.as_mut().poll(&mut cx).is_pending());
+    assert!(erase.as_mut().poll(&mut cx).is_pending());
+}
+
+#[test]
+fn nothing_to_do_means_no_window() {
+    let _guard = MockTicker::lock();
+    let quiet = QuietWindows::new();
+    assert!(
+        quiet
+            .quiet_for(TickDuration::millis(3))
+            .now_or_never()
+            .is_some()
+    );
+}
+
+#[test]
+fn erase_goes_ahead_without_windows() {
+    let _guard = MockTicker::lock();
+    let quiet = QuietWindows::new();
+    let mut flash =
+        ScheduledFlash::new(FakeFlash::new(), &quiet).with_max_wait(TickDuration::millis(10));
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    {
+        let mut erase = pin!(flash.erase(0, 256));
+        // Two pages of three steps each, each waiting out the longest wait
+        let mut polls = 0;
+        while erase.as_mut().poll(&mut cx) == Poll::Pending {
+            MockTicker::advance(TickDuration::millis(10));
+            polls += 1;
+        }
+        assert_eq!(polls, 6);
+    }
+    let flash = flash.free();
+    assert_eq!(flash.steps, 6);
+    assert!(flash.bytes.iter().all(|&byte| byte == 0xFF));
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use async_fluid::framing::{
+    ByteLink, CobsDecoder, FramedLink, FramingError, MAX_PAYLOAD, SlipDecoder, cobs_decode,
+    cobs_encode, slip_encode,
+};
+use futures::FutureExt;
+
+fn cobs(data: &[u8]) -> Vec<u8> {
+    let mut out = [0; 1000];
+    let len = cobs_encode(data, &mut out).unwrap();
+    out[..len].to_vec()
+}
+
+fn uncobs(data: &[u8]) -> Result<Vec<u8>, FramingError> {
+    let mut out = [0; 1000];
+    let len = cobs_decode(data, &mut out)?;
+    Ok(out[..len].to_vec())
+}
+
+#[test]
+fn cobs_matches_known_encodings() {
+    let cases: [(&[u8], &[u8]); 5] = [
+        (&[], &[0x01]),
+        (&[0x00], &[0x01, 0x01]),
+        (&[0x00, 0x00], &[0x01, 0x01, 0x01]),
+        (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33]),
+        (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01]),
+    ];
+    for (data, encoded) in cases {
+        assert_eq!(cobs(data), encoded);
+        assert_eq!(uncobs(encoded).unwrap(), data);
+    }
+}
+
+#[test]
+fn cobs_splits_long_runs_into_blocks() {
+    let data: Vec<u8> = (1..=254).collect();
+    let encoded = cobs(&data);
+    assert_eq!(encoded.len(), 255);
+    assert_eq!(encoded[0], 0xFF);
+    assert_eq!(uncobs(&encoded).unwrap(), data);
+
+    let data: Vec<u8> = (0..=255).cycle().take(600).collect();
+    let encoded = cobs(&data);
+    assert!(!encoded.contains(&0));
+    let mut out = [0; 600];
+    let len = cobs_decode(&encoded, &mut out).unwrap();
+    assert_eq!(out[..len], data[..]);
+}
+
+#[test]
+fn cobs_refuses_bad_frames() {
+    assert!(matches!(
+        uncobs(&[0x05, 0x11]),
+        Err(FramingError::Malformed)
+    ));
+    assert!(matches!(
+        uncobs(&[0x03, 0x11, 0x00]),
+        Err(FramingError::Malformed)
+    ));
+    let mut small = [0; 1];
+    assert!(matches!(
+        cobs_encode(&[1, 2], &mut small),
+        Err(FramingError::TooLong)
+    ));
+}
+
+#[test]
+fn decoders_pick_up_after_noise() {
+    let mut decoder = CobsDecoder::<16>::new();
+    // The tail of a frame that started before the receiver did, then a whole one
+    let stream = [0x33, 0x44, 0x00, 0x03, 0x11, 0x22, 0x02, 0x33, 0x00];
+    let frames: Vec<_> = stream.iter().filter_map(|&b| decoder.feed(b)).collect();
+    assert!(matches!(frames[0], Err(FramingError::Malformed)));
+    assert_eq!(frames[1].as_ref().unwrap()[..], [0x11, 0x22, 0x00, 0x33]);
+
+    let mut out = [0; 16];
+    let len = slip_encode(&[0x01, 0xC0, 0xDB, 0x02], &mut out).unwrap();
+    assert_eq!(out[..len], [0xC0, 0x01, 0xDB, 0xDC, 0xDB, 0xDD, 0x02, 0xC0]);
+    let mut decoder = SlipDecoder::<16>::new();
+    let frames: Vec<_> = out[..len].iter().filter_map(|&b| decoder.feed(b)).collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].as_ref().unwrap()[..], [0x01, 0xC0, 0xDB, 0x02]);
+    assert!(decoder.feed(0xDB).is_none());
+    assert!(decoder.feed(0x00).is_none());
+    assert!(matches!(
+        decoder.feed(0xC0),
+        Some(Err(FramingError::Malformed))
+    ));
+}
+
+// Loops writes back to reads
+#[derive(Default)]
+struct Loopback {
+    bytes: VecDeque<u8>,
+}
+
+impl ByteLink for Loopback {
+    async fn read_byte(&mut self) -> u8 {
+        self.bytes.pop_front().expect("Nothing left to read")
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+}
+
+#[test]
+fn framed_link_drops_damaged_frames() {
+    let mut link = FramedLink::new(Loopback::default());
+    link.send(&[1, 0, 2]).now_or_never().unwrap().unwrap();
+    link.send(&[3; MAX_PAYLOAD])
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+    link.send(&[4, 5]).now_or_never().unwrap().unwrap();
+    assert!(matches!(
+        link.send(&[0; MAX_PAYLOAD + 1]).now_or_never().unwrap(),
+        Err(FramingError::TooLong)
+    ));
+
+    // Damages the first frame
+    let mut loopback = link.free();
+    loopback.bytes[2] ^= 0x40;
+    let mut link = FramedLink::new(loopback);
+    assert_eq!(link.recv().now_or_never().unwrap()[..], [3; MAX_PAYLOAD]);
+    assert_eq!(link.recv().now_or_never().unwrap()[..], [4, 5]);
+    assert_eq!(link.dropped(), 1);
+}
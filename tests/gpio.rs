@@ -0,0 +1,107 @@
+use std::{
+    pin::{Pin, pin},
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::{
+    gpio::{Edge, InputChannel, MockPin},
+    time::{Elapsed, MockTicker, TickDuration, TickInstant},
+};
+use embedded_hal::digital::PinState;
+use futures::Stream;
+
+#[test]
+fn edge_stream_yields_each_change_with_its_time() {
+    static PIN: MockPin = MockPin::new(PinState::Low);
+    let _guard = MockTicker::lock();
+    let mut edges = InputChannel::from_edge(&PIN).into_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(Pin::new(&mut edges).poll_next(&mut cx).is_pending());
+
+    MockTicker::advance(TickDuration::from_ticks(10));
+    PIN.set(PinState::High);
+    assert_eq!(
+        Pin::new(&mut edges).poll_next(&mut cx),
+        Poll::Ready(Some((Edge::Rising, TickInstant::from_ticks(10))))
+    );
+    assert!(Pin::new(&mut edges).poll_next(&mut cx).is_pending());
+
+    MockTicker::advance(TickDuration::from_ticks(5));
+    PIN.set(PinState::Low);
+    assert_eq!(
+        Pin::new(&mut edges).poll_next(&mut cx),
+        Poll::Ready(Some((Edge::Falling, TickInstant::from_ticks(15))))
+    );
+}
+
+#[test]
+fn edges_between_polls_keep_the_time_they_happened() {
+    static PIN: MockPin = MockPin::new(PinState::High);
+    let _guard = MockTicker::lock();
+    let mut edges = InputChannel::from_edge(&PIN).into_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+    // A short burst while the task is busy elsewhere
+    MockTicker::advance(TickDuration::from_ticks(3));
+    PIN.set(PinState::Low);
+    MockTicker::advance(TickDuration::from_ticks(18));
+    PIN.set(PinState::High);
+    MockTicker::advance(TickDuration::from_ticks(100));
+    assert_eq!(
+        Pin::new(&mut edges).poll_next(&mut cx),
+        Poll::Ready(Some((Edge::Falling, TickInstant::from_ticks(3))))
+    );
+    assert_eq!(
+        Pin::new(&mut edges).poll_next(&mut cx),
+        Poll::Ready(Some((Edge::Rising, TickInstant::from_ticks(21))))
+    );
+    assert!(Pin::new(&mut edges).poll_next(&mut cx).is_pending());
+}
+
+#[test]
+fn edges_that_dont_fit_are_counted() {
+    static PIN: MockPin = MockPin::new(PinState::Low);
+    let _guard = MockTicker::lock();
+    let mut edges = InputChannel::from_edge(&PIN).into_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+    for i in 0..20 {
+        PIN.set(if i % 2 == 0 {
+            PinState::High
+        } else {
+            PinState::Low
+        });
+    }
+    assert_eq!(edges.overflows(), 4);
+    let mut queued = 0;
+    while Pin::new(&mut edges).poll_next(&mut cx).is_ready() {
+        queued += 1;
+    }
+    assert_eq!(queued, 16);
+}
+
+#[test]
+fn wait_for_timeout_gives_up_after_the_duration() {
+    static PIN: MockPin = MockPin::new(PinState::Low);
+    let _guard = MockTicker::lock();
+    let mut input = InputChannel::from_edge(&PIN);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut wait = pin!(input.wait_for_timeout(PinState::High, TickDuration::from_ticks(10)));
+    assert!(wait.as_mut().poll(&mut cx).is_pending());
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert!(matches!(
+        wait.as_mut().poll(&mut cx),
+        Poll::Ready(Err(Elapsed))
+    ));
+}
+
+#[test]
+fn wait_for_timeout_finishes_on_the_edge() {
+    static PIN: MockPin = MockPin::new(PinState::Low);
+    let _guard = MockTicker::lock();
+    let mut input = InputChannel::from_edge(&PIN);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut wait = pin!(input.wait_for_timeout(PinState::High, TickDuration::from_ticks(10)));
+    assert!(wait.as_mut().poll(&mut cx).is_pending());
+    MockTicker::advance(TickDuration::from_ticks(5));
+    PIN.set(PinState::High);
+    assert!(matches!(wait.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+}
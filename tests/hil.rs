@@ -0,0 +1,181 @@
+/*
+On target tests, run with `cargo test --features hil-tests --test hil` with a micro:bit attached.
+The GPIOTE tests need edge connector pin P0 wired to P1.
+*/
+#![no_std]
+#![no_main]
+
+use core::{
+    cell::RefCell,
+    pin::pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use async_fluid::time::{TickDuration, Timer};
+use cortex_m::asm;
+use defmt_rtt as _;
+use futures::{FutureExt, select_biased};
+use panic_probe as _;
+
+const TEST_TIMEOUT: TickDuration = TickDuration::secs(1);
+
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |p| RawWaker::new(p, &VTABLE),
+    |_| WOKEN.store(true, Ordering::Relaxed),
+    |_| WOKEN.store(true, Ordering::Relaxed),
+    |_| {},
+);
+
+// Runs the future to completion, sleeping until an interrupt whenever it isn't woken. Panics if it
+// takes longer than TEST_TIMEOUT.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(async {
+        select_biased! {
+            output = future.fuse() => output,
+            () = Timer::delay(TEST_TIMEOUT).fuse() => panic!("Test timed out"),
+        }
+    });
+    // SAFETY: The vtable functions don't use the data pointer
+    let waker = unsafe { Waker::new(core::ptr::null(), &VTABLE) };
+    loop {
+        WOKEN.store(false, Ordering::Relaxed);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            return output;
+        }
+        if !WOKEN.load(Ordering::Relaxed) {
+            asm::wfi();
+        }
+    }
+}
+
+async fn record_after(order: &RefCell<heapless::Vec<u8, 8>>, id: u8, duration: TickDuration) {
+    Timer::delay(duration).await;
+    order.borrow_mut().push(id).unwrap();
+}
+
+#[defmt_test::tests]
+mod tests {
+    use core::cell::RefCell;
+
+    use async_fluid::{
+        channel::Channel,
+        config::Config,
+        gpio::InputChannel,
+        gpiote::{GpioteEdge, GpioteManager},
+        time::{TickDuration, Ticker, Timer},
+    };
+    use embedded_hal::digital::{OutputPin, PinState};
+    use futures::{FutureExt, future::join, select_biased};
+    use nrf52833_hal::{
+        clocks::Clocks,
+        gpio::{Level, Output, Pin, PushPull, p0},
+        pac,
+    };
+
+    use super::{block_on, record_after};
+
+    struct State {
+        loopback_out: Pin<Output<PushPull>>,
+        loopback_in: InputChannel<GpioteEdge>,
+    }
+
+    #[init]
+    fn init() -> State {
+        let p = pac::Peripherals::take().unwrap();
+        let mut core_p = pac::CorePeripherals::take().unwrap();
+        Clocks::new(p.CLOCK).start_lfclk();
+        let config = Config::default();
+        Ticker::init(p.RTC0, &mut core_p.NVIC, config.rtc0);
+        GpioteManager::init(p.GPIOTE, &mut core_p.NVIC, config.gpiote);
+        let p0 = p0::Parts::new(p.P0);
+        State {
+            // Edge connector P0 and P1
+            loopback_out: p0.p0_02.into_push_pull_output(Level::High).degrade(),
+            loopback_in: InputChannel::new(p0.p0_03.into_floating_input().degrade()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn timer_waits_for_its_duration() {
+        let start = Ticker::now();
+        block_on(Timer::delay(TickDuration::millis(20)));
+        let elapsed = Ticker::now() - start;
+        assert!(elapsed >= TickDuration::millis(20));
+        assert!(elapsed < TickDuration::millis(25));
+    }
+
+    #[test]
+    fn timers_complete_in_deadline_order() {
+        let order = RefCell::new(heapless::Vec::new());
+        block_on(join(
+            join(
+                record_after(&order, 0, TickDuration::millis(30)),
+                record_after(&order, 1, TickDuration::millis(10)),
+            ),
+            record_after(&order, 2, TickDuration::millis(20)),
+        ));
+        assert_eq!(order.into_inner(), [1, 2, 0]);
+    }
+
+    #[test]
+    fn concurrent_equal_deadlines_all_complete() {
+        let order = RefCell::new(heapless::Vec::new());
+        block_on(join(
+            join(
+                record_after(&order, 0, TickDuration::millis(10)),
+                record_after(&order, 1, TickDuration::millis(10)),
+            ),
+            join(
+                record_after(&order, 2, TickDuration::millis(10)),
+                record_after(&order, 3, TickDuration::millis(10)),
+            ),
+        ));
+        assert_eq!(order.into_inner().len(), 4);
+    }
+
+    #[test]
+    fn dropped_timer_leaves_queue_consistent() {
+        block_on(async {
+            select_biased! {
+                () = Timer::delay(TickDuration::millis(50)).fuse() => panic!("Wrong timer finished"),
+                () = Timer::delay(TickDuration::millis(10)).fuse() => {}
+            }
+        });
+        // The dropped 50ms timer mustn't be woken, or stop later timers from working
+        let start = Ticker::now();
+        block_on(Timer::delay(TickDuration::millis(60)));
+        assert!(Ticker::now() - start >= TickDuration::millis(60));
+    }
+
+    #[test]
+    fn channel_delivers_value_from_another_task() {
+        let channel = Channel::new();
+        let mut recv = channel.get_recv();
+        let sender = channel.get_sender();
+        let (value, ()) = block_on(join(recv.recv(), async {
+            Timer::delay(TickDuration::millis(5)).await;
+            sender.send(42u32).unwrap();
+        }));
+        assert_eq!(value.unwrap(), 42);
+    }
+
+    #[test]
+    fn gpiote_loopback_wakes_on_edges(state: &mut State) {
+        let State {
+            loopback_out,
+            loopback_in,
+        } = state;
+        block_on(loopback_in.wait_for(PinState::High));
+        block_on(join(loopback_in.wait_for(PinState::Low), async {
+            Timer::delay(TickDuration::millis(5)).await;
+            loopback_out.set_low().unwrap();
+        }));
+        block_on(join(loopback_in.wait_for(PinState::High), async {
+            Timer::delay(TickDuration::millis(5)).await;
+            loopback_out.set_high().unwrap();
+        }));
+    }
+}
@@ -0,0 +1,64 @@
+use async_fluid::image::Image;
+
+const HEART: Image = Image::parse(
+    "01010
+     11111
+     11111
+     01110
+     00100",
+);
+
+#[test]
+fn parses_rows_with_column_zero_in_the_lowest_bit() {
+    assert_eq!(HEART.rows(), [0b01010, 0b11111, 0b11111, 0b01110, 0b00100]);
+    let arrow = Image::parse("10000\n11000\n11100\n11000\n10000");
+    assert_eq!(arrow.rows(), [0b00001, 0b00011, 0b00111, 0b00011, 0b00001]);
+    assert!(arrow.is_on(2, 2));
+    assert!(!arrow.is_on(2, 3));
+}
+
+#[test]
+fn ignores_indentation_and_blank_lines() {
+    let indented = Image::parse(
+        "
+        0 1 0 1 0
+        1 1 1 1 1
+        1 1 1 1 1
+        0 1 1 1 0
+        0 0 1 0 0
+        ",
+    );
+    assert_eq!(indented, HEART);
+    assert_eq!(Image::parse("00000\r\n".repeat(5).as_str()), Image::BLANK);
+}
+
+#[test]
+#[should_panic(expected = "5 columns")]
+fn refuses_short_rows() {
+    Image::parse("0000\n00000\n00000\n00000\n00000");
+}
+
+#[test]
+#[should_panic(expected = "5 rows")]
+fn refuses_missing_rows() {
+    Image::parse("00000\n00000\n00000\n00000");
+}
+
+#[test]
+#[should_panic(expected = "0 and 1")]
+fn refuses_other_characters() {
+    Image::parse("00000\n00000\n00x00\n00000\n00000");
+}
+
+#[test]
+fn transforms() {
+    assert_eq!(Image::BLANK.inverted(), Image::FULL);
+    let arrow = Image::parse("10000\n11000\n11100\n11000\n10000");
+    assert_eq!(
+        arrow.mirrored(),
+        Image::parse("00001\n00011\n00111\n00011\n00001")
+    );
+    assert_eq!(HEART.mirrored(), HEART);
+    assert_eq!(Image::from_rows([0xFF; 5]).rows(), [0b11111; 5]);
+    assert_eq!(<[u8; 5]>::from(HEART), HEART.rows());
+}
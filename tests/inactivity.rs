@@ -0,0 +1,85 @@
+use std::{
+    cell::RefCell,
+    pin::pin,
+    task::{Context, Waker},
+};
+
+use async_fluid::{
+    inactivity::{Activity, InactivityConfig, InactivityError, InactivityManager},
+    time::{MockTicker, TickDuration},
+};
+
+const CONFIG: InactivityConfig = InactivityConfig {
+    idle_after: TickDuration::millis(100),
+    sleep_after: Some(TickDuration::millis(300)),
+};
+
+#[test]
+fn goes_idle_then_asleep_and_wakes_on_activity() {
+    let _guard = MockTicker::lock();
+    let manager = InactivityManager::<2>::new(CONFIG);
+    let button = manager.register("button").unwrap();
+    let changes = RefCell::new(Vec::new());
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(manager.run(|activity| changes.borrow_mut().push(activity)));
+    let mut step = |millis| {
+        MockTicker::advance(TickDuration::millis(millis));
+        assert!(run.as_mut().poll(&mut cx).is_pending());
+    };
+    step(0);
+    step(99);
+    assert!(changes.borrow().is_empty());
+    step(1);
+    assert_eq!(*changes.borrow(), [Activity::Idle]);
+    button.touch();
+    step(0);
+    assert_eq!(*changes.borrow(), [Activity::Idle, Activity::Active]);
+    step(150);
+    assert_eq!(manager.activity(), Activity::Idle);
+    step(149);
+    assert_eq!(manager.activity(), Activity::Idle);
+    step(10);
+    assert_eq!(manager.activity(), Activity::Asleep);
+    assert_eq!(
+        *changes.borrow(),
+        [
+            Activity::Idle,
+            Activity::Active,
+            Activity::Idle,
+            Activity::Asleep
+        ]
+    );
+    assert_eq!(manager.last_source(), Some("button"));
+}
+
+#[test]
+fn paused_tasks_resume_on_activity() {
+    let _guard = MockTicker::lock();
+    let manager = InactivityManager::<1>::new(InactivityConfig {
+        sleep_after: None,
+        ..CONFIG
+    });
+    let radio = manager.register("radio").unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(manager.run(|_| {}));
+    assert!(run.as_mut().poll(&mut cx).is_pending());
+    assert!(pin!(manager.until_active()).poll(&mut cx).is_ready());
+    MockTicker::advance(TickDuration::millis(1000));
+    assert!(run.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(manager.activity(), Activity::Idle);
+    let mut paused = pin!(manager.until_active());
+    assert!(paused.as_mut().poll(&mut cx).is_pending());
+    radio.touch();
+    assert!(paused.as_mut().poll(&mut cx).is_ready());
+}
+
+#[test]
+fn sources_are_limited() {
+    let _guard = MockTicker::lock();
+    let manager = InactivityManager::<1>::new(CONFIG);
+    assert!(manager.register("button").is_ok());
+    assert!(matches!(
+        manager.register("touch"),
+        Err(InactivityError::TooManySources)
+    ));
+}
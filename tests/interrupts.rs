@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_fluid::interrupts::{HandlerTable, InterruptError};
+
+static FIRST: AtomicUsize = AtomicUsize::new(0);
+static SECOND: AtomicUsize = AtomicUsize::new(0);
+
+fn first() {
+    FIRST.fetch_add(1, Ordering::Relaxed);
+}
+
+fn second() {
+    SECOND.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test]
+fn dispatches_to_the_registered_handler() {
+    let table = HandlerTable::<4>::new();
+    assert!(!table.dispatch(1));
+    table.register(1, first).unwrap();
+    table.register(2, second).unwrap();
+    assert!(table.is_registered(1));
+    assert!(!table.is_registered(3));
+    assert!(table.dispatch(1));
+    assert!(table.dispatch(1));
+    assert!(table.dispatch(2));
+    assert_eq!(FIRST.load(Ordering::Relaxed), 2);
+    assert_eq!(SECOND.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn each_interrupt_has_one_handler() {
+    let table = HandlerTable::<4>::new();
+    table.register(0, first).unwrap();
+    assert!(matches!(
+        table.register(0, second),
+        Err(InterruptError::AlreadyRegistered { number: 0 })
+    ));
+    table.unregister(0);
+    assert!(!table.dispatch(0));
+    table.register(0, second).unwrap();
+}
+
+#[test]
+fn refuses_interrupts_past_the_table() {
+    let table = HandlerTable::<4>::new();
+    assert!(matches!(
+        table.register(4, first),
+        Err(InterruptError::NoSuchInterrupt { number: 4 })
+    ));
+    assert!(!table.dispatch(4));
+    assert!(!table.is_registered(4));
+}
@@ -0,0 +1,180 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::{
+    gpio::{Edge, InputChannel, MockPin},
+    ir::{NecDecoder, NecKey, NecReceiver},
+    time::{MockTicker, TickDuration, TickInstant},
+};
+use embedded_hal::digital::PinState;
+use futures::Stream;
+
+// Alternating low and high lengths in microseconds, starting with a burst
+fn frame(address: u8, command: u8) -> Vec<u64> {
+    let bits = u32::from_le_bytes([address, !address, command, !command]);
+    let mut lengths = vec![9000, 4500];
+    for bit in 0..32 {
+        lengths.push(562);
+        lengths.push(if bits & 1 << bit == 0 { 562 } else { 1687 });
+    }
+    lengths.push(562);
+    lengths
+}
+
+const REPEAT: [u64; 3] = [9000, 2250, 562];
+
+// Feeds the lengths as edges from `start`, returning the keys and when the last edge was
+fn decode(decoder: &mut NecDecoder, start: u64, lengths: &[u64]) -> (Vec<NecKey>, u64) {
+    let mut keys = Vec::new();
+    let mut at = start;
+    for (i, length) in lengths.iter().enumerate() {
+        let edge = if i % 2 == 0 {
+            Edge::Falling
+        } else {
+            Edge::Rising
+        };
+        keys.extend(decoder.edge(edge, instant(at)));
+        at += length;
+    }
+    keys.extend(decoder.edge(Edge::Rising, instant(at)));
+    (keys, at)
+}
+
+fn instant(micros: u64) -> TickInstant {
+    TickInstant::from_ticks(micros * 32768 / 1_000_000)
+}
+
+#[test]
+fn decodes_a_frame() {
+    let mut decoder = NecDecoder::new();
+    let (keys, _) = decode(&mut decoder, 1000, &frame(0x04, 0x08));
+    assert_eq!(
+        keys,
+        [NecKey {
+            address: 0x04,
+            command: 0x08,
+            repeat: false
+        }]
+    );
+}
+
+#[test]
+fn extended_addresses_use_both_bytes() {
+    let mut lengths = frame(0x34, 0x08);
+    // Overwrite the address inverse with 0x12
+    for bit in 0..8 {
+        lengths[2 + 2 * (8 + bit) + 1] = if 0x12 & 1 << bit == 0 { 562 } else { 1687 };
+    }
+    let mut decoder = NecDecoder::new();
+    let (keys, _) = decode(&mut decoder, 0, &lengths);
+    assert_eq!(keys[0].address, 0x1234);
+}
+
+#[test]
+fn repeats_follow_a_held_key() {
+    let mut decoder = NecDecoder::new();
+    let (_, end) = decode(&mut decoder, 0, &frame(0x00, 0x45));
+    let (keys, _) = decode(&mut decoder, end + 40_000, &REPEAT);
+    assert_eq!(
+        keys,
+        [NecKey {
+            address: 0x00,
+            command: 0x45,
+            repeat: true
+        }]
+    );
+}
+
+#[test]
+fn late_or_orphan_repeats_are_ignored() {
+    let mut decoder = NecDecoder::new();
+    assert_eq!(decode(&mut decoder, 0, &REPEAT).0, []);
+    let (_, end) = decode(&mut decoder, 0, &frame(0x00, 0x45));
+    assert_eq!(decode(&mut decoder, end + 500_000, &REPEAT).0, []);
+}
+
+#[test]
+fn bad_checksums_and_timing_drop_the_frame() {
+    let mut decoder = NecDecoder::new();
+    let mut lengths = frame(0x04, 0x08);
+    // Flip a bit of the command inverse
+    let space = 2 + 2 * 24 + 1;
+    lengths[space] = if lengths[space] == 562 { 1687 } else { 562 };
+    assert_eq!(decode(&mut decoder, 0, &lengths).0, []);
+
+    let mut lengths = frame(0x04, 0x08);
+    lengths[10] = 3000;
+    assert_eq!(decode(&mut decoder, 0, &lengths).0, []);
+
+    // And the next good frame still decodes
+    let (keys, _) = decode(&mut decoder, 200_000, &frame(0x04, 0x08));
+    assert_eq!(keys.len(), 1);
+}
+
+#[test]
+fn receiver_reads_keys_from_an_edge_stream() {
+    static PIN: MockPin = MockPin::new(PinState::High);
+    let _guard = MockTicker::lock();
+    let mut keys = NecReceiver::new(InputChannel::from_edge(&PIN).into_stream());
+    let mut cx = Context::from_waker(Waker::noop());
+    let lengths = frame(0x10, 0x20);
+    // Everything up to the final burst, whose falling edge completes the frame
+    let (last, lengths) = lengths.split_last().unwrap();
+    for (i, length) in lengths.iter().enumerate() {
+        PIN.set(if i % 2 == 0 {
+            PinState::Low
+        } else {
+            PinState::High
+        });
+        assert!(Pin::new(&mut keys).poll_next(&mut cx).is_pending());
+        MockTicker::advance(TickDuration::micros(*length));
+    }
+    PIN.set(PinState::Low);
+    assert_eq!(
+        Pin::new(&mut keys).poll_next(&mut cx),
+        Poll::Ready(Some(NecKey {
+            address: 0x10,
+            command: 0x20,
+            repeat: false
+        }))
+    );
+    MockTicker::advance(TickDuration::micros(*last));
+    PIN.set(PinState::High);
+    assert!(Pin::new(&mut keys).poll_next(&mut cx).is_pending());
+}
+
+// The task only gets round to the stream every dozen edges, so each batch is read well after the
+// edges in it happened
+#[test]
+fn receiver_decodes_edges_it_reads_late() {
+    static PIN: MockPin = MockPin::new(PinState::High);
+    let _guard = MockTicker::lock();
+    let mut keys = NecReceiver::new(InputChannel::from_edge(&PIN).into_stream());
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(Pin::new(&mut keys).poll_next(&mut cx).is_pending());
+    let lengths = frame(0x10, 0x20);
+    let mut read = Vec::new();
+    for (i, length) in lengths.iter().enumerate() {
+        PIN.set(if i % 2 == 0 {
+            PinState::Low
+        } else {
+            PinState::High
+        });
+        MockTicker::advance(TickDuration::micros(*length));
+        if i % 12 == 11 || i == lengths.len() - 1 {
+            while let Poll::Ready(Some(key)) = Pin::new(&mut keys).poll_next(&mut cx) {
+                read.push(key);
+            }
+        }
+    }
+    assert_eq!(
+        read,
+        [NecKey {
+            address: 0x10,
+            command: 0x20,
+            repeat: false
+        }]
+    );
+}
@@ -0,0 +1,121 @@
+use std::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::{
+    keypad::{Key, KeyEvent, Keypad},
+    time::{MockTicker, TickDuration},
+};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+
+// Which keys are held, and which row is being driven
+#[derive(Default)]
+struct Matrix {
+    held: RefCell<Vec<Key>>,
+    driven: Cell<Option<usize>>,
+}
+
+struct RowPin(Rc<Matrix>, usize);
+
+impl ErrorType for RowPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for RowPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.driven.set(Some(self.1));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        if self.0.driven.get() == Some(self.1) {
+            self.0.driven.set(None);
+        }
+        Ok(())
+    }
+}
+
+struct ColPin(Rc<Matrix>, usize);
+
+impl ErrorType for ColPin {
+    type Error = Infallible;
+}
+
+impl InputPin for ColPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        self.is_low().map(|low| !low)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        let Some(row) = self.0.driven.get() else {
+            return Ok(false);
+        };
+        let key = Key { row, col: self.1 };
+        Ok(self.0.held.borrow().contains(&key))
+    }
+}
+
+fn keypad(matrix: &Rc<Matrix>) -> Keypad<RowPin, ColPin, 4, 3> {
+    Keypad::new(
+        std::array::from_fn(|row| RowPin(matrix.clone(), row)),
+        std::array::from_fn(|col| ColPin(matrix.clone(), col)),
+    )
+}
+
+// Scans for up to `millis`, returning the first event
+fn next_event(keypad: &mut Keypad<RowPin, ColPin, 4, 3>, millis: u64) -> Option<KeyEvent> {
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut event = pin!(keypad.next_event());
+    for _ in 0..millis {
+        if let Poll::Ready(event) = event.as_mut().poll(&mut cx) {
+            return Some(event);
+        }
+        MockTicker::advance(TickDuration::millis(1));
+    }
+    None
+}
+
+#[test]
+fn press_and_release_after_debouncing() {
+    let _guard = MockTicker::lock();
+    let matrix = Rc::new(Matrix::default());
+    let mut keypad = keypad(&matrix);
+    let key = Key { row: 2, col: 1 };
+    matrix.held.borrow_mut().push(key);
+    assert_eq!(next_event(&mut keypad, 50), Some(KeyEvent::Pressed(key)));
+    assert!(keypad.is_pressed(key));
+    assert_eq!(next_event(&mut keypad, 50), None);
+    matrix.held.borrow_mut().clear();
+    assert_eq!(next_event(&mut keypad, 50), Some(KeyEvent::Released(key)));
+}
+
+#[test]
+fn bounces_are_ignored() {
+    let _guard = MockTicker::lock();
+    let matrix = Rc::new(Matrix::default());
+    let mut keypad = keypad(&matrix);
+    let key = Key { row: 0, col: 2 };
+    // Closed for only a couple of scans at a time
+    for _ in 0..5 {
+        matrix.held.borrow_mut().push(key);
+        assert_eq!(next_event(&mut keypad, 10), None);
+        matrix.held.borrow_mut().clear();
+        assert_eq!(next_event(&mut keypad, 10), None);
+    }
+    assert!(!keypad.is_pressed(key));
+}
+
+#[test]
+fn simultaneous_presses_come_out_in_order() {
+    let _guard = MockTicker::lock();
+    let matrix = Rc::new(Matrix::default());
+    let mut keypad = keypad(&matrix);
+    let (first, second) = (Key { row: 1, col: 0 }, Key { row: 3, col: 2 });
+    matrix.held.borrow_mut().extend([second, first]);
+    assert_eq!(next_event(&mut keypad, 50), Some(KeyEvent::Pressed(first)));
+    assert_eq!(next_event(&mut keypad, 1), Some(KeyEvent::Pressed(second)));
+}
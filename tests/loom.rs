@@ -0,0 +1,192 @@
+/*
+Model checks the wake and registration races in the concurrency primitives. Run with
+`RUSTFLAGS="--cfg loom" cargo test --release --target <host triple> --no-default-features
+--features std --test loom`.
+*/
+#![cfg(loom)]
+
+use std::task::{Wake, Waker};
+
+use async_fluid::{
+    channel::spsc::Spsc,
+    utils::{AtomicWaker, WakerSlotArray, mpmc::Queue},
+};
+use loom::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl CountingWaker {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: std::sync::Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn wake_racing_register_is_not_lost() {
+    loom::model(|| {
+        let slot = Arc::new(AtomicWaker::new());
+        let ready = Arc::new(AtomicBool::new(false));
+        let woken = std::sync::Arc::new(CountingWaker::default());
+
+        let waker_thread = {
+            let (slot, ready) = (slot.clone(), ready.clone());
+            thread::spawn(move || {
+                ready.store(true, Ordering::Release);
+                slot.wake();
+            })
+        };
+        slot.register(&Waker::from(woken.clone()));
+        let saw_ready = ready.load(Ordering::Acquire);
+        waker_thread.join().unwrap();
+
+        // Either the task saw the event after registering, or it must have been woken
+        assert!(saw_ready || woken.count() > 0);
+    });
+}
+
+#[test]
+fn wake_racing_reregister_wakes_new_waker() {
+    loom::model(|| {
+        let slot = Arc::new(AtomicWaker::new());
+        let ready = Arc::new(AtomicBool::new(false));
+        let old = std::sync::Arc::new(CountingWaker::default());
+        let new = std::sync::Arc::new(CountingWaker::default());
+        slot.register(&Waker::from(old.clone()));
+
+        let waker_thread = {
+            let (slot, ready) = (slot.clone(), ready.clone());
+            thread::spawn(move || {
+                ready.store(true, Ordering::Release);
+                slot.wake();
+            })
+        };
+        slot.register(&Waker::from(new.clone()));
+        let saw_ready = ready.load(Ordering::Acquire);
+        waker_thread.join().unwrap();
+
+        // The old waker may also be woken spuriously, but the new one must never be missed
+        assert!(saw_ready || new.count() > 0);
+    });
+}
+
+#[test]
+fn slot_wake_only_reaches_its_own_task() {
+    loom::model(|| {
+        let slots = Arc::new(WakerSlotArray::<2>::new());
+        let ready = Arc::new(AtomicBool::new(false));
+        let first = std::sync::Arc::new(CountingWaker::default());
+        let second = std::sync::Arc::new(CountingWaker::default());
+        slots.register(0, &Waker::from(first.clone()));
+
+        let waker_thread = {
+            let (slots, ready) = (slots.clone(), ready.clone());
+            thread::spawn(move || {
+                ready.store(true, Ordering::Release);
+                slots.wake(1);
+            })
+        };
+        slots.register(1, &Waker::from(second.clone()));
+        let saw_ready = ready.load(Ordering::Acquire);
+        waker_thread.join().unwrap();
+
+        assert!(saw_ready || second.count() > 0);
+        assert_eq!(first.count(), 0);
+    });
+}
+
+#[test]
+fn queue_delivers_each_item_once() {
+    loom::model(|| {
+        let queue = Arc::new(Queue::<usize, 2>::new());
+        let producers: Vec<_> = (0..2)
+            .map(|item| {
+                let queue = queue.clone();
+                thread::spawn(move || queue.enqueue(item).unwrap())
+            })
+            .collect();
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            match queue.dequeue() {
+                Some(item) => received.push(item),
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        received.sort_unstable();
+        assert_eq!(received, [0, 1]);
+        assert_eq!(queue.dequeue(), None);
+    });
+}
+
+#[test]
+fn queue_keeps_order_when_wrapping() {
+    loom::model(|| {
+        let queue = Arc::new(Queue::<usize, 2>::new());
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for item in 0..3 {
+                    while queue.enqueue(item).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            match queue.dequeue() {
+                Some(item) => received.push(item),
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+
+        assert_eq!(received, [0, 1, 2]);
+    });
+}
+
+#[test]
+fn spsc_keeps_order_when_wrapping() {
+    loom::model(|| {
+        // Leaked so the producer can be moved to its own thread
+        let ring: &'static Spsc<usize, 2> = Box::leak(Box::new(Spsc::new()));
+        let mut producer = ring.producer().unwrap();
+        let mut consumer = ring.consumer().unwrap();
+        let producer = thread::spawn(move || {
+            for item in 0..3 {
+                while producer.enqueue(item).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            match consumer.dequeue() {
+                Some(item) => received.push(item),
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+
+        assert_eq!(received, [0, 1, 2]);
+    });
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+};
+
+use async_fluid::{
+    lsm303agr::{Calibration, FIFO_DEPTH, Lsm303Error, Lsm303agr, MagCalibrator, Vector},
+    settings::Settings,
+};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use embedded_storage::nor_flash::{ErrorType as FlashErrorType, NorFlash, ReadNorFlash};
+use futures::FutureExt;
+
+const ACCEL: u8 = 0x19;
+const MAG: u8 = 0x1E;
+
+// Register maps for both halves of the chip, reading on from the last register written
+#[derive(Default)]
+struct FakeBus {
+    registers: HashMap<(u8, u8), u8>,
+    // Accelerometer readings, read out from X onwards before the output registers
+    fifo: VecDeque<[i16; 3]>,
+    reads: usize,
+}
+
+impl FakeBus {
+    fn new() -> Self {
+        let mut bus = Self::default();
+        bus.registers.insert((ACCEL, 0x0F), 0x33);
+        bus.registers.insert((MAG, 0x4F), 0x40);
+        bus
+    }
+
+    fn fifo_status(&self) -> u8 {
+        let watermark = self.registers.get(&(ACCEL, 0x2E)).copied().unwrap_or(0) & 0x1F;
+        let len = self.fifo.len();
+        let mut status = len.min(FIFO_DEPTH - 1) as u8;
+        if len >= usize::from(watermark) {
+            status |= 0x80;
+        }
+        if len >= FIFO_DEPTH {
+            status |= 0x40;
+        }
+        status
+    }
+
+    fn set_axes(&mut self, address: u8, register: u8, axes: [i16; 3]) {
+        for (axis, value) in axes.into_iter().enumerate() {
+            let [lo, hi] = value.to_le_bytes();
+            self.registers
+                .insert((address, register + 2 * axis as u8), lo);
+            self.registers
+                .insert((address, register + 2 * axis as u8 + 1), hi);
+        }
+    }
+}
+
+impl ErrorType for FakeBus {
+    type Error = Infallible;
+}
+
+impl I2c for FakeBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Infallible> {
+        let mut register = 0;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    // Without the accelerometer's auto increment bit
+                    register = bytes[0] & 0x7F;
+                    if let [_, value] = bytes[..] {
+                        self.registers.insert((address, register), value);
+                    }
+                }
+                Operation::Read(buf) => {
+                    self.reads += 1;
+                    if address == ACCEL && register == 0x2F {
+                        buf[0] = self.fifo_status();
+                        continue;
+                    }
+                    if address == ACCEL && register == 0x28 && !self.fifo.is_empty() {
+                        for sample in buf.chunks_mut(6) {
+                            let axes = self.fifo.pop_front().unwrap_or_default();
+                            for (axis, value) in axes.into_iter().enumerate() {
+                                sample[2 * axis..][..2].copy_from_slice(&value.to_le_bytes());
+                            }
+                        }
+                        continue;
+                    }
+                    for byte in buf.iter_mut() {
+                        *byte = self
+                            .registers
+                            .get(&(address, register))
+                            .copied()
+                            .unwrap_or(0);
+                        register += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FakeFlash(Vec<u8>);
+
+impl FlashErrorType for FakeFlash {
+    type Error = Infallible;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Infallible> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 256;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Infallible> {
+        self.0[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Infallible> {
+        for (stored, byte) in self.0[offset as usize..].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn wrong_device_is_refused() {
+    let mut bus = FakeBus::new();
+    bus.registers.insert((MAG, 0x4F), 0x3D);
+    assert!(matches!(Lsm303agr::new(bus), Err(Lsm303Error::WrongDevice)));
+}
+
+#[test]
+fn new_starts_both_halves_measuring() {
+    let bus = Lsm303agr::new(FakeBus::new()).unwrap().free();
+    assert_eq!(bus.registers[&(ACCEL, 0x20)], 0x57);
+    assert_eq!(bus.registers[&(MAG, 0x60)], 0x80);
+}
+
+#[test]
+fn readings_are_scaled_and_calibrated() {
+    let mut bus = FakeBus::new();
+    // Left justified 12 bit readings
+    bus.set_axes(ACCEL, 0x28, [100 << 4, -50 << 4, 1000 << 4]);
+    bus.set_axes(MAG, 0x68, [200, -100, 0]);
+    let mut imu = Lsm303agr::new(bus).unwrap();
+    assert_eq!(
+        imu.accel().unwrap(),
+        Vector {
+            x: 100,
+            y: -50,
+            z: 1000
+        }
+    );
+    assert_eq!(
+        imu.mag().unwrap(),
+        Vector {
+            x: 300,
+            y: -150,
+            z: 0
+        }
+    );
+
+    imu.set_calibration(Calibration {
+        accel_offset: [100, 0, 0],
+        mag_offset: [100, 0, 0],
+        // Stretches y by half again
+        mag_scale: [1 << 14, 3 << 13, 1 << 14],
+    });
+    assert_eq!(
+        imu.accel().unwrap(),
+        Vector {
+            x: 0,
+            y: -50,
+            z: 1000
+        }
+    );
+    assert_eq!(
+        imu.mag().unwrap(),
+        Vector {
+            x: 200,
+            y: -225,
+            z: 0
+        }
+    );
+    assert_eq!(
+        imu.mag_raw().unwrap(),
+        Vector {
+            x: 300,
+            y: -150,
+            z: 0
+        }
+    );
+}
+
+// Readings 1 mg apart on x, left justified
+fn fill_fifo(bus: &mut FakeBus, readings: i16) {
+    bus.fifo
+        .extend((0..readings).map(|i| [i << 4, -i << 4, 1000 << 4]));
+}
+
+#[test]
+fn fifo_batches_are_read_in_one_transfer() {
+    let mut bus = FakeBus::new();
+    fill_fifo(&mut bus, 3);
+    let mut imu = Lsm303agr::new(bus).unwrap();
+    imu.enable_fifo(3).unwrap();
+    imu.set_calibration(Calibration {
+        accel_offset: [10, 0, 0],
+        ..Calibration::new()
+    });
+    let batch = imu
+        .accel_batch(async || panic!("The watermark was already reached"))
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+    assert!(!batch.overrun);
+    assert_eq!(
+        batch.samples,
+        [0, 1, 2].map(|i| Vector {
+            x: i - 10,
+            y: -i,
+            z: 1000
+        })
+    );
+    let bus = imu.free();
+    // The IDs when it was made, then the FIFO's status and its readings
+    assert_eq!(bus.reads, 4);
+    assert_eq!(bus.registers[&(ACCEL, 0x24)], 0x40);
+    assert_eq!(bus.registers[&(ACCEL, 0x2E)], 0x83);
+    assert_eq!(bus.registers[&(ACCEL, 0x22)], 0x04);
+}
+
+#[test]
+fn fifo_batches_wait_for_the_watermark() {
+    let mut bus = FakeBus::new();
+    fill_fifo(&mut bus, 1);
+    let mut imu = Lsm303agr::new(bus).unwrap();
+    imu.enable_fifo(4).unwrap();
+    let mut waited = false;
+    let batch = imu
+        .accel_batch(async || waited = true)
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+    assert!(waited);
+    assert_eq!(batch.samples.len(), 1);
+    assert!(imu.read_fifo().unwrap().samples.is_empty());
+}
+
+#[test]
+fn full_fifos_are_flagged_as_overrun() {
+    let mut bus = FakeBus::new();
+    fill_fifo(&mut bus, FIFO_DEPTH as i16);
+    let mut imu = Lsm303agr::new(bus).unwrap();
+    imu.enable_fifo(16).unwrap();
+    let batch = imu.read_fifo().unwrap();
+    assert!(batch.overrun);
+    assert_eq!(batch.samples.len(), FIFO_DEPTH);
+    assert_eq!(batch.samples[31].x, 31);
+
+    imu.disable_fifo().unwrap();
+    let bus = imu.free();
+    assert_eq!(bus.registers[&(ACCEL, 0x22)], 0);
+    assert_eq!(bus.registers[&(ACCEL, 0x24)], 0);
+    assert_eq!(bus.registers[&(ACCEL, 0x2E)], 0);
+}
+
+#[test]
+#[should_panic(expected = "watermark")]
+fn fifo_watermark_has_to_fit() {
+    let mut imu = Lsm303agr::new(FakeBus::new()).unwrap();
+    let _ = imu.enable_fifo(32);
+}
+
+#[test]
+fn calibrator_centres_and_evens_out_the_axes() {
+    let mut calibrator = MagCalibrator::new();
+    calibrator.add(Vector {
+        x: -400,
+        y: -200,
+        z: -600,
+    });
+    assert!(matches!(
+        calibrator.finish(),
+        Err(Lsm303Error::NotEnoughMovement)
+    ));
+    calibrator.add(Vector {
+        x: 600,
+        y: 400,
+        z: 400,
+    });
+    assert_eq!(calibrator.swing(), 600);
+    let (offset, scale) = calibrator.finish().unwrap();
+    assert_eq!(offset, [100, 100, -100]);
+    // Radii of 500, 300 and 500, averaging 433
+    assert_eq!(scale, [14188, 23647, 14188]);
+}
+
+#[test]
+fn calibration_is_kept_in_the_settings() {
+    let calibration = Calibration {
+        accel_offset: [1, -2, 3],
+        mag_offset: [-400, 500, -600],
+        mag_scale: [16000, 17000, 18000],
+    };
+    assert_eq!(
+        Calibration::from_bytes(&calibration.to_bytes()),
+        calibration
+    );
+
+    let mut settings = Settings::new(FakeFlash(vec![0xFF; 256]));
+    assert_eq!(Calibration::load(&mut settings).unwrap(), None);
+    calibration.save(&mut settings).unwrap();
+    let mut settings = Settings::new(settings.free());
+    assert_eq!(Calibration::load(&mut settings).unwrap(), Some(calibration));
+}
@@ -0,0 +1,86 @@
+use std::{
+    cell::{Cell, RefCell},
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::menu::{Menu, MenuInput, MenuItem};
+use futures::stream;
+
+#[test]
+fn turning_wraps_through_the_items() {
+    let (mut a, mut b) = (|| {}, |_| {});
+    let mut menu = Menu::new([
+        MenuItem::action("Reset", &mut a),
+        MenuItem::toggle("Sound", true, &mut b),
+    ]);
+    assert_eq!(menu.text(), "Reset");
+    let _ = menu.handle(MenuInput::Turn(1));
+    assert_eq!(menu.text(), "Sound on");
+    let _ = menu.handle(MenuInput::Turn(1));
+    assert_eq!(menu.selected(), 0);
+    let _ = menu.handle(MenuInput::Turn(-3));
+    assert_eq!(menu.selected(), 1);
+}
+
+#[test]
+fn values_are_edited_then_kept_or_put_back() {
+    let changes = RefCell::new(Vec::new());
+    let mut on_change = |value| changes.borrow_mut().push(value);
+    {
+        let mut menu = Menu::new([MenuItem::value("Level", 5, 0..=9, 2, &mut on_change)]);
+        assert_eq!(menu.text(), "Level 5");
+        let _ = menu.handle(MenuInput::Select);
+        assert!(menu.is_editing());
+        let _ = menu.handle(MenuInput::Turn(1));
+        assert_eq!(menu.text(), "7");
+        // Clamped to the range
+        let _ = menu.handle(MenuInput::Turn(5));
+        assert_eq!(menu.text(), "9");
+        let _ = menu.handle(MenuInput::Select);
+        assert_eq!(menu.text(), "Level 9");
+        let _ = menu.handle(MenuInput::Select);
+        let _ = menu.handle(MenuInput::Turn(-2));
+        assert_eq!(menu.handle(MenuInput::Back), ControlFlow::Continue(()));
+        assert_eq!(menu.text(), "Level 9");
+    }
+    assert_eq!(*changes.borrow(), [9]);
+}
+
+#[test]
+fn selecting_runs_actions_and_flips_toggles() {
+    let (resets, sound) = (Cell::new(0), Cell::new(true));
+    let mut reset = || resets.set(resets.get() + 1);
+    let mut set_sound = |on| sound.set(on);
+    {
+        let mut menu = Menu::new([
+            MenuItem::action("Reset", &mut reset),
+            MenuItem::toggle("Sound", true, &mut set_sound),
+        ]);
+        let _ = menu.handle(MenuInput::Select);
+        let _ = menu.handle(MenuInput::Turn(1));
+        let _ = menu.handle(MenuInput::Select);
+        assert_eq!(menu.text(), "Sound off");
+        assert_eq!(menu.handle(MenuInput::Back), ControlFlow::Break(()));
+    }
+    assert_eq!(resets.get(), 1);
+    assert!(!sound.get());
+}
+
+#[test]
+fn run_renders_each_change_until_backed_out_of() {
+    let (mut a, mut b) = (|| {}, |_| {});
+    let mut menu = Menu::new([
+        MenuItem::action("Reset", &mut a),
+        MenuItem::toggle("Sound", false, &mut b),
+    ]);
+    let shown = RefCell::new(Vec::new());
+    let inputs = stream::iter([MenuInput::Turn(1), MenuInput::Select, MenuInput::Back]);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(menu.run(inputs, async |text: &str| {
+        shown.borrow_mut().push(text.to_owned())
+    }));
+    assert_eq!(run.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert_eq!(*shown.borrow(), ["Reset", "Sound off", "Sound on"]);
+}
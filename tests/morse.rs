@@ -0,0 +1,88 @@
+use std::{
+    cell::RefCell,
+    convert::Infallible,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use async_fluid::{
+    morse::{Element, Morse, elements, encode},
+    time::{MockTicker, TickDuration, Ticker},
+};
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+fn on(units: u32) -> Element {
+    Element { on: true, units }
+}
+
+fn off(units: u32) -> Element {
+    Element { on: false, units }
+}
+
+#[test]
+fn encodes_letters_and_digits_in_either_case() {
+    assert_eq!(encode('s'), Some("..."));
+    assert_eq!(encode('O'), Some("---"));
+    assert_eq!(encode('7'), Some("--..."));
+    assert_eq!(encode('?'), None);
+}
+
+#[test]
+fn elements_are_timed_in_dits() {
+    let timing: Vec<_> = elements(" E T  ET ").collect();
+    assert_eq!(timing, [on(1), off(7), on(3), off(7), on(1), off(3), on(3)]);
+}
+
+#[test]
+fn characters_without_a_code_are_skipped() {
+    let timing: Vec<_> = elements("E?E").collect();
+    assert_eq!(timing, [on(1), off(3), on(1)]);
+}
+
+// Records the tick of every edge
+#[derive(Clone, Default)]
+struct RecordingPin(Rc<RefCell<Vec<(u64, bool)>>>);
+
+impl ErrorType for RecordingPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for RecordingPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.borrow_mut().push((Ticker::now().ticks(), false));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.borrow_mut().push((Ticker::now().ticks(), true));
+        Ok(())
+    }
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[test]
+fn keyed_pin_follows_the_timing() {
+    let _guard = MockTicker::lock();
+    let pin = RecordingPin::default();
+    let mut morse = Morse::new(pin.clone(), TickDuration::from_ticks(10));
+    let mut send = Box::pin(morse.send("A"));
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    let result = loop {
+        if let Poll::Ready(result) = send.as_mut().poll(&mut cx) {
+            break result;
+        }
+        MockTicker::advance(TickDuration::from_ticks(1));
+    };
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *pin.0.borrow(),
+        [(0, true), (10, false), (20, true), (50, false)]
+    );
+}
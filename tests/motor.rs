@@ -0,0 +1,147 @@
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Waker},
+};
+
+use async_fluid::{
+    motor::Motor,
+    time::{MockTicker, TickDuration, Ticker},
+};
+use embedded_hal::{
+    digital::{ErrorType, OutputPin},
+    pwm::{self, SetDutyCycle},
+};
+
+#[derive(Clone, Default)]
+struct FakePin(Rc<Cell<bool>>);
+
+impl ErrorType for FakePin {
+    type Error = Infallible;
+}
+
+impl OutputPin for FakePin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct FakePwm(Rc<Cell<u16>>);
+
+impl pwm::ErrorType for FakePwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for FakePwm {
+    fn max_duty_cycle(&self) -> u16 {
+        1000
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.0.set(duty);
+        Ok(())
+    }
+}
+
+struct Probe {
+    forward: FakePin,
+    backward: FakePin,
+    duty: FakePwm,
+}
+
+impl Probe {
+    fn new() -> (Self, Motor<FakePin, FakePin, FakePwm>) {
+        let probe = Self {
+            forward: FakePin::default(),
+            backward: FakePin::default(),
+            duty: FakePwm::default(),
+        };
+        let motor = Motor::new(
+            probe.forward.clone(),
+            probe.backward.clone(),
+            probe.duty.clone(),
+        );
+        (probe, motor)
+    }
+
+    // Direction pins and duty out of 1000
+    fn state(&self) -> (bool, bool, u16) {
+        (
+            self.forward.0.get(),
+            self.backward.0.get(),
+            self.duty.0.get(),
+        )
+    }
+}
+
+#[test]
+fn speed_sets_direction_and_duty() {
+    let (probe, mut motor) = Probe::new();
+    assert_eq!(probe.state(), (false, false, 0));
+    motor.set_speed(40);
+    assert_eq!(probe.state(), (true, false, 400));
+    motor.set_speed(-75);
+    assert_eq!(probe.state(), (false, true, 750));
+    motor.set_speed(0);
+    assert_eq!(probe.state(), (false, false, 0));
+}
+
+#[test]
+fn speeds_are_clamped() {
+    let (probe, mut motor) = Probe::new();
+    motor.set_speed(i8::MIN);
+    assert_eq!(motor.speed(), -100);
+    assert_eq!(probe.state(), (false, true, 1000));
+}
+
+#[test]
+fn reversed_motors_swap_the_pins() {
+    let (probe, motor) = Probe::new();
+    let mut motor = motor.reversed();
+    motor.set_speed(50);
+    assert_eq!(probe.state(), (false, true, 500));
+}
+
+#[test]
+fn brake_drives_both_pins() {
+    let (probe, mut motor) = Probe::new();
+    motor.set_speed(30);
+    motor.brake();
+    assert_eq!(probe.state(), (true, true, 1000));
+    assert_eq!(motor.speed(), 0);
+}
+
+#[test]
+fn ramp_steps_a_percent_each_period() {
+    let _guard = MockTicker::lock();
+    let (probe, mut motor) = Probe::new();
+    motor.set_speed(2);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut speeds = Vec::new();
+    {
+        let mut ramp = pin!(motor.ramp_to(-2, TickDuration::from_ticks(10)));
+        while ramp.as_mut().poll(&mut cx).is_pending() {
+            speeds.push((Ticker::now().ticks(), probe.state()));
+            MockTicker::advance(TickDuration::from_ticks(10));
+        }
+    }
+    assert_eq!(
+        speeds,
+        [
+            (0, (true, false, 10)),
+            (10, (false, false, 0)),
+            (20, (false, true, 10)),
+        ]
+    );
+    assert_eq!(motor.speed(), -2);
+    assert_eq!(probe.state(), (false, true, 20));
+}
@@ -0,0 +1,62 @@
+use std::{
+    cell::Cell,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Waker},
+};
+
+use async_fluid::{
+    channel::watch::Watch,
+    odometry::{self, Odometry, OdometryConfig, WheelEncoder},
+    time::{MockTicker, TickDuration},
+};
+
+struct FakeEncoder(Rc<Cell<i32>>);
+
+impl WheelEncoder for FakeEncoder {
+    fn take_steps(&mut self) -> i32 {
+        self.0.take()
+    }
+}
+
+#[test]
+fn publishes_distance_and_speed_each_period() {
+    let _guard = MockTicker::lock();
+    let steps = Rc::new(Cell::new(5));
+    let watch = Watch::new();
+    let mut receiver = watch.receiver();
+    let config = OdometryConfig {
+        um_per_step: 500,
+        // A quarter of a second
+        period: TickDuration::from_ticks(8192),
+    };
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut run = pin!(odometry::run(FakeEncoder(steps.clone()), config, &watch));
+    assert!(run.as_mut().poll(&mut cx).is_pending());
+    // The steps from before the start were dropped
+    assert_eq!(steps.get(), 0);
+
+    steps.set(100);
+    MockTicker::advance(config.period);
+    assert!(run.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(
+        receiver.try_changed(),
+        Some(Odometry {
+            steps: 100,
+            distance_mm: 50,
+            speed_mm_s: 200
+        })
+    );
+
+    steps.set(-40);
+    MockTicker::advance(config.period);
+    assert!(run.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(
+        receiver.try_changed(),
+        Some(Odometry {
+            steps: 60,
+            distance_mm: 30,
+            speed_mm_s: -80
+        })
+    );
+}
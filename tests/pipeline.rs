@@ -0,0 +1,45 @@
+use std::{
+    cell::RefCell,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::{
+    dsp::MovingAverage,
+    pipeline::{self, ForEach, Stage},
+    time::{MockTicker, TickDuration, Ticker},
+};
+
+#[test]
+fn filters_are_stages() {
+    let mut average = MovingAverage::<2>::new();
+    assert_eq!(average.process(4), Some(4));
+    assert_eq!(average.process(8), Some(6));
+}
+
+#[test]
+fn samples_on_a_fixed_period_through_the_stages() {
+    let _guard = MockTicker::lock();
+    let mut count = 0;
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = {
+        let received = received.clone();
+        ForEach(move |value| received.borrow_mut().push((Ticker::now().ticks(), value)))
+    };
+    let pipeline = pipeline::sample(TickDuration::from_ticks(10), || {
+        count += 1;
+        count
+    })
+    .filter(|count| count % 2 == 1)
+    .decimate(2)
+    .map(|count| count * 100);
+    let mut run = pin!(pipeline.run_into(sink));
+    let mut cx = Context::from_waker(Waker::noop());
+    while Ticker::now().ticks() < 100 {
+        assert!(matches!(run.as_mut().poll(&mut cx), Poll::Pending));
+        MockTicker::advance(TickDuration::from_ticks(1));
+    }
+    // Samples 1..=10 at ticks 0..=90, odd ones are kept, then every other one of those
+    assert_eq!(*received.borrow(), [(0, 100), (40, 500), (80, 900)]);
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use async_fluid::{
+    crc::crc16,
+    framing::{ByteLink, CobsDecoder, FramedLink, cobs_encode},
+    remote::{PROTOCOL_VERSION, PinMode, Remote, RemoteError, RemoteTarget, Status, handle},
+};
+use futures::task::noop_waker;
+
+// Two pins, 0 and 1, and one PWM channel. There's no display.
+#[derive(Default)]
+struct FakeTarget {
+    modes: [Option<PinMode>; 2],
+    levels: [bool; 2],
+    duty: u16,
+}
+
+impl FakeTarget {
+    fn mode(&self, pin: u8) -> Result<Option<PinMode>, RemoteError> {
+        self.modes
+            .get(usize::from(pin))
+            .copied()
+            .ok_or(RemoteError::NoSuchPin { pin })
+    }
+}
+
+impl RemoteTarget for FakeTarget {
+    fn pin_mode(&mut self, pin: u8, mode: PinMode) -> Result<(), RemoteError> {
+        self.mode(pin)?;
+        self.modes[usize::from(pin)] = Some(mode);
+        Ok(())
+    }
+
+    fn digital_write(&mut self, pin: u8, high: bool) -> Result<(), RemoteError> {
+        if self.mode(pin)? != Some(PinMode::Output) {
+            return Err(RemoteError::WrongMode { pin });
+        }
+        self.levels[usize::from(pin)] = high;
+        Ok(())
+    }
+
+    fn digital_read(&mut self, pin: u8) -> Result<bool, RemoteError> {
+        self.mode(pin)?;
+        Ok(self.levels[usize::from(pin)])
+    }
+
+    fn analog_read(&mut self, pin: u8) -> Result<u16, RemoteError> {
+        self.mode(pin)?;
+        Ok(0x1234)
+    }
+
+    fn pwm_write(&mut self, channel: u8, duty: u16) -> Result<(), RemoteError> {
+        if channel != 0 {
+            return Err(RemoteError::NoSuchPin { pin: channel });
+        }
+        self.duty = duty;
+        Ok(())
+    }
+}
+
+fn answer(target: &mut FakeTarget, request: &[u8]) -> Vec<u8> {
+    handle(target, request).unwrap().to_vec()
+}
+
+#[test]
+fn ping_answers_the_protocol_version() {
+    let mut target = FakeTarget::default();
+    assert_eq!(
+        answer(&mut target, &[7, 0x00]),
+        [7, Status::Ok as u8, PROTOCOL_VERSION]
+    );
+}
+
+#[test]
+fn commands_reach_the_target() {
+    let mut target = FakeTarget::default();
+    assert_eq!(answer(&mut target, &[1, 0x01, 1, 2]), [1, 0]);
+    assert_eq!(target.modes[1], Some(PinMode::Output));
+    assert_eq!(answer(&mut target, &[2, 0x02, 1, 1]), [2, 0]);
+    assert!(target.levels[1]);
+    assert_eq!(answer(&mut target, &[3, 0x03, 1]), [3, 0, 1]);
+    assert_eq!(answer(&mut target, &[4, 0x04, 0]), [4, 0, 0x34, 0x12]);
+    assert_eq!(answer(&mut target, &[5, 0x05, 0, 0xCD, 0xAB]), [5, 0]);
+    assert_eq!(target.duty, 0xABCD);
+}
+
+#[test]
+fn failures_come_back_as_statuses() {
+    let mut target = FakeTarget::default();
+    // Not an output yet
+    assert_eq!(
+        answer(&mut target, &[1, 0x02, 0, 1]),
+        [1, Status::WrongMode as u8]
+    );
+    assert_eq!(
+        answer(&mut target, &[2, 0x03, 9]),
+        [2, Status::NoSuchPin as u8]
+    );
+    assert_eq!(
+        answer(&mut target, &[3, 0x05, 1, 0, 0]),
+        [3, Status::NoSuchPin as u8]
+    );
+    // The fake has no display
+    assert_eq!(
+        answer(&mut target, &[4, 0x06, 1, 2, 3, 4, 5]),
+        [4, Status::NotSupported as u8]
+    );
+    assert_eq!(
+        answer(&mut target, &[5, 0x07, 100]),
+        [5, Status::NotSupported as u8]
+    );
+}
+
+#[test]
+fn malformed_requests_are_refused() {
+    let mut target = FakeTarget::default();
+    assert_eq!(
+        answer(&mut target, &[1, 0x42]),
+        [1, Status::UnknownCommand as u8]
+    );
+    assert_eq!(answer(&mut target, &[2]), [2, Status::UnknownCommand as u8]);
+    // Too short, too long, and a mode that doesn't exist
+    assert_eq!(
+        answer(&mut target, &[3, 0x03]),
+        [3, Status::BadArguments as u8]
+    );
+    assert_eq!(
+        answer(&mut target, &[4, 0x00, 0]),
+        [4, Status::BadArguments as u8]
+    );
+    assert_eq!(
+        answer(&mut target, &[5, 0x01, 0, 3]),
+        [5, Status::BadArguments as u8]
+    );
+    assert_eq!(target.modes, [None, None]);
+    assert!(handle(&mut target, &[]).is_none());
+}
+
+// Reads what the host sent, and keeps what the board answers
+#[derive(Default)]
+struct Host {
+    to_board: VecDeque<u8>,
+    from_board: Vec<u8>,
+}
+
+impl Host {
+    fn send(&mut self, request: &[u8]) {
+        let mut frame = request.to_vec();
+        frame.extend_from_slice(&crc16(request).to_le_bytes());
+        let mut encoded = [0; 300];
+        let len = cobs_encode(&frame, &mut encoded).unwrap();
+        self.to_board.extend(&encoded[..len]);
+        self.to_board.push_back(0);
+    }
+
+    fn answers(&self) -> Vec<Vec<u8>> {
+        let mut decoder = CobsDecoder::<300>::new();
+        self.from_board
+            .iter()
+            .filter_map(|&byte| decoder.feed(byte))
+            .map(|frame| {
+                let frame = frame.unwrap();
+                let (payload, crc) = frame.split_at(frame.len() - 2);
+                assert_eq!(crc, crc16(payload).to_le_bytes());
+                payload.to_vec()
+            })
+            .collect()
+    }
+}
+
+impl ByteLink for &mut Host {
+    async fn read_byte(&mut self) -> u8 {
+        poll_fn(|_| match self.to_board.pop_front() {
+            Some(byte) => Poll::Ready(byte),
+            None => Poll::Pending,
+        })
+        .await
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        self.from_board.extend_from_slice(bytes);
+    }
+}
+
+#[test]
+fn remote_answers_over_the_link() {
+    let mut host = Host::default();
+    host.send(&[1, 0x00]);
+    host.send(&[]);
+    host.send(&[2, 0x03, 0]);
+    {
+        let mut remote = Remote::new(FramedLink::new(&mut host), FakeTarget::default());
+        let mut run = pin!(remote.run());
+        let waker = noop_waker();
+        assert!(
+            run.as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending()
+        );
+    }
+    assert_eq!(
+        host.answers(),
+        [vec![1, 0, PROTOCOL_VERSION], vec![2, 0, 0]]
+    );
+}
@@ -0,0 +1,47 @@
+use std::{
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+
+use async_fluid::utils::ReplySlots;
+
+// Two tasks' `send_to`s to the same board, each waiting for the ack to its own frame, as
+// (from, to, sequence number)
+#[test]
+fn concurrent_sends_each_get_their_own_ack() {
+    static ACKS: ReplySlots<(u8, u8, u8), 4> = ReplySlots::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let first = ACKS.expect((2, 1, 10)).unwrap();
+    let second = ACKS.expect((2, 1, 11)).unwrap();
+    let mut first_wait = pin!(first.wait());
+    let mut second_wait = pin!(second.wait());
+    assert!(first_wait.as_mut().poll(&mut cx).is_pending());
+    assert!(second_wait.as_mut().poll(&mut cx).is_pending());
+
+    // Acks the other isn't waiting for don't use up its own
+    assert!(ACKS.deliver((2, 1, 11)));
+    assert!(!ACKS.deliver((3, 1, 10)));
+    assert!(first_wait.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(second_wait.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert!(ACKS.deliver((2, 1, 10)));
+    assert_eq!(first_wait.as_mut().poll(&mut cx), Poll::Ready(()));
+}
+
+#[test]
+fn an_ack_before_the_wait_still_counts() {
+    let acks = ReplySlots::<u8, 1>::new();
+    let ack = acks.expect(7).unwrap();
+    assert!(acks.deliver(7));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(pin!(ack.wait()).poll(&mut cx), Poll::Ready(()));
+}
+
+#[test]
+fn slots_are_freed_when_dropped() {
+    let acks = ReplySlots::<u8, 1>::new();
+    let ack = acks.expect(1).unwrap();
+    assert!(acks.expect(2).is_none());
+    drop(ack);
+    assert!(!acks.deliver(1));
+    assert!(acks.expect(2).is_some());
+}
@@ -0,0 +1,78 @@
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Waker},
+};
+
+use async_fluid::{
+    dsp::ONE,
+    servo::{Easing, Servo, ServoConfig},
+    time::{MockTicker, TickDuration},
+};
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+// Duty out of 20000, so it reads as the pulse width in us
+#[derive(Clone, Default)]
+struct FakePwm(Rc<Cell<u16>>);
+
+impl ErrorType for FakePwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for FakePwm {
+    fn max_duty_cycle(&self) -> u16 {
+        20000
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.0.set(duty);
+        Ok(())
+    }
+}
+
+#[test]
+fn easing_curves_start_and_end_in_place() {
+    for easing in [Easing::Linear, Easing::Quadratic, Easing::Cubic] {
+        assert_eq!(easing.apply(0), 0);
+        assert_eq!(easing.apply(ONE / 2), ONE / 2);
+        assert_eq!(easing.apply(ONE), ONE);
+    }
+    // Slower than linear at the start, faster just before the middle
+    assert!(Easing::Cubic.apply(ONE / 8) < Easing::Quadratic.apply(ONE / 8));
+    assert!(Easing::Quadratic.apply(ONE / 8) < ONE / 8);
+    assert!(Easing::Cubic.apply(ONE * 7 / 8) > ONE * 7 / 8);
+}
+
+#[test]
+fn angles_map_to_pulse_widths() {
+    let duty = FakePwm::default();
+    let mut servo = Servo::new(duty.clone(), ServoConfig::new(), 0);
+    assert_eq!(duty.0.get(), 500);
+    servo.set_angle(90);
+    assert_eq!(duty.0.get(), 1500);
+    assert_eq!(servo.angle(), 90);
+    servo.set_angle(200);
+    assert_eq!(duty.0.get(), 2500);
+    assert_eq!(servo.angle(), 180);
+}
+
+#[test]
+fn move_to_steps_each_period_along_the_curve() {
+    let _guard = MockTicker::lock();
+    let duty = FakePwm::default();
+    let mut servo = Servo::new(duty.clone(), ServoConfig::new(), 0);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut pulses = Vec::new();
+    {
+        let mut glide = pin!(servo.move_to(180, TickDuration::millis(80), Easing::Linear));
+        while glide.as_mut().poll(&mut cx).is_pending() {
+            MockTicker::advance(TickDuration::millis(20));
+            pulses.push(duty.0.get());
+        }
+    }
+    assert_eq!(pulses, [500, 1000, 1500, 2000]);
+    assert_eq!(duty.0.get(), 2500);
+    assert_eq!(servo.angle(), 180);
+}
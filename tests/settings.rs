@@ -0,0 +1,135 @@
+use std::convert::Infallible;
+
+use async_fluid::settings::{Settings, SettingsError};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+
+// A page of NOR flash, where writes can only clear bits
+struct FakeFlash {
+    bytes: Vec<u8>,
+    erases: usize,
+}
+
+impl FakeFlash {
+    fn new(size: usize) -> Self {
+        Self {
+            bytes: vec![0xFF; size],
+            erases: 0,
+        }
+    }
+}
+
+impl ErrorType for FakeFlash {
+    type Error = Infallible;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Infallible> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 64;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Infallible> {
+        self.bytes[from as usize..to as usize].fill(0xFF);
+        self.erases += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Infallible> {
+        assert_eq!(offset % 4, 0);
+        assert_eq!(bytes.len() % 4, 0);
+        let offset = offset as usize;
+        for (stored, byte) in self.bytes[offset..].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+fn read(settings: &mut Settings<FakeFlash>, key: u16) -> Option<Vec<u8>> {
+    let mut buf = [0; 64];
+    let len = settings.read(key, &mut buf).unwrap()?;
+    Some(buf[..len].to_vec())
+}
+
+#[test]
+fn latest_value_wins_and_survives_a_reset() {
+    let mut settings = Settings::new(FakeFlash::new(256));
+    assert_eq!(read(&mut settings, 1), None);
+    settings.write(1, b"abc").unwrap();
+    settings.write(2, &[7; 8]).unwrap();
+    settings.write(1, b"defgh").unwrap();
+    assert_eq!(read(&mut settings, 1).as_deref(), Some(&b"defgh"[..]));
+    let mut settings = Settings::new(settings.free());
+    assert_eq!(read(&mut settings, 1).as_deref(), Some(&b"defgh"[..]));
+    assert_eq!(read(&mut settings, 2), Some(vec![7; 8]));
+}
+
+#[test]
+fn filling_up_compacts_to_the_latest_values() {
+    let mut settings = Settings::new(FakeFlash::new(64));
+    settings.write(1, b"keep").unwrap();
+    for i in 0..20u8 {
+        settings.write(2, &[i; 4]).unwrap();
+    }
+    let mut settings = Settings::new(settings.free());
+    assert_eq!(read(&mut settings, 1).as_deref(), Some(&b"keep"[..]));
+    assert_eq!(read(&mut settings, 2), Some(vec![19; 4]));
+    assert!(settings.free().erases > 0);
+}
+
+#[test]
+fn unchanged_values_arent_rewritten() {
+    let mut settings = Settings::new(FakeFlash::new(64));
+    for _ in 0..20 {
+        settings.write(1, b"same").unwrap();
+    }
+    assert_eq!(settings.free().erases, 0);
+}
+
+#[test]
+fn torn_write_is_ignored() {
+    let mut flash = FakeFlash::new(64);
+    {
+        let mut settings = Settings::new(&mut flash);
+        settings.write(1, b"good").unwrap();
+    }
+    // The value of a second record made it, but not its header
+    flash.bytes[12..16].copy_from_slice(b"torn");
+    let mut settings = Settings::new(flash);
+    assert_eq!(read(&mut settings, 1).as_deref(), Some(&b"good"[..]));
+    settings.write(2, b"next").unwrap();
+    let mut settings = Settings::new(settings.free());
+    assert_eq!(read(&mut settings, 1).as_deref(), Some(&b"good"[..]));
+    assert_eq!(read(&mut settings, 2).as_deref(), Some(&b"next"[..]));
+}
+
+#[test]
+fn bad_writes_are_refused() {
+    let mut settings = Settings::new(FakeFlash::new(64));
+    assert!(matches!(
+        settings.write(1, &[0; 65]),
+        Err(SettingsError::TooLong)
+    ));
+    assert!(matches!(
+        settings.write(0xFFFF, b"x"),
+        Err(SettingsError::ReservedKey)
+    ));
+    settings.write(1, b"long value").unwrap();
+    let mut buf = [0; 4];
+    assert!(matches!(
+        settings.read(1, &mut buf),
+        Err(SettingsError::TooLong)
+    ));
+}
@@ -0,0 +1,82 @@
+use std::{
+    cell::Cell,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use async_fluid::{
+    shutdown::{ShutdownError, ShutdownHooks},
+    time::{MockTicker, TickDuration},
+};
+use futures::{future::pending, task::noop_waker};
+
+fn poll<F: Future>(future: std::pin::Pin<&mut F>) -> Poll<F::Output> {
+    let waker = noop_waker();
+    future.poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn hooks_flush_when_shutdown_runs() {
+    let _guard = MockTicker::lock();
+    let hooks = ShutdownHooks::<2>::new();
+    let flushed = Cell::new(0);
+    let mut first = pin!(
+        hooks
+            .register("first")
+            .unwrap()
+            .flush_on_shutdown(async || flushed.set(flushed.get() + 1))
+    );
+    let mut second = pin!(
+        hooks
+            .register("second")
+            .unwrap()
+            .flush_on_shutdown(async || flushed.set(flushed.get() + 1))
+    );
+    assert!(poll(first.as_mut()).is_pending());
+    assert!(poll(second.as_mut()).is_pending());
+    assert_eq!(flushed.get(), 0);
+    assert!(!hooks.is_requested());
+
+    let mut run = pin!(hooks.run(TickDuration::millis(5)));
+    assert!(poll(run.as_mut()).is_pending());
+    assert!(hooks.is_requested());
+    assert!(poll(first.as_mut()).is_ready());
+    assert!(poll(run.as_mut()).is_pending());
+    assert!(poll(second.as_mut()).is_ready());
+    assert_eq!(poll(run.as_mut()), Poll::Ready(true));
+    assert_eq!(flushed.get(), 2);
+}
+
+#[test]
+fn shutdown_gives_up_at_the_deadline() {
+    let _guard = MockTicker::lock();
+    let hooks = ShutdownHooks::<1>::new();
+    let mut stuck = pin!(
+        hooks
+            .register("stuck")
+            .unwrap()
+            .flush_on_shutdown(async || pending::<()>().await)
+    );
+    assert!(poll(stuck.as_mut()).is_pending());
+    let mut run = pin!(hooks.run(TickDuration::millis(5)));
+    assert!(poll(run.as_mut()).is_pending());
+    assert!(poll(stuck.as_mut()).is_pending());
+    MockTicker::advance(TickDuration::millis(5));
+    assert_eq!(poll(run.as_mut()), Poll::Ready(false));
+}
+
+#[test]
+fn dropped_hooks_dont_hold_up_shutdown() {
+    let _guard = MockTicker::lock();
+    let hooks = ShutdownHooks::<2>::new();
+    drop(hooks.register("gone").unwrap());
+    let _kept = hooks.register("kept").unwrap();
+    assert!(matches!(
+        hooks.register("extra"),
+        Err(ShutdownError::TooManyHooks)
+    ));
+    let mut run = pin!(hooks.run(TickDuration::millis(5)));
+    assert!(poll(run.as_mut()).is_pending());
+    drop(_kept);
+    assert_eq!(poll(run.as_mut()), Poll::Ready(true));
+}
@@ -0,0 +1,36 @@
+// The executor isn't built under loom
+#![cfg(not(loom))]
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use async_fluid::{executor::Executor, pin_array};
+
+// More than fit in the static tasks' ready queue
+const SPAWNED: usize = 12;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn every_spawned_task_runs() {
+    // `run_tasks` never returns, so it's left running on its own thread
+    thread::spawn(|| {
+        let tasks = pin_array!(async {
+            let spawner = Executor::spawner();
+            for _ in 0..SPAWNED {
+                spawner.spawn(async {
+                    FINISHED.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Executor::run_tasks(tasks)
+    });
+    let start = Instant::now();
+    while FINISHED.load(Ordering::Relaxed) < SPAWNED && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(FINISHED.load(Ordering::Relaxed), SPAWNED);
+}
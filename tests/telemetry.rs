@@ -0,0 +1,145 @@
+use std::{
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use async_fluid::{
+    framing::{ByteLink, CobsDecoder, FramedLink},
+    telemetry::{MAX_FIELDS, Record, Telemetry, TelemetryError},
+    time::{MockTicker, TickDuration},
+};
+use futures::{future::pending, task::noop_waker};
+
+// Collects what's written, and never has anything to read
+#[derive(Default)]
+struct Sink {
+    written: Vec<u8>,
+}
+
+impl ByteLink for &mut Sink {
+    async fn read_byte(&mut self) -> u8 {
+        pending().await
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        self.written.extend_from_slice(bytes);
+    }
+}
+
+// The payloads of the frames written, without their CRCs
+fn payloads(written: &[u8]) -> Vec<Vec<u8>> {
+    let mut decoder = CobsDecoder::<300>::new();
+    written
+        .iter()
+        .filter_map(|&byte| decoder.feed(byte))
+        .map(|frame| {
+            let frame = frame.unwrap();
+            frame[..frame.len() - 2].to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn records_are_messagepack_arrays() {
+    let mut record = Record::new("motor", 1_500_000).unwrap();
+    record
+        .field("rpm", 1200u16)
+        .unwrap()
+        .field("stalled", false)
+        .unwrap();
+    let mut expected = vec![0x93, 0xA5];
+    expected.extend_from_slice(b"motor");
+    expected.extend_from_slice(&[0xCE, 0x00, 0x16, 0xE3, 0x60, 0x82, 0xA3]);
+    expected.extend_from_slice(b"rpm");
+    expected.extend_from_slice(&[0xCD, 0x04, 0xB0, 0xA7]);
+    expected.extend_from_slice(b"stalled");
+    expected.push(0xC2);
+    assert_eq!(record.as_bytes(), expected);
+    assert_eq!(record.fields(), 2);
+}
+
+#[test]
+fn numbers_take_the_fewest_bytes() {
+    let cases: [(i64, &[u8]); 8] = [
+        (5, &[0x05]),
+        (-1, &[0xFF]),
+        (-32, &[0xE0]),
+        (-33, &[0xD0, 0xDF]),
+        (200, &[0xCC, 0xC8]),
+        (-200, &[0xD1, 0xFF, 0x38]),
+        (-70_000, &[0xD2, 0xFF, 0xFE, 0xEE, 0x90]),
+        (1 << 40, &[0xCF, 0, 0, 1, 0, 0, 0, 0, 0]),
+    ];
+    for (value, encoded) in cases {
+        let mut record = Record::new("", 0).unwrap();
+        let header = record.as_bytes().len();
+        record.field("", value).unwrap();
+        // After the key, an empty fixstr
+        assert_eq!(&record.as_bytes()[header + 1..], encoded, "{value}");
+    }
+    let mut record = Record::new("", 0).unwrap();
+    record.field("", 1.5f32).unwrap();
+    assert_eq!(record.as_bytes()[5..], [0xCA, 0x3F, 0xC0, 0x00, 0x00]);
+}
+
+#[test]
+fn fields_that_dont_fit_leave_the_record_as_it_was() {
+    let mut record = Record::new("long", 0).unwrap();
+    record.field("a", 1u8).unwrap();
+    let before = record.as_bytes().to_vec();
+    let long = "x".repeat(60);
+    assert!(matches!(
+        record.field("text", long.as_str()),
+        Err(TelemetryError::RecordTooLong)
+    ));
+    assert_eq!(record.as_bytes(), before);
+    assert_eq!(record.fields(), 1);
+
+    let mut record = Record::new("many", 0).unwrap();
+    for _ in 0..MAX_FIELDS {
+        record.field("", true).unwrap();
+    }
+    assert!(matches!(
+        record.field("", true),
+        Err(TelemetryError::TooManyFields)
+    ));
+}
+
+#[test]
+fn records_are_stamped_with_the_ticker() {
+    let _guard = MockTicker::lock();
+    MockTicker::advance(TickDuration::millis(250));
+    let record = Record::now("tick").unwrap();
+    // 250 ms is 8192 ticks, which is 250000 us
+    assert_eq!(record.as_bytes()[6..11], [0xCE, 0x00, 0x03, 0xD0, 0x90]);
+}
+
+#[test]
+fn emitted_records_are_sent_a_frame_each() {
+    let telemetry = Telemetry::<2>::new();
+    let mut first = Record::new("a", 1).unwrap();
+    first.field("x", 1u8).unwrap();
+    let second = Record::new("b", 2).unwrap();
+    telemetry.emit(first.clone()).unwrap();
+    telemetry.emit(second.clone()).unwrap();
+    assert!(matches!(
+        telemetry.emit(second.clone()),
+        Err(TelemetryError::QueueFull)
+    ));
+    assert_eq!(telemetry.dropped(), 1);
+
+    let mut sink = Sink::default();
+    {
+        let mut link = FramedLink::new(&mut sink);
+        let mut run = pin!(telemetry.run(&mut link));
+        let waker = noop_waker();
+        assert!(matches!(
+            run.as_mut().poll(&mut Context::from_waker(&waker)),
+            Poll::Pending
+        ));
+    }
+    assert_eq!(
+        payloads(&sink.written),
+        [first.as_bytes().to_vec(), second.as_bytes().to_vec()]
+    );
+}
@@ -0,0 +1,379 @@
+use std::{
+    cell::RefCell,
+    pin::{Pin, pin},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use async_fluid::{
+    rate_limiter::RateLimiter,
+    scheduler::Scheduler,
+    state_machine::{Event, State, StateMachine},
+    time::{
+        BUSY_WAIT_LIMIT_US, MockTicker, PeriodicTimer, TickDuration, TickInstant, Ticker, Timer,
+        delay_us,
+    },
+};
+use futures::{FutureExt, future::pending};
+
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl CountingWaker {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct TestTimer {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    woken: Arc<CountingWaker>,
+}
+
+impl TestTimer {
+    fn new(ticks: u64) -> Self {
+        Self {
+            future: Box::pin(Timer::delay(TickDuration::from_ticks(ticks))),
+            woken: Arc::default(),
+        }
+    }
+
+    fn poll(&mut self) -> Poll<()> {
+        let waker = Waker::from(self.woken.clone());
+        self.future.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+
+    fn woken(&self) -> bool {
+        self.woken.count() > 0
+    }
+}
+
+#[test]
+fn now_follows_the_mock() {
+    let _guard = MockTicker::lock();
+    assert_eq!(Ticker::now().ticks(), 0);
+    MockTicker::advance(TickDuration::from_ticks(1234));
+    assert_eq!(Ticker::now().ticks(), 1234);
+}
+
+#[test]
+fn now_counts_counter_overflows() {
+    let _guard = MockTicker::lock();
+    MockTicker::advance(TickDuration::from_ticks((1 << 24) + 5));
+    assert_eq!(Ticker::now().ticks(), (1 << 24) + 5);
+    MockTicker::advance(TickDuration::from_ticks(3 << 24));
+    assert_eq!(Ticker::now().ticks(), (4 << 24) + 5);
+}
+
+#[test]
+fn timer_is_pending_until_deadline() {
+    let _guard = MockTicker::lock();
+    let mut timer = TestTimer::new(100);
+    assert!(timer.poll().is_pending());
+    MockTicker::advance(TickDuration::from_ticks(99));
+    assert!(!timer.woken());
+    assert!(timer.poll().is_pending());
+    MockTicker::advance(TickDuration::from_ticks(1));
+    assert!(timer.woken());
+    assert!(timer.poll().is_ready());
+}
+
+#[test]
+fn timers_fire_in_deadline_order() {
+    let _guard = MockTicker::lock();
+    let mut timers = [TestTimer::new(30), TestTimer::new(10), TestTimer::new(20)];
+    for timer in &mut timers {
+        assert!(timer.poll().is_pending());
+    }
+    assert_eq!(MockTicker::compare(), Some(10));
+
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert_eq!(
+        timers.each_ref().map(TestTimer::woken),
+        [false, true, false]
+    );
+    assert_eq!(MockTicker::compare(), Some(20));
+
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert_eq!(timers.each_ref().map(TestTimer::woken), [false, true, true]);
+
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert_eq!(timers.each_ref().map(TestTimer::woken), [true, true, true]);
+    for timer in &mut timers {
+        assert!(timer.poll().is_ready());
+    }
+}
+
+#[test]
+fn timers_sharing_a_deadline_all_fire() {
+    let _guard = MockTicker::lock();
+    let mut timers = [TestTimer::new(50), TestTimer::new(50), TestTimer::new(50)];
+    for timer in &mut timers {
+        assert!(timer.poll().is_pending());
+    }
+    MockTicker::advance(TickDuration::from_ticks(50));
+    assert!(timers.iter().all(TestTimer::woken));
+}
+
+#[test]
+fn dropped_timer_is_removed_from_queue() {
+    let _guard = MockTicker::lock();
+    let mut early = TestTimer::new(10);
+    let mut late = TestTimer::new(20);
+    assert!(early.poll().is_pending());
+    assert!(late.poll().is_pending());
+    drop(early);
+    assert_eq!(MockTicker::compare(), Some(20));
+    MockTicker::advance(TickDuration::from_ticks(20));
+    assert!(late.woken());
+    assert!(late.poll().is_ready());
+}
+
+#[test]
+fn short_delays_busy_wait_and_long_ones_use_a_timer() {
+    let _guard = MockTicker::lock();
+    // Mock time never moves on its own, so only a busy wait can finish
+    assert!(delay_us(BUSY_WAIT_LIMIT_US - 1).now_or_never().is_some());
+
+    // 1 ms is 32.768 ticks, so 33 rounded up, plus one for the tick already under way
+    let mut delay = pin!(delay_us(1000));
+    let waker = Waker::noop();
+    let mut cx = Context::from_waker(waker);
+    assert!(delay.as_mut().poll(&mut cx).is_pending());
+    MockTicker::advance(TickDuration::from_ticks(33));
+    assert!(delay.as_mut().poll(&mut cx).is_pending());
+    MockTicker::advance(TickDuration::from_ticks(1));
+    assert!(delay.as_mut().poll(&mut cx).is_ready());
+}
+
+#[test]
+fn spurious_alarm_without_timers_is_ignored() {
+    let _guard = MockTicker::lock();
+    MockTicker::fire_alarm();
+    let mut timer = TestTimer::new(10);
+    assert!(timer.poll().is_pending());
+    // Firing before the deadline mustn't wake the timer
+    MockTicker::fire_alarm();
+    assert!(!timer.woken());
+}
+
+#[test]
+fn timer_fires_across_counter_wrap() {
+    let _guard = MockTicker::lock_at(TickInstant::from_ticks((1 << 24) - 5));
+    let mut timer = TestTimer::new(10);
+    assert!(timer.poll().is_pending());
+    assert_eq!(MockTicker::compare(), Some(5));
+    MockTicker::advance(TickDuration::from_ticks(9));
+    assert!(!timer.woken());
+    MockTicker::advance(TickDuration::from_ticks(1));
+    assert!(timer.woken());
+    assert!(timer.poll().is_ready());
+}
+
+#[test]
+fn timer_longer_than_counter_period_does_not_fire_early() {
+    let _guard = MockTicker::lock();
+    let mut timer = TestTimer::new((1 << 24) + 100);
+    assert!(timer.poll().is_pending());
+    // The compare register matches after 100 ticks, a whole period early
+    MockTicker::advance(TickDuration::from_ticks(100));
+    assert!(!timer.woken());
+    MockTicker::advance(TickDuration::from_ticks(1 << 24));
+    assert!(timer.woken());
+}
+
+#[test]
+fn timer_wakes_the_latest_waker() {
+    let _guard = MockTicker::lock();
+    let mut timer = Box::pin(Timer::delay(TickDuration::from_ticks(10)));
+    let first = Arc::new(CountingWaker::default());
+    let second = Arc::new(CountingWaker::default());
+    let mut poll_with = |woken: &Arc<CountingWaker>| {
+        let waker = Waker::from(woken.clone());
+        timer.as_mut().poll(&mut Context::from_waker(&waker))
+    };
+    assert!(poll_with(&first).is_pending());
+    assert!(poll_with(&second).is_pending());
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert_eq!(first.count(), 0);
+    assert_eq!(second.count(), 1);
+    assert!(poll_with(&second).is_ready());
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Blink {
+    On,
+    Off,
+}
+
+impl State for Blink {
+    fn timeout(&self) -> Option<TickDuration> {
+        match self {
+            Blink::On => Some(TickDuration::from_ticks(100)),
+            Blink::Off => None,
+        }
+    }
+}
+
+#[test]
+fn state_times_out_from_when_it_was_entered() {
+    let _guard = MockTicker::lock();
+    let mut machine = StateMachine::new(Blink::On);
+    MockTicker::advance(TickDuration::from_ticks(40));
+    let woken = Arc::new(CountingWaker::default());
+    let waker = Waker::from(woken.clone());
+    let mut event = Box::pin(machine.next_event(pending::<()>()));
+    assert!(
+        event
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+    );
+    MockTicker::advance(TickDuration::from_ticks(60));
+    assert_eq!(woken.count(), 1);
+    let polled = event.as_mut().poll(&mut Context::from_waker(&waker));
+    assert!(matches!(polled, Poll::Ready(Event::Timeout)));
+    drop(event);
+
+    machine.transition(Blink::Off);
+    let mut event = Box::pin(machine.next_event(pending::<()>()));
+    assert!(
+        event
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+    );
+    MockTicker::advance(TickDuration::from_ticks(1000));
+    assert_eq!(woken.count(), 1);
+}
+
+#[test]
+fn rate_limiter_allows_a_burst_then_spaces_out() {
+    let _guard = MockTicker::lock();
+    let limiter = RateLimiter::new(2, TickDuration::from_ticks(100));
+    assert!(limiter.try_acquire());
+    assert!(limiter.try_acquire());
+    assert!(!limiter.try_acquire());
+
+    let woken = Arc::new(CountingWaker::default());
+    let waker = Waker::from(woken.clone());
+    let mut acquire = Box::pin(limiter.acquire());
+    assert!(
+        acquire
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+    );
+    MockTicker::advance(TickDuration::from_ticks(49));
+    assert_eq!(woken.count(), 0);
+    MockTicker::advance(TickDuration::from_ticks(1));
+    assert_eq!(woken.count(), 1);
+    assert!(
+        acquire
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready()
+    );
+    assert!(!limiter.try_acquire());
+}
+
+#[test]
+fn scheduler_runs_jobs_at_their_period_and_phase() {
+    let _guard = MockTicker::lock();
+    let fast = RefCell::new(Vec::new());
+    let slow = RefCell::new(Vec::new());
+    let mut record_fast = |due: TickInstant| fast.borrow_mut().push(due.ticks());
+    let mut record_slow = |due: TickInstant| slow.borrow_mut().push(due.ticks());
+    let mut scheduler = Scheduler::<2>::new();
+    let ticks = TickDuration::from_ticks;
+    scheduler
+        .register("fast", ticks(10), ticks(0), &mut record_fast)
+        .unwrap();
+    scheduler
+        .register("slow", ticks(25), ticks(5), &mut record_slow)
+        .unwrap();
+
+    let mut run = Box::pin(scheduler.run());
+    let mut cx = Context::from_waker(Waker::noop());
+    for _ in 0..=50 {
+        assert!(run.as_mut().poll(&mut cx).is_pending());
+        MockTicker::advance(ticks(1));
+    }
+    drop(run);
+    assert_eq!(*fast.borrow(), [0, 10, 20, 30, 40, 50]);
+    assert_eq!(*slow.borrow(), [5, 30]);
+}
+
+#[test]
+fn drift_correction_scales_time_from_when_it_was_set() {
+    let _guard = MockTicker::lock_at(TickInstant::from_ticks(1000));
+    // 5% fast, so 1000 raw ticks are really 950
+    Ticker::set_drift_ppm(50_000);
+    MockTicker::advance(TickDuration::from_ticks(1000));
+    assert_eq!(Ticker::now().ticks(), 1950);
+    assert_eq!(Ticker::drift_ppm(), 50_000);
+
+    // Changing it again doesn't make time jump
+    Ticker::set_drift_ppm(0);
+    assert_eq!(Ticker::now().ticks(), 1950);
+    MockTicker::advance(TickDuration::from_ticks(100));
+    assert_eq!(Ticker::now().ticks(), 2050);
+}
+
+#[test]
+fn timers_fire_at_the_corrected_deadline() {
+    let _guard = MockTicker::lock();
+    // 5% slow, so 105 corrected ticks pass in 100 raw ones
+    Ticker::set_drift_ppm(-50_000);
+    let mut timer = TestTimer::new(105);
+    assert_eq!(timer.poll(), Poll::Pending);
+    MockTicker::advance(TickDuration::from_ticks(99));
+    assert_eq!(timer.woken.count(), 0);
+    MockTicker::advance(TickDuration::from_ticks(1));
+    assert_eq!(timer.woken.count(), 1);
+    assert_eq!(timer.poll(), Poll::Ready(()));
+}
+
+#[test]
+fn periodic_timer_keeps_to_its_schedule() {
+    let _guard = MockTicker::lock();
+    let mut timer = Box::pin(PeriodicTimer::new(TickDuration::from_ticks(10)));
+    let waker = Arc::new(CountingWaker::default());
+    let mut ticks = Vec::new();
+    while Ticker::now().ticks() < 60 {
+        let mut tick = pin!(timer.as_mut().tick());
+        while tick
+            .as_mut()
+            .poll(&mut Context::from_waker(&Waker::from(waker.clone())))
+            .is_pending()
+        {
+            MockTicker::advance(TickDuration::from_ticks(3));
+        }
+        ticks.push(Ticker::now().ticks());
+    }
+    // Each wait polls a little late, but the next deadline is still a period after the last one
+    assert_eq!(ticks, [12, 21, 30, 42, 51, 60]);
+    assert_eq!(waker.count(), 6);
+}
+
+#[test]
+fn periodic_timer_skips_missed_ticks() {
+    let _guard = MockTicker::lock();
+    let mut timer = Box::pin(PeriodicTimer::new(TickDuration::from_ticks(10)));
+    let mut cx = Context::from_waker(Waker::noop());
+    MockTicker::advance(TickDuration::from_ticks(35));
+    assert_eq!(pin!(timer.as_mut().tick()).poll(&mut cx), Poll::Ready(()));
+    // The deadlines at 20 and 30 are gone, so the next one is at 40
+    assert_eq!(pin!(timer.as_mut().tick()).poll(&mut cx), Poll::Pending);
+    MockTicker::advance(TickDuration::from_ticks(5));
+    assert_eq!(pin!(timer.as_mut().tick()).poll(&mut cx), Poll::Ready(()));
+}
@@ -0,0 +1,68 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_fluid::time::{MockTicker, QueueMetrics, TickDuration, Ticker, Timer};
+use futures::task::noop_waker;
+
+fn start(ticks: u64) -> Pin<Box<dyn Future<Output = ()>>> {
+    let mut timer: Pin<Box<dyn Future<Output = ()>>> =
+        Box::pin(Timer::delay(TickDuration::from_ticks(ticks)));
+    assert!(poll(&mut timer).is_pending());
+    timer
+}
+
+fn poll(timer: &mut Pin<Box<dyn Future<Output = ()>>>) -> Poll<()> {
+    let waker = noop_waker();
+    timer.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn counts_inserts_removes_and_fires() {
+    let _guard = MockTicker::lock();
+    let mut first = start(10);
+    let second = start(20);
+    let _third = start(30);
+    drop(second);
+    MockTicker::advance(TickDuration::from_ticks(10));
+    assert!(poll(&mut first).is_ready());
+    assert_eq!(
+        Ticker::queue_metrics(),
+        QueueMetrics {
+            inserts: 3,
+            removes: 1,
+            fired: 1,
+            len: 1,
+            max_len: 3,
+            max_insert_scan: 2,
+        }
+    );
+}
+
+#[test]
+fn scan_length_stays_logarithmic() {
+    let _guard = MockTicker::lock();
+    // In order, which would be the worst case for a sorted list
+    let _timers: Vec<_> = (1..=255).map(|ticks| start(ticks * 10)).collect();
+    let metrics = Ticker::queue_metrics();
+    assert_eq!(metrics.len, 255);
+    // A red-black tree is at most twice as deep as a balanced one
+    assert!(metrics.max_insert_scan <= 16);
+}
+
+#[test]
+fn reset_keeps_the_waiting_timers() {
+    let _guard = MockTicker::lock();
+    let _first = start(10);
+    let _second = start(20);
+    Ticker::reset_queue_metrics();
+    assert_eq!(
+        Ticker::queue_metrics(),
+        QueueMetrics {
+            len: 2,
+            max_len: 2,
+            ..QueueMetrics::default()
+        }
+    );
+}
@@ -0,0 +1,157 @@
+use std::{
+    cell::Cell,
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+};
+
+use async_fluid::utils::TransferSlot;
+
+mod common;
+
+use common::block_on;
+
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+    let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    (count.clone(), Waker::from(count))
+}
+
+#[test]
+fn a_second_transfer_waits_for_the_first() {
+    let slot = TransferSlot::<4>::new();
+    let started = Cell::new(0);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut first = pin!(slot.transfer(|| started.set(started.get() + 1)));
+    let mut second = pin!(slot.transfer(|| started.set(started.get() + 1)));
+    assert_eq!(first.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(started.get(), 1);
+    assert!(slot.is_busy());
+
+    slot.finish();
+    assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(started.get(), 2);
+
+    slot.finish();
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert!(!slot.is_busy());
+}
+
+#[test]
+fn finishing_wakes_every_waiter() {
+    let slot = TransferSlot::<4>::new();
+    let (sending, sending_waker) = counting_waker();
+    let (waiting, waiting_waker) = counting_waker();
+    let mut first = pin!(slot.transfer(|| {}));
+    let mut second = pin!(slot.transfer(|| {}));
+    assert_eq!(
+        first
+            .as_mut()
+            .poll(&mut Context::from_waker(&sending_waker)),
+        Poll::Pending
+    );
+    assert_eq!(
+        second
+            .as_mut()
+            .poll(&mut Context::from_waker(&waiting_waker)),
+        Poll::Pending
+    );
+    slot.finish();
+    assert_eq!(sending.0.load(Ordering::Relaxed), 1);
+    assert_eq!(waiting.0.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn a_transfer_sees_its_finish_after_the_next_has_started() {
+    let slot = TransferSlot::<4>::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut first = pin!(slot.transfer(|| {}));
+    let mut second = pin!(slot.transfer(|| {}));
+    assert_eq!(first.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Pending);
+    slot.finish();
+    // The second is polled first, and starts before the first sees its own has finished
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Pending);
+    assert!(slot.is_busy());
+    assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert_eq!(second.as_mut().poll(&mut cx), Poll::Pending);
+}
+
+#[test]
+fn finishing_with_nothing_in_progress_does_nothing() {
+    let slot = TransferSlot::<4>::new();
+    slot.finish();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut transfer = pin!(slot.transfer(|| {}));
+    assert_eq!(transfer.as_mut().poll(&mut cx), Poll::Pending);
+    slot.finish();
+    assert_eq!(transfer.as_mut().poll(&mut cx), Poll::Ready(()));
+}
+
+#[test]
+fn more_waiters_than_fit_poll_again() {
+    let slot = TransferSlot::<1>::new();
+    let mut cx = Context::from_waker(Waker::noop());
+    let (overflow, overflow_waker) = counting_waker();
+    let mut first = pin!(slot.transfer(|| {}));
+    let mut second = pin!(slot.transfer(|| {}));
+    assert_eq!(first.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(
+        second
+            .as_mut()
+            .poll(&mut Context::from_waker(&overflow_waker)),
+        Poll::Pending
+    );
+    // The first's waker took the only place, so the second is woken straight away instead
+    assert_eq!(overflow.0.load(Ordering::Relaxed), 1);
+}
+
+// The reliable radio's receiving task acks frames while another task's `send_to` is sending, with
+// a thread standing in for the radio interrupt. With a single waker, one of them never woke.
+#[test]
+fn acks_go_out_while_a_send_is_in_flight() {
+    static TX: TransferSlot<4> = TransferSlot::new();
+    static DONE: AtomicBool = AtomicBool::new(false);
+    static SENT: AtomicUsize = AtomicUsize::new(0);
+    let interrupt = thread::spawn(|| {
+        while !DONE.load(Ordering::Relaxed) {
+            if TX.is_busy() {
+                TX.finish();
+            }
+            thread::yield_now();
+        }
+    });
+    let send_to = thread::spawn(|| {
+        for _ in 0..1000 {
+            block_on(TX.transfer(|| {
+                SENT.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+    });
+    let recv = thread::spawn(|| {
+        for _ in 0..1000 {
+            block_on(TX.transfer(|| {
+                SENT.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+    });
+    send_to.join().unwrap();
+    recv.join().unwrap();
+    DONE.store(true, Ordering::Relaxed);
+    interrupt.join().unwrap();
+    assert_eq!(SENT.load(Ordering::Relaxed), 2000);
+    assert!(!TX.is_busy());
+}
@@ -0,0 +1,51 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Wake, Waker},
+};
+
+use async_fluid::utils::WaiterList;
+
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+    let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    (count.clone(), Waker::from(count))
+}
+
+#[test]
+fn every_waiter_is_woken_once() {
+    let list = WaiterList::<4>::new();
+    let (first, first_waker) = counting_waker();
+    let (second, second_waker) = counting_waker();
+    list.register(&mut Context::from_waker(&first_waker));
+    list.register(&mut Context::from_waker(&second_waker));
+    // Registering again on the next poll doesn't wake it twice
+    list.register(&mut Context::from_waker(&first_waker));
+    list.wake_all();
+    assert_eq!(first.0.load(Ordering::Relaxed), 1);
+    assert_eq!(second.0.load(Ordering::Relaxed), 1);
+
+    // They were taken, so they have to register again
+    list.wake_all();
+    assert_eq!(first.0.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn waiters_that_dont_fit_poll_again() {
+    let list = WaiterList::<1>::new();
+    let (first, first_waker) = counting_waker();
+    let (overflow, overflow_waker) = counting_waker();
+    list.register(&mut Context::from_waker(&first_waker));
+    list.register(&mut Context::from_waker(&overflow_waker));
+    assert_eq!(first.0.load(Ordering::Relaxed), 0);
+    assert_eq!(overflow.0.load(Ordering::Relaxed), 1);
+}
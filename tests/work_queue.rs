@@ -0,0 +1,63 @@
+// The work queue isn't built under loom
+#![cfg(not(loom))]
+
+use std::{
+    pin::pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use async_fluid::work_queue::{IsrWorkQueue, Work};
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Sample(u16),
+    Flush,
+}
+
+#[test]
+fn commands_come_out_in_order() {
+    static QUEUE: IsrWorkQueue<Command, 4> = IsrWorkQueue::new();
+    // Pushed from another thread, standing in for an interrupt
+    thread::spawn(|| {
+        QUEUE.push(Command::Sample(7)).unwrap();
+        QUEUE.push(Command::Flush).unwrap();
+    })
+    .join()
+    .unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(
+        pin!(QUEUE.next()).poll(&mut cx),
+        Poll::Ready(Command::Sample(7))
+    );
+    assert_eq!(
+        pin!(QUEUE.next()).poll(&mut cx),
+        Poll::Ready(Command::Flush)
+    );
+    assert_eq!(pin!(QUEUE.next()).poll(&mut cx), Poll::Pending);
+}
+
+#[test]
+fn full_queue_counts_dropped_work() {
+    let queue = IsrWorkQueue::<u8, 2>::new();
+    assert_eq!(queue.push(1), Ok(()));
+    assert_eq!(queue.push(2), Ok(()));
+    assert_eq!(queue.push(3), Err(3));
+    assert_eq!(queue.dropped(), 1);
+}
+
+#[test]
+fn functions_are_called_by_the_runner() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    fn work() {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let queue = IsrWorkQueue::<Work, 4>::new();
+    queue.push(work).unwrap();
+    queue.push(work).unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(pin!(queue.run_work()).poll(&mut cx).is_pending());
+    assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+}